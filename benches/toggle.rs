@@ -0,0 +1,80 @@
+//
+// This file provides benchmarks of the writes of an output pin, to choose the access fitting
+// the frequency a pin is toggled at: a sysfs write opening and closing the value file, a
+// `FastPin` keeping it open, and a write to the memory-mapped registers.
+// The sysfs root and the physical memory are fakes in the temporary directory, so the
+// benchmarks run without GPIO pins and measure the overhead of the library and the system calls.
+//
+// Run them with `cargo bench --features mmio`.
+//
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use opi_gpio_rs::{
+    fast::FastPin,
+    gpio::{Gpio, GpioConfig},
+    mmio::{MmioBackend, Soc},
+    pin::OutputPin,
+    sysfs::{SysfsBackend, SysfsExportMode},
+};
+use std::{fs, hint::black_box, path::PathBuf, sync::Arc};
+use tokio::runtime::Runtime;
+
+/// Pin toggled through sysfs.
+const SYSFS_PIN: u8 = 3;
+/// Pin toggled through the registers, PC6 of the H616.
+const MMIO_PIN: u8 = 70;
+
+/// Create a fake sysfs root with the pin already exported.
+fn sysfs_gpio(root: &PathBuf) -> Gpio {
+    fs::create_dir_all(root.join(format!("gpio{}", SYSFS_PIN))).unwrap();
+    fs::write(root.join(format!("gpio{}/value", SYSFS_PIN)), "0").unwrap();
+    let config = GpioConfig {
+        sysfs_root: root.clone(),
+        ..Default::default()
+    };
+    let backend = SysfsBackend::new(root).with_export_mode(SysfsExportMode::Files);
+    Gpio::with_backend(config, Arc::new(backend))
+}
+
+/// Create a sparse file standing in for `/dev/mem`, covering the registers of the H616.
+fn mmio_gpio(memory: &PathBuf) -> Gpio {
+    fs::File::create(memory)
+        .unwrap()
+        .set_len(0x0400_0000)
+        .unwrap();
+    let backend = MmioBackend::with_device(Soc::AllwinnerH616, memory).unwrap();
+    Gpio::with_backend(GpioConfig::default(), Arc::new(backend))
+}
+
+fn toggle(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let dir = std::env::temp_dir().join(format!("opi_gpio_bench_{}", std::process::id()));
+    let gpio = sysfs_gpio(&dir.join("gpio"));
+    let mut group = c.benchmark_group("toggle");
+
+    let pin = runtime
+        .block_on(OutputPin::new(&gpio, SYSFS_PIN, 0))
+        .unwrap();
+    group.bench_function("sysfs", |b| {
+        b.iter(|| runtime.block_on(pin.toggle()).unwrap());
+    });
+
+    let pin = FastPin::new(pin).unwrap();
+    group.bench_function("fast", |b| {
+        b.iter(|| black_box(pin.toggle().unwrap()));
+    });
+
+    let gpio = mmio_gpio(&dir.join("mem"));
+    let pin = runtime
+        .block_on(OutputPin::new(&gpio, MMIO_PIN, 0))
+        .unwrap();
+    group.bench_function("mmio", |b| {
+        b.iter(|| runtime.block_on(pin.toggle()).unwrap());
+    });
+
+    group.finish();
+    fs::remove_dir_all(dir).unwrap_or_default();
+}
+
+criterion_group!(benches, toggle);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+//
+// This file provides a driver for the MCP3004 and MCP3008 analog to digital converters, the
+// Orange Pi boards having no analog inputs. They convert 4 or 8 channels to 10-bit values,
+// relative to their reference voltage, either single ended or as differential pairs.
+//
+// Each conversion is a 3-byte SPI transfer: a start bit, the channel and the input mode, then
+// the 10 bits of the result clocked back. The sample is held while the bits are clocked out,
+// so the slow clock of the software master loses some accuracy at high temperatures.
+//
+
+use super::bus::BitOrder;
+use super::error::{GpioError, Result};
+use super::spi::{Spi, SpiMode};
+
+/// Highest value of a conversion.
+const MAX_VALUE: u16 = 0x3FF;
+
+/// Model of a MCP300x converter, they differ in their number of channels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mcp3008Model {
+    /// MCP3004, 4 channels
+    Mcp3004,
+    /// MCP3008, 8 channels
+    Mcp3008,
+}
+
+impl Mcp3008Model {
+    /// Get the number of single ended channels.
+    pub fn channels(&self) -> u8 {
+        match self {
+            Self::Mcp3004 => 4,
+            Self::Mcp3008 => 8,
+        }
+    }
+}
+
+/// Driver of a MCP3004 or MCP3008 converter on an SPI bus.
+#[derive(Debug)]
+pub struct Mcp3008 {
+    spi: Spi,
+    model: Mcp3008Model,
+}
+
+impl Mcp3008 {
+    /// Create a driver on an SPI bus in mode 0 or 3, sending the most significant bits first,
+    /// with the chip select of the converter.
+    pub fn new(spi: Spi, model: Mcp3008Model) -> Result<Self> {
+        if !matches!(spi.mode(), SpiMode::Mode0 | SpiMode::Mode3) {
+            return Err(GpioError::InvalidValue(format!(
+                "MCP3008 needs SPI mode 0 or 3, got {:?}",
+                spi.mode()
+            )));
+        }
+        if spi.bit_order() != BitOrder::MsbFirst {
+            return Err(GpioError::InvalidValue(
+                "MCP3008 needs the most significant bits first".to_string(),
+            ));
+        }
+        Ok(Self { spi, model })
+    }
+
+    /// Get the model of the converter.
+    pub fn model(&self) -> Mcp3008Model {
+        self.model
+    }
+
+    /// Convert a single ended channel, from 0 at the ground to 1023 at the reference voltage.
+    pub async fn read_channel(&self, channel: u8) -> Result<u16> {
+        self.convert(channel, true).await
+    }
+
+    /// Convert the differential pair of a channel, the input of the channel being positive
+    /// and the other input of its pair, e.g. 1 for 0, negative. The result is 0 when the
+    /// positive input is below the negative one.
+    pub async fn read_differential(&self, channel: u8) -> Result<u16> {
+        self.convert(channel, false).await
+    }
+
+    /// Convert a single ended channel to a voltage, given the reference voltage.
+    pub async fn read_voltage(&self, channel: u8, reference: f64) -> Result<f64> {
+        let value = self.read_channel(channel).await?;
+        Ok(value as f64 * reference / MAX_VALUE as f64)
+    }
+
+    /// Give the SPI bus back.
+    pub fn into_spi(self) -> Spi {
+        self.spi
+    }
+
+    /// Run a conversion and decode its result.
+    async fn convert(&self, channel: u8, single_ended: bool) -> Result<u16> {
+        if channel >= self.model.channels() {
+            return Err(GpioError::InvalidValue(format!(
+                "{:?} channel must be between 0 and {}, got {}",
+                self.model,
+                self.model.channels() - 1,
+                channel
+            )));
+        }
+
+        // The start bit is the last of the first byte,
+        // so the 10 bits of the result end the last byte
+        let mut buffer = [0x01, (single_ended as u8) << 7 | channel << 4, 0x00];
+        self.spi.transfer(&mut buffer).await?;
+        Ok(u16::from_be_bytes([buffer[1], buffer[2]]) & MAX_VALUE)
+    }
+}
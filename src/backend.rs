@@ -0,0 +1,79 @@
+//
+// This file provides the interface between the pins and the kernel.
+// A [GpioBackend] implements the low level operations on pin numbers, so pins and watchers
+// work the same way whether the pins are accessed through sysfs, the GPIO character device
+// or a mock used for testing.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use async_trait::async_trait;
+use std::fmt;
+use tokio_stream::Stream;
+
+/// Stream yielding an item each time the value of a watched pin changes.
+pub type ChangeStream = std::pin::Pin<Box<dyn Stream<Item = Result<()>> + Send>>;
+
+/// Low level access to GPIO pins by pin number.
+/// Pins hold their backend behind an `Arc`, so a single backend can be shared by many pins
+/// and the interface can be chosen at runtime.
+#[async_trait]
+pub trait GpioBackend: fmt::Debug + Send + Sync {
+    /// Export the pin as an input.
+    async fn export_input(&self, pin_number: u8) -> Result<()>;
+
+    /// Export the pin as an output driving the default value.
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()>;
+
+    /// Make an exported pin an input, keeping its export.
+    /// Backends without a cheaper way export the pin again.
+    async fn set_input(&self, pin_number: u8) -> Result<()> {
+        self.export_input(pin_number).await
+    }
+
+    /// Make an exported pin an output driving the line at the given level, keeping its export.
+    /// Backends without a cheaper way export the pin again.
+    async fn set_output(&self, pin_number: u8, level: u8) -> Result<()> {
+        self.export_output(pin_number, level).await
+    }
+
+    /// Get the current direction of an exported pin.
+    async fn direction(&self, pin_number: u8) -> Result<Direction>;
+
+    /// Release the pin back to the system.
+    async fn unexport(&self, pin_number: u8) -> Result<()>;
+
+    /// Read the value of the pin.
+    async fn read(&self, pin_number: u8) -> Result<u8>;
+
+    /// Write a value to an output pin.
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()>;
+
+    /// Enable edge notification on the given edge, or disable it with `None`.
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()>;
+
+    /// Get the edge notification is enabled on, `None` if it's disabled.
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>>;
+
+    /// List the exported pins, by increasing number.
+    /// Backends that can't know them fail with [GpioError::Unsupported].
+    async fn exported(&self) -> Result<Vec<u8>> {
+        Err(GpioError::Unsupported(format!(
+            "Listing the exported pins of {:?}",
+            self
+        )))
+    }
+
+    /// Configure the internal pull resistors of the pin.
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()>;
+
+    /// Set the current the pin can drive, in mA, rounded up to a strength of the SoC.
+    async fn set_drive_strength(&self, pin_number: u8, milliamps: u8) -> Result<()>;
+
+    /// Invert the logic of the pin, so 1 means the line is driven or read low.
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()>;
+
+    /// Get a stream of the changes of the pin's value.
+    /// Edge notification must be enabled with [GpioBackend::set_edge] first.
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream>;
+}
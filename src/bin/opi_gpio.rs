@@ -0,0 +1,93 @@
+//
+// This file provides the `opi-gpio` command line tool, built on the library.
+// It reads, writes, watches, exports and unexports pins by SoC number, and prints
+// the state of all the pins of the board header, which makes it a quick way to check
+// the wiring and the library on a new board.
+//
+
+use opi_gpio_rs::error::{GpioError, Result};
+use opi_gpio_rs::gpio::{Gpio, GpioConfig};
+use opi_gpio_rs::pin::{Edge, GpioPin, InputPin, OutputPin};
+use opi_gpio_rs::pinmap::PinMap;
+use opi_gpio_rs::watcher::GpioWatcher;
+use std::{env, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: opi-gpio [--root <sysfs root>] <command>
+
+Commands:
+  read <pin>              Print the value of the pin
+  write <pin> <0|1>       Export the pin as an output driving the value
+  watch <pin>...          Print the changes of the pins until interrupted
+  export <pin> <in|out>   Export the pin in the given direction
+  unexport <pin>          Release the pin
+  readall                 Print the state of the pins of the board header and the exported pins
+
+Pins are SoC GPIO numbers, the header of the detected board is used for readall.";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut config = GpioConfig::default();
+    if args.first().map(String::as_str) == Some("--root") {
+        if args.len() < 2 {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+        config.sysfs_root = args[1].clone().into();
+        args.drain(..2);
+    }
+    config.pin_map = PinMap::detect().unwrap_or_default();
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match run(&Gpio::new(config), &args).await {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("opi-gpio: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run a command, returns false if the arguments don't match any.
+async fn run(gpio: &Gpio, args: &[&str]) -> Result<bool> {
+    match args {
+        ["read", pin] => {
+            println!("{}", gpio.backend().read(parse(pin)?).await?);
+        }
+        ["write", pin, value] => {
+            OutputPin::new(gpio, parse(pin)?, parse(value)?).await?;
+        }
+        ["watch", pins @ ..] if !pins.is_empty() => {
+            let mut watched = Vec::new();
+            for pin in pins {
+                let mut pin = InputPin::new(gpio, parse(pin)?).await?;
+                pin.enable_watch(Edge::Both).await?;
+                watched.push(GpioPin::from(pin));
+            }
+            let (_watcher, mut events) = GpioWatcher::with_channel(watched, 64).await?;
+            loop {
+                tokio::select! {
+                    Some((pin, value)) = events.recv() => println!("{} {}", pin, value),
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+        }
+        ["export", pin, "in"] => gpio.backend().export_input(parse(pin)?).await?,
+        ["export", pin, "out"] => gpio.backend().export_output(parse(pin)?, 0).await?,
+        ["unexport", pin] => gpio.backend().unexport(parse(pin)?).await?,
+        ["readall"] => println!("{}", gpio.snapshot().await?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Parse a pin number or a value.
+fn parse(arg: &str) -> Result<u8> {
+    arg.parse()
+        .map_err(|_| GpioError::InvalidValue(format!("Invalid number {:?}", arg)))
+}
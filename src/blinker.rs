@@ -0,0 +1,237 @@
+//
+// This file provides a scheduler playing on/off patterns on an output pin, e.g. SOS or
+// status codes made of a number of short blinks followed by a pause.
+// The pattern plays from a tokio task and can be replaced at any time, the new one
+// starting right away, so the pin can report a changing status without blocking.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+/// Sequence of on and off steps played by a [Blinker].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BlinkPattern {
+    steps: Vec<(bool, Duration)>,
+    repeat: bool,
+}
+
+impl BlinkPattern {
+    /// Create an empty pattern, played once.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step with the pin on for the given time.
+    pub fn on(mut self, duration: Duration) -> Self {
+        self.steps.push((true, duration));
+        self
+    }
+
+    /// Add a step with the pin off for the given time.
+    pub fn off(mut self, duration: Duration) -> Self {
+        self.steps.push((false, duration));
+        self
+    }
+
+    /// Set whether the pattern loops until replaced, or plays once.
+    pub fn repeat(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Create a looping status code: the given number of short blinks, then a pause.
+    pub fn code(count: usize, blink: Duration, pause: Duration) -> Self {
+        let mut pattern = Self::new().repeat(true);
+        for _ in 0..count {
+            pattern = pattern.on(blink).off(blink);
+        }
+        pattern.off(pause)
+    }
+
+    /// Create a looping SOS in Morse code, a dot lasting the given unit.
+    pub fn sos(unit: Duration) -> Self {
+        let mut pattern = Self::new().repeat(true);
+        for (i, length) in [1, 3, 1].into_iter().enumerate() {
+            for j in 0..3 {
+                pattern = pattern.on(unit * length);
+                // Gaps of 1 unit between the signals, 3 between the letters and 7 between words
+                pattern = pattern.off(match (i, j) {
+                    (2, 2) => unit * 7,
+                    (_, 2) => unit * 3,
+                    _ => unit,
+                });
+            }
+        }
+        pattern
+    }
+
+    /// Get the steps of the pattern, whether the pin is on and for how long.
+    pub fn steps(&self) -> &[(bool, Duration)] {
+        &self.steps
+    }
+
+    /// Check if the pattern loops until replaced.
+    pub fn is_repeating(&self) -> bool {
+        self.repeat
+    }
+
+    /// Get the time it takes to play the pattern once.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+/// Command sent to the task of a [Blinker].
+#[derive(Debug, Clone, PartialEq)]
+enum BlinkerCommand {
+    Play(BlinkPattern),
+    /// Set by the task once a pattern played once ended
+    Done,
+    Off,
+    Exit,
+}
+
+/// Pattern scheduler driving an [OutputPin] from a tokio task.
+///
+/// Dropping this will stop the pattern and leave the pin low.
+#[derive(Debug)]
+pub struct Blinker {
+    command: Arc<watch::Sender<BlinkerCommand>>,
+    blinker_thread: Option<JoinHandle<OutputPin>>,
+}
+
+impl Drop for Blinker {
+    fn drop(&mut self) {
+        // The task drives the pin low and exits once it sees the exit command
+        self.command.send_replace(BlinkerCommand::Exit);
+    }
+}
+
+impl Blinker {
+    /// Start a scheduler on the given pin, which is off until a pattern is played.
+    pub fn new(pin: OutputPin) -> Self {
+        let (command, receiver) = watch::channel(BlinkerCommand::Off);
+        let command = Arc::new(command);
+        let blinker_thread = tokio::spawn(run_blinker(pin, command.clone(), receiver));
+
+        Self {
+            command,
+            blinker_thread: Some(blinker_thread),
+        }
+    }
+
+    /// Play a pattern from its start, replacing the current one.
+    pub fn play(&self, pattern: BlinkPattern) -> Result<()> {
+        if pattern.repeat && pattern.duration().is_zero() {
+            return Err(GpioError::InvalidValue(
+                "A repeating pattern must not be empty".to_string(),
+            ));
+        }
+        self.command.send_replace(BlinkerCommand::Play(pattern));
+        Ok(())
+    }
+
+    /// Stop the current pattern and turn the pin off.
+    pub fn stop(&self) {
+        self.command.send_replace(BlinkerCommand::Off);
+    }
+
+    /// Check if a pattern is playing. A pattern played once stops playing at its end.
+    pub fn is_playing(&self) -> bool {
+        matches!(*self.command.borrow(), BlinkerCommand::Play(_))
+    }
+
+    /// Wait for the current pattern to end, which never happens for a repeating one.
+    pub async fn wait(&self) {
+        let mut command = self.command.subscribe();
+        let _ = command
+            .wait_for(|command| !matches!(command, BlinkerCommand::Play(_)))
+            .await;
+    }
+
+    /// Stop the pattern, leave the pin low and give the pin back.
+    pub async fn into_pin(mut self) -> Result<OutputPin> {
+        self.command.send_replace(BlinkerCommand::Exit);
+        match self.blinker_thread.take() {
+            Some(blinker_thread) => blinker_thread
+                .await
+                .map_err(|e| GpioError::TaskFailed(e.to_string())),
+            None => Err(GpioError::TaskFailed(
+                "Blinker is already stopped".to_string(),
+            )),
+        }
+    }
+}
+
+/// Play the patterns received until the exit command.
+async fn run_blinker(
+    pin: OutputPin,
+    sender: Arc<watch::Sender<BlinkerCommand>>,
+    mut command: watch::Receiver<BlinkerCommand>,
+) -> OutputPin {
+    loop {
+        let current = command.borrow_and_update().clone();
+        match current {
+            BlinkerCommand::Exit => break,
+            BlinkerCommand::Done | BlinkerCommand::Off => write_level(&pin, 0).await,
+            BlinkerCommand::Play(pattern) => {
+                if play(&pin, &pattern, &mut command).await {
+                    continue;
+                }
+                write_level(&pin, 0).await;
+
+                // Mark the pattern as ended, unless a new command came in the meantime
+                sender.send_if_modified(|current| {
+                    let ended = !command.has_changed().unwrap_or(true);
+                    if ended {
+                        *current = BlinkerCommand::Done;
+                    }
+                    ended
+                });
+            }
+        }
+        if command.changed().await.is_err() {
+            break;
+        }
+    }
+
+    write_level(&pin, 0).await;
+    pin
+}
+
+/// Play a pattern once or until interrupted if it repeats.
+/// Returns whether a new command interrupted it.
+async fn play(
+    pin: &OutputPin,
+    pattern: &BlinkPattern,
+    command: &mut watch::Receiver<BlinkerCommand>,
+) -> bool {
+    // Use absolute deadlines so the pattern doesn't drift with the write latency
+    let mut deadline = Instant::now();
+    loop {
+        for &(on, duration) in &pattern.steps {
+            write_level(pin, on as u8).await;
+            deadline += duration;
+            tokio::select! {
+                _ = time::sleep_until(deadline) => {}
+                _ = command.changed() => return true,
+            }
+        }
+        if !pattern.repeat {
+            return false;
+        }
+    }
+}
+
+/// Write a level to the pin, logging failures instead of stopping the pattern.
+async fn write_level(pin: &OutputPin, value: u8) {
+    if let Err(e) = pin.write(value).await {
+        log::error!("Error writing blink pattern level: {}", e);
+    }
+}
@@ -0,0 +1,286 @@
+//
+// This file provides a synchronous variant of the pins for programs without an async runtime.
+// It only uses `std`, with the configured `gpio` command for export operations and the sysfs interface
+// for reading and writing, like the default sysfs backend.
+// Edge notification is not available here, use the async API to watch pins.
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::{GpioCommand, GpioConfig, Subcommand};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Represents a GPIO pin configured as an input, accessed synchronously.
+/// Use [InputPin::new] to ensure the pin is properly initialized.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct InputPin {
+    pin_number: u8,
+    root: PathBuf,
+    command: GpioCommand,
+}
+
+/// Represents a GPIO pin configured as an output, accessed synchronously.
+/// Use [OutputPin::new] to ensure the pin is properly initialized.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct OutputPin {
+    pin_number: u8,
+    root: PathBuf,
+    command: GpioCommand,
+}
+
+/// Represents a GPIO pin which can either be an input or an output, accessed synchronously.
+/// This is a type-erased wrapper around [InputPin] and [OutputPin].
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum GpioPin {
+    Input(InputPin),
+    Output(OutputPin),
+}
+
+impl InputPin {
+    /// Initialize a new input pin under the sysfs root of the configuration.
+    pub fn new(config: &GpioConfig, pin_number: u8) -> Result<Self> {
+        export(&config.command, pin_number, "in")?;
+
+        Ok(Self {
+            pin_number,
+            root: config.sysfs_root.clone(),
+            command: config.command.clone(),
+        })
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Read the value from the pin.
+    pub fn read(&self) -> Result<u8> {
+        read_value(&self.root, self.pin_number)
+    }
+
+    /// Invert the logic of the pin through its `active_low` attribute.
+    pub fn set_active_low(&self, active_low: bool) -> Result<()> {
+        set_active_low(&self.root, self.pin_number, active_low)
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    pub fn release(self) -> Result<()> {
+        unexport(&self.command, &self.root, self.pin_number)
+    }
+}
+
+impl OutputPin {
+    /// Initialize a new output pin under the sysfs root of the configuration.
+    pub fn new(config: &GpioConfig, pin_number: u8, default: u8) -> Result<Self> {
+        check_value(default)?;
+        export(&config.command, pin_number, "out")?;
+
+        let pin = Self {
+            pin_number,
+            root: config.sysfs_root.clone(),
+            command: config.command.clone(),
+        };
+        pin.write(default)?;
+
+        Ok(pin)
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Write a value to the pin.
+    pub fn write(&self, value: u8) -> Result<()> {
+        check_value(value)?;
+        fs::write(value_path(&self.root, self.pin_number), value.to_string())
+            .map_err(|e| pin_error(self.pin_number, e))
+    }
+
+    /// Invert the value of the pin and return the new value.
+    pub fn toggle(&self) -> Result<u8> {
+        let value = if self.read()? == 0 { 1 } else { 0 };
+        self.write(value)?;
+        Ok(value)
+    }
+
+    /// Read the current value of the pin.
+    pub fn read(&self) -> Result<u8> {
+        read_value(&self.root, self.pin_number)
+    }
+
+    /// Invert the logic of the pin through its `active_low` attribute.
+    pub fn set_active_low(&self, active_low: bool) -> Result<()> {
+        set_active_low(&self.root, self.pin_number, active_low)
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    pub fn release(self) -> Result<()> {
+        unexport(&self.command, &self.root, self.pin_number)
+    }
+}
+
+impl GpioPin {
+    /// Initialize a new input pin
+    pub fn new_input(config: &GpioConfig, pin_number: u8) -> Result<Self> {
+        Ok(Self::Input(InputPin::new(config, pin_number)?))
+    }
+
+    /// Initialize a new output pin
+    pub fn new_output(config: &GpioConfig, pin_number: u8, default: u8) -> Result<Self> {
+        Ok(Self::Output(OutputPin::new(config, pin_number, default)?))
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        match self {
+            Self::Input(pin) => pin.get_pin_number(),
+            Self::Output(pin) => pin.get_pin_number(),
+        }
+    }
+
+    /// Write a value to the pin.
+    /// Writing to an input pin is not allowed.
+    pub fn write(&self, value: u8) -> Result<()> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.write(value),
+        }
+    }
+
+    /// Invert the value of the pin and return the new value.
+    /// Toggling an input pin is not allowed.
+    pub fn toggle(&self) -> Result<u8> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.toggle(),
+        }
+    }
+
+    /// Read the value from the pin.
+    pub fn read(&self) -> Result<u8> {
+        match self {
+            Self::Input(pin) => pin.read(),
+            Self::Output(pin) => pin.read(),
+        }
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    pub fn release(self) -> Result<()> {
+        match self {
+            Self::Input(pin) => pin.release(),
+            Self::Output(pin) => pin.release(),
+        }
+    }
+}
+
+impl From<InputPin> for GpioPin {
+    fn from(pin: InputPin) -> Self {
+        Self::Input(pin)
+    }
+}
+
+impl From<OutputPin> for GpioPin {
+    fn from(pin: OutputPin) -> Self {
+        Self::Output(pin)
+    }
+}
+
+/// Export the pin with the given direction ("in" or "out") using the gpio command.
+fn export(command: &GpioCommand, pin_number: u8, direction: &str) -> Result<()> {
+    run_gpio(command, Subcommand::Export, pin_number, Some(direction)).map_err(|e| match e {
+        GpioError::CommandFailed { stderr, .. } => GpioError::ExportFailed(format!(
+            "Failed to export pin {} as {}: {}",
+            pin_number, direction, stderr
+        )),
+        e => e,
+    })
+}
+
+/// Unexport the pin using the gpio command and verify that the pin directory is gone.
+fn unexport(command: &GpioCommand, root: &Path, pin_number: u8) -> Result<()> {
+    run_gpio(command, Subcommand::Unexport, pin_number, None)?;
+
+    if fs::exists(root.join(format!("gpio{}", pin_number)))? {
+        return Err(GpioError::ExportFailed(format!(
+            "Pin {} is still exported after unexport",
+            pin_number
+        )));
+    }
+
+    Ok(())
+}
+
+/// Write the `active_low` attribute of the pin, the kernel then inverts the value file.
+fn set_active_low(root: &Path, pin_number: u8, active_low: bool) -> Result<()> {
+    let path = root.join(format!("gpio{}/active_low", pin_number));
+    fs::write(path, if active_low { "1" } else { "0" }).map_err(|e| pin_error(pin_number, e))
+}
+
+/// Run a subcommand of the gpio command on a pin.
+fn run_gpio(
+    command: &GpioCommand,
+    subcommand: Subcommand,
+    pin_number: u8,
+    value: Option<&str>,
+) -> Result<()> {
+    let args = command.args(subcommand, pin_number, value);
+    let output = Command::new(&command.program).args(&args).output()?;
+    if !output.status.success() {
+        return Err(GpioError::CommandFailed {
+            command: command.describe(&args),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Read the value of the pin using sysfs interface.
+fn read_value(root: &Path, pin_number: u8) -> Result<u8> {
+    let content =
+        fs::read_to_string(value_path(root, pin_number)).map_err(|e| pin_error(pin_number, e))?;
+
+    content.trim().parse().map_err(|_| {
+        GpioError::InvalidValue(format!(
+            "Failed to parse the value {:?} of pin {}",
+            content, pin_number
+        ))
+    })
+}
+
+/// Check that a value can be written to a pin.
+fn check_value(value: u8) -> Result<()> {
+    if value != 0 && value != 1 {
+        return Err(GpioError::InvalidValue(format!(
+            "Value must be 0 or 1, got {}",
+            value
+        )));
+    }
+    Ok(())
+}
+
+/// Get the sysfs path to the value of the pin.
+fn value_path(root: &Path, pin_number: u8) -> PathBuf {
+    root.join(format!("gpio{}/value", pin_number))
+}
+
+/// Convert an error accessing the files of a pin, a missing file means the pin isn't exported.
+fn pin_error(pin_number: u8, error: io::Error) -> GpioError {
+    match error.kind() {
+        io::ErrorKind::NotFound => GpioError::NotExported(pin_number),
+        _ => error.into(),
+    }
+}
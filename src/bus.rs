@@ -0,0 +1,90 @@
+//
+// This file provides a parallel bus made of a group of output pins.
+// Each pin of the bus carries one bit of a value, which makes it easy to drive
+// parallel interfaces like character LCDs or resistor ladder DACs.
+// The pins can be any [PinLike] pins, e.g. the lines of a port expander.
+//
+
+use super::error::{GpioError, Result};
+use super::group::PinGroup;
+use super::pin::OutputPin;
+use super::pinlike::PinLike;
+
+/// Order in which the bits of a value are mapped to the pins of a [PinBus].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// The first pin carries the most significant bit
+    MsbFirst,
+    /// The first pin carries the least significant bit
+    LsbFirst,
+}
+
+/// Bus of N output pins carrying the bits of a value.
+/// N can be at most 8 as values are read and written as bytes.
+#[derive(Debug)]
+pub struct PinBus<const N: usize, P = OutputPin> {
+    group: PinGroup<P>,
+    order: BitOrder,
+}
+
+impl<const N: usize, P: PinLike> PinBus<N, P> {
+    /// Create a bus from a group of exactly N pins.
+    pub fn new(group: PinGroup<P>, order: BitOrder) -> Result<Self> {
+        if N > 8 {
+            return Err(GpioError::InvalidValue(format!(
+                "A bus can have at most 8 pins, got {}",
+                N
+            )));
+        }
+        if group.len() != N {
+            return Err(GpioError::InvalidValue(format!(
+                "Bus of {} pins created from {} pins",
+                N,
+                group.len()
+            )));
+        }
+
+        Ok(Self { group, order })
+    }
+
+    /// Get the bit order of the bus.
+    pub fn order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Write a value onto the bus, one bit per pin.
+    /// The value must fit in N bits.
+    pub async fn write_u8(&self, value: u8) -> Result<()> {
+        if N < 8 && value >> N != 0 {
+            return Err(GpioError::InvalidValue(format!(
+                "Value {} doesn't fit in {} bits",
+                value, N
+            )));
+        }
+
+        let pattern: Vec<u8> = (0..N).map(|i| (value >> self.bit(i)) & 1).collect();
+        self.group.write_pattern(&pattern).await
+    }
+
+    /// Read the value currently on the bus.
+    pub async fn read_u8(&self) -> Result<u8> {
+        let values = self.group.read_all().await?;
+        Ok(values
+            .iter()
+            .enumerate()
+            .fold(0, |value, (i, &bit)| value | ((bit & 1) << self.bit(i))))
+    }
+
+    /// Give the group of pins back.
+    pub fn into_group(self) -> PinGroup<P> {
+        self.group
+    }
+
+    /// Get the bit carried by the pin at the given position.
+    fn bit(&self, position: usize) -> usize {
+        match self.order {
+            BitOrder::MsbFirst => N - 1 - position,
+            BitOrder::LsbFirst => position,
+        }
+    }
+}
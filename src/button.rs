@@ -0,0 +1,194 @@
+//
+// This file provides a push button on a watched input pin, turning the raw edges into
+// typed events. Changes are debounced first, then every press and release is reported,
+// along with long presses and double clicks detected from the timing of the presses.
+//
+// The button is pressed when the pin reads 1, use an active-low pin for buttons
+// pulling the line to ground. Buttons on pins without edge notification, like the lines
+// of a port expander, are polled instead.
+//
+
+use super::backend::ChangeStream;
+use super::error::Result;
+use super::pin::{Edge, InputPin};
+use super::pinlike::{self, PinLike};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_stream::StreamExt;
+
+/// Time between two reads of a polled button, well under the debounce time.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Event of a [Button].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ButtonEvent {
+    /// The button was pressed
+    Pressed,
+    /// The button was released
+    Released,
+    /// The button was released after being held for at least the long press time,
+    /// sent after [ButtonEvent::Released] with how long it was held
+    LongPress(Duration),
+    /// The button was clicked twice within the double click time,
+    /// sent after the [ButtonEvent::Released] of the second click
+    DoubleClick,
+}
+
+/// Timings of a [Button].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ButtonConfig {
+    /// Time the pin must be stable before a change counts, 20ms by default
+    pub debounce: Duration,
+    /// Time the button must be held for a long press, 1s by default
+    pub long_press: Duration,
+    /// Longest time between the release of a click and the next press for a double click,
+    /// 400ms by default
+    pub double_click: Duration,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(20),
+            long_press: Duration::from_secs(1),
+            double_click: Duration::from_millis(400),
+        }
+    }
+}
+
+/// Push button on an input pin.
+///
+/// Dropping this will stop the events.
+pub struct Button {
+    config: ButtonConfig,
+    button_thread: JoinHandle<()>,
+}
+
+impl Drop for Button {
+    fn drop(&mut self) {
+        self.button_thread.abort();
+    }
+}
+
+impl Button {
+    /// Start watching the button on the given pin, enabling edge notification on it.
+    /// The returned receiver gets the events of the button.
+    pub async fn new(
+        mut pin: InputPin,
+        config: ButtonConfig,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ButtonEvent>)> {
+        pin.enable_watch(Edge::Both).await?;
+        let changes = pin.changes()?;
+        let pressed = pin.read().await? == 1;
+
+        Ok(Self::start(Box::new(pin), changes, pressed, config))
+    }
+
+    /// Start watching the button on any pin, e.g. the line of a port expander, by polling it.
+    /// The returned receiver gets the events of the button.
+    pub async fn polled(
+        pin: impl PinLike + 'static,
+        config: ButtonConfig,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ButtonEvent>)> {
+        let pin: Arc<dyn PinLike> = Arc::new(pin);
+        let changes = pinlike::poll_changes(pin.clone(), POLL_INTERVAL).await?;
+        let pressed = pin.read().await? == 1;
+        Ok(Self::start(Box::new(pin), changes, pressed, config))
+    }
+
+    /// Spawn the task turning the changes of the pin into events.
+    fn start(
+        pin: Box<dyn PinLike>,
+        changes: ChangeStream,
+        pressed: bool,
+        config: ButtonConfig,
+    ) -> (Self, mpsc::UnboundedReceiver<ButtonEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let button_thread = tokio::spawn(run_button(pin, changes, pressed, config, sender));
+        (
+            Self {
+                config,
+                button_thread,
+            },
+            receiver,
+        )
+    }
+
+    /// Get the timings of the button.
+    pub fn config(&self) -> ButtonConfig {
+        self.config
+    }
+}
+
+/// Debounce the changes of the pin and send the events of the button.
+async fn run_button(
+    pin: Box<dyn PinLike>,
+    mut changes: ChangeStream,
+    mut pressed: bool,
+    config: ButtonConfig,
+    sender: mpsc::UnboundedSender<ButtonEvent>,
+) {
+    let mut deadline = None;
+    let mut pressed_at = Instant::now();
+    let mut last_click: Option<Instant> = None;
+    let mut double_click = false;
+
+    loop {
+        let settle_timer = time::sleep_until(deadline.unwrap_or_else(Instant::now));
+        tokio::select! {
+            // Wait for the pin to settle after each change
+            change = changes.next() => match change {
+                Some(Ok(())) => deadline = Some(Instant::now() + config.debounce),
+                Some(Err(e)) => log::error!("Error watching button: {}", e),
+                None => break,
+            },
+
+            // Turn the settled value into events
+            _ = settle_timer, if deadline.is_some() => {
+                deadline = None;
+                let value = match pin.read().await {
+                    Ok(value) => value == 1,
+                    Err(e) => {
+                        log::error!("Error reading button: {}", e);
+                        continue;
+                    }
+                };
+                if value == pressed {
+                    continue;
+                }
+                pressed = value;
+
+                let now = Instant::now();
+                let mut events = Vec::new();
+                if pressed {
+                    pressed_at = now;
+                    double_click = last_click
+                        .is_some_and(|click| now - click <= config.double_click);
+                    events.push(ButtonEvent::Pressed);
+                } else {
+                    events.push(ButtonEvent::Released);
+                    let held = now - pressed_at;
+                    if held >= config.long_press {
+                        last_click = None;
+                        events.push(ButtonEvent::LongPress(held));
+                    } else if double_click {
+                        last_click = None;
+                        events.push(ButtonEvent::DoubleClick);
+                    } else {
+                        last_click = Some(now);
+                    }
+                }
+
+                for event in events {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
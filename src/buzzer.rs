@@ -0,0 +1,179 @@
+//
+// This file provides a passive buzzer on a software PWM, playing square-wave tones and melodies.
+// A melody plays as a background task, replaced by the next tone or melody, or cancelled with
+// `stop`. The end of each note is left silent for a moment, so repeated notes are heard apart.
+//
+// The tokio timer limits the tones of a task-based PWM to a few hundred Hz, a precise PWM on
+// its own thread plays the usual range of buzzers up to a few kHz.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use super::pinlike::PinLike;
+use super::softpwm::SoftPwm;
+use std::{sync::Arc, time::Duration};
+use tokio::{task::JoinHandle, time};
+
+/// Silence at the end of each note of a melody.
+const NOTE_GAP: Duration = Duration::from_millis(10);
+
+/// Note of a melody, a tone or a rest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    /// Frequency of the tone in Hz, None for a rest
+    pub frequency: Option<f64>,
+    /// Length of the note
+    pub duration: Duration,
+}
+
+impl Note {
+    /// Create a note playing a tone of the given frequency in Hz.
+    pub fn tone(frequency: f64, duration: Duration) -> Self {
+        Self {
+            frequency: Some(frequency),
+            duration,
+        }
+    }
+
+    /// Create a silent note.
+    pub fn rest(duration: Duration) -> Self {
+        Self {
+            frequency: None,
+            duration,
+        }
+    }
+
+    /// Create a note playing the given number of semitones above A4 (440Hz),
+    /// below it when negative, e.g. -9 for C4.
+    pub fn semitones(semitones: i32, duration: Duration) -> Self {
+        Self::tone(440.0 * 2f64.powf(semitones as f64 / 12.0), duration)
+    }
+}
+
+/// Passive buzzer playing tones and melodies.
+///
+/// Dropping this will stop the melody and silence the buzzer.
+#[derive(Debug)]
+pub struct Buzzer<P = OutputPin> {
+    pwm: Arc<SoftPwm<P>>,
+    melody_thread: Option<JoinHandle<()>>,
+}
+
+impl<P> Drop for Buzzer<P> {
+    fn drop(&mut self) {
+        if let Some(melody_thread) = &self.melody_thread {
+            melody_thread.abort();
+        }
+    }
+}
+
+impl<P: PinLike + 'static> Buzzer<P> {
+    /// Create a buzzer on the given pin, initially silent, with a PWM running on a tokio task.
+    pub fn new(pin: P) -> Result<Self> {
+        Ok(Self::with_pwm(SoftPwm::new(pin, 440.0, 0.0)?))
+    }
+
+    /// Play a tone of the given frequency in Hz until it's changed or stopped.
+    pub async fn tone(&mut self, frequency: f64) -> Result<()> {
+        self.stop().await?;
+        play(&self.pwm, Some(frequency))
+    }
+
+    /// Play a tone of the given frequency in Hz for the duration, then silence the buzzer.
+    pub async fn beep(&mut self, frequency: f64, duration: Duration) -> Result<()> {
+        self.tone(frequency).await?;
+        time::sleep(duration).await;
+        self.pwm.set_duty(0.0)
+    }
+
+    /// Start playing the notes in a background task, stopping the current melody.
+    pub async fn play(&mut self, notes: &[Note]) -> Result<()> {
+        self.stop().await?;
+        for note in notes {
+            if let Some(frequency) = note.frequency {
+                check_frequency(frequency)?;
+            }
+        }
+
+        let pwm = self.pwm.clone();
+        let notes = notes.to_vec();
+        self.melody_thread = Some(tokio::spawn(async move {
+            for note in notes {
+                if let Err(e) = play(&pwm, note.frequency) {
+                    log::error!("Error playing buzzer note: {}", e);
+                }
+                let gap = NOTE_GAP.min(note.duration / 2);
+                time::sleep(note.duration - gap).await;
+                if let Err(e) = pwm.set_duty(0.0) {
+                    log::error!("Error silencing buzzer: {}", e);
+                }
+                time::sleep(gap).await;
+            }
+        }));
+        Ok(())
+    }
+
+    /// Check if a melody is playing.
+    pub fn is_playing(&self) -> bool {
+        self.melody_thread
+            .as_ref()
+            .is_some_and(|melody_thread| !melody_thread.is_finished())
+    }
+
+    /// Wait for the melody to finish.
+    pub async fn wait(&mut self) -> Result<()> {
+        match self.melody_thread.take() {
+            Some(melody_thread) => melody_thread
+                .await
+                .map_err(|e| GpioError::TaskFailed(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the melody or the tone, silencing the buzzer.
+    pub async fn stop(&mut self) -> Result<()> {
+        if let Some(melody_thread) = self.melody_thread.take() {
+            melody_thread.abort();
+            let _ = melody_thread.await;
+        }
+        self.pwm.set_duty(0.0)
+    }
+
+    /// Wrap a PWM, which must be silent.
+    fn with_pwm(pwm: SoftPwm<P>) -> Self {
+        Self {
+            pwm: Arc::new(pwm),
+            melody_thread: None,
+        }
+    }
+}
+
+impl Buzzer {
+    /// Create a buzzer on the given pin, initially silent, with a precise PWM on its own thread,
+    /// for tones above a few hundred Hz.
+    pub fn precise(pin: OutputPin) -> Result<Self> {
+        Ok(Self::with_pwm(SoftPwm::precise(pin, 440.0, 0.0)?))
+    }
+}
+
+/// Play a square wave of the given frequency, or silence the buzzer.
+fn play<P: PinLike + 'static>(pwm: &SoftPwm<P>, frequency: Option<f64>) -> Result<()> {
+    match frequency {
+        Some(frequency) => {
+            pwm.set_frequency(frequency)?;
+            pwm.set_duty(0.5)
+        }
+        None => pwm.set_duty(0.0),
+    }
+}
+
+/// Check that the frequency is a usable tone.
+fn check_frequency(frequency: f64) -> Result<()> {
+    if !(frequency > 0.0 && frequency.is_finite()) {
+        return Err(GpioError::InvalidValue(format!(
+            "Tone frequency must be positive, got {}",
+            frequency
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,624 @@
+//
+// This file provides access to GPIO lines through the GPIO character device (`/dev/gpiochipN`).
+// The sysfs GPIO interface is deprecated in the kernel, this is its replacement.
+// Lines are requested with the v1 line handle and line event ioctls. A requested line stays
+// reserved for this process until its file descriptor is closed.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::File,
+    io,
+    mem::size_of,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll, Waker, ready},
+};
+use tokio::io::unix::AsyncFd;
+
+const GPIOHANDLES_MAX: usize = 64;
+
+const GPIOHANDLE_REQUEST_INPUT: u32 = 1 << 0;
+const GPIOHANDLE_REQUEST_OUTPUT: u32 = 1 << 1;
+const GPIOHANDLE_REQUEST_ACTIVE_LOW: u32 = 1 << 2;
+const GPIOHANDLE_REQUEST_BIAS_PULL_UP: u32 = 1 << 5;
+const GPIOHANDLE_REQUEST_BIAS_PULL_DOWN: u32 = 1 << 6;
+const GPIOHANDLE_REQUEST_BIAS_DISABLE: u32 = 1 << 7;
+
+const GPIOEVENT_REQUEST_RISING_EDGE: u32 = 1 << 0;
+const GPIOEVENT_REQUEST_FALLING_EDGE: u32 = 1 << 1;
+const GPIOEVENT_REQUEST_BOTH_EDGES: u32 =
+    GPIOEVENT_REQUEST_RISING_EDGE | GPIOEVENT_REQUEST_FALLING_EDGE;
+
+const GPIOEVENT_EVENT_RISING_EDGE: u32 = 0x01;
+const GPIOEVENT_EVENT_FALLING_EDGE: u32 = 0x02;
+
+/// `struct gpiohandle_request` from `linux/gpio.h`
+#[repr(C)]
+struct GpioHandleRequest {
+    lineoffsets: [u32; GPIOHANDLES_MAX],
+    flags: u32,
+    default_values: [u8; GPIOHANDLES_MAX],
+    consumer_label: [u8; 32],
+    lines: u32,
+    fd: i32,
+}
+
+/// `struct gpioevent_request` from `linux/gpio.h`
+#[repr(C)]
+struct GpioEventRequest {
+    lineoffset: u32,
+    handleflags: u32,
+    eventflags: u32,
+    consumer_label: [u8; 32],
+    fd: i32,
+}
+
+/// `struct gpiochip_info` from `linux/gpio.h`
+#[repr(C)]
+struct GpioChipInfo {
+    _name: [u8; 32],
+    label: [u8; 32],
+    _lines: u32,
+}
+
+/// `struct gpiohandle_data` from `linux/gpio.h`
+#[repr(C)]
+struct GpioHandleData {
+    values: [u8; GPIOHANDLES_MAX],
+}
+
+/// `struct gpioevent_data` from `linux/gpio.h`
+#[repr(C)]
+struct GpioEventData {
+    _timestamp: u64,
+    id: u32,
+}
+
+/// Compute a read/write ioctl request number like the `_IOWR` macro.
+const fn iowr(nr: u32, size: usize) -> libc::Ioctl {
+    ((3 << 30) | ((size as u32) << 16) | (0xB4 << 8) | nr) as libc::Ioctl
+}
+
+/// Compute a read ioctl request number like the `_IOR` macro.
+const fn ior(nr: u32, size: usize) -> libc::Ioctl {
+    ((2 << 30) | ((size as u32) << 16) | (0xB4 << 8) | nr) as libc::Ioctl
+}
+
+const GPIO_GET_CHIPINFO_IOCTL: libc::Ioctl = ior(0x01, size_of::<GpioChipInfo>());
+const GPIO_GET_LINEHANDLE_IOCTL: libc::Ioctl = iowr(0x03, size_of::<GpioHandleRequest>());
+const GPIO_GET_LINEEVENT_IOCTL: libc::Ioctl = iowr(0x04, size_of::<GpioEventRequest>());
+const GPIOHANDLE_GET_LINE_VALUES_IOCTL: libc::Ioctl = iowr(0x08, size_of::<GpioHandleData>());
+const GPIOHANDLE_SET_LINE_VALUES_IOCTL: libc::Ioctl = iowr(0x09, size_of::<GpioHandleData>());
+
+/// Label reported to the kernel as the consumer of the requested lines.
+const CONSUMER_LABEL: &[u8] = b"opi_gpio_rs";
+
+/// Backend accessing the pins as lines of a GPIO character device.
+/// Pin numbers are the line offsets on the chip.
+#[derive(Debug)]
+pub struct CdevBackend {
+    chip: PathBuf,
+    lines: Mutex<HashMap<u8, Line>>,
+}
+
+impl CdevBackend {
+    /// Create a backend for the given chip, e.g. `/dev/gpiochip0`.
+    pub fn new(chip: impl Into<PathBuf>) -> Self {
+        Self {
+            chip: chip.into(),
+            lines: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the path of the chip.
+    pub fn chip(&self) -> &Path {
+        &self.chip
+    }
+
+    /// Run a function on a requested line.
+    fn with_line<T>(&self, pin_number: u8, f: impl FnOnce(&Line) -> Result<T>) -> Result<T> {
+        let lines = self.lines.lock().unwrap();
+        let line = lines
+            .get(&pin_number)
+            .ok_or(GpioError::NotExported(pin_number))?;
+        f(line)
+    }
+}
+
+#[async_trait]
+impl GpioBackend for CdevBackend {
+    /// A line that is already requested is released first, so its direction can be changed.
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        self.lines.lock().unwrap().remove(&pin_number);
+        let line = Line::request_input(&self.chip, pin_number as u32)?;
+        self.lines.lock().unwrap().insert(pin_number, line);
+        Ok(())
+    }
+
+    /// A line that is already requested is released first, so its direction can be changed.
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        self.lines.lock().unwrap().remove(&pin_number);
+        let line = Line::request_output(&self.chip, pin_number as u32, default)?;
+        self.lines.lock().unwrap().insert(pin_number, line);
+        Ok(())
+    }
+
+    /// Only the lines requested by this backend can be known.
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        self.with_line(pin_number, Line::direction)
+    }
+
+    /// Dropping the line releases it.
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        match self.lines.lock().unwrap().remove(&pin_number) {
+            Some(_) => Ok(()),
+            None => Err(GpioError::NotExported(pin_number)),
+        }
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        self.with_line(pin_number, Line::get_value)
+    }
+
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        self.with_line(pin_number, |line| line.set_value(value))
+    }
+
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        self.with_line(pin_number, |line| line.set_edge(edge))
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        self.with_line(pin_number, Line::edge)
+    }
+
+    /// Only the lines requested by this backend are listed.
+    async fn exported(&self) -> Result<Vec<u8>> {
+        let mut pins: Vec<u8> = self.lines.lock().unwrap().keys().copied().collect();
+        pins.sort_unstable();
+        Ok(pins)
+    }
+
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()> {
+        self.with_line(pin_number, |line| line.set_bias(bias))
+    }
+
+    /// The character device has no drive strength setting.
+    async fn set_drive_strength(&self, _pin_number: u8, _milliamps: u8) -> Result<()> {
+        Err(GpioError::Unsupported(
+            "Drive strength through the GPIO character device".to_string(),
+        ))
+    }
+
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        self.with_line(pin_number, |line| {
+            line.reconfigure(|config| config.active_low = active_low)
+        })
+    }
+
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        self.with_line(pin_number, Line::events)
+    }
+}
+
+/// Configuration a line is requested with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineConfig {
+    output: bool,
+    active_low: bool,
+    bias: Option<Bias>,
+    edge: Option<Edge>,
+}
+
+impl LineConfig {
+    /// Get the `GPIOHANDLE_REQUEST_*` flags of the configuration.
+    fn handle_flags(&self) -> u32 {
+        let direction = if self.output {
+            GPIOHANDLE_REQUEST_OUTPUT
+        } else {
+            GPIOHANDLE_REQUEST_INPUT
+        };
+        let bias = match self.bias {
+            Some(Bias::PullUp) => GPIOHANDLE_REQUEST_BIAS_PULL_UP,
+            Some(Bias::PullDown) => GPIOHANDLE_REQUEST_BIAS_PULL_DOWN,
+            Some(Bias::Disabled) => GPIOHANDLE_REQUEST_BIAS_DISABLE,
+            None => 0,
+        };
+        let active_low = if self.active_low {
+            GPIOHANDLE_REQUEST_ACTIVE_LOW
+        } else {
+            0
+        };
+        direction | bias | active_low
+    }
+}
+
+/// A GPIO line requested from a GPIO character device.
+/// Changing the configuration re-requests the line with the new flags.
+struct Line {
+    chip: PathBuf,
+    offset: u32,
+    request: Mutex<Option<(OwnedFd, LineConfig)>>,
+    /// Event fds of the change streams, closed with the request
+    streams: Mutex<Vec<Weak<Mutex<EventSlot>>>>,
+}
+
+/// Duplicate of the event fd of a line owned by a change stream.
+/// The line takes the fd to close it, ending the stream.
+struct EventSlot {
+    fd: Option<AsyncFd<OwnedFd>>,
+    /// Waker of the task waiting for the next event
+    waker: Option<Waker>,
+}
+
+impl fmt::Debug for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Line")
+            .field("chip", &self.chip)
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+impl Drop for Line {
+    fn drop(&mut self) {
+        self.close_streams();
+    }
+}
+
+impl Line {
+    /// Request a line as an input.
+    fn request_input(chip: &Path, offset: u32) -> Result<Self> {
+        let config = LineConfig {
+            output: false,
+            active_low: false,
+            bias: None,
+            edge: None,
+        };
+        Self::request(chip, offset, config, 0)
+    }
+
+    /// Request a line as an output driving the default value.
+    fn request_output(chip: &Path, offset: u32, default: u8) -> Result<Self> {
+        let config = LineConfig {
+            output: true,
+            active_low: false,
+            bias: None,
+            edge: None,
+        };
+        Self::request(chip, offset, config, default)
+    }
+
+    fn request(chip: &Path, offset: u32, config: LineConfig, value: u8) -> Result<Self> {
+        let fd = request_fd(chip, offset, config, value)?;
+        Ok(Self {
+            chip: chip.to_path_buf(),
+            offset,
+            request: Mutex::new(Some((fd, config))),
+            streams: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Read the value of the line.
+    fn get_value(&self) -> Result<u8> {
+        let request = self.request.lock().unwrap();
+        let (fd, _) = request.as_ref().ok_or_else(|| self.not_requested())?;
+        get_fd_value(fd)
+    }
+
+    /// Get the direction the line is requested with.
+    fn direction(&self) -> Result<Direction> {
+        let request = self.request.lock().unwrap();
+        let (_, config) = request.as_ref().ok_or_else(|| self.not_requested())?;
+        Ok(if config.output {
+            Direction::Out
+        } else {
+            Direction::In
+        })
+    }
+
+    /// Get the edge events the line is requested with.
+    fn edge(&self) -> Result<Option<Edge>> {
+        let request = self.request.lock().unwrap();
+        let (_, config) = request.as_ref().ok_or_else(|| self.not_requested())?;
+        Ok(config.edge)
+    }
+
+    /// Drive the value of an output line.
+    fn set_value(&self, value: u8) -> Result<()> {
+        let request = self.request.lock().unwrap();
+        let (fd, _) = request.as_ref().ok_or_else(|| self.not_requested())?;
+
+        let mut data = GpioHandleData {
+            values: [0; GPIOHANDLES_MAX],
+        };
+        data.values[0] = value;
+        // SAFETY: the fd is a line handle and data matches `struct gpiohandle_data`
+        let result = unsafe {
+            libc::ioctl(
+                fd.as_raw_fd(),
+                GPIOHANDLE_SET_LINE_VALUES_IOCTL,
+                &mut data as *mut GpioHandleData,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+
+    /// Enable edge events on the line, or disable them with `None`.
+    fn set_edge(&self, edge: Option<Edge>) -> Result<()> {
+        self.reconfigure(|config| config.edge = edge)
+    }
+
+    /// Configure the internal pull resistors of the line.
+    fn set_bias(&self, bias: Bias) -> Result<()> {
+        self.reconfigure(|config| config.bias = Some(bias))
+    }
+
+    /// Get a stream yielding an item each time an edge event arrives on the line.
+    /// Edge events must be enabled with [Line::set_edge] first.
+    /// The stream ends when the line is reconfigured or released.
+    fn events(&self) -> Result<ChangeStream> {
+        let request = self.request.lock().unwrap();
+        let (fd, config) = request.as_ref().ok_or_else(|| self.not_requested())?;
+        if config.edge.is_none() {
+            return Err(GpioError::WatchUnsupported(self.offset as u8));
+        }
+
+        // Duplicate the event fd so the stream can wait on it, the line keeping a handle to close it
+        let fd = fd.try_clone()?;
+        set_nonblocking(&fd)?;
+        let slot = Arc::new(Mutex::new(EventSlot {
+            fd: Some(AsyncFd::new(fd)?),
+            waker: None,
+        }));
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|stream| stream.strong_count() > 0);
+        streams.push(Arc::downgrade(&slot));
+
+        Ok(Box::pin(futures::stream::poll_fn(move |cx| {
+            let mut slot = slot.lock().unwrap();
+            let Some(fd) = &slot.fd else {
+                return Poll::Ready(None);
+            };
+            let event = poll_event(fd, cx);
+            if event.is_pending() {
+                slot.waker = Some(cx.waker().clone());
+            }
+            event.map(Some)
+        })))
+    }
+
+    /// Re-request the line with a modified configuration.
+    /// Output lines keep their current value. The change streams of the line end.
+    fn reconfigure(&self, modify: impl FnOnce(&mut LineConfig)) -> Result<()> {
+        let mut request = self.request.lock().unwrap();
+        let (fd, previous) = request.as_ref().ok_or_else(|| self.not_requested())?;
+        let previous = *previous;
+        let value = if previous.output {
+            get_fd_value(fd)?
+        } else {
+            0
+        };
+        let mut config = previous;
+        modify(&mut config);
+
+        // The line must be released by the request and the streams before it can be requested again
+        self.close_streams();
+        *request = None;
+        match request_fd(&self.chip, self.offset, config, value) {
+            Ok(fd) => {
+                *request = Some((fd, config));
+                Ok(())
+            }
+            Err(e) => {
+                // Request the line back as it was
+                *request = request_fd(&self.chip, self.offset, previous, value)
+                    .ok()
+                    .map(|fd| (fd, previous));
+                Err(e)
+            }
+        }
+    }
+
+    /// Close the event fds of the change streams, and wake them up to end.
+    fn close_streams(&self) {
+        for stream in self.streams.lock().unwrap().drain(..) {
+            if let Some(slot) = stream.upgrade() {
+                let mut slot = slot.lock().unwrap();
+                slot.fd = None;
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Get the error returned when the line was lost by a failed reconfiguration.
+    fn not_requested(&self) -> GpioError {
+        GpioError::NotExported(self.offset as u8)
+    }
+}
+
+/// Request a line from the chip and return the line fd.
+/// Lines with an edge are requested as event lines, others as line handles.
+fn request_fd(chip: &Path, offset: u32, config: LineConfig, value: u8) -> Result<OwnedFd> {
+    let chip_file = File::open(chip)?;
+    let mut consumer_label = [0u8; 32];
+    consumer_label[..CONSUMER_LABEL.len()].copy_from_slice(CONSUMER_LABEL);
+
+    let fd = match config.edge {
+        None => {
+            let mut request = GpioHandleRequest {
+                lineoffsets: [0; GPIOHANDLES_MAX],
+                flags: config.handle_flags(),
+                default_values: [0; GPIOHANDLES_MAX],
+                consumer_label,
+                lines: 1,
+                fd: -1,
+            };
+            request.lineoffsets[0] = offset;
+            request.default_values[0] = value;
+            // SAFETY: the fd is a GPIO chip and request matches `struct gpiohandle_request`
+            let result = unsafe {
+                libc::ioctl(
+                    chip_file.as_raw_fd(),
+                    GPIO_GET_LINEHANDLE_IOCTL,
+                    &mut request as *mut GpioHandleRequest,
+                )
+            };
+            if result < 0 {
+                return Err(request_error(offset));
+            }
+            request.fd
+        }
+        Some(edge) => {
+            let mut request = GpioEventRequest {
+                lineoffset: offset,
+                handleflags: config.handle_flags(),
+                eventflags: match edge {
+                    Edge::Rising => GPIOEVENT_REQUEST_RISING_EDGE,
+                    Edge::Falling => GPIOEVENT_REQUEST_FALLING_EDGE,
+                    Edge::Both => GPIOEVENT_REQUEST_BOTH_EDGES,
+                },
+                consumer_label,
+                fd: -1,
+            };
+            // SAFETY: the fd is a GPIO chip and request matches `struct gpioevent_request`
+            let result = unsafe {
+                libc::ioctl(
+                    chip_file.as_raw_fd(),
+                    GPIO_GET_LINEEVENT_IOCTL,
+                    &mut request as *mut GpioEventRequest,
+                )
+            };
+            if result < 0 {
+                return Err(request_error(offset));
+            }
+            request.fd
+        }
+    };
+
+    // SAFETY: the kernel returned a new fd that nothing else owns
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Get the label of a GPIO chip, naming its controller, e.g. `pinctrl-bcm2711`.
+pub fn chip_label(chip: impl AsRef<Path>) -> Result<String> {
+    let chip_file = File::open(chip)?;
+    let mut info = GpioChipInfo {
+        _name: [0; 32],
+        label: [0; 32],
+        _lines: 0,
+    };
+    // SAFETY: the fd is a GPIO chip and info matches `struct gpiochip_info`
+    let result = unsafe {
+        libc::ioctl(
+            chip_file.as_raw_fd(),
+            GPIO_GET_CHIPINFO_IOCTL,
+            &mut info as *mut GpioChipInfo,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let len = info.label.iter().position(|&c| c == 0).unwrap_or(32);
+    Ok(String::from_utf8_lossy(&info.label[..len]).into_owned())
+}
+
+/// Read the value of a requested line.
+fn get_fd_value(fd: &OwnedFd) -> Result<u8> {
+    let mut data = GpioHandleData {
+        values: [0; GPIOHANDLES_MAX],
+    };
+    // SAFETY: the fd is a line handle or event line and data matches `struct gpiohandle_data`
+    let result = unsafe {
+        libc::ioctl(
+            fd.as_raw_fd(),
+            GPIOHANDLE_GET_LINE_VALUES_IOCTL,
+            &mut data as *mut GpioHandleData,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(data.values[0])
+}
+
+/// Switch a fd to non-blocking mode so it can be used with [AsyncFd].
+fn set_nonblocking(fd: &OwnedFd) -> Result<()> {
+    // SAFETY: fcntl on a valid fd with integer arguments
+    let result = unsafe {
+        let flags = libc::fcntl(fd.as_raw_fd(), libc::F_GETFL);
+        libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK)
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Poll for the next edge event on an event line.
+fn poll_event(fd: &AsyncFd<OwnedFd>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+    loop {
+        let mut guard = ready!(fd.poll_read_ready(cx))?;
+        let mut event = GpioEventData {
+            _timestamp: 0,
+            id: 0,
+        };
+        let result = guard.try_io(|inner| {
+            // SAFETY: reading at most the size of event into it
+            let read = unsafe {
+                libc::read(
+                    inner.get_ref().as_raw_fd(),
+                    &mut event as *mut GpioEventData as *mut libc::c_void,
+                    size_of::<GpioEventData>(),
+                )
+            };
+            if read < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(read as usize)
+            }
+        });
+
+        match result {
+            Ok(Ok(read)) if read == size_of::<GpioEventData>() => {
+                return Poll::Ready(match event.id {
+                    GPIOEVENT_EVENT_RISING_EDGE | GPIOEVENT_EVENT_FALLING_EDGE => Ok(()),
+                    id => Err(invalid_event(format!("Unknown line event id {}", id))),
+                });
+            }
+            Ok(Ok(read)) => {
+                return Poll::Ready(Err(invalid_event(format!("Short event read of {}", read))));
+            }
+            Ok(Err(e)) => return Poll::Ready(Err(e.into())),
+            Err(_would_block) => continue,
+        }
+    }
+}
+
+/// Get the error of a failed line request, e.g. when the line is used by another consumer.
+fn request_error(offset: u32) -> GpioError {
+    let error = io::Error::last_os_error();
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => GpioError::PermissionDenied(error),
+        _ => GpioError::ExportFailed(format!("Failed to request line {}: {}", offset, error)),
+    }
+}
+
+/// Get the error of an unexpected line event.
+fn invalid_event(message: String) -> GpioError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
@@ -0,0 +1,145 @@
+//
+// This file provides a driver for the DHT11 and DHT22 temperature and humidity sensors.
+// The sensor is woken up by holding its data line low, then answers with 40 bits
+// encoded in the length of the high pulses: about 27µs for a 0 and 70µs for a 1.
+// The last byte is a checksum of the first four.
+//
+// The pulses are timed by polling the line as fast as possible during the 5ms of the answer.
+// Reads may fail when the line isn't polled fast enough, e.g. through sysfs,
+// so they should be retried, waiting at least 2 seconds between two reads.
+//
+
+use super::error::{GpioError, Result};
+use super::opendrain::OpenDrain;
+use super::pin::{Bias, GpioPin};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Longest time the line is expected to stay at the same level while the sensor answers.
+const LEVEL_TIMEOUT: Duration = Duration::from_millis(1);
+/// High pulses longer than this are 1 bits.
+const ONE_THRESHOLD: Duration = Duration::from_micros(50);
+
+/// Model of a DHT sensor, they differ in the start pulse and in the data format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DhtModel {
+    /// DHT11, integer values with a 1°C and 1% resolution
+    Dht11,
+    /// DHT22 (AM2302), values with a 0.1°C and 0.1% resolution
+    Dht22,
+}
+
+/// Temperature and relative humidity read from a DHT sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DhtReading {
+    /// Temperature in °C
+    pub temperature: f32,
+    /// Relative humidity in %
+    pub humidity: f32,
+}
+
+impl DhtModel {
+    /// Get the time the line is held low to wake the sensor up.
+    fn start_pulse(&self) -> Duration {
+        match self {
+            Self::Dht11 => Duration::from_millis(18),
+            Self::Dht22 => Duration::from_millis(1),
+        }
+    }
+
+    /// Decode the 5 bytes sent by the sensor, the last one being the checksum.
+    pub fn decode(&self, data: [u8; 5]) -> Result<DhtReading> {
+        let sum = data[..4]
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if sum != data[4] {
+            return Err(GpioError::InvalidValue(format!(
+                "DHT checksum mismatch, expected {:#04x} and got {:#04x}",
+                sum, data[4]
+            )));
+        }
+
+        Ok(match self {
+            Self::Dht11 => DhtReading {
+                humidity: data[0] as f32 + data[1] as f32 / 10.0,
+                temperature: data[2] as f32 + (data[3] & 0x7f) as f32 / 10.0,
+            },
+            Self::Dht22 => {
+                let humidity = u16::from_be_bytes([data[0], data[1]]);
+                let temperature = u16::from_be_bytes([data[2] & 0x7f, data[3]]);
+                let sign = if data[2] & 0x80 != 0 { -1.0 } else { 1.0 };
+                DhtReading {
+                    humidity: humidity as f32 / 10.0,
+                    temperature: sign * temperature as f32 / 10.0,
+                }
+            }
+        })
+    }
+}
+
+/// Driver of a DHT sensor on a single pin.
+pub struct Dht {
+    line: OpenDrain,
+    model: DhtModel,
+}
+
+impl Dht {
+    /// Create a driver for a sensor of the given model.
+    /// The internal pull-up resistor is enabled and the line is released.
+    pub async fn new(pin: GpioPin, model: DhtModel) -> Result<Self> {
+        let mut line = OpenDrain::new(pin);
+        line.set_bias(Bias::PullUp).await?;
+        line.release().await?;
+        Ok(Self { line, model })
+    }
+
+    /// Get the model of the sensor.
+    pub fn model(&self) -> DhtModel {
+        self.model
+    }
+
+    /// Wake the sensor up and read the temperature and humidity.
+    pub async fn read(&mut self) -> Result<DhtReading> {
+        // Start pulse
+        self.line.drive().await?;
+        time::sleep(self.model.start_pulse()).await;
+        self.line.release().await?;
+
+        // The sensor answers with a low then a high pulse of 80µs each
+        self.wait_while(true).await?;
+        self.wait_while(false).await?;
+        self.wait_while(true).await?;
+
+        // Each bit is a low pulse followed by a high pulse whose length gives the bit
+        let mut data = [0u8; 5];
+        for i in 0..40 {
+            self.wait_while(false).await?;
+            let high = self.wait_while(true).await?;
+            if high > ONE_THRESHOLD {
+                data[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+
+        self.model.decode(data)
+    }
+
+    /// Give the pin back.
+    pub fn into_pin(self) -> Result<GpioPin> {
+        self.line.into_pin()
+    }
+
+    /// Poll the line while it's at the given level and return how long it stayed there.
+    async fn wait_while(&self, level: bool) -> Result<Duration> {
+        let start = Instant::now();
+        while self.line.read().await? == level {
+            if start.elapsed() > LEVEL_TIMEOUT {
+                return Err(GpioError::Timeout(format!(
+                    "DHT line stayed {} for more than {:?}",
+                    if level { "high" } else { "low" },
+                    LEVEL_TIMEOUT
+                )));
+            }
+        }
+        Ok(start.elapsed())
+    }
+}
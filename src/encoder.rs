@@ -0,0 +1,143 @@
+//
+// This file provides a decoder for rotary encoders, whose two pins produce quadrature
+// signals: turning the knob one detent steps both pins through the 4 states of a Gray code,
+// in an order depending on the direction.
+// A task watches both pins and follows the transitions. Invalid transitions, where both
+// pins changed at once, are ignored, and a detent only counts if the steps add up
+// once the encoder is back to rest, which filters out the bounces of the contacts.
+//
+
+use super::backend::ChangeStream;
+use super::error::Result;
+use super::pin::{Edge, InputPin};
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_stream::StreamExt;
+
+/// State of the pins when the encoder rests on a detent, both high with pull-ups.
+const REST: u8 = 0b11;
+
+/// Steps between two states of the pins, indexed by the previous and current states.
+/// A change of pin A leading pin B is a positive step, 0 means no or an invalid transition.
+const STEPS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Direction of a detent of a [RotaryEncoder].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RotaryEvent {
+    /// Pin A leads pin B, usually clockwise
+    Increment,
+    /// Pin B leads pin A, usually counterclockwise
+    Decrement,
+}
+
+/// Decoder of a rotary encoder on two input pins.
+///
+/// Dropping this will stop the decoder.
+pub struct RotaryEncoder {
+    position: Arc<AtomicI64>,
+    encoder_thread: JoinHandle<()>,
+}
+
+impl Drop for RotaryEncoder {
+    fn drop(&mut self) {
+        self.encoder_thread.abort();
+    }
+}
+
+impl RotaryEncoder {
+    /// Start decoding the encoder on the given pins, enabling edge notification on both.
+    /// The returned receiver gets an event for each detent the knob is turned by.
+    pub async fn new(
+        mut a: InputPin,
+        mut b: InputPin,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RotaryEvent>)> {
+        a.enable_watch(Edge::Both).await?;
+        b.enable_watch(Edge::Both).await?;
+        let changes = a.changes()?.merge(b.changes()?);
+        let state = read_state(&a, &b).await?;
+
+        let position = Arc::new(AtomicI64::new(0));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let encoder_thread = tokio::spawn(run_encoder(
+            a,
+            b,
+            Box::pin(changes),
+            state,
+            position.clone(),
+            sender,
+        ));
+
+        Ok((
+            Self {
+                position,
+                encoder_thread,
+            },
+            receiver,
+        ))
+    }
+
+    /// Get the number of detents turned since the decoder started, increments minus decrements.
+    pub fn position(&self) -> i64 {
+        self.position.load(Ordering::Relaxed)
+    }
+}
+
+/// Read the state of the pins, A being the high bit.
+async fn read_state(a: &InputPin, b: &InputPin) -> Result<u8> {
+    Ok(a.read().await? << 1 | b.read().await?)
+}
+
+/// Follow the transitions of the pins and send an event for each detent.
+async fn run_encoder(
+    a: InputPin,
+    b: InputPin,
+    mut changes: ChangeStream,
+    mut state: u8,
+    position: Arc<AtomicI64>,
+    sender: mpsc::UnboundedSender<RotaryEvent>,
+) {
+    let mut steps = 0i8;
+    while let Some(change) = changes.next().await {
+        if let Err(e) = change {
+            log::error!("Error watching rotary encoder: {}", e);
+            continue;
+        }
+
+        let current = match read_state(&a, &b).await {
+            Ok(current) => current,
+            Err(e) => {
+                log::error!("Error reading rotary encoder: {}", e);
+                continue;
+            }
+        };
+        steps += STEPS[(state << 2 | current) as usize];
+        state = current;
+
+        // Count the detent once back to rest, tolerating a missed transition
+        if state != REST {
+            continue;
+        }
+        let event = match steps {
+            3.. => RotaryEvent::Increment,
+            ..=-3 => RotaryEvent::Decrement,
+            _ => {
+                steps = 0;
+                continue;
+            }
+        };
+        steps = 0;
+        position.fetch_add(
+            match event {
+                RotaryEvent::Increment => 1,
+                RotaryEvent::Decrement => -1,
+            },
+            Ordering::Relaxed,
+        );
+
+        // The position is still tracked when nobody listens for the events
+        let _ = sender.send(event);
+    }
+}
@@ -0,0 +1,92 @@
+//
+// This file provides the error type returned by all the APIs of the crate.
+// Errors are grouped by failure kind, so callers can match on them to decide
+// whether to retry an operation or abort.
+//
+
+use std::io;
+use thiserror::Error;
+
+/// Result type of the crate.
+pub type Result<T> = std::result::Result<T, GpioError>;
+
+/// Errors returned when accessing GPIO pins.
+#[derive(Debug, Error)]
+pub enum GpioError {
+    /// A pin or channel could not be exported or unexported
+    #[error("{0}")]
+    ExportFailed(String),
+    /// The pin is not exported, or was released in the meantime
+    #[error("Pin {0} is not exported")]
+    NotExported(u8),
+    /// The process is not allowed to access the GPIO interface
+    #[error("Permission denied, check the access rights to the GPIO interface")]
+    PermissionDenied(#[source] io::Error),
+    /// The value read back after a write is not the value written,
+    /// e.g. the pin is actually an input or is muxed to another function
+    #[error("Pin {pin} reads {actual} after writing {expected}")]
+    VerifyFailed { pin: u8, expected: u8, actual: u8 },
+    /// A value or setting is out of range, or a value read from a pin can't be parsed
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+    /// Edge notification is not enabled or not possible for the pin
+    #[error("Pin {0} does not support watch")]
+    WatchUnsupported(u8),
+    /// The operation is not possible in the direction of the pin
+    #[error("Pin {pin} is not an {expected} pin")]
+    WrongDirection { pin: u8, expected: &'static str },
+    /// The pin is already watched by the watcher
+    #[error("Pin {0} is already watched")]
+    AlreadyWatched(u8),
+    /// Changes of the pin were lost because too many came at once
+    #[error("Changes of pin {0} were lost on an overflow")]
+    WatchOverflow(u8),
+    /// The pin is not watched by the watcher
+    #[error("Pin {0} is not watched")]
+    NotWatched(u8),
+    /// The receiving end of a notification channel was dropped
+    #[error("The notification channel is closed")]
+    ChannelClosed,
+    /// The notification channel is full, so the value was dropped
+    #[error("The notification channel is full")]
+    ChannelFull,
+    /// A background task stopped unexpectedly
+    #[error("Background task failed: {0}")]
+    TaskFailed(String),
+    /// The pin is already claimed by another part of the process
+    #[error("Pin {pin} is already claimed by {owner}")]
+    PinClaimed { pin: u8, owner: String },
+    /// The relay can't be turned on while a relay interlocked with it is on
+    #[error("Relay {channel} is interlocked with {active}, which is on")]
+    Interlocked { channel: String, active: String },
+    /// The pin number doesn't match a GPIO pin of the board header
+    #[error("{scheme} pin {pin} is not a GPIO pin of the header")]
+    UnmappedPin { scheme: &'static str, pin: u8 },
+    /// The board model is not one with a known pin map
+    #[error("Unknown board model {0:?}")]
+    UnknownBoard(String),
+    /// A device on a bus didn't acknowledge a transfer
+    #[error("No acknowledge from device {address:#04x}")]
+    Nack { address: u8 },
+    /// The backend or the SoC doesn't support the operation
+    #[error("Not supported: {0}")]
+    Unsupported(String),
+    /// An operation didn't complete in time
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    /// Any other I/O error
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+    /// An external command exited with an error
+    #[error("Command `{command}` failed: {stderr}")]
+    CommandFailed { command: String, stderr: String },
+}
+
+impl From<io::Error> for GpioError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(error),
+            _ => Self::Io(error),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//
+// This file provides a fast path for writing an output pin through sysfs. The value file of
+// the pin is opened once and written in place with `pwrite`, instead of being opened, written
+// and closed by each write, which takes most of the time of a sysfs write.
+// The writes are synchronous and bypass the metrics, retries and timeouts of the pin, so they
+// fit tight loops toggling a pin, e.g. bit-banging. For faster writes, use the `mmio` backend.
+//
+// The benchmarks in `benches/toggle.rs` compare the writes of the pins, the fast path
+// and the memory-mapped registers.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// Output pin whose sysfs value file is kept open for fast writes.
+/// The pin must be accessed through sysfs, e.g. with the default backend.
+#[derive(Debug)]
+pub struct FastPin {
+    pin: OutputPin,
+    file: File,
+    value: AtomicU8,
+}
+
+impl FastPin {
+    /// Open the value file of the pin, which must be exported through sysfs.
+    pub fn new(pin: OutputPin) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(pin.get_value_path())
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => GpioError::NotExported(pin.get_pin_number()),
+                _ => e.into(),
+            })?;
+
+        Ok(Self {
+            value: AtomicU8::new(pin.last_value()),
+            pin,
+            file,
+        })
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin.get_pin_number()
+    }
+
+    /// Write a value to the pin.
+    pub fn write(&self, value: u8) -> Result<()> {
+        let content: &[u8] = match value {
+            0 => b"0",
+            1 => b"1",
+            _ => {
+                return Err(GpioError::InvalidValue(format!(
+                    "Value must be 0 or 1, got {}",
+                    value
+                )));
+            }
+        };
+        self.file.write_at(content, 0)?;
+        self.value.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Invert the last value written to the pin and return the new value.
+    pub fn toggle(&self) -> Result<u8> {
+        let value = 1 - self.value.load(Ordering::Relaxed);
+        self.write(value)?;
+        Ok(value)
+    }
+
+    /// Read the current value of the pin.
+    pub fn read(&self) -> Result<u8> {
+        let mut content = [0u8; 1];
+        self.file.read_exact_at(&mut content, 0)?;
+        match content[0] {
+            b'0' => Ok(0),
+            b'1' => Ok(1),
+            other => Err(GpioError::InvalidValue(format!(
+                "Failed to parse the value {:?} of pin {}",
+                other as char,
+                self.get_pin_number()
+            ))),
+        }
+    }
+
+    /// Get the last value written to the pin.
+    pub fn last_value(&self) -> u8 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Close the value file and give the pin back, remembering the last value written.
+    pub fn into_pin(self) -> OutputPin {
+        self.pin.set_last_value(self.last_value());
+        self.pin
+    }
+}
@@ -0,0 +1,686 @@
+//
+// This file provides a backend driving the GPIO lines of an FTDI FT232H or FT2232H USB adapter,
+// so the same application code can run on a laptop with a USB dongle and on the Orange Pi.
+// It's available with the `ftdi` feature.
+//
+// The adapter is put in MPSSE mode and talked to through the usbfs interface of the kernel
+// (`/dev/bus/usb`), which needs the rights on the device, e.g. with a udev rule. The `ftdi_sio`
+// serial driver is detached from the adapter while it's used, and attached again on drop.
+// Pins 0 to 7 are the ADBUS lines (AD0 to AD7) and pins 8 to 15 the ACBUS lines (AC0 to AC7),
+// of the channel A on the FT2232H. Every access is a USB round trip of about a millisecond,
+// and changes are detected by polling the lines.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    mem::size_of,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{self, MissedTickBehavior};
+
+/// Time between two reads of the lines of the watched pins.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// Number of GPIO lines of the adapter, the ADBUS and ACBUS ones.
+const LINES: u8 = 16;
+
+/// USB vendor ID of FTDI.
+const FTDI_VENDOR_ID: u16 = 0x0403;
+/// Timeout of the USB transfers, in milliseconds.
+const USB_TIMEOUT_MS: u32 = 1000;
+/// Size of the packets read from the adapter, each starting with 2 bytes of modem status.
+const PACKET_SIZE: usize = 512;
+/// Number of empty reads before giving up on an answer of the adapter.
+const READ_ATTEMPTS: usize = 10;
+
+/// Vendor requests to the channel A of the adapter.
+const REQUEST_TYPE_OUT: u8 = 0x40;
+const SIO_RESET: u8 = 0x00;
+const SIO_RESET_PURGE_RX: u16 = 1;
+const SIO_RESET_PURGE_TX: u16 = 2;
+const SIO_SET_LATENCY_TIMER: u8 = 0x09;
+const SIO_SET_BITMODE: u8 = 0x0B;
+const BITMODE_RESET: u16 = 0x0000;
+const BITMODE_MPSSE: u16 = 0x0200;
+const CHANNEL_A: u16 = 1;
+const INTERFACE_A: u32 = 0;
+const ENDPOINT_OUT: u32 = 0x02;
+const ENDPOINT_IN: u32 = 0x81;
+
+/// MPSSE commands setting and reading the levels of the lines.
+const SET_BITS_LOW: u8 = 0x80;
+const GET_BITS_LOW: u8 = 0x81;
+const SET_BITS_HIGH: u8 = 0x82;
+const GET_BITS_HIGH: u8 = 0x83;
+const SEND_IMMEDIATE: u8 = 0x87;
+/// Invalid command answered by `0xFA` and the command, to synchronize with the MPSSE.
+const BAD_COMMAND: u8 = 0xAA;
+const BAD_COMMAND_ANSWER: u8 = 0xFA;
+
+/// `struct usbdevfs_ctrltransfer` from `linux/usbdevice_fs.h`
+#[repr(C)]
+struct CtrlTransfer {
+    request_type: u8,
+    request: u8,
+    value: u16,
+    index: u16,
+    length: u16,
+    timeout: u32,
+    data: *mut libc::c_void,
+}
+
+/// `struct usbdevfs_bulktransfer` from `linux/usbdevice_fs.h`
+#[repr(C)]
+struct BulkTransfer {
+    endpoint: u32,
+    length: u32,
+    timeout: u32,
+    data: *mut libc::c_void,
+}
+
+/// `struct usbdevfs_ioctl` from `linux/usbdevice_fs.h`
+#[repr(C)]
+struct UsbIoctl {
+    interface: libc::c_int,
+    code: libc::c_int,
+    data: *mut libc::c_void,
+}
+
+/// Compute an usbfs ioctl request number like the `_IOC` macro.
+const fn ioc(direction: u32, nr: u32, size: usize) -> libc::Ioctl {
+    ((direction << 30) | ((size as u32) << 16) | ((b'U' as u32) << 8) | nr) as libc::Ioctl
+}
+
+const USBDEVFS_CONTROL: libc::Ioctl = ioc(3, 0, size_of::<CtrlTransfer>());
+const USBDEVFS_BULK: libc::Ioctl = ioc(3, 2, size_of::<BulkTransfer>());
+const USBDEVFS_CLAIMINTERFACE: libc::Ioctl = ioc(2, 15, size_of::<u32>());
+const USBDEVFS_RELEASEINTERFACE: libc::Ioctl = ioc(2, 16, size_of::<u32>());
+const USBDEVFS_IOCTL: libc::Ioctl = ioc(3, 18, size_of::<UsbIoctl>());
+const USBDEVFS_DISCONNECT: libc::c_int = ioc(0, 22, 0) as libc::c_int;
+const USBDEVFS_CONNECT: libc::c_int = ioc(0, 23, 0) as libc::c_int;
+
+/// FTDI chip driven by a [FtdiBackend].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FtdiChip {
+    /// FT232H, a single channel, e.g. the Adafruit FT232H breakout
+    Ft232h,
+    /// FT2232H, two channels of which the channel A is used
+    Ft2232h,
+}
+
+impl FtdiChip {
+    /// Get the USB product ID of the chip.
+    fn product_id(self) -> u16 {
+        match self {
+            Self::Ft232h => 0x6014,
+            Self::Ft2232h => 0x6010,
+        }
+    }
+}
+
+/// Channel to the MPSSE of the adapter.
+trait Port: fmt::Debug + Send {
+    /// Send MPSSE commands.
+    fn send(&mut self, commands: &[u8]) -> io::Result<()>;
+
+    /// Receive the bytes answered to the commands sent.
+    fn receive(&mut self, answer: &mut [u8]) -> io::Result<()>;
+}
+
+/// Adapter opened through usbfs, with its interface claimed.
+#[derive(Debug)]
+struct UsbPort {
+    device: File,
+}
+
+impl Drop for UsbPort {
+    fn drop(&mut self) {
+        // Give the adapter back to the serial driver
+        let _ = self.control(SIO_SET_BITMODE, BITMODE_RESET);
+        let _ = self.interface_ioctl(USBDEVFS_RELEASEINTERFACE);
+        let _ = self.driver_ioctl(USBDEVFS_CONNECT);
+    }
+}
+
+impl UsbPort {
+    /// Open the adapter at the given usbfs path, e.g. `/dev/bus/usb/001/004`,
+    /// and put it in MPSSE mode.
+    fn open(path: &Path) -> Result<Self> {
+        let device = OpenOptions::new().read(true).write(true).open(path)?;
+        let port = Self { device };
+
+        // No driver may be bound to the interface, which isn't an error
+        if let Err(e) = port.driver_ioctl(USBDEVFS_DISCONNECT)
+            && e.raw_os_error() != Some(libc::ENODATA)
+        {
+            return Err(e.into());
+        }
+        port.interface_ioctl(USBDEVFS_CLAIMINTERFACE)?;
+
+        port.control(SIO_RESET, 0)?;
+        port.control(SIO_RESET, SIO_RESET_PURGE_RX)?;
+        port.control(SIO_RESET, SIO_RESET_PURGE_TX)?;
+        port.control(SIO_SET_LATENCY_TIMER, 1)?;
+        port.control(SIO_SET_BITMODE, BITMODE_RESET)?;
+        port.control(SIO_SET_BITMODE, BITMODE_MPSSE)?;
+        Ok(port)
+    }
+
+    /// Send a vendor request without data to the channel A.
+    fn control(&self, request: u8, value: u16) -> io::Result<()> {
+        let mut transfer = CtrlTransfer {
+            request_type: REQUEST_TYPE_OUT,
+            request,
+            value,
+            index: CHANNEL_A,
+            length: 0,
+            timeout: USB_TIMEOUT_MS,
+            data: std::ptr::null_mut(),
+        };
+        // SAFETY: the transfer outlives the call and has no data
+        if unsafe { libc::ioctl(self.device.as_raw_fd(), USBDEVFS_CONTROL, &mut transfer) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Run a bulk transfer on an endpoint, returning the number of bytes transferred.
+    fn bulk(&self, endpoint: u32, data: &mut [u8]) -> io::Result<usize> {
+        let mut transfer = BulkTransfer {
+            endpoint,
+            length: data.len() as u32,
+            timeout: USB_TIMEOUT_MS,
+            data: data.as_mut_ptr().cast(),
+        };
+        // SAFETY: the transfer and its buffer outlive the call
+        let transferred =
+            unsafe { libc::ioctl(self.device.as_raw_fd(), USBDEVFS_BULK, &mut transfer) };
+        if transferred < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(transferred as usize)
+    }
+
+    /// Claim or release the interface.
+    fn interface_ioctl(&self, request: libc::Ioctl) -> io::Result<()> {
+        let mut interface = INTERFACE_A;
+        // SAFETY: the interface number outlives the call
+        if unsafe { libc::ioctl(self.device.as_raw_fd(), request, &mut interface) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Detach or attach the kernel driver of the interface.
+    fn driver_ioctl(&self, code: libc::c_int) -> io::Result<()> {
+        let mut command = UsbIoctl {
+            interface: INTERFACE_A as libc::c_int,
+            code,
+            data: std::ptr::null_mut(),
+        };
+        // SAFETY: the command outlives the call and has no data
+        if unsafe { libc::ioctl(self.device.as_raw_fd(), USBDEVFS_IOCTL, &mut command) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Port for UsbPort {
+    fn send(&mut self, commands: &[u8]) -> io::Result<()> {
+        let mut data = commands.to_vec();
+        let mut sent = 0;
+        while sent < data.len() {
+            sent += self.bulk(ENDPOINT_OUT, &mut data[sent..])?;
+        }
+        Ok(())
+    }
+
+    /// The modem status starting each packet is skipped.
+    fn receive(&mut self, answer: &mut [u8]) -> io::Result<()> {
+        let mut received = 0;
+        let mut attempts = 0;
+        let mut packet = [0u8; PACKET_SIZE];
+        while received < answer.len() {
+            let len = self.bulk(ENDPOINT_IN, &mut packet)?;
+            let data = packet.get(2..len).unwrap_or_default();
+            if data.is_empty() {
+                attempts += 1;
+                if attempts == READ_ATTEMPTS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "The FTDI adapter didn't answer",
+                    ));
+                }
+                continue;
+            }
+            let len = data.len().min(answer.len() - received);
+            answer[received..received + len].copy_from_slice(&data[..len]);
+            received += len;
+        }
+        Ok(())
+    }
+}
+
+/// Lines of a simulated MPSSE.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct FakeLines {
+    levels: u16,
+    outputs: u16,
+    /// Levels seen on the input lines
+    inputs: u16,
+}
+
+/// Simulated MPSSE, running the commands on [FakeLines].
+#[cfg(test)]
+#[derive(Debug)]
+struct FakeMpsse {
+    lines: Arc<Mutex<FakeLines>>,
+    answer: Vec<u8>,
+}
+
+#[cfg(test)]
+impl FakeMpsse {
+    fn new(lines: Arc<Mutex<FakeLines>>) -> Self {
+        Self {
+            lines,
+            answer: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Port for FakeMpsse {
+    fn send(&mut self, commands: &[u8]) -> io::Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        let mut commands = commands.iter().copied();
+        while let Some(command) = commands.next() {
+            let shift = if command & 0x02 == 0 { 0 } else { 8 };
+            match command {
+                SET_BITS_LOW | SET_BITS_HIGH => {
+                    let (Some(levels), Some(outputs)) = (commands.next(), commands.next()) else {
+                        return Err(io::ErrorKind::InvalidInput.into());
+                    };
+                    lines.levels = (lines.levels & !(0xFF << shift)) | (levels as u16) << shift;
+                    lines.outputs = (lines.outputs & !(0xFF << shift)) | (outputs as u16) << shift;
+                }
+                GET_BITS_LOW | GET_BITS_HIGH => {
+                    let levels = (lines.levels & lines.outputs) | (lines.inputs & !lines.outputs);
+                    self.answer.push((levels >> shift) as u8);
+                }
+                SEND_IMMEDIATE => {}
+                _ => self.answer.extend([BAD_COMMAND_ANSWER, command]),
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, answer: &mut [u8]) -> io::Result<()> {
+        if self.answer.len() < answer.len() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        answer.copy_from_slice(&self.answer[..answer.len()]);
+        self.answer.drain(..answer.len());
+        Ok(())
+    }
+}
+
+/// State of the lines of the adapter, the MPSSE setting the levels and directions
+/// of 8 lines at once.
+#[derive(Debug)]
+struct Lines {
+    port: Box<dyn Port>,
+    /// Levels driven on the output lines
+    levels: u16,
+    /// Lines configured as outputs
+    outputs: u16,
+    exported: u16,
+    active_low: u16,
+}
+
+impl Lines {
+    fn new(mut port: Box<dyn Port>) -> Result<Self> {
+        // The MPSSE echoes an invalid command once it's ready
+        port.send(&[BAD_COMMAND, SEND_IMMEDIATE])?;
+        let mut answer = [0u8; 2];
+        port.receive(&mut answer)?;
+        if answer != [BAD_COMMAND_ANSWER, BAD_COMMAND] {
+            return Err(GpioError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected answer of the FTDI adapter {:02x?}", answer),
+            )));
+        }
+
+        let mut lines = Self {
+            port,
+            levels: 0,
+            outputs: 0,
+            exported: 0,
+            active_low: 0,
+        };
+        lines.apply()?;
+        Ok(lines)
+    }
+
+    /// Send the levels and directions of all the lines.
+    fn apply(&mut self) -> Result<()> {
+        let [levels_low, levels_high] = self.levels.to_le_bytes();
+        let [outputs_low, outputs_high] = self.outputs.to_le_bytes();
+        self.port.send(&[
+            SET_BITS_LOW,
+            levels_low,
+            outputs_low,
+            SET_BITS_HIGH,
+            levels_high,
+            outputs_high,
+        ])?;
+        Ok(())
+    }
+
+    /// Read the levels of all the lines.
+    fn read(&mut self) -> Result<u16> {
+        self.port
+            .send(&[GET_BITS_LOW, GET_BITS_HIGH, SEND_IMMEDIATE])?;
+        let mut answer = [0u8; 2];
+        self.port.receive(&mut answer)?;
+        Ok(u16::from_le_bytes(answer))
+    }
+
+    /// Set the level and direction of a line.
+    fn set(&mut self, pin_number: u8, direction: Direction, level: u8) -> Result<()> {
+        let bit = 1 << pin_number;
+        self.levels = (self.levels & !bit) | (level as u16) << pin_number;
+        match direction {
+            Direction::In => self.outputs &= !bit,
+            Direction::Out => self.outputs |= bit,
+        }
+        self.apply()
+    }
+
+    /// Convert between the level of the line and the value of the pin.
+    fn logical(&self, pin_number: u8, value: u8) -> u8 {
+        value ^ (self.active_low >> pin_number & 1) as u8
+    }
+
+    fn check_exported(&self, pin_number: u8) -> Result<()> {
+        check_line(pin_number)?;
+        if self.exported & 1 << pin_number == 0 {
+            return Err(GpioError::NotExported(pin_number));
+        }
+        Ok(())
+    }
+}
+
+/// Check that the pin is one of the lines of the adapter.
+fn check_line(pin_number: u8) -> Result<()> {
+    if pin_number >= LINES {
+        return Err(GpioError::InvalidValue(format!(
+            "Pin {} is not a line of the FTDI adapter, only 0 to {} are",
+            pin_number,
+            LINES - 1
+        )));
+    }
+    Ok(())
+}
+
+/// Find the usbfs path of a connected adapter, with the given serial number if any,
+/// by looking at the USB devices listed under the given sysfs directory.
+pub(crate) fn find_device(
+    sysfs_root: &Path,
+    chip: FtdiChip,
+    serial: Option<&str>,
+) -> Result<PathBuf> {
+    let attribute = |device: &Path, name: &str| {
+        fs::read_to_string(device.join(name))
+            .map(|content| content.trim().to_string())
+            .ok()
+    };
+
+    let mut entries = fs::read_dir(sysfs_root)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for device in entries {
+        let matches = attribute(&device, "idVendor") == Some(format!("{:04x}", FTDI_VENDOR_ID))
+            && attribute(&device, "idProduct") == Some(format!("{:04x}", chip.product_id()))
+            && serial.is_none_or(|serial| attribute(&device, "serial").as_deref() == Some(serial));
+        if !matches {
+            continue;
+        }
+        if let (Some(bus), Some(address)) =
+            (attribute(&device, "busnum"), attribute(&device, "devnum"))
+        {
+            let (Ok(bus), Ok(address)) = (bus.parse::<u16>(), address.parse::<u16>()) else {
+                continue;
+            };
+            return Ok(PathBuf::from(format!(
+                "/dev/bus/usb/{:03}/{:03}",
+                bus, address
+            )));
+        }
+    }
+
+    Err(GpioError::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        match serial {
+            Some(serial) => format!("No {:?} adapter with serial {} is connected", chip, serial),
+            None => format!("No {:?} adapter is connected", chip),
+        },
+    )))
+}
+
+/// Backend driving the GPIO lines of an FTDI USB adapter in MPSSE mode.
+#[derive(Debug)]
+pub struct FtdiBackend {
+    chip: FtdiChip,
+    lines: Arc<Mutex<Lines>>,
+    /// Edges set on the pins, only reported as changes are polled on any edge
+    edges: Mutex<HashMap<u8, Edge>>,
+    /// Lines of the simulated MPSSE in the tests
+    #[cfg(test)]
+    fake: Option<Arc<Mutex<FakeLines>>>,
+}
+
+impl FtdiBackend {
+    /// Open the first connected adapter with the given chip.
+    pub fn new(chip: FtdiChip) -> Result<Self> {
+        Self::open(
+            &find_device(Path::new("/sys/bus/usb/devices"), chip, None)?,
+            chip,
+        )
+    }
+
+    /// Open the connected adapter with the given chip and serial number,
+    /// to pick one of several adapters.
+    pub fn with_serial(chip: FtdiChip, serial: &str) -> Result<Self> {
+        let path = find_device(Path::new("/sys/bus/usb/devices"), chip, Some(serial))?;
+        Self::open(&path, chip)
+    }
+
+    /// Open the adapter at the given usbfs path, e.g. `/dev/bus/usb/001/004`.
+    pub fn open(path: &Path, chip: FtdiChip) -> Result<Self> {
+        Self::from_port(chip, Box::new(UsbPort::open(path)?))
+    }
+
+    /// Create a backend on a simulated MPSSE, to test the commands sent to the adapter.
+    #[cfg(test)]
+    pub(crate) fn in_memory(chip: FtdiChip) -> Self {
+        let fake = Arc::new(Mutex::new(FakeLines::default()));
+        let mut backend = Self::from_port(chip, Box::new(FakeMpsse::new(fake.clone()))).unwrap();
+        backend.fake = Some(fake);
+        backend
+    }
+
+    /// Get the levels and the outputs set on the simulated MPSSE.
+    #[cfg(test)]
+    pub(crate) fn fake_lines(&self) -> (u16, u16) {
+        let fake = self.fake.as_ref().unwrap().lock().unwrap();
+        (fake.levels, fake.outputs)
+    }
+
+    /// Set the levels seen on the input lines of the simulated MPSSE.
+    #[cfg(test)]
+    pub(crate) fn set_fake_inputs(&self, inputs: u16) {
+        self.fake.as_ref().unwrap().lock().unwrap().inputs = inputs;
+    }
+
+    fn from_port(chip: FtdiChip, port: Box<dyn Port>) -> Result<Self> {
+        Ok(Self {
+            chip,
+            lines: Arc::new(Mutex::new(Lines::new(port)?)),
+            edges: Mutex::new(HashMap::new()),
+            #[cfg(test)]
+            fake: None,
+        })
+    }
+
+    /// Get the chip of the adapter.
+    pub fn chip(&self) -> FtdiChip {
+        self.chip
+    }
+}
+
+#[async_trait]
+impl GpioBackend for FtdiBackend {
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        check_line(pin_number)?;
+        let mut lines = self.lines.lock().unwrap();
+        lines.exported |= 1 << pin_number;
+        lines.active_low &= !(1 << pin_number);
+        let level = (lines.levels >> pin_number & 1) as u8;
+        lines.set(pin_number, Direction::In, level)
+    }
+
+    /// The level and direction are sent in the same command, so the line doesn't glitch.
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        check_line(pin_number)?;
+        let mut lines = self.lines.lock().unwrap();
+        lines.exported |= 1 << pin_number;
+        lines.active_low &= !(1 << pin_number);
+        lines.set(pin_number, Direction::Out, default)
+    }
+
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        let lines = self.lines.lock().unwrap();
+        lines.check_exported(pin_number)?;
+        Ok(if lines.outputs & 1 << pin_number != 0 {
+            Direction::Out
+        } else {
+            Direction::In
+        })
+    }
+
+    /// The line is made an input, so it stops driving.
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        lines.check_exported(pin_number)?;
+        lines.exported &= !(1 << pin_number);
+        lines.active_low &= !(1 << pin_number);
+        self.edges.lock().unwrap().remove(&pin_number);
+        lines.set(pin_number, Direction::In, 0)
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        let mut lines = self.lines.lock().unwrap();
+        lines.check_exported(pin_number)?;
+        let level = (lines.read()? >> pin_number & 1) as u8;
+        Ok(lines.logical(pin_number, level))
+    }
+
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        lines.check_exported(pin_number)?;
+        let level = lines.logical(pin_number, value);
+        lines.set(pin_number, Direction::Out, level)
+    }
+
+    /// Changes are polled, so any edge is accepted.
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        self.lines.lock().unwrap().check_exported(pin_number)?;
+        let mut edges = self.edges.lock().unwrap();
+        match edge {
+            Some(edge) => edges.insert(pin_number, edge),
+            None => edges.remove(&pin_number),
+        };
+        Ok(())
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        self.lines.lock().unwrap().check_exported(pin_number)?;
+        Ok(self.edges.lock().unwrap().get(&pin_number).copied())
+    }
+
+    async fn exported(&self) -> Result<Vec<u8>> {
+        let exported = self.lines.lock().unwrap().exported;
+        Ok((0..LINES).filter(|pin| exported & 1 << pin != 0).collect())
+    }
+
+    async fn set_bias(&self, _pin_number: u8, _bias: Bias) -> Result<()> {
+        Err(GpioError::Unsupported(format!(
+            "Pull resistors on the {:?}",
+            self.chip
+        )))
+    }
+
+    async fn set_drive_strength(&self, _pin_number: u8, _milliamps: u8) -> Result<()> {
+        Err(GpioError::Unsupported(format!(
+            "Setting the drive strength of a single line of the {:?}",
+            self.chip
+        )))
+    }
+
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        let mut lines = self.lines.lock().unwrap();
+        lines.check_exported(pin_number)?;
+        if active_low {
+            lines.active_low |= 1 << pin_number;
+        } else {
+            lines.active_low &= !(1 << pin_number);
+        }
+        Ok(())
+    }
+
+    /// Poll the lines of the adapter, yielding when the level of the pin changes.
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        let lines = self.lines.clone();
+        let level = {
+            let mut locked = lines.lock().unwrap();
+            locked.check_exported(pin_number)?;
+            locked.read()? >> pin_number & 1
+        };
+
+        Ok(Box::pin(futures::stream::unfold(
+            (lines, None, level),
+            move |(lines, interval, mut level)| async move {
+                let mut interval: time::Interval = interval.unwrap_or_else(|| {
+                    let mut interval = time::interval(WATCH_POLL_INTERVAL);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                    interval
+                });
+                let change = loop {
+                    interval.tick().await;
+                    let current = lines.lock().unwrap().read();
+                    match current.map(|levels| levels >> pin_number & 1) {
+                        Ok(current) if current == level => {}
+                        Ok(current) => {
+                            level = current;
+                            break Ok(());
+                        }
+                        Err(e) => break Err(e),
+                    }
+                };
+                Some((change, (lines, Some(interval), level)))
+            },
+        )))
+    }
+}
@@ -0,0 +1,376 @@
+//
+// This file provides the GPIO context, holding the configuration and the backend
+// the pins are accessed through. It replaces the environment variables previously
+// used to locate the sysfs interface, so several roots can be used side by side.
+// Only the configuration is available without the `async` feature, for the blocking API.
+//
+// The configuration also holds the pin map of the board, for creating pins by their
+// position on the header, and the retry policy of the operations that can fail for a few
+// milliseconds after an export, while udev creates the files of the pin and sets their rights,
+// and the timeout of the reads and writes, so a hung sysfs mount can't stall a control loop.
+//
+// The clock tells the helpers timing pulses below the tokio timer resolution whether to busy
+// wait on the system clock, or to sleep on the tokio timer so tests pausing the tokio time
+// run them instantly and deterministically.
+//
+
+#[cfg(feature = "async")]
+use super::backend::GpioBackend;
+#[cfg(feature = "cdev")]
+use super::cdev::CdevBackend;
+#[cfg(feature = "async")]
+use super::error::{GpioError, Result};
+#[cfg(feature = "ftdi")]
+use super::ftdi::{FtdiBackend, FtdiChip};
+#[cfg(feature = "async")]
+use super::metrics::GpioMetrics;
+#[cfg(feature = "mmio")]
+use super::mmio::{MmioBackend, Soc};
+#[cfg(feature = "rpi")]
+use super::pinmap::Board;
+use super::pinmap::PinMap;
+#[cfg(feature = "rpi")]
+use super::rpi::RpiBackend;
+#[cfg(feature = "async")]
+use super::snapshot::Snapshot;
+#[cfg(feature = "async")]
+use super::sysfs::SysfsBackend;
+#[cfg(feature = "async")]
+use std::sync::Arc;
+use std::{path::PathBuf, time::Duration};
+
+/// Configuration of a [Gpio] context.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GpioConfig {
+    /// Root of the sysfs GPIO interface, `/sys/class/gpio` by default
+    pub sysfs_root: PathBuf,
+    /// Root of the sysfs PWM interface, `/sys/class/pwm` by default
+    pub pwm_root: PathBuf,
+    /// Pin map of the board header, the Orange Pi Zero 2 by default.
+    /// Use [Board::pin_map](super::pinmap::Board::pin_map) for other boards.
+    pub pin_map: PinMap,
+    /// Retries of the exports and writes of the pins failing because the files of the pin
+    /// are missing or not accessible yet
+    pub retry: RetryPolicy,
+    /// Longest time a read or write of a pin, or a `gpio` command run by the sysfs backend,
+    /// may take before failing with [GpioError::Timeout], or `None` to wait forever, the default
+    pub timeout: Option<Duration>,
+    /// External command exporting and configuring the pins through sysfs,
+    /// wiringOP's `gpio` by default
+    pub command: GpioCommand,
+    /// Clock timing the pulses and the polling of the helpers, [Clock::System] by default
+    pub clock: Clock,
+}
+
+impl Default for GpioConfig {
+    fn default() -> Self {
+        Self {
+            sysfs_root: PathBuf::from("/sys/class/gpio"),
+            pwm_root: PathBuf::from("/sys/class/pwm"),
+            pin_map: PinMap::default(),
+            retry: RetryPolicy::default(),
+            timeout: None,
+            command: GpioCommand::default(),
+            clock: Clock::default(),
+        }
+    }
+}
+
+/// External command exporting and configuring the pins, with the names of its subcommands.
+/// Each subcommand is called with the pin number, then its value if it takes one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GpioCommand {
+    /// Name of the program, looked up in the `PATH`, or its path, `gpio` by default
+    pub program: PathBuf,
+    /// Subcommand exporting a pin with a direction, `in` or `out`, `export` by default
+    pub export: String,
+    /// Subcommand unexporting a pin, `unexport` by default
+    pub unexport: String,
+    /// Subcommand setting the edge of a pin, `none`, `rising`, `falling` or `both`,
+    /// `edge` by default
+    pub edge: String,
+    /// Subcommand setting the pull resistors of a pin, `up`, `down` or `tri`, `mode` by default
+    pub mode: String,
+    /// Subcommand setting the drive level of a pin, `drive` by default
+    pub drive: String,
+    /// Flags placed before the mode and drive subcommands so they take SoC numbers
+    /// like the others, `-g` by default
+    pub numbering: Vec<String>,
+}
+
+impl Default for GpioCommand {
+    fn default() -> Self {
+        Self {
+            program: PathBuf::from("gpio"),
+            export: "export".to_string(),
+            unexport: "unexport".to_string(),
+            edge: "edge".to_string(),
+            mode: "mode".to_string(),
+            drive: "drive".to_string(),
+            numbering: vec!["-g".to_string()],
+        }
+    }
+}
+
+/// Subcommand of a [GpioCommand].
+/// The blocking pins only export and unexport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(feature = "async"), allow(dead_code))]
+pub(crate) enum Subcommand {
+    Export,
+    Unexport,
+    Edge,
+    Mode,
+    Drive,
+}
+
+#[cfg(any(feature = "async", feature = "blocking"))]
+impl GpioCommand {
+    /// Get the arguments running the subcommand on a pin, with its value if it takes one.
+    pub(crate) fn args(
+        &self,
+        subcommand: Subcommand,
+        pin_number: u8,
+        value: Option<&str>,
+    ) -> Vec<String> {
+        let (name, numbered) = match subcommand {
+            Subcommand::Export => (&self.export, false),
+            Subcommand::Unexport => (&self.unexport, false),
+            Subcommand::Edge => (&self.edge, false),
+            Subcommand::Mode => (&self.mode, true),
+            Subcommand::Drive => (&self.drive, true),
+        };
+
+        let mut args = if numbered {
+            self.numbering.clone()
+        } else {
+            Vec::new()
+        };
+        args.push(name.clone());
+        args.push(pin_number.to_string());
+        args.extend(value.map(str::to_string));
+        args
+    }
+
+    /// Describe a run of the command with the given arguments, for the errors.
+    pub(crate) fn describe(&self, args: &[String]) -> String {
+        format!("{} {}", self.program.display(), args.join(" "))
+    }
+}
+
+/// Retries with an exponential backoff of the operations failing transiently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt, 5 by default
+    pub retries: u32,
+    /// Delay before the first retry, doubled for each next one, 2ms by default
+    pub initial_delay: Duration,
+    /// Longest delay between two attempts, 50ms by default
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 5,
+            initial_delay: Duration::from_millis(2),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy failing on the first error.
+    pub fn none() -> Self {
+        Self {
+            retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Get the delay before the given retry, starting at 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+}
+
+/// Clock the helpers time their pulses and poll the pins with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Clock {
+    /// Busy wait for the delays below the tokio timer resolution and poll the pins
+    /// as fast as possible, for the real hardware
+    #[default]
+    System,
+    /// Sleep on the tokio timer for every delay and between two polls, so the helpers run
+    /// on the paused time of the tests. Delays are rounded up to the millisecond.
+    Virtual,
+}
+
+#[cfg(feature = "async")]
+impl Clock {
+    /// Wait for the given time.
+    pub async fn delay(self, delay: Duration) {
+        if self == Clock::Virtual || delay >= Duration::from_millis(1) {
+            tokio::time::sleep(delay).await;
+        } else {
+            let deadline = std::time::Instant::now() + delay;
+            while std::time::Instant::now() < deadline {
+                std::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Let the other tasks run between two polls of a pin.
+    pub async fn pause(self) {
+        match self {
+            Clock::System => tokio::task::yield_now().await,
+            Clock::Virtual => tokio::time::sleep(Duration::from_millis(1)).await,
+        }
+    }
+}
+
+/// Context the pins are created from.
+/// It's cheap to clone and every pin keeps a clone of the context it was created from.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone)]
+pub struct Gpio {
+    config: Arc<GpioConfig>,
+    backend: Arc<dyn GpioBackend>,
+    metrics: Arc<GpioMetrics>,
+}
+
+#[cfg(feature = "async")]
+impl Default for Gpio {
+    /// Access the pins through sysfs under the default roots.
+    fn default() -> Self {
+        Self::new(GpioConfig::default())
+    }
+}
+
+#[cfg(feature = "async")]
+impl Gpio {
+    /// Create a context accessing the pins through sysfs under the configured root.
+    pub fn new(config: GpioConfig) -> Self {
+        let backend = Arc::new(
+            SysfsBackend::new(&config.sysfs_root)
+                .with_command(config.command.clone())
+                .with_command_timeout(config.timeout),
+        );
+        Self::with_backend(config, backend)
+    }
+
+    /// Create a context accessing the pins through the given backend.
+    pub fn with_backend(config: GpioConfig, backend: Arc<dyn GpioBackend>) -> Self {
+        Self {
+            config: Arc::new(config),
+            backend,
+            metrics: Arc::new(GpioMetrics::default()),
+        }
+    }
+
+    /// Create a context accessing the pins as lines of a GPIO character device,
+    /// e.g. `/dev/gpiochip0`. Pin numbers are the line offsets on the chip.
+    #[cfg(feature = "cdev")]
+    pub fn cdev(chip: impl Into<PathBuf>) -> Self {
+        Self::with_backend(GpioConfig::default(), Arc::new(CdevBackend::new(chip)))
+    }
+
+    /// Create a context accessing the pins through the GPIO registers of the SoC,
+    /// mapped from `/dev/mem`. Pin numbers are the SoC numbers, like with sysfs.
+    #[cfg(feature = "mmio")]
+    pub fn mmio(soc: Soc) -> Result<Self> {
+        Ok(Self::with_backend(
+            GpioConfig::default(),
+            Arc::new(MmioBackend::new(soc)?),
+        ))
+    }
+
+    /// Create a context accessing the pins as the GPIO lines of the first connected FTDI USB
+    /// adapter with the given chip. Pins 0 to 7 are ADBUS0 to 7, pins 8 to 15 ACBUS0 to 7.
+    #[cfg(feature = "ftdi")]
+    pub fn ftdi(chip: FtdiChip) -> Result<Self> {
+        Ok(Self::with_backend(
+            GpioConfig::default(),
+            Arc::new(FtdiBackend::new(chip)?),
+        ))
+    }
+
+    /// Create a context accessing the GPIOs of a Raspberry Pi header by their BCM numbers,
+    /// with the pin map of the Raspberry Pi.
+    #[cfg(feature = "rpi")]
+    pub fn rpi() -> Result<Self> {
+        let config = GpioConfig {
+            pin_map: Board::RaspberryPi.pin_map(),
+            ..Default::default()
+        };
+        Ok(Self::with_backend(config, Arc::new(RpiBackend::new()?)))
+    }
+
+    /// Get the configuration of the context.
+    pub fn config(&self) -> &GpioConfig {
+        &self.config
+    }
+
+    /// Get the backend the pins are accessed through.
+    pub fn backend(&self) -> &Arc<dyn GpioBackend> {
+        &self.backend
+    }
+
+    /// Read the direction, value and edge of the pins of the board header
+    /// and of the other exported pins, like `gpio readall`.
+    pub async fn snapshot(&self) -> Result<Snapshot> {
+        Snapshot::take(self).await
+    }
+
+    /// Run an operation on a pin, retrying it with the retry policy of the configuration
+    /// while the files of the pin are missing or not accessible.
+    pub(crate) async fn retry<T, F, Fut>(&self, pin_number: u8, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let policy = self.config.retry;
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(e @ (GpioError::NotExported(_) | GpioError::PermissionDenied(_)))
+                    if retry < policy.retries =>
+                {
+                    let delay = policy.delay(retry);
+                    log::debug!("Retrying pin {} in {:?} after: {}", pin_number, delay, e);
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run an operation on a pin, failing with [GpioError::Timeout] if it takes longer
+    /// than the timeout of the configuration.
+    pub(crate) async fn timeout<T>(
+        &self,
+        pin_number: u8,
+        operation: &str,
+        future: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        match self.config.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, future)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(GpioError::Timeout(format!(
+                        "{} of pin {} took more than {:?}",
+                        operation, pin_number, timeout
+                    )))
+                }),
+            None => future.await,
+        }
+    }
+
+    /// Get the counters of the activity of the pins, shared by the clones of the context.
+    pub fn metrics(&self) -> &GpioMetrics {
+        &self.metrics
+    }
+}
@@ -0,0 +1,112 @@
+//
+// This file provides a test harness for the code driving the pins, e.g. the drivers of crates
+// built on this one. It creates a context on a mock backend recording every write to its pins
+// with its time, so the waveforms generated on the outputs can be checked against the expected
+// sequence of values, each at its time within a tolerance.
+//
+// The context uses the virtual clock and the times are measured on the tokio time, so tests
+// pausing it run the drivers instantly and see the exact times of the writes.
+//
+
+use super::gpio::{Clock, Gpio, GpioConfig};
+use super::mock::MockBackend;
+use super::recorder::{Recorder, Transition};
+use std::{sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+/// Context on a mock backend recording the writes to its pins, with assertions on them.
+#[derive(Debug, Clone)]
+pub struct GpioTestHarness {
+    gpio: Gpio,
+    backend: Arc<MockBackend>,
+    recorder: Recorder,
+    tolerance: Duration,
+}
+
+impl Default for GpioTestHarness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GpioTestHarness {
+    /// Create a harness on a new mock backend, with the default configuration
+    /// but the [Clock::Virtual], and a tolerance of 1ms, the resolution of the tokio timer.
+    pub fn new() -> Self {
+        Self::with_config(GpioConfig {
+            clock: Clock::Virtual,
+            ..Default::default()
+        })
+    }
+
+    /// Create a harness on a new mock backend with the given configuration.
+    pub fn with_config(config: GpioConfig) -> Self {
+        let backend = Arc::new(MockBackend::new());
+        let recorder = Recorder::new(usize::MAX);
+        backend.record_writes(&recorder);
+        Self {
+            gpio: Gpio::with_backend(config, backend.clone()),
+            backend,
+            recorder,
+            tolerance: Duration::from_millis(1),
+        }
+    }
+
+    /// Set how far the times of the writes may be from the expected ones.
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Get the context to create the pins from.
+    pub fn gpio(&self) -> &Gpio {
+        &self.gpio
+    }
+
+    /// Get the mock backend, e.g. to drive the inputs or inject faults.
+    pub fn backend(&self) -> &Arc<MockBackend> {
+        &self.backend
+    }
+
+    /// Get the time the harness was created, the origin of the times of the writes.
+    pub fn start(&self) -> Instant {
+        self.recorder.start()
+    }
+
+    /// Get the recorded writes to all the pins, oldest first.
+    pub fn writes(&self) -> Vec<Transition> {
+        self.recorder.transitions()
+    }
+
+    /// Get the recorded writes to a pin, oldest first.
+    pub fn writes_of(&self, pin: u8) -> Vec<Transition> {
+        self.recorder.transitions_of(pin)
+    }
+
+    /// Forget the recorded writes, the times are still counted from the creation of the harness.
+    pub fn clear(&self) {
+        self.recorder.clear();
+    }
+
+    /// Assert that the writes to a pin are the expected values, each written at the given
+    /// time since the creation of the harness, within the tolerance.
+    #[track_caller]
+    pub fn assert_sequence(&self, pin: u8, expected: &[(u8, Duration)]) {
+        let writes: Vec<(u8, Duration)> = self
+            .writes_of(pin)
+            .iter()
+            .map(|write| (write.level.into(), write.time))
+            .collect();
+        let matches = writes.len() == expected.len()
+            && writes.iter().zip(expected).all(
+                |(&(value, time), &(expected_value, expected_time))| {
+                    value == expected_value && time.abs_diff(expected_time) <= self.tolerance
+                },
+            );
+        assert!(
+            matches,
+            "Unexpected writes to pin {} (tolerance {:?})\n  expected: {:?}\n  written:  {:?}",
+            pin, self.tolerance, expected, writes
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//
+// This file provides a DC motor on an H-bridge driver like the L298N, with two inputs choosing
+// the direction and an enable input taking a PWM signal for the speed, hardware or software.
+// The inputs can be any [PinLike] pins, e.g. the lines of a port expander.
+//
+// Reversing a spinning motor at once makes the bridge short the supply for a moment and sends
+// the back-EMF of the motor into it, so the bridge is cut and left to settle for a dead time
+// before driving the other way. Braking shorts the motor through the bridge, stopping it faster
+// than coasting with the bridge off.
+//
+
+use super::error::{GpioError, Result};
+use super::pinlike::PinLike;
+use super::pwm::PwmPin;
+use super::softpwm::SoftPwm;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Settings of an [HBridge].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HBridgeConfig {
+    /// Frequency of the PWM signal on the enable input in Hz, 100Hz by default
+    pub frequency: f64,
+    /// Time the bridge stays cut before driving the other way or braking, 50ms by default
+    pub dead_time: Duration,
+}
+
+impl Default for HBridgeConfig {
+    fn default() -> Self {
+        Self {
+            frequency: 100.0,
+            dead_time: Duration::from_millis(50),
+        }
+    }
+}
+
+/// What an [HBridge] does with its motor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotorState {
+    /// The bridge is off and the motor runs freely
+    Coast,
+    /// The motor is driven forward at a speed between 0.0 and 1.0
+    Forward(f64),
+    /// The motor is driven in reverse at a speed between 0.0 and 1.0
+    Reverse(f64),
+    /// The motor is shorted by the bridge
+    Brake,
+}
+
+impl MotorState {
+    /// Get the levels of both inputs and the duty ratio of the enable input.
+    fn drive(&self) -> ((u8, u8), f64) {
+        match *self {
+            Self::Coast => ((0, 0), 0.0),
+            Self::Forward(speed) => ((1, 0), speed),
+            Self::Reverse(speed) => ((0, 1), speed),
+            Self::Brake => ((1, 1), 1.0),
+        }
+    }
+}
+
+/// PWM on the enable input of an [HBridge].
+#[derive(Debug)]
+enum EnableOutput {
+    Hardware(PwmPin),
+    Software(SoftPwm<Box<dyn PinLike>>),
+}
+
+/// DC motor on an H-bridge, coasting until driven.
+#[derive(Debug)]
+pub struct HBridge {
+    in1: Box<dyn PinLike>,
+    in2: Box<dyn PinLike>,
+    enable: EnableOutput,
+    config: HBridgeConfig,
+    state: MotorState,
+    /// Inputs last driven and when the bridge was cut, to settle before driving other inputs
+    last_driven: Option<((u8, u8), Instant)>,
+}
+
+impl HBridge {
+    /// Create a motor with the speed set by a hardware PWM channel on the enable input.
+    pub async fn new_hardware(
+        in1: impl PinLike + 'static,
+        in2: impl PinLike + 'static,
+        mut enable: PwmPin,
+        config: HBridgeConfig,
+    ) -> Result<Self> {
+        check_config(&config)?;
+        enable.set_frequency(config.frequency).await?;
+        enable.set_duty(0.0).await?;
+        enable.enable().await?;
+        Self::start(in1, in2, EnableOutput::Hardware(enable), config).await
+    }
+
+    /// Create a motor with the speed set by a software PWM on the enable pin.
+    pub async fn new_software(
+        in1: impl PinLike + 'static,
+        in2: impl PinLike + 'static,
+        enable: impl PinLike + 'static,
+        config: HBridgeConfig,
+    ) -> Result<Self> {
+        check_config(&config)?;
+        let enable: Box<dyn PinLike> = Box::new(enable);
+        let pwm = SoftPwm::new(enable, config.frequency, 0.0)?;
+        Self::start(in1, in2, EnableOutput::Software(pwm), config).await
+    }
+
+    /// Get the settings of the motor.
+    pub fn config(&self) -> HBridgeConfig {
+        self.config
+    }
+
+    /// Get what the bridge does with the motor.
+    pub fn state(&self) -> MotorState {
+        self.state
+    }
+
+    /// Drive the motor forward at a speed between 0.0 and 1.0.
+    pub async fn forward(&mut self, speed: f64) -> Result<()> {
+        check_speed(speed)?;
+        self.set_state(MotorState::Forward(speed)).await
+    }
+
+    /// Drive the motor in reverse at a speed between 0.0 and 1.0.
+    pub async fn reverse(&mut self, speed: f64) -> Result<()> {
+        check_speed(speed)?;
+        self.set_state(MotorState::Reverse(speed)).await
+    }
+
+    /// Short the motor to stop it quickly.
+    pub async fn brake(&mut self) -> Result<()> {
+        self.set_state(MotorState::Brake).await
+    }
+
+    /// Turn the bridge off, letting the motor run down freely.
+    pub async fn coast(&mut self) -> Result<()> {
+        self.set_state(MotorState::Coast).await
+    }
+
+    /// Set both inputs low and the enable input off, then wrap the pins.
+    async fn start(
+        in1: impl PinLike + 'static,
+        in2: impl PinLike + 'static,
+        enable: EnableOutput,
+        config: HBridgeConfig,
+    ) -> Result<Self> {
+        in1.write(0).await?;
+        in2.write(0).await?;
+        Ok(Self {
+            in1: Box::new(in1),
+            in2: Box::new(in2),
+            enable,
+            config,
+            state: MotorState::Coast,
+            last_driven: None,
+        })
+    }
+
+    /// Change the inputs and the duty of the enable input, cutting the bridge when the inputs
+    /// change while driven and waiting for the dead time since the cut before driving other
+    /// inputs, even if the motor was left coasting in between.
+    async fn set_state(&mut self, state: MotorState) -> Result<()> {
+        let (previous, _) = self.state.drive();
+        let (inputs, duty) = state.drive();
+
+        if inputs != previous {
+            if previous != (0, 0) {
+                self.set_duty(0.0).await?;
+                self.in1.write(0).await?;
+                self.in2.write(0).await?;
+                self.state = MotorState::Coast;
+                self.last_driven = Some((previous, Instant::now()));
+            }
+            if inputs != (0, 0) {
+                if let Some((driven, cut_at)) = self.last_driven
+                    && driven != inputs
+                {
+                    time::sleep_until(cut_at + self.config.dead_time).await;
+                }
+                self.in1.write(inputs.0).await?;
+                self.in2.write(inputs.1).await?;
+            }
+        }
+        self.set_duty(duty).await?;
+        self.state = state;
+        Ok(())
+    }
+
+    /// Set the duty ratio of the enable input.
+    async fn set_duty(&mut self, duty: f64) -> Result<()> {
+        match &mut self.enable {
+            EnableOutput::Hardware(pwm) => pwm.set_duty(duty).await,
+            EnableOutput::Software(pwm) => pwm.set_duty(duty),
+        }
+    }
+}
+
+/// Check that the speed is between 0.0 and 1.0.
+fn check_speed(speed: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&speed) {
+        return Err(GpioError::InvalidValue(format!(
+            "Speed must be between 0.0 and 1.0, got {}",
+            speed
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the PWM frequency is usable.
+fn check_config(config: &HBridgeConfig) -> Result<()> {
+    if !(config.frequency > 0.0 && config.frequency.is_finite()) {
+        return Err(GpioError::InvalidValue(format!(
+            "Frequency must be positive, got {}",
+            config.frequency
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+//
+// This file provides a driver for 74HC165 shift registers, adding 8 inputs per chip
+// for the price of three pins, the counterpart of the 74HC595 output expander.
+// Chips can be daisy-chained, the serial output of each chip feeding the serial input
+// of the previous one, and the inputs of the whole chain are then addressed by index,
+// inputs 0 to 7 being D0 to D7 of the chip wired to the data pin.
+//
+// A low pulse on the load pin latches all the inputs at once, then the bits are shifted
+// out on the clock pin, D7 of each chip first. The clock inhibit pin must be tied low.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{InputPin, OutputPin};
+use super::pinlike::PinLike;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Chain of 74HC165 shift registers.
+#[derive(Debug)]
+pub struct Hc165 {
+    load: OutputPin,
+    clock: OutputPin,
+    data: InputPin,
+    chips: usize,
+    lock: Mutex<()>,
+}
+
+/// Input of a [Hc165] chain, used like an input pin.
+/// Each read latches and shifts the whole chain.
+#[derive(Debug, Clone)]
+pub struct Hc165Pin {
+    chain: Arc<Hc165>,
+    index: usize,
+}
+
+impl Hc165 {
+    /// Create a driver for a chain of the given number of chips, on the load (SH/LD),
+    /// clock (CLK) and data (QH) pins.
+    pub async fn new(
+        load: OutputPin,
+        clock: OutputPin,
+        data: InputPin,
+        chips: usize,
+    ) -> Result<Self> {
+        if chips == 0 {
+            return Err(GpioError::InvalidValue(
+                "A chain needs at least one chip".to_string(),
+            ));
+        }
+
+        load.write(1).await?;
+        clock.write(0).await?;
+        Ok(Self {
+            load,
+            clock,
+            data,
+            chips,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Get the number of inputs of the chain, 8 per chip.
+    pub fn input_count(&self) -> usize {
+        self.chips * 8
+    }
+
+    /// Latch the inputs and read them, one byte per chip in the order of the chain.
+    /// Bit 0 of each byte is D0 of its chip.
+    pub async fn read_bytes(&self) -> Result<Vec<u8>> {
+        let _lock = self.lock.lock().await;
+
+        // Latch the parallel inputs
+        self.load.write(0).await?;
+        self.load.write(1).await?;
+
+        // Shift them out, D7 of the first chip comes first
+        let mut bytes = vec![0; self.chips];
+        for byte in bytes.iter_mut() {
+            for bit in (0..8).rev() {
+                *byte |= self.data.read().await? << bit;
+                self.clock.write(1).await?;
+                self.clock.write(0).await?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Latch the inputs and read them as a bitmask, bit N being input N.
+    /// Only chains of up to 8 chips fit in the mask.
+    pub async fn read_mask(&self) -> Result<u64> {
+        if self.chips > 8 {
+            return Err(GpioError::InvalidValue(format!(
+                "A chain of {} chips doesn't fit in a 64-bit mask",
+                self.chips
+            )));
+        }
+
+        let bytes = self.read_bytes().await?;
+        Ok(bytes
+            .iter()
+            .enumerate()
+            .fold(0, |mask, (chip, &byte)| mask | (byte as u64) << (chip * 8)))
+    }
+
+    /// Latch the inputs and read a single one.
+    pub async fn read(&self, index: usize) -> Result<u8> {
+        let (chip, bit) = self.check_index(index)?;
+        Ok(self.read_bytes().await?[chip] >> bit & 1)
+    }
+
+    /// Get a handle to a single input of the chain, used like an input pin.
+    pub fn pin(self: &Arc<Self>, index: usize) -> Result<Hc165Pin> {
+        self.check_index(index)?;
+        Ok(Hc165Pin {
+            chain: self.clone(),
+            index,
+        })
+    }
+
+    /// Get the chip and bit of an input, checking that it's in the chain.
+    fn check_index(&self, index: usize) -> Result<(usize, usize)> {
+        if index >= self.input_count() {
+            return Err(GpioError::InvalidValue(format!(
+                "Input {} is out of a chain of {} inputs",
+                index,
+                self.input_count()
+            )));
+        }
+        Ok((index / 8, index % 8))
+    }
+}
+
+impl Hc165Pin {
+    /// Get the index of the input in the chain.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Read the current value of the input.
+    pub async fn read(&self) -> Result<u8> {
+        self.chain.read(self.index).await
+    }
+}
+
+#[async_trait]
+impl PinLike for Hc165Pin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, _value: u8) -> Result<()> {
+        Err(GpioError::Unsupported(
+            "74HC165 inputs can't be written".to_string(),
+        ))
+    }
+}
@@ -0,0 +1,187 @@
+//
+// This file provides a driver for 74HC595 shift registers, adding 8 outputs per chip
+// for the price of three pins, for projects running out of header pins.
+// Chips can be daisy-chained, the serial output of each chip feeding the data input
+// of the next one, and the outputs of the whole chain are then addressed by index,
+// outputs 0 to 7 being Q0 to Q7 of the first chip.
+//
+// Bits are shifted in on the clock pin and only appear on the outputs on the latch pulse,
+// so all the outputs change at once. The driver keeps a copy of the outputs, as the chip
+// can't be read back, so single outputs can be changed without touching the others.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use super::pinlike::PinLike;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Chain of 74HC595 shift registers.
+#[derive(Debug)]
+pub struct Hc595 {
+    data: OutputPin,
+    clock: OutputPin,
+    latch: OutputPin,
+    chips: usize,
+    outputs: Mutex<Vec<u8>>,
+}
+
+/// Output of a [Hc595] chain, used like an output pin.
+#[derive(Debug, Clone)]
+pub struct Hc595Pin {
+    chain: Arc<Hc595>,
+    index: usize,
+}
+
+impl Hc595 {
+    /// Create a driver for a chain of the given number of chips, on the data (SER),
+    /// clock (SRCLK) and latch (RCLK) pins. All the outputs are cleared.
+    pub async fn new(
+        data: OutputPin,
+        clock: OutputPin,
+        latch: OutputPin,
+        chips: usize,
+    ) -> Result<Self> {
+        if chips == 0 {
+            return Err(GpioError::InvalidValue(
+                "A chain needs at least one chip".to_string(),
+            ));
+        }
+
+        clock.write(0).await?;
+        latch.write(0).await?;
+        let chain = Self {
+            data,
+            clock,
+            latch,
+            chips,
+            outputs: Mutex::new(vec![0; chips]),
+        };
+        chain.write_bytes(&vec![0; chips]).await?;
+
+        Ok(chain)
+    }
+
+    /// Get the number of outputs of the chain, 8 per chip.
+    pub fn output_count(&self) -> usize {
+        self.chips * 8
+    }
+
+    /// Set all the outputs at once, one byte per chip in the order of the chain.
+    /// Bit 0 of each byte is Q0 of its chip.
+    pub async fn write_bytes(&self, bytes: &[u8]) -> Result<()> {
+        if bytes.len() != self.chips {
+            return Err(GpioError::InvalidValue(format!(
+                "Got {} bytes for a chain of {} chips",
+                bytes.len(),
+                self.chips
+            )));
+        }
+
+        let mut outputs = self.outputs.lock().await;
+        self.shift_out(bytes).await?;
+        outputs.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Get the current state of the outputs, one byte per chip.
+    pub async fn bytes(&self) -> Vec<u8> {
+        self.outputs.lock().await.clone()
+    }
+
+    /// Set a single output, the others keep their values.
+    pub async fn write(&self, index: usize, value: u8) -> Result<()> {
+        if value > 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Value must be 0 or 1, got {}",
+                value
+            )));
+        }
+
+        let (chip, bit) = check_index(index, self.chips)?;
+        let mut outputs = self.outputs.lock().await;
+        let mut bytes = outputs.clone();
+        bytes[chip] = bytes[chip] & !(1 << bit) | value << bit;
+
+        self.shift_out(&bytes).await?;
+        *outputs = bytes;
+        Ok(())
+    }
+
+    /// Get the current value of a single output.
+    pub async fn read(&self, index: usize) -> Result<u8> {
+        let (chip, bit) = check_index(index, self.chips)?;
+        Ok(self.outputs.lock().await[chip] >> bit & 1)
+    }
+
+    /// Get a handle to a single output of the chain, used like an output pin.
+    pub fn pin(self: &Arc<Self>, index: usize) -> Result<Hc595Pin> {
+        check_index(index, self.chips)?;
+        Ok(Hc595Pin {
+            chain: self.clone(),
+            index,
+        })
+    }
+
+    /// Shift the bytes into the chain and latch them onto the outputs.
+    /// The last chip comes first as the bits ripple through the whole chain.
+    async fn shift_out(&self, bytes: &[u8]) -> Result<()> {
+        for &byte in bytes.iter().rev() {
+            for bit in (0..8).rev() {
+                self.data.write(byte >> bit & 1).await?;
+                self.clock.write(1).await?;
+                self.clock.write(0).await?;
+            }
+        }
+        self.latch.write(1).await?;
+        self.latch.write(0).await
+    }
+}
+
+impl Hc595Pin {
+    /// Get the index of the output in the chain.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Write a value (0 or 1) to the output.
+    pub async fn write(&self, value: u8) -> Result<()> {
+        self.chain.write(self.index, value).await
+    }
+
+    /// Get the current value of the output.
+    pub async fn read(&self) -> Result<u8> {
+        self.chain.read(self.index).await
+    }
+
+    /// Invert the value of the output and return the new value.
+    pub async fn toggle(&self) -> Result<u8> {
+        let value = 1 - self.read().await?;
+        self.write(value).await?;
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl PinLike for Hc595Pin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        self.write(value).await
+    }
+}
+
+/// Get the chip and bit of an output, checking that it's in the chain.
+fn check_index(index: usize, chips: usize) -> Result<(usize, usize)> {
+    if index >= chips * 8 {
+        return Err(GpioError::InvalidValue(format!(
+            "Output {} is out of a chain of {} outputs",
+            index,
+            chips * 8
+        )));
+    }
+    Ok((index / 8, index % 8))
+}
@@ -0,0 +1,223 @@
+//
+// This file provides a driver for HD44780 character LCDs (e.g. 16x2 and 20x4 modules)
+// in 4-bit mode, using the RS and E pins and the D4 to D7 data pins written as a group.
+// The RW pin is usually tied to ground, as the driver never reads the busy flag
+// and waits for the longest execution time of each command instead.
+//
+// Each byte is sent as two nibbles, the high one first, latched on the falling edge of E.
+//
+
+use super::error::{GpioError, Result};
+use super::group::PinGroup;
+use super::pin::OutputPin;
+use std::time::Duration;
+use tokio::time;
+
+/// Command clearing the display and moving the cursor home.
+const CLEAR_DISPLAY: u8 = 0x01;
+/// Command moving the cursor home and undoing the display shift.
+const RETURN_HOME: u8 = 0x02;
+/// Command setting the cursor direction, here moving right after each character.
+const ENTRY_MODE_SET: u8 = 0x06;
+/// Command switching the display, cursor and blinking on and off with bits 2, 1 and 0.
+const DISPLAY_CONTROL: u8 = 0x08;
+/// Command setting 4-bit mode with two lines of 5x8 characters.
+const FUNCTION_SET: u8 = 0x28;
+/// Command setting the address of the character generator RAM.
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+/// Command setting the address of the display RAM, i.e. the cursor position.
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+
+/// Execution time of most commands, with some margin over the 37µs of the datasheet.
+const COMMAND_DELAY: Duration = Duration::from_micros(50);
+/// Execution time of the clear and home commands.
+const CLEAR_DELAY: Duration = Duration::from_millis(2);
+
+/// HD44780 character LCD in 4-bit mode.
+#[derive(Debug)]
+pub struct Hd44780 {
+    rs: OutputPin,
+    enable: OutputPin,
+    data: PinGroup,
+    columns: u8,
+    rows: u8,
+    display_control: u8,
+}
+
+impl Hd44780 {
+    /// Initialize a display of the given size on the RS, E and D4 to D7 pins.
+    /// The display is cleared and switched on, with the cursor hidden.
+    pub async fn new(
+        rs: OutputPin,
+        enable: OutputPin,
+        data: PinGroup,
+        columns: u8,
+        rows: u8,
+    ) -> Result<Self> {
+        if data.len() != 4 {
+            return Err(GpioError::InvalidValue(format!(
+                "4-bit mode needs the 4 pins D4 to D7, got {}",
+                data.len()
+            )));
+        }
+        if columns == 0 || !(1..=4).contains(&rows) {
+            return Err(GpioError::InvalidValue(format!(
+                "Invalid display size {}x{}",
+                columns, rows
+            )));
+        }
+
+        let mut lcd = Self {
+            rs,
+            enable,
+            data,
+            columns,
+            rows,
+            display_control: 0x04,
+        };
+        lcd.rs.write(0).await?;
+        lcd.enable.write(0).await?;
+
+        // Reset into 8-bit mode whatever state the controller was in, then switch to 4-bit
+        time::sleep(Duration::from_millis(50)).await;
+        for delay in [
+            Duration::from_millis(5),
+            Duration::from_micros(150),
+            COMMAND_DELAY,
+        ] {
+            lcd.write_nibble(0x3).await?;
+            lcd.enable.clock().delay(delay).await;
+        }
+        lcd.write_nibble(0x2).await?;
+        lcd.enable.clock().delay(COMMAND_DELAY).await;
+
+        lcd.command(FUNCTION_SET).await?;
+        lcd.command(DISPLAY_CONTROL | lcd.display_control).await?;
+        lcd.clear().await?;
+        lcd.command(ENTRY_MODE_SET).await?;
+        Ok(lcd)
+    }
+
+    /// Get the number of columns and rows of the display.
+    pub fn size(&self) -> (u8, u8) {
+        (self.columns, self.rows)
+    }
+
+    /// Clear the display and move the cursor to the top left corner.
+    pub async fn clear(&mut self) -> Result<()> {
+        self.command(CLEAR_DISPLAY).await?;
+        self.enable.clock().delay(CLEAR_DELAY).await;
+        Ok(())
+    }
+
+    /// Move the cursor to the top left corner without clearing the display.
+    pub async fn home(&mut self) -> Result<()> {
+        self.command(RETURN_HOME).await?;
+        self.enable.clock().delay(CLEAR_DELAY).await;
+        Ok(())
+    }
+
+    /// Move the cursor to the given column and row, counted from 0.
+    pub async fn set_cursor(&mut self, column: u8, row: u8) -> Result<()> {
+        if column >= self.columns || row >= self.rows {
+            return Err(GpioError::InvalidValue(format!(
+                "Position ({}, {}) is out of a {}x{} display",
+                column, row, self.columns, self.rows
+            )));
+        }
+
+        // Rows 2 and 3 continue rows 0 and 1 in the display RAM
+        let row_start = [0x00, 0x40, self.columns, 0x40 + self.columns][row as usize];
+        self.command(SET_DDRAM_ADDRESS | (row_start + column)).await
+    }
+
+    /// Switch the display on or off, its content is kept while off.
+    pub async fn set_display(&mut self, on: bool) -> Result<()> {
+        self.set_display_control(0x04, on).await
+    }
+
+    /// Show or hide the underline cursor.
+    pub async fn set_cursor_visible(&mut self, visible: bool) -> Result<()> {
+        self.set_display_control(0x02, visible).await
+    }
+
+    /// Make the character at the cursor blink or not.
+    pub async fn set_blink(&mut self, blink: bool) -> Result<()> {
+        self.set_display_control(0x01, blink).await
+    }
+
+    /// Write text at the cursor. Only ASCII characters are supported,
+    /// and custom characters can be written with the characters `\u{0}` to `\u{7}`.
+    pub async fn write_str(&mut self, text: &str) -> Result<()> {
+        if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+            return Err(GpioError::InvalidValue(format!(
+                "Character {:?} is not supported by the display",
+                c
+            )));
+        }
+
+        for byte in text.bytes() {
+            self.write_byte(byte).await?;
+        }
+        Ok(())
+    }
+
+    /// Write a character code of the display ROM at the cursor.
+    pub async fn write_byte(&mut self, byte: u8) -> Result<()> {
+        self.send(byte, true).await
+    }
+
+    /// Define one of the 8 custom characters, 5x8 pixels with one byte per row.
+    /// The cursor must be moved afterwards, before writing text.
+    pub async fn create_char(&mut self, location: u8, pattern: [u8; 8]) -> Result<()> {
+        if location > 7 {
+            return Err(GpioError::InvalidValue(format!(
+                "Custom character location must be between 0 and 7, got {}",
+                location
+            )));
+        }
+
+        self.command(SET_CGRAM_ADDRESS | location << 3).await?;
+        for row in pattern {
+            self.write_byte(row & 0x1f).await?;
+        }
+        Ok(())
+    }
+
+    /// Give the pins back.
+    pub fn into_pins(self) -> (OutputPin, OutputPin, PinGroup) {
+        (self.rs, self.enable, self.data)
+    }
+
+    /// Set or clear a bit of the display control and send it.
+    async fn set_display_control(&mut self, bit: u8, on: bool) -> Result<()> {
+        if on {
+            self.display_control |= bit;
+        } else {
+            self.display_control &= !bit;
+        }
+        self.command(DISPLAY_CONTROL | self.display_control).await
+    }
+
+    /// Send an instruction byte.
+    async fn command(&mut self, command: u8) -> Result<()> {
+        self.send(command, false).await
+    }
+
+    /// Send a byte as two nibbles, to the data register or as an instruction.
+    async fn send(&mut self, byte: u8, data: bool) -> Result<()> {
+        self.rs.write_if_changed(data as u8).await?;
+        self.write_nibble(byte >> 4).await?;
+        self.write_nibble(byte & 0x0f).await?;
+        self.enable.clock().delay(COMMAND_DELAY).await;
+        Ok(())
+    }
+
+    /// Put a nibble on D4 to D7 and latch it with a pulse on E.
+    async fn write_nibble(&self, nibble: u8) -> Result<()> {
+        let pattern: Vec<u8> = (0..4).map(|bit| nibble >> bit & 1).collect();
+        self.data.write_pattern(&pattern).await?;
+        self.enable.write(1).await?;
+        self.enable.write(0).await
+    }
+}
@@ -0,0 +1,184 @@
+//
+// This file provides a heartbeat output, toggling a pin at a fixed interval for external
+// hardware watchdogs, which reset the board when the toggling stops.
+// A heartbeat from a task running on its own would keep the watchdog happy even when
+// the application hangs, so the application must pet the heartbeat regularly:
+// when it misses the pet timeout, the toggling stops until it pets again.
+//
+// The pin is left low whenever the task ends, whether stopped, dropped or cancelled
+// with the runtime, so the watchdog always sees the heartbeat stop.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+/// Timings of a [Heartbeat].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeartbeatConfig {
+    /// Time between two toggles of the pin, 500ms by default
+    pub interval: Duration,
+    /// Longest time without a pet before the toggling stops, 5s by default,
+    /// or `None` to toggle until stopped
+    pub pet_timeout: Option<Duration>,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(500),
+            pet_timeout: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// State shared with the task of a [Heartbeat], `None` once stopped.
+type HeartbeatState = Option<Instant>;
+
+/// Heartbeat toggling an [OutputPin] from a tokio task while the application pets it.
+///
+/// Dropping this will stop the heartbeat and leave the pin low.
+#[derive(Debug)]
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    last_pet: watch::Sender<HeartbeatState>,
+    beating: Arc<AtomicBool>,
+    heartbeat_thread: Option<JoinHandle<OutputPin>>,
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        let _ = self.last_pet.send(None);
+    }
+}
+
+impl Heartbeat {
+    /// Start toggling the pin, counting as a first pet.
+    pub fn new(pin: OutputPin, config: HeartbeatConfig) -> Result<Self> {
+        if config.interval.is_zero() || config.pet_timeout.is_some_and(|t| t.is_zero()) {
+            return Err(GpioError::InvalidValue(format!(
+                "Invalid heartbeat timings {:?}",
+                config
+            )));
+        }
+
+        let (last_pet, receiver) = watch::channel(Some(Instant::now()));
+        let beating = Arc::new(AtomicBool::new(true));
+        let heartbeat_thread = tokio::spawn(run_heartbeat(pin, config, receiver, beating.clone()));
+
+        Ok(Self {
+            config,
+            last_pet,
+            beating,
+            heartbeat_thread: Some(heartbeat_thread),
+        })
+    }
+
+    /// Get the timings of the heartbeat.
+    pub fn config(&self) -> HeartbeatConfig {
+        self.config
+    }
+
+    /// Tell the heartbeat that the application is alive, resuming the toggling if it stopped.
+    pub fn pet(&self) {
+        self.last_pet.send_if_modified(|last_pet| match last_pet {
+            Some(last_pet) => {
+                *last_pet = Instant::now();
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Check if the pin is toggling, i.e. the heartbeat was petted in time
+    /// and its task is running.
+    pub fn is_beating(&self) -> bool {
+        self.beating.load(Ordering::Relaxed)
+            && self
+                .heartbeat_thread
+                .as_ref()
+                .is_some_and(|heartbeat_thread| !heartbeat_thread.is_finished())
+    }
+
+    /// Stop the heartbeat, leave the pin low and give the pin back.
+    pub async fn stop(mut self) -> Result<OutputPin> {
+        let _ = self.last_pet.send(None);
+        match self.heartbeat_thread.take() {
+            Some(heartbeat_thread) => heartbeat_thread
+                .await
+                .map_err(|e| GpioError::TaskFailed(e.to_string())),
+            None => Err(GpioError::TaskFailed(
+                "Heartbeat is already stopped".to_string(),
+            )),
+        }
+    }
+}
+
+/// Drives the pin low when dropped, so it's left low even if the task is cancelled.
+struct LowOnDrop(Option<OutputPin>);
+
+impl Drop for LowOnDrop {
+    fn drop(&mut self) {
+        // Only reached when the task is cancelled, as the pin is taken back on a normal exit
+        if let Some(pin) = self.0.take()
+            && let Ok(handle) = tokio::runtime::Handle::try_current()
+        {
+            handle.spawn(async move { pin.write(0).await });
+        }
+    }
+}
+
+/// Toggle the pin at every interval while the last pet is recent enough.
+async fn run_heartbeat(
+    pin: OutputPin,
+    config: HeartbeatConfig,
+    mut last_pet: watch::Receiver<HeartbeatState>,
+    beating: Arc<AtomicBool>,
+) -> OutputPin {
+    let mut guard = LowOnDrop(Some(pin));
+    let pin = guard.0.as_ref().expect("Pin is set until the task exits");
+    let mut interval = time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            // Pets only matter at the next tick, but stopping is immediate
+            changed = last_pet.changed() => match changed {
+                Ok(()) if last_pet.borrow_and_update().is_some() => continue,
+                _ => break,
+            },
+        }
+        let Some(pet) = *last_pet.borrow() else {
+            break;
+        };
+
+        let alive = config
+            .pet_timeout
+            .is_none_or(|timeout| pet.elapsed() <= timeout);
+        if beating.swap(alive, Ordering::Relaxed) && !alive {
+            log::warn!("Heartbeat not petted in time, stopping the toggling");
+        }
+        if alive && let Err(e) = pin.toggle().await {
+            log::error!("Error toggling heartbeat: {}", e);
+        }
+    }
+
+    beating.store(false, Ordering::Relaxed);
+    let pin = guard.0.take().expect("Pin is set until the task exits");
+    if let Err(e) = pin.write(0).await {
+        log::error!("Error stopping heartbeat: {}", e);
+    }
+    pin
+}
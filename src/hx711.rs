@@ -0,0 +1,184 @@
+//
+// This file provides a driver for the HX711 load cell amplifier, a 24-bit ADC made for
+// weighing scales. The chip pulls its data line low when a conversion is ready,
+// which is then clocked out MSB first, followed by 1 to 3 extra clock pulses selecting
+// the channel and gain of the next conversion.
+//
+// Raw readings are turned into weights with an offset, measured by taring the empty scale,
+// and a calibration factor in raw units per weight unit, measured with a known weight.
+// The clock must not stay high for more than 60µs during a read, or the chip powers down.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{InputPin, OutputPin};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Longest time to wait for a conversion, the slowest rate being 10 per second.
+const READY_TIMEOUT: Duration = Duration::from_millis(500);
+/// Time the clock is held high to power the chip down.
+const POWER_DOWN: Duration = Duration::from_micros(100);
+
+/// Channel and gain of the conversions of a [Hx711].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hx711Gain {
+    /// Channel A with a gain of 128
+    A128,
+    /// Channel B with a gain of 32
+    B32,
+    /// Channel A with a gain of 64
+    A64,
+}
+
+impl Hx711Gain {
+    /// Get the number of clock pulses after the 24 bits selecting this gain.
+    fn extra_pulses(&self) -> usize {
+        match self {
+            Self::A128 => 1,
+            Self::B32 => 2,
+            Self::A64 => 3,
+        }
+    }
+}
+
+/// HX711 load cell amplifier.
+#[derive(Debug)]
+pub struct Hx711 {
+    clock: OutputPin,
+    data: InputPin,
+    gain: Hx711Gain,
+    offset: f64,
+    scale: f64,
+}
+
+impl Hx711 {
+    /// Create a driver on the clock (PD_SCK) and data (DOUT) pins.
+    /// A first conversion is read to select the gain, which applies to the next ones.
+    pub async fn new(clock: OutputPin, data: InputPin, gain: Hx711Gain) -> Result<Self> {
+        clock.write(0).await?;
+        let mut hx711 = Self {
+            clock,
+            data,
+            gain,
+            offset: 0.0,
+            scale: 1.0,
+        };
+        hx711.read_raw().await?;
+        Ok(hx711)
+    }
+
+    /// Get the channel and gain of the conversions.
+    pub fn gain(&self) -> Hx711Gain {
+        self.gain
+    }
+
+    /// Select the channel and gain, a conversion is read and discarded to apply it.
+    pub async fn set_gain(&mut self, gain: Hx711Gain) -> Result<()> {
+        self.gain = gain;
+        self.read_raw().await?;
+        Ok(())
+    }
+
+    /// Check if a conversion is ready to be read.
+    pub async fn is_ready(&self) -> Result<bool> {
+        Ok(self.data.read().await? == 0)
+    }
+
+    /// Wait for a conversion and read its signed 24-bit value.
+    pub async fn read_raw(&mut self) -> Result<i32> {
+        let deadline = Instant::now() + READY_TIMEOUT;
+        while !self.is_ready().await? {
+            if Instant::now() >= deadline {
+                return Err(GpioError::Timeout(format!(
+                    "HX711 not ready after {:?}",
+                    READY_TIMEOUT
+                )));
+            }
+            time::sleep(Duration::from_millis(1)).await;
+        }
+
+        let mut value: u32 = 0;
+        for _ in 0..24 {
+            self.clock.write(1).await?;
+            value = value << 1 | self.data.read().await? as u32;
+            self.clock.write(0).await?;
+        }
+        for _ in 0..self.gain.extra_pulses() {
+            self.clock.write(1).await?;
+            self.clock.write(0).await?;
+        }
+
+        // Sign-extend the 24-bit two's complement value
+        Ok(((value << 8) as i32) >> 8)
+    }
+
+    /// Read the average of the given number of conversions.
+    pub async fn read_average(&mut self, samples: usize) -> Result<f64> {
+        if samples == 0 {
+            return Err(GpioError::InvalidValue(
+                "At least one sample is needed".to_string(),
+            ));
+        }
+
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += self.read_raw().await? as f64;
+        }
+        Ok(sum / samples as f64)
+    }
+
+    /// Set the offset to the average of the given number of conversions,
+    /// so the current load weighs 0.
+    pub async fn tare(&mut self, samples: usize) -> Result<()> {
+        self.offset = self.read_average(samples).await?;
+        Ok(())
+    }
+
+    /// Get the raw value of an empty scale.
+    pub fn offset(&self) -> f64 {
+        self.offset
+    }
+
+    /// Set the raw value of an empty scale, e.g. saved from a previous tare.
+    pub fn set_offset(&mut self, offset: f64) {
+        self.offset = offset;
+    }
+
+    /// Get the calibration factor in raw units per weight unit.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Set the calibration factor in raw units per weight unit,
+    /// i.e. the raw value of a known weight minus the offset, divided by that weight.
+    pub fn set_scale(&mut self, scale: f64) -> Result<()> {
+        if scale == 0.0 || !scale.is_finite() {
+            return Err(GpioError::InvalidValue(format!(
+                "Calibration factor must be finite and non-zero, got {}",
+                scale
+            )));
+        }
+        self.scale = scale;
+        Ok(())
+    }
+
+    /// Read the weight averaged over the given number of conversions.
+    pub async fn weight(&mut self, samples: usize) -> Result<f64> {
+        Ok((self.read_average(samples).await? - self.offset) / self.scale)
+    }
+
+    /// Power the chip down by holding the clock high.
+    pub async fn power_down(&self) -> Result<()> {
+        self.clock.write(1).await?;
+        time::sleep(POWER_DOWN).await;
+        Ok(())
+    }
+
+    /// Power the chip up, it resets to channel A with a gain of 128,
+    /// so a conversion is read and discarded to select the gain again.
+    pub async fn power_up(&mut self) -> Result<()> {
+        self.clock.write(0).await?;
+        self.read_raw().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//
+// This file provides a software I2C master for boards whose hardware I2C pins are taken.
+// The lines are open-drain: a line is driven low by making its pin an output,
+// and released by making it an input so the pull-up resistor sets it high.
+// Devices may hold SCL low to slow the master down (clock stretching), which is
+// waited for up to a configurable timeout.
+//
+// The timing relies on the tokio timer, so the clock is limited to a few hundred Hz.
+// This is enough for slow sensors, but not for reading large amounts of data.
+//
+// The device drivers take any [I2cBus], this master or a kernel I2C adapter.
+//
+
+use super::error::{GpioError, Result};
+use super::opendrain::OpenDrain;
+use super::pin::{Bias, GpioPin};
+use async_trait::async_trait;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Default time a device can hold SCL low before the transfer is aborted.
+const DEFAULT_STRETCH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// I2C bus the device drivers talk through.
+#[async_trait]
+pub trait I2cBus: Send {
+    /// Write bytes to the device at the given 7-bit address.
+    async fn write(&mut self, address: u8, data: &[u8]) -> Result<()>;
+
+    /// Read bytes from the device at the given 7-bit address.
+    async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<()>;
+
+    /// Write bytes to the device then read its answer, with a repeated start in between.
+    async fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<()>;
+}
+
+/// Software I2C master over two pins.
+pub struct I2c {
+    sda: OpenDrain,
+    scl: OpenDrain,
+    half_period: Duration,
+    stretch_timeout: Duration,
+}
+
+impl I2c {
+    /// Create an I2C master on the given pins, with the clock frequency in Hz.
+    /// The internal pull-up resistors are enabled, external ones are still recommended.
+    pub async fn new(sda: GpioPin, scl: GpioPin, frequency: f64) -> Result<Self> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return Err(GpioError::InvalidValue(format!(
+                "Frequency must be positive, got {}",
+                frequency
+            )));
+        }
+
+        let mut i2c = Self {
+            sda: OpenDrain::new(sda),
+            scl: OpenDrain::new(scl),
+            half_period: Duration::from_secs_f64(0.5 / frequency),
+            stretch_timeout: DEFAULT_STRETCH_TIMEOUT,
+        };
+
+        // Leave the bus idle with both lines released
+        for line in [&mut i2c.sda, &mut i2c.scl] {
+            line.set_bias(Bias::PullUp).await?;
+            line.release().await?;
+        }
+
+        Ok(i2c)
+    }
+
+    /// Set the time a device can hold SCL low before the transfer is aborted.
+    pub fn set_stretch_timeout(&mut self, timeout: Duration) {
+        self.stretch_timeout = timeout;
+    }
+
+    /// Get the time a device can hold SCL low before the transfer is aborted.
+    pub fn stretch_timeout(&self) -> Duration {
+        self.stretch_timeout
+    }
+
+    /// Write bytes to the device at the given 7-bit address.
+    pub async fn write(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        let result = self.write_transfer(address, data).await;
+        self.stop().await?;
+        result
+    }
+
+    /// Read bytes from the device at the given 7-bit address.
+    pub async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<()> {
+        let result = self.read_transfer(address, buffer).await;
+        self.stop().await?;
+        result
+    }
+
+    /// Write bytes to the device then read its answer, with a repeated start in between.
+    /// This is the usual way of reading a register of a sensor.
+    pub async fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let mut result = self.write_transfer(address, data).await;
+        if result.is_ok() {
+            result = self.read_transfer(address, buffer).await;
+        }
+        self.stop().await?;
+        result
+    }
+
+    /// Generate a start condition, SDA falling while SCL is high.
+    /// Calling this during a transfer generates a repeated start.
+    pub async fn start(&mut self) -> Result<()> {
+        self.sda.release().await?;
+        self.scl_high().await?;
+        self.delay().await;
+        self.sda.drive().await?;
+        self.delay().await;
+        self.scl.drive().await
+    }
+
+    /// Generate a stop condition, SDA rising while SCL is high.
+    pub async fn stop(&mut self) -> Result<()> {
+        self.sda.drive().await?;
+        self.delay().await;
+        self.scl_high().await?;
+        self.delay().await;
+        self.sda.release().await?;
+        self.delay().await;
+        Ok(())
+    }
+
+    /// Write a byte, most significant bit first.
+    /// Returns whether the device acknowledged it.
+    pub async fn write_byte(&mut self, byte: u8) -> Result<bool> {
+        for i in (0..8).rev() {
+            self.write_bit(byte >> i & 1 == 1).await?;
+        }
+        Ok(!self.read_bit().await?)
+    }
+
+    /// Read a byte, most significant bit first.
+    /// The byte is acknowledged if `ack` is true, the last byte of a read is not.
+    pub async fn read_byte(&mut self, ack: bool) -> Result<u8> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = byte << 1 | self.read_bit().await? as u8;
+        }
+        self.write_bit(!ack).await?;
+        Ok(byte)
+    }
+
+    /// Give the pins back, with both lines released.
+    pub fn into_pins(self) -> Result<(GpioPin, GpioPin)> {
+        let Self { sda, scl, .. } = self;
+        Ok((sda.into_pin()?, scl.into_pin()?))
+    }
+
+    /// Address the device for writing and write the bytes, without stop condition.
+    async fn write_transfer(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        self.start().await?;
+        self.address(address, false).await?;
+        for &byte in data {
+            if !self.write_byte(byte).await? {
+                return Err(GpioError::Nack { address });
+            }
+        }
+        Ok(())
+    }
+
+    /// Address the device for reading and read the bytes, without stop condition.
+    async fn read_transfer(&mut self, address: u8, buffer: &mut [u8]) -> Result<()> {
+        self.start().await?;
+        self.address(address, true).await?;
+        let len = buffer.len();
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.read_byte(i + 1 < len).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the address byte of a transfer and check that the device answered.
+    async fn address(&mut self, address: u8, read: bool) -> Result<()> {
+        if address > 0x7f {
+            return Err(GpioError::InvalidValue(format!(
+                "I2C address must be 7 bits, got {:#04x}",
+                address
+            )));
+        }
+        if !self.write_byte(address << 1 | read as u8).await? {
+            return Err(GpioError::Nack { address });
+        }
+        Ok(())
+    }
+
+    /// Clock a bit out on SDA.
+    async fn write_bit(&mut self, bit: bool) -> Result<()> {
+        self.sda.set(bit).await?;
+        self.delay().await;
+        self.scl_high().await?;
+        self.delay().await;
+        self.scl.drive().await
+    }
+
+    /// Clock a bit in from SDA.
+    async fn read_bit(&mut self) -> Result<bool> {
+        self.sda.release().await?;
+        self.delay().await;
+        self.scl_high().await?;
+        let bit = self.sda.read().await?;
+        self.delay().await;
+        self.scl.drive().await?;
+        Ok(bit)
+    }
+
+    /// Release SCL and wait for it to go high, devices may hold it low to stretch the clock.
+    async fn scl_high(&mut self) -> Result<()> {
+        self.scl.release().await?;
+        let deadline = Instant::now() + self.stretch_timeout;
+        while !self.scl.read().await? {
+            if Instant::now() >= deadline {
+                return Err(GpioError::Timeout(format!(
+                    "SCL held low for more than {:?}",
+                    self.stretch_timeout
+                )));
+            }
+            self.delay().await;
+        }
+        Ok(())
+    }
+
+    /// Wait for half a clock period.
+    async fn delay(&self) {
+        time::sleep(self.half_period).await;
+    }
+}
+
+#[async_trait]
+impl I2cBus for I2c {
+    async fn write(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        I2c::write(self, address, data).await
+    }
+
+    async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<()> {
+        I2c::read(self, address, buffer).await
+    }
+
+    async fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<()> {
+        I2c::write_read(self, address, data, buffer).await
+    }
+}
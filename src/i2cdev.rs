@@ -0,0 +1,134 @@
+//
+// This file provides access to the I2C adapters of the kernel through their character devices
+// (`/dev/i2c-N`), to talk to devices through the hardware I2C controllers of the SoC, much
+// faster than the software master. It's available with the `i2cdev` feature.
+//
+// Each transfer is a single `I2C_RDWR` ioctl, so a write followed by a read is done with
+// a repeated start, like with the software master.
+//
+
+use super::error::{GpioError, Result};
+use super::i2c::I2cBus;
+use async_trait::async_trait;
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// `I2C_RDWR` from `linux/i2c-dev.h`
+const I2C_RDWR: libc::Ioctl = 0x0707 as libc::Ioctl;
+/// Flag of the messages reading from the device.
+const I2C_M_RD: u16 = 0x0001;
+
+/// `struct i2c_msg` from `linux/i2c.h`
+#[repr(C)]
+struct I2cMsg {
+    addr: u16,
+    flags: u16,
+    len: u16,
+    buf: *mut u8,
+}
+
+/// `struct i2c_rdwr_ioctl_data` from `linux/i2c-dev.h`
+#[repr(C)]
+struct I2cRdwrData {
+    msgs: *mut I2cMsg,
+    nmsgs: u32,
+}
+
+/// I2C adapter of the kernel.
+#[derive(Debug)]
+pub struct I2cDev {
+    path: PathBuf,
+    file: File,
+}
+
+impl I2cDev {
+    /// Open the adapter with the given number, e.g. 3 for `/dev/i2c-3`.
+    pub fn bus(number: u8) -> Result<Self> {
+        Self::open(format!("/dev/i2c-{}", number))
+    }
+
+    /// Open the adapter at the given path.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    /// Get the path of the adapter.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run the messages as a single transfer, with repeated starts between them.
+    fn transfer(&self, address: u8, messages: &mut [I2cMsg]) -> Result<()> {
+        let mut data = I2cRdwrData {
+            msgs: messages.as_mut_ptr(),
+            nmsgs: messages.len() as u32,
+        };
+        // SAFETY: the messages and their buffers outlive the call
+        let result = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                I2C_RDWR,
+                &mut data as *mut I2cRdwrData,
+            )
+        };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            // The adapters report a missing acknowledge with one or the other
+            return Err(match error.raw_os_error() {
+                Some(libc::ENXIO | libc::EREMOTEIO) => GpioError::Nack { address },
+                _ => error.into(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Describe a message to or from the device at the given address.
+fn message(address: u8, flags: u16, buffer: *mut u8, len: usize) -> I2cMsg {
+    I2cMsg {
+        addr: address as u16,
+        flags,
+        len: len as u16,
+        buf: buffer,
+    }
+}
+
+#[async_trait]
+impl I2cBus for I2cDev {
+    async fn write(&mut self, address: u8, data: &[u8]) -> Result<()> {
+        let mut data = data.to_vec();
+        self.transfer(
+            address,
+            &mut [message(address, 0, data.as_mut_ptr(), data.len())],
+        )
+    }
+
+    async fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<()> {
+        self.transfer(
+            address,
+            &mut [message(
+                address,
+                I2C_M_RD,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )],
+        )
+    }
+
+    async fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let mut data = data.to_vec();
+        self.transfer(
+            address,
+            &mut [
+                message(address, 0, data.as_mut_ptr(), data.len()),
+                message(address, I2C_M_RD, buffer.as_mut_ptr(), buffer.len()),
+            ],
+        )
+    }
+}
@@ -0,0 +1,200 @@
+//
+// This file provides a receiver for infrared remotes using the NEC protocol, the most common
+// one on cheap remotes, through a demodulating receiver module (e.g. VS1838B, TSOP38238)
+// whose output is low during the bursts of the carrier.
+//
+// A frame is a 9ms burst and a 4.5ms space, then 32 bits sent LSB first: the address,
+// its inverse, the command and its inverse. Each bit is a 562.5µs burst followed by
+// a space, 562.5µs for a 0 and 1687.5µs for a 1. While a key is held, the remote sends
+// repeat frames, a 9ms burst and a 2.25ms space, every 108ms.
+// The pulses are timed from the changes of the pin, which requires a backend with fast
+// edge notification, e.g. the character device.
+//
+
+use super::backend::ChangeStream;
+use super::error::Result;
+use super::pin::{Edge, InputPin};
+use std::time::Duration;
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+use tokio_stream::StreamExt;
+
+/// Burst starting a frame.
+const LEADER_MARK: Duration = Duration::from_micros(9000);
+/// Space after the leader burst of a frame with data.
+const DATA_SPACE: Duration = Duration::from_micros(4500);
+/// Space after the leader burst of a repeat frame.
+const REPEAT_SPACE: Duration = Duration::from_micros(2250);
+/// Burst starting each bit and ending the frame.
+const BIT_MARK: Duration = Duration::from_nanos(562_500);
+/// Space of a 0 bit.
+const ZERO_SPACE: Duration = Duration::from_nanos(562_500);
+/// Space of a 1 bit.
+const ONE_SPACE: Duration = Duration::from_nanos(1_687_500);
+
+/// Key decoded by an [IrReceiver].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NecEvent {
+    /// Address of the remote, 16 bits wide with the extended protocol
+    pub address: u16,
+    /// Command of the key
+    pub command: u8,
+    /// Whether the key is still held, sent from a repeat frame
+    pub repeat: bool,
+}
+
+/// Receiver of NEC infrared remotes on an input pin.
+///
+/// Dropping this will stop the events.
+pub struct IrReceiver {
+    ir_thread: JoinHandle<()>,
+}
+
+impl Drop for IrReceiver {
+    fn drop(&mut self) {
+        self.ir_thread.abort();
+    }
+}
+
+impl IrReceiver {
+    /// Start decoding the frames received on the given pin, enabling edge notification on it.
+    /// The returned receiver gets the keys of the remote.
+    pub async fn new(mut pin: InputPin) -> Result<(Self, mpsc::UnboundedReceiver<NecEvent>)> {
+        pin.enable_watch(Edge::Both).await?;
+        let changes = pin.changes()?;
+        let level = pin.read().await?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let ir_thread = tokio::spawn(run_ir(pin, changes, level, sender));
+
+        Ok((Self { ir_thread }, receiver))
+    }
+}
+
+/// State of the NEC decoder, advanced by each burst or space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NecState {
+    /// Waiting for the leader burst
+    Idle,
+    /// Got the leader burst, the space tells the kind of frame
+    Leader,
+    /// Waiting for the burst ending a repeat frame
+    Repeat,
+    /// Receiving the bits, waiting for the burst of the next one
+    BitMark { bits: u8, data: u32 },
+    /// Receiving the bits, waiting for the space giving the value of the current one
+    BitSpace { bits: u8, data: u32 },
+}
+
+/// Time the pulses of the pin and send the decoded keys.
+async fn run_ir(
+    pin: InputPin,
+    mut changes: ChangeStream,
+    mut level: u8,
+    sender: mpsc::UnboundedSender<NecEvent>,
+) {
+    let mut state = NecState::Idle;
+    let mut last_key: Option<NecEvent> = None;
+    let mut changed_at = Instant::now();
+
+    while let Some(change) = changes.next().await {
+        let now = Instant::now();
+        if let Err(e) = change {
+            log::error!("Error watching IR receiver: {}", e);
+            continue;
+        }
+        let value = match pin.read().await {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("Error reading IR receiver: {}", e);
+                continue;
+            }
+        };
+        if value == level {
+            continue;
+        }
+
+        // The pulse that just ended, a burst while the pin was low
+        let mark = level == 0;
+        let duration = now - changed_at;
+        level = value;
+        changed_at = now;
+
+        let (next, key) = decode(state, mark, duration);
+        state = next;
+        let event = match key {
+            Some(Some(key)) => Some(key),
+            Some(None) => last_key.map(|key| NecEvent {
+                repeat: true,
+                ..key
+            }),
+            None => None,
+        };
+        if let Some(event) = event {
+            last_key = Some(event);
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Advance the decoder by a pulse, returning the next state and, at the end of a frame,
+/// the decoded key or `None` for a repeat frame.
+fn decode(state: NecState, mark: bool, duration: Duration) -> (NecState, Option<Option<NecEvent>>) {
+    let next = match (state, mark) {
+        (NecState::Leader, false) if matches(duration, DATA_SPACE) => {
+            NecState::BitMark { bits: 0, data: 0 }
+        }
+        (NecState::Leader, false) if matches(duration, REPEAT_SPACE) => NecState::Repeat,
+        (NecState::Repeat, true) if matches(duration, BIT_MARK) => {
+            return (NecState::Idle, Some(None));
+        }
+        (NecState::BitMark { bits: 32, data }, true) if matches(duration, BIT_MARK) => {
+            return (NecState::Idle, parse_frame(data).map(Some));
+        }
+        (NecState::BitMark { bits, data }, true) if matches(duration, BIT_MARK) => {
+            NecState::BitSpace { bits, data }
+        }
+        (NecState::BitSpace { bits, data }, false) if matches(duration, ZERO_SPACE) => {
+            NecState::BitMark {
+                bits: bits + 1,
+                data,
+            }
+        }
+        (NecState::BitSpace { bits, data }, false) if matches(duration, ONE_SPACE) => {
+            NecState::BitMark {
+                bits: bits + 1,
+                data: data | 1 << bits,
+            }
+        }
+        // Anything else restarts the decoding, possibly at a new frame
+        _ if mark && matches(duration, LEADER_MARK) => NecState::Leader,
+        _ => NecState::Idle,
+    };
+    (next, None)
+}
+
+/// Check the inverted bytes of a frame and get its key.
+fn parse_frame(data: u32) -> Option<NecEvent> {
+    let [address, address_inverse, command, command_inverse] = data.to_le_bytes();
+    if command != !command_inverse {
+        return None;
+    }
+
+    // The extended protocol uses the inverted address byte for 16-bit addresses
+    let address = if address == !address_inverse {
+        address as u16
+    } else {
+        u16::from_le_bytes([address, address_inverse])
+    };
+    Some(NecEvent {
+        address,
+        command,
+        repeat: false,
+    })
+}
+
+/// Check that a pulse is within 25% of its expected duration.
+fn matches(duration: Duration, expected: Duration) -> bool {
+    duration >= expected.mul_f64(0.75) && duration <= expected.mul_f64(1.25)
+}
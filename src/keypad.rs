@@ -0,0 +1,206 @@
+//
+// This file provides a scanner for matrix keypads, with a key at each crossing of a row
+// and a column. The rows are driven low one at a time while the columns are pulled up,
+// so a column reading low has the key of the driven row pressed.
+//
+// Each key is debounced on its own. When keys pressed on 3 corners of a rectangle make
+// the 4th one appear pressed too (ghosting), the scan is ambiguous and ignored.
+// Diodes on the keys prevent ghosting and let any combination of keys be read.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{Bias, InputPin, OutputPin};
+use std::time::Duration;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+/// Position of a key in a [Keypad], counted from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// Event of a [Keypad].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeypadEvent {
+    /// The key was pressed
+    Pressed(Key),
+    /// The key was released
+    Released(Key),
+}
+
+/// Timings of a [Keypad].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeypadConfig {
+    /// Time between two scans of the matrix, 10ms by default
+    pub scan_interval: Duration,
+    /// Time a key must be stable before a change counts, 20ms by default
+    pub debounce: Duration,
+}
+
+impl Default for KeypadConfig {
+    fn default() -> Self {
+        Self {
+            scan_interval: Duration::from_millis(10),
+            debounce: Duration::from_millis(20),
+        }
+    }
+}
+
+/// Matrix keypad scanned on output rows and input columns.
+///
+/// Dropping this will stop the scans.
+pub struct Keypad {
+    config: KeypadConfig,
+    keypad_thread: JoinHandle<()>,
+}
+
+impl Drop for Keypad {
+    fn drop(&mut self) {
+        self.keypad_thread.abort();
+    }
+}
+
+impl Keypad {
+    /// Start scanning the keypad, enabling the pull-up resistors of the columns.
+    /// The returned receiver gets the events of the keys.
+    pub async fn new(
+        rows: Vec<OutputPin>,
+        columns: Vec<InputPin>,
+        config: KeypadConfig,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<KeypadEvent>)> {
+        if rows.is_empty() || columns.is_empty() {
+            return Err(GpioError::InvalidValue(
+                "A keypad needs at least one row and one column".to_string(),
+            ));
+        }
+        if config.scan_interval.is_zero() {
+            return Err(GpioError::InvalidValue(
+                "Scan interval must not be zero".to_string(),
+            ));
+        }
+
+        for row in &rows {
+            row.write(1).await?;
+        }
+        for column in &columns {
+            column.set_bias(Bias::PullUp).await?;
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let keypad_thread = tokio::spawn(run_keypad(rows, columns, config, sender));
+
+        Ok((
+            Self {
+                config,
+                keypad_thread,
+            },
+            receiver,
+        ))
+    }
+
+    /// Get the timings of the keypad.
+    pub fn config(&self) -> KeypadConfig {
+        self.config
+    }
+}
+
+/// Debounced state of a key.
+#[derive(Debug, Clone, Copy)]
+struct KeyState {
+    pressed: bool,
+    raw: bool,
+    raw_since: Instant,
+}
+
+/// Scan the matrix at regular intervals and send the events of the keys.
+async fn run_keypad(
+    rows: Vec<OutputPin>,
+    columns: Vec<InputPin>,
+    config: KeypadConfig,
+    sender: mpsc::UnboundedSender<KeypadEvent>,
+) {
+    let mut states = vec![
+        KeyState {
+            pressed: false,
+            raw: false,
+            raw_since: Instant::now(),
+        };
+        rows.len() * columns.len()
+    ];
+    let mut interval = time::interval(config.scan_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        let scan = match scan(&rows, &columns).await {
+            Ok(scan) => scan,
+            Err(e) => {
+                log::error!("Error scanning keypad: {}", e);
+                continue;
+            }
+        };
+        if is_ghosting(&scan, columns.len()) {
+            continue;
+        }
+
+        let now = Instant::now();
+        for (index, (state, &raw)) in states.iter_mut().zip(&scan).enumerate() {
+            if raw != state.raw {
+                state.raw = raw;
+                state.raw_since = now;
+            }
+            if state.raw == state.pressed || now - state.raw_since < config.debounce {
+                continue;
+            }
+
+            state.pressed = state.raw;
+            let key = Key {
+                row: index / columns.len(),
+                column: index % columns.len(),
+            };
+            let event = if state.pressed {
+                KeypadEvent::Pressed(key)
+            } else {
+                KeypadEvent::Released(key)
+            };
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Read which keys are pressed, row by row.
+async fn scan(rows: &[OutputPin], columns: &[InputPin]) -> Result<Vec<bool>> {
+    let mut scan = Vec::with_capacity(rows.len() * columns.len());
+    for row in rows {
+        row.write(0).await?;
+        for column in columns {
+            match column.read().await {
+                Ok(value) => scan.push(value == 0),
+                Err(e) => {
+                    row.write(1).await?;
+                    return Err(e);
+                }
+            }
+        }
+        row.write(1).await?;
+    }
+    Ok(scan)
+}
+
+/// Check if two rows have pressed keys in the same two columns,
+/// in which case any of the 4 keys may be a ghost.
+fn is_ghosting(scan: &[bool], columns: usize) -> bool {
+    let rows: Vec<&[bool]> = scan.chunks(columns).collect();
+    rows.iter().enumerate().any(|(i, a)| {
+        rows[i + 1..]
+            .iter()
+            .any(|b| a.iter().zip(b.iter()).filter(|(a, b)| **a && **b).count() >= 2)
+    })
+}
@@ -0,0 +1,214 @@
+//
+// This file provides an LED on a software PWM, with effects changing its brightness over time:
+// fading to a brightness, breathing and blinking. Each effect runs as a background task,
+// replaced by the next effect or brightness change, or cancelled with `stop_effect`.
+// The LED can be on any [PinLike] pin, e.g. the line of a port expander, the PWM being
+// limited by the rate of its writes.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use super::pinlike::PinLike;
+use super::softpwm::SoftPwm;
+use std::{f64::consts::PI, sync::Arc, time::Duration};
+use tokio::{
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+/// PWM frequency of the LED, high enough not to flicker.
+const LED_FREQUENCY: f64 = 100.0;
+/// Time between two brightness updates of the fading and breathing effects.
+const EFFECT_STEP: Duration = Duration::from_millis(20);
+
+/// LED with a dimmable brightness, between 0.0 (off) and 1.0 (fully on).
+///
+/// Dropping this will stop the effect and turn the LED off.
+#[derive(Debug)]
+pub struct Led<P = OutputPin> {
+    pwm: Arc<SoftPwm<P>>,
+    effect_thread: Option<JoinHandle<()>>,
+}
+
+impl<P> Drop for Led<P> {
+    fn drop(&mut self) {
+        if let Some(effect_thread) = &self.effect_thread {
+            effect_thread.abort();
+        }
+    }
+}
+
+impl<P: PinLike + 'static> Led<P> {
+    /// Create an LED on the given pin, initially off.
+    pub fn new(pin: P) -> Result<Self> {
+        Ok(Self {
+            pwm: Arc::new(SoftPwm::new(pin, LED_FREQUENCY, 0.0)?),
+            effect_thread: None,
+        })
+    }
+
+    /// Get the current brightness, which changes over time while an effect runs.
+    pub fn brightness(&self) -> f64 {
+        self.pwm.duty()
+    }
+
+    /// Stop the effect and set the brightness.
+    pub async fn set_brightness(&mut self, brightness: f64) -> Result<()> {
+        self.stop_effect().await;
+        self.pwm.set_duty(brightness)
+    }
+
+    /// Turn the LED fully on.
+    pub async fn on(&mut self) -> Result<()> {
+        self.set_brightness(1.0).await
+    }
+
+    /// Turn the LED off.
+    pub async fn off(&mut self) -> Result<()> {
+        self.set_brightness(0.0).await
+    }
+
+    /// Start fading linearly from the current brightness to the given one over the duration.
+    pub async fn fade_to(&mut self, brightness: f64, duration: Duration) -> Result<()> {
+        check_brightness(brightness)?;
+        self.stop_effect().await;
+
+        let from = self.brightness();
+        self.start_effect(move |pwm| async move {
+            let start = Instant::now();
+            let mut interval = effect_interval();
+            loop {
+                interval.tick().await;
+                let progress = if duration.is_zero() {
+                    1.0
+                } else {
+                    (start.elapsed().as_secs_f64() / duration.as_secs_f64()).min(1.0)
+                };
+                set_duty(&pwm, from + (brightness - from) * progress);
+                if progress >= 1.0 {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Start breathing, the brightness rising and falling smoothly once per period,
+    /// until the effect is stopped.
+    pub async fn breathe(&mut self, period: Duration) -> Result<()> {
+        check_period(period)?;
+        self.stop_effect().await;
+
+        self.start_effect(move |pwm| async move {
+            let start = Instant::now();
+            let mut interval = effect_interval();
+            loop {
+                interval.tick().await;
+                let phase = start.elapsed().as_secs_f64() / period.as_secs_f64();
+                set_duty(&pwm, (1.0 - (2.0 * PI * phase).cos()) / 2.0);
+            }
+        });
+        Ok(())
+    }
+
+    /// Start blinking, fully on and off for the given times, until the effect is stopped.
+    pub async fn blink(&mut self, on: Duration, off: Duration) -> Result<()> {
+        check_period(on + off)?;
+        self.stop_effect().await;
+
+        self.start_effect(move |pwm| async move {
+            let mut deadline = Instant::now();
+            loop {
+                set_duty(&pwm, 1.0);
+                deadline += on;
+                time::sleep_until(deadline).await;
+                set_duty(&pwm, 0.0);
+                deadline += off;
+                time::sleep_until(deadline).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Check if an effect is running. Fading stops by itself once the brightness is reached.
+    pub fn is_effect_running(&self) -> bool {
+        self.effect_thread
+            .as_ref()
+            .is_some_and(|effect_thread| !effect_thread.is_finished())
+    }
+
+    /// Wait for the effect to finish, which never happens for breathing and blinking.
+    pub async fn wait_effect(&mut self) -> Result<()> {
+        match self.effect_thread.take() {
+            Some(effect_thread) => effect_thread
+                .await
+                .map_err(|e| GpioError::TaskFailed(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Stop the effect, keeping the current brightness.
+    pub async fn stop_effect(&mut self) {
+        if let Some(effect_thread) = self.effect_thread.take() {
+            effect_thread.abort();
+            let _ = effect_thread.await;
+        }
+    }
+
+    /// Stop the effect and the PWM, leave the pin low and give it back.
+    pub async fn into_pin(mut self) -> Result<P> {
+        self.stop_effect().await;
+        let pwm = self.pwm.clone();
+        drop(self);
+        match Arc::try_unwrap(pwm) {
+            Ok(pwm) => pwm.stop().await,
+            Err(_) => Err(GpioError::TaskFailed(
+                "LED effect is still running".to_string(),
+            )),
+        }
+    }
+
+    /// Run an effect on the PWM in a background task.
+    fn start_effect<F, Fut>(&mut self, effect: F)
+    where
+        F: FnOnce(Arc<SoftPwm<P>>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.effect_thread = Some(tokio::spawn(effect(self.pwm.clone())));
+    }
+}
+
+/// Get the interval of the brightness updates, skipping updates that are late.
+fn effect_interval() -> time::Interval {
+    let mut interval = time::interval(EFFECT_STEP);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+/// Set the duty of the PWM from an effect, the brightness being checked beforehand.
+fn set_duty<P: PinLike + 'static>(pwm: &SoftPwm<P>, duty: f64) {
+    if let Err(e) = pwm.set_duty(duty.clamp(0.0, 1.0)) {
+        log::error!("Error setting LED brightness: {}", e);
+    }
+}
+
+/// Check that the brightness is between 0.0 and 1.0.
+fn check_brightness(brightness: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&brightness) {
+        return Err(GpioError::InvalidValue(format!(
+            "Brightness must be between 0.0 and 1.0, got {}",
+            brightness
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the period of an effect is not zero.
+fn check_period(period: Duration) -> Result<()> {
+    if period.is_zero() {
+        return Err(GpioError::InvalidValue(
+            "Effect period must not be zero".to_string(),
+        ));
+    }
+    Ok(())
+}
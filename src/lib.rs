@@ -1,3 +1,118 @@
+#[cfg(feature = "async")]
+pub mod adc;
+#[cfg(feature = "async")]
+pub mod backend;
+#[cfg(feature = "async")]
+pub mod blinker;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "async")]
+pub mod bus;
+#[cfg(feature = "async")]
+pub mod button;
+#[cfg(feature = "async")]
+pub mod buzzer;
+#[cfg(feature = "cdev")]
+pub mod cdev;
+#[cfg(feature = "async")]
+pub mod dht;
+#[cfg(feature = "async")]
+pub mod encoder;
+pub mod error;
+#[cfg(feature = "async")]
+pub mod fast;
+#[cfg(feature = "ftdi")]
+pub mod ftdi;
+pub mod gpio;
+#[cfg(feature = "async")]
+pub mod group;
+#[cfg(any(all(test, feature = "async"), feature = "mock"))]
+pub mod harness;
+#[cfg(feature = "async")]
+pub mod hbridge;
+#[cfg(feature = "async")]
+pub mod hc165;
+#[cfg(feature = "async")]
+pub mod hc595;
+#[cfg(feature = "async")]
+pub mod hd44780;
+#[cfg(feature = "async")]
+pub mod heartbeat;
+#[cfg(feature = "async")]
+pub mod hx711;
+#[cfg(feature = "async")]
+pub mod i2c;
+#[cfg(feature = "i2cdev")]
+pub mod i2cdev;
+#[cfg(feature = "async")]
+pub mod ir;
+#[cfg(feature = "async")]
+pub mod keypad;
+#[cfg(feature = "async")]
+pub mod led;
+#[cfg(feature = "async")]
+pub mod manager;
+#[cfg(feature = "async")]
+pub mod mcp23017;
+#[cfg(feature = "async")]
+pub mod metrics;
+#[cfg(feature = "mmio")]
+pub mod mmio;
+#[cfg(any(all(test, feature = "async"), feature = "mock"))]
+pub mod mock;
+#[cfg(feature = "async")]
+pub mod onewire;
+#[cfg(feature = "async")]
+pub mod opendrain;
+#[cfg(feature = "async")]
+pub mod pcf8574;
+#[cfg(feature = "async")]
 pub mod pin;
+#[cfg(feature = "async")]
+pub mod pinlike;
+pub mod pinmap;
+#[cfg(feature = "async")]
+pub mod polling;
+#[cfg(feature = "async")]
+pub mod pulse;
+#[cfg(feature = "async")]
+pub mod pwm;
+#[cfg(feature = "async")]
+pub mod recorder;
+#[cfg(feature = "async")]
+pub mod relay;
+#[cfg(any(all(test, feature = "async"), feature = "mock"))]
+pub mod replay;
+#[cfg(feature = "rpi")]
+pub mod rpi;
+#[cfg(feature = "async")]
+pub mod servo;
+#[cfg(feature = "config")]
+pub mod setup;
+#[cfg(feature = "async")]
+pub mod sevenseg;
+#[cfg(feature = "async")]
+pub mod shared;
+#[cfg(any(all(test, feature = "async"), feature = "mock"))]
+pub mod signal;
+#[cfg(feature = "async")]
+pub mod snapshot;
+#[cfg(feature = "async")]
+pub mod softpwm;
+#[cfg(feature = "async")]
+pub mod spi;
+#[cfg(feature = "config")]
+pub mod state;
+#[cfg(feature = "async")]
+pub mod stepper;
+#[cfg(feature = "async")]
+pub mod sysfs;
 mod test;
+#[cfg(feature = "async")]
+pub mod uart;
+#[cfg(feature = "async")]
+pub mod ultrasonic;
+#[cfg(feature = "async")]
 pub mod watcher;
+#[cfg(feature = "async")]
+pub mod ws2812;
@@ -0,0 +1,200 @@
+//
+// This file provides a registry of the pins claimed within the process, so two parts of
+// an application can't silently drive the same pin.
+// A claim is held by a guard and released when the guard is dropped, so a pin is
+// claimed exactly as long as the pin created through the manager lives.
+// Claims are only tracked within a manager and its clones: pins created without it,
+// or by other processes, are not seen.
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Gpio;
+use super::pin::{InputPin, OutputPin};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, OnceLock},
+};
+use tokio::sync::Notify;
+
+/// Claims shared by the clones of a [GpioManager].
+#[derive(Debug, Default)]
+struct Registry {
+    owners: Mutex<HashMap<u8, String>>,
+    released: Notify,
+}
+
+/// Registry of the pins claimed within the process, creating pins of a [Gpio] context
+/// only once they are claimed.
+/// It's cheap to clone and the clones share the same claims.
+#[derive(Debug, Clone)]
+pub struct GpioManager {
+    gpio: Gpio,
+    registry: Arc<Registry>,
+}
+
+impl GpioManager {
+    /// Create a manager for the pins of the context, with no pin claimed.
+    pub fn new(gpio: Gpio) -> Self {
+        Self {
+            gpio,
+            registry: Arc::new(Registry::default()),
+        }
+    }
+
+    /// Get the manager of the default context, shared by the whole process.
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<GpioManager> = OnceLock::new();
+        GLOBAL.get_or_init(|| Self::new(Gpio::default()))
+    }
+
+    /// Get the context the pins are created from.
+    pub fn gpio(&self) -> &Gpio {
+        &self.gpio
+    }
+
+    /// Claim a pin for the given owner, failing if it's already claimed.
+    /// The owner is only used to tell who holds the pin in errors.
+    pub fn claim(&self, pin_number: u8, owner: impl Into<String>) -> Result<PinClaim> {
+        let mut owners = self.registry.owners.lock().unwrap();
+        if let Some(owner) = owners.get(&pin_number) {
+            return Err(GpioError::PinClaimed {
+                pin: pin_number,
+                owner: owner.clone(),
+            });
+        }
+
+        let owner = owner.into();
+        owners.insert(pin_number, owner.clone());
+        Ok(PinClaim {
+            pin_number,
+            owner,
+            registry: self.registry.clone(),
+        })
+    }
+
+    /// Claim a pin for the given owner, waiting for it to be released if it's claimed.
+    pub async fn claim_wait(&self, pin_number: u8, owner: impl Into<String>) -> PinClaim {
+        let owner = owner.into();
+        loop {
+            // Listen for releases before trying, so none is missed
+            let released = self.registry.released.notified();
+            match self.claim(pin_number, owner.clone()) {
+                Ok(claim) => return claim,
+                Err(_) => released.await,
+            }
+        }
+    }
+
+    /// Get the owner of a pin, `None` if it's not claimed.
+    pub fn owner(&self, pin_number: u8) -> Option<String> {
+        self.registry
+            .owners
+            .lock()
+            .unwrap()
+            .get(&pin_number)
+            .cloned()
+    }
+
+    /// Get the claimed pins and their owners, sorted by pin number.
+    pub fn claims(&self) -> Vec<(u8, String)> {
+        let mut claims: Vec<_> = self
+            .registry
+            .owners
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pin_number, owner)| (*pin_number, owner.clone()))
+            .collect();
+        claims.sort();
+        claims
+    }
+
+    /// Claim a pin and initialize it as an input.
+    pub async fn input(
+        &self,
+        pin_number: u8,
+        owner: impl Into<String>,
+    ) -> Result<Claimed<InputPin>> {
+        let claim = self.claim(pin_number, owner)?;
+        let pin = InputPin::new(&self.gpio, pin_number).await?;
+        Ok(Claimed { pin, claim })
+    }
+
+    /// Claim a pin and initialize it as an output driving the default value.
+    pub async fn output(
+        &self,
+        pin_number: u8,
+        owner: impl Into<String>,
+        default: u8,
+    ) -> Result<Claimed<OutputPin>> {
+        let claim = self.claim(pin_number, owner)?;
+        let pin = OutputPin::new(&self.gpio, pin_number, default).await?;
+        Ok(Claimed { pin, claim })
+    }
+}
+
+/// Claim of a pin in a [GpioManager], released when dropped.
+#[derive(Debug)]
+pub struct PinClaim {
+    pin_number: u8,
+    owner: String,
+    registry: Arc<Registry>,
+}
+
+impl Drop for PinClaim {
+    fn drop(&mut self) {
+        self.registry
+            .owners
+            .lock()
+            .unwrap()
+            .remove(&self.pin_number);
+        self.registry.released.notify_waiters();
+    }
+}
+
+impl PinClaim {
+    /// Get the number of the claimed pin.
+    pub fn pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Get the owner of the claim.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+}
+
+/// Pin created through a [GpioManager], claimed for as long as it lives.
+/// It dereferences to the pin, so it's used like the pin itself.
+#[derive(Debug)]
+pub struct Claimed<T> {
+    pin: T,
+    claim: PinClaim,
+}
+
+impl<T> Claimed<T> {
+    /// Get the claim of the pin.
+    pub fn claim(&self) -> &PinClaim {
+        &self.claim
+    }
+
+    /// Split the pin from its claim, e.g. to hand the pin over while keeping it claimed.
+    pub fn into_parts(self) -> (T, PinClaim) {
+        (self.pin, self.claim)
+    }
+}
+
+impl<T> Deref for Claimed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.pin
+    }
+}
+
+impl<T> DerefMut for Claimed<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.pin
+    }
+}
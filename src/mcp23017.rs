@@ -0,0 +1,334 @@
+//
+// This file provides a driver for the MCP23017 I2C port expander, adding 16 lines per chip for
+// the price of an I2C bus shared by up to 8 chips. The lines GPA0 to GPA7 are numbered 0 to 7
+// and GPB0 to GPB7 8 to 15, each an input with an optional pull-up or an output.
+// The chip is driven through any [I2cBus], the software master or a kernel adapter.
+//
+// The chip pulls its INTA pin low when a watched input changes, mirrored on INTB so either one
+// can be wired, until the lines are read. Wired to an input pin, it triggers reading the lines,
+// and the changes are delivered as [GpioEvent]s through a [Notifier], like the changes of the
+// native pins, with the line as pin number. Changes reverted before the lines are read are missed.
+//
+
+use super::error::{GpioError, Result};
+use super::i2c::I2cBus;
+use super::pin::InputPin;
+use super::pinlike::PinLike;
+use super::watcher::{self, Notifier};
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// Registers of the port A, followed by the ones of the port B, with IOCON.BANK cleared.
+const IODIR: u8 = 0x00;
+const GPINTEN: u8 = 0x04;
+const INTCON: u8 = 0x08;
+const IOCON: u8 = 0x0A;
+const GPPU: u8 = 0x0C;
+const GPIO: u8 = 0x12;
+const OLAT: u8 = 0x14;
+/// IOCON bit connecting both interrupt pins.
+const IOCON_MIRROR: u8 = 0x40;
+/// Number of lines of a chip.
+const LINES: u8 = 16;
+
+/// Registers of the chip written by the driver, cached to change a line at a time.
+#[derive(Debug, Clone, Copy)]
+struct Registers {
+    /// Lines configured as inputs
+    inputs: u16,
+    /// Levels driven on the output lines
+    latch: u16,
+    pull_ups: u16,
+    interrupts: u16,
+}
+
+struct State {
+    bus: Box<dyn I2cBus>,
+    registers: Registers,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("registers", &self.registers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// MCP23017 port expander on an I2C bus.
+#[derive(Debug)]
+pub struct Mcp23017 {
+    address: u8,
+    state: Mutex<State>,
+}
+
+/// Line of a [Mcp23017], used like a pin.
+#[derive(Debug, Clone)]
+pub struct Mcp23017Pin {
+    chip: Arc<Mcp23017>,
+    line: u8,
+}
+
+/// Watch of the inputs of a [Mcp23017] through its interrupt pin, stopped on drop.
+#[derive(Debug)]
+pub struct Mcp23017Watch {
+    chip: Arc<Mcp23017>,
+    lines: u16,
+    task: JoinHandle<()>,
+}
+
+impl Mcp23017 {
+    /// Create a driver for the chip at the given address, 0x20 to 0x27 depending on its A0 to A2
+    /// pins, and reset its lines to inputs without pull-ups.
+    pub async fn new(bus: impl I2cBus + 'static, address: u8) -> Result<Self> {
+        if !(0x20..=0x27).contains(&address) {
+            return Err(GpioError::InvalidValue(format!(
+                "MCP23017 address must be between 0x20 and 0x27, got {:#04x}",
+                address
+            )));
+        }
+
+        let registers = Registers {
+            inputs: 0xFFFF,
+            latch: 0,
+            pull_ups: 0,
+            interrupts: 0,
+        };
+        let chip = Self {
+            address,
+            state: Mutex::new(State {
+                bus: Box::new(bus),
+                registers,
+            }),
+        };
+        {
+            let mut state = chip.state.lock().await;
+            let bus = &mut state.bus;
+            bus.write(address, &[IOCON, IOCON_MIRROR, IOCON_MIRROR])
+                .await?;
+            write_pair(bus.as_mut(), address, OLAT, registers.latch).await?;
+            write_pair(bus.as_mut(), address, IODIR, registers.inputs).await?;
+            write_pair(bus.as_mut(), address, GPPU, registers.pull_ups).await?;
+            // Interrupts on any change, compared to the previous level
+            write_pair(bus.as_mut(), address, INTCON, 0).await?;
+            write_pair(bus.as_mut(), address, GPINTEN, registers.interrupts).await?;
+        }
+        Ok(chip)
+    }
+
+    /// Get the I2C address of the chip.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Make a line an input.
+    pub async fn set_input(&self, line: u8) -> Result<()> {
+        let bit = check_line(line)?;
+        self.update(IODIR, |registers| &mut registers.inputs, bit, true)
+            .await
+    }
+
+    /// Make a line an output driving the given value.
+    /// The value is latched before the direction changes, so the line doesn't glitch.
+    pub async fn set_output(&self, line: u8, value: u8) -> Result<()> {
+        let bit = check_line(line)?;
+        self.update(OLAT, |registers| &mut registers.latch, bit, value != 0)
+            .await?;
+        self.update(IODIR, |registers| &mut registers.inputs, bit, false)
+            .await
+    }
+
+    /// Enable or disable the 100kΩ pull-up resistor of an input line.
+    pub async fn set_pull_up(&self, line: u8, enabled: bool) -> Result<()> {
+        let bit = check_line(line)?;
+        self.update(GPPU, |registers| &mut registers.pull_ups, bit, enabled)
+            .await
+    }
+
+    /// Read the levels of all the lines, line 0 being the lowest bit.
+    /// Reading the lines clears the pending interrupt.
+    pub async fn read_all(&self) -> Result<u16> {
+        let mut state = self.state.lock().await;
+        let mut levels = [0u8; 2];
+        state
+            .bus
+            .write_read(self.address, &[GPIO], &mut levels)
+            .await?;
+        Ok(u16::from_le_bytes(levels))
+    }
+
+    /// Read the level of a line.
+    pub async fn read(&self, line: u8) -> Result<u8> {
+        check_line(line)?;
+        Ok((self.read_all().await? >> line & 1) as u8)
+    }
+
+    /// Write the value of an output line.
+    pub async fn write(&self, line: u8, value: u8) -> Result<()> {
+        let bit = check_line(line)?;
+        check_value(value)?;
+        self.update(OLAT, |registers| &mut registers.latch, bit, value == 1)
+            .await
+    }
+
+    /// Write the values of all the output lines at once, line 0 being the lowest bit.
+    pub async fn write_all(&self, values: u16) -> Result<()> {
+        let mut state = self.state.lock().await;
+        write_pair(state.bus.as_mut(), self.address, OLAT, values).await?;
+        state.registers.latch = values;
+        Ok(())
+    }
+
+    /// Get a handle to a single line, used like a pin.
+    pub fn pin(self: &Arc<Self>, line: u8) -> Result<Mcp23017Pin> {
+        check_line(line)?;
+        Ok(Mcp23017Pin {
+            chip: self.clone(),
+            line,
+        })
+    }
+
+    /// Watch the input lines of the mask, line 0 being the lowest bit, through the interrupt pin
+    /// of the chip wired to an input pin. The changes are delivered to the notifier as events
+    /// numbered by their line, until the returned watch is stopped or dropped.
+    pub async fn watch(
+        self: &Arc<Self>,
+        interrupt: InputPin,
+        lines: u16,
+        notifier: impl Into<Notifier>,
+    ) -> Result<Mcp23017Watch> {
+        let notifier = notifier.into();
+        {
+            let mut state = self.state.lock().await;
+            let interrupts = state.registers.interrupts | lines;
+            write_pair(state.bus.as_mut(), self.address, GPINTEN, interrupts).await?;
+            state.registers.interrupts = interrupts;
+        }
+        let levels = self.read_all().await?;
+
+        let chip = self.clone();
+        let read = move || {
+            let chip = chip.clone();
+            async move { chip.read_all().await }
+        };
+        let task =
+            watcher::spawn_interrupt_watch("MCP23017", interrupt, lines, levels, notifier, read);
+
+        Ok(Mcp23017Watch {
+            chip: self.clone(),
+            lines,
+            task,
+        })
+    }
+
+    /// Set or clear the bit of a line in a pair of registers.
+    async fn update(
+        &self,
+        register: u8,
+        field: impl Fn(&mut Registers) -> &mut u16,
+        bit: u16,
+        set: bool,
+    ) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let mut registers = state.registers;
+        let value = field(&mut registers);
+        if set {
+            *value |= bit;
+        } else {
+            *value &= !bit;
+        }
+        write_pair(state.bus.as_mut(), self.address, register, *value).await?;
+        state.registers = registers;
+        Ok(())
+    }
+}
+
+impl Mcp23017Pin {
+    /// Get the line of the chip.
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    /// Make the line an input.
+    pub async fn set_input(&self) -> Result<()> {
+        self.chip.set_input(self.line).await
+    }
+
+    /// Make the line an output driving the given value.
+    pub async fn set_output(&self, value: u8) -> Result<()> {
+        self.chip.set_output(self.line, value).await
+    }
+
+    /// Enable or disable the pull-up resistor of the line.
+    pub async fn set_pull_up(&self, enabled: bool) -> Result<()> {
+        self.chip.set_pull_up(self.line, enabled).await
+    }
+
+    /// Read the level of the line.
+    pub async fn read(&self) -> Result<u8> {
+        self.chip.read(self.line).await
+    }
+
+    /// Write the value of the line, if it's an output.
+    pub async fn write(&self, value: u8) -> Result<()> {
+        self.chip.write(self.line, value).await
+    }
+}
+
+#[async_trait]
+impl PinLike for Mcp23017Pin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        self.write(value).await
+    }
+}
+
+impl Drop for Mcp23017Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Mcp23017Watch {
+    /// Stop watching and disable the interrupts of the watched lines.
+    pub async fn stop(self) -> Result<()> {
+        self.task.abort();
+        let mut state = self.chip.state.lock().await;
+        let interrupts = state.registers.interrupts & !self.lines;
+        write_pair(state.bus.as_mut(), self.chip.address, GPINTEN, interrupts).await?;
+        state.registers.interrupts = interrupts;
+        Ok(())
+    }
+}
+
+/// Write the registers of the port A and B, the chip incrementing the register address.
+async fn write_pair(bus: &mut dyn I2cBus, address: u8, register: u8, value: u16) -> Result<()> {
+    let [a, b] = value.to_le_bytes();
+    bus.write(address, &[register, a, b]).await
+}
+
+/// Check that a line is one of the chip, and get its bit.
+fn check_line(line: u8) -> Result<u16> {
+    if line >= LINES {
+        return Err(GpioError::InvalidValue(format!(
+            "MCP23017 line must be between 0 and {}, got {}",
+            LINES - 1,
+            line
+        )));
+    }
+    Ok(1 << line)
+}
+
+fn check_value(value: u8) -> Result<()> {
+    if value > 1 {
+        return Err(GpioError::InvalidValue(format!(
+            "Value must be 0 or 1, got {}",
+            value
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//
+// This file provides counters of the activity of each pin of a context: reads, writes,
+// events delivered by the watchers and errors. Long-running controllers can sample them
+// to detect an input that stopped changing or one chattering far more than expected.
+//
+// Only the accesses through the pins are counted, the drivers accessing a backend
+// directly are not.
+//
+
+use super::error::{GpioError, Result};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Counters of a pin since its context was created or the metrics were reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PinMetrics {
+    /// Successful reads
+    pub reads: u64,
+    /// Successful writes
+    pub writes: u64,
+    /// Events delivered by the watchers
+    pub events: u64,
+    /// Failed reads and writes, and errors of the watchers
+    pub errors: u64,
+    /// Changes lost by the watchers, on overflows of the change stream or a full channel
+    pub overflows: u64,
+}
+
+/// Counters of all the pins of a [Gpio](super::gpio::Gpio) context.
+#[derive(Debug, Default)]
+pub struct GpioMetrics {
+    pins: Mutex<HashMap<u8, PinMetrics>>,
+}
+
+impl GpioMetrics {
+    /// Get the counters of a pin, all 0 if it was never used.
+    pub fn pin(&self, pin_number: u8) -> PinMetrics {
+        self.pins
+            .lock()
+            .unwrap()
+            .get(&pin_number)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the counters of all the pins used, sorted by pin number.
+    pub fn pins(&self) -> Vec<(u8, PinMetrics)> {
+        let mut pins: Vec<_> = self
+            .pins
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(pin_number, metrics)| (*pin_number, *metrics))
+            .collect();
+        pins.sort_by_key(|(pin_number, _)| *pin_number);
+        pins
+    }
+
+    /// Reset the counters of all the pins to 0.
+    pub fn reset(&self) {
+        self.pins.lock().unwrap().clear();
+    }
+
+    /// Update the counters of a pin.
+    pub(crate) fn record(&self, pin_number: u8, update: impl FnOnce(&mut PinMetrics)) {
+        update(self.pins.lock().unwrap().entry(pin_number).or_default());
+    }
+
+    /// Count a read or a write, depending on its result.
+    pub(crate) fn record_access<T>(
+        &self,
+        pin_number: u8,
+        result: &Result<T>,
+        counter: fn(&mut PinMetrics) -> &mut u64,
+    ) {
+        self.record(pin_number, |metrics| match result {
+            Ok(_) => *counter(metrics) += 1,
+            Err(_) => metrics.errors += 1,
+        });
+    }
+
+    /// Count the delivery of an event by a watcher, depending on its result.
+    pub(crate) fn record_event(&self, pin_number: u8, result: &Result<()>) {
+        self.record(pin_number, |metrics| match result {
+            Ok(()) => metrics.events += 1,
+            Err(GpioError::ChannelFull) => {
+                metrics.errors += 1;
+                metrics.overflows += 1;
+            }
+            Err(_) => metrics.errors += 1,
+        });
+    }
+}
@@ -0,0 +1,518 @@
+//
+// This file provides a backend accessing the GPIO registers of the SoC directly, mapped from
+// `/dev/mem`. Reads and writes take well under a microsecond instead of the tens of microseconds
+// of a sysfs access, which the bit-banged protocols need.
+// It's available with the `mmio` feature and needs root, or the rights on `/dev/mem`.
+//
+// The kernel doesn't know about the pins driven this way: nothing is exported, so the pins must
+// not be used through another interface at the same time, and changes are detected by polling
+// the data registers instead of interrupts.
+// The registers of the Allwinner H3 (also in the H2+) and H616, and of the Rockchip RK3588 are
+// supported. The Rockchip pins must already be muxed to GPIO, as the pin muxing, pull resistors
+// and drive strength live in other register blocks.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use super::pinmap::Board;
+use super::sysfs::drive_level;
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::OpenOptions,
+    io,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+    ptr::NonNull,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{self, MissedTickBehavior};
+
+/// Time between two reads of the data register of a watched pin.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Size of the registers of a bank of the Allwinner PIO.
+const ALLWINNER_BANK_SIZE: usize = 0x24;
+/// Offset of the data register in an Allwinner bank.
+const ALLWINNER_DATA: usize = 0x10;
+/// Offset of the first drive level register in an Allwinner bank.
+const ALLWINNER_DRIVE: usize = 0x14;
+/// Offset of the first pull register in an Allwinner bank.
+const ALLWINNER_PULL: usize = 0x1C;
+/// Functions of an Allwinner pin.
+const ALLWINNER_INPUT: u32 = 0;
+const ALLWINNER_OUTPUT: u32 = 1;
+const ALLWINNER_DISABLED: u32 = 7;
+
+/// Offsets of the data, direction and input registers of a Rockchip controller.
+/// Data and direction are split in two registers of 16 pins, whose upper half is a write mask.
+const ROCKCHIP_DATA: usize = 0x00;
+const ROCKCHIP_DIRECTION: usize = 0x08;
+const ROCKCHIP_INPUT: usize = 0x70;
+
+/// SoC whose GPIO registers are accessed by a [MmioBackend].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Soc {
+    /// Allwinner H3 and H2+, e.g. the Orange Pi PC and Zero
+    AllwinnerH3,
+    /// Allwinner H616, e.g. the Orange Pi Zero 2
+    AllwinnerH616,
+    /// Rockchip RK3588 and RK3588S, e.g. the Orange Pi 5
+    RockchipRk3588,
+}
+
+impl Soc {
+    /// Get the SoC of a board, failing for the boards whose registers aren't supported.
+    pub fn of(board: Board) -> Result<Self> {
+        match board {
+            Board::OrangePiZero | Board::OrangePiPc => Ok(Self::AllwinnerH3),
+            Board::OrangePiZero2 => Ok(Self::AllwinnerH616),
+            Board::OrangePi5 => Ok(Self::RockchipRk3588),
+            Board::RaspberryPi => Err(GpioError::Unsupported(
+                "The GPIO registers of the Raspberry Pi".to_string(),
+            )),
+        }
+    }
+
+    /// Get the physical addresses of the register blocks, one per controller.
+    fn blocks(self) -> &'static [usize] {
+        match self {
+            Self::AllwinnerH3 => &[0x01C2_0800],
+            Self::AllwinnerH616 => &[0x0300_B000],
+            Self::RockchipRk3588 => &[
+                0xFD8A_0000,
+                0xFEC2_0000,
+                0xFEC3_0000,
+                0xFEC4_0000,
+                0xFEC5_0000,
+            ],
+        }
+    }
+
+    /// Get the size of the register blocks.
+    fn block_size(self) -> usize {
+        match self {
+            Self::AllwinnerH3 | Self::AllwinnerH616 => 0x400,
+            Self::RockchipRk3588 => 0x100,
+        }
+    }
+
+    /// Get the banks of 32 pins the SoC has, as a bit mask, e.g. PA, PC to PG for the H3.
+    fn banks(self) -> u32 {
+        match self {
+            Self::AllwinnerH3 => 0b111_1101,
+            Self::AllwinnerH616 => 0b1110_0100,
+            Self::RockchipRk3588 => 0b1_1111,
+        }
+    }
+
+    /// Check if the SoC has the Allwinner PIO, all its banks being in a single block.
+    fn is_allwinner(self) -> bool {
+        matches!(self, Self::AllwinnerH3 | Self::AllwinnerH616)
+    }
+}
+
+/// Register block of a GPIO controller.
+#[derive(Debug)]
+struct Block {
+    registers: NonNull<u32>,
+    /// Pages mapped from the memory device, unmapped on drop
+    mapping: Option<(NonNull<libc::c_void>, usize)>,
+    /// Plain memory standing in for the registers in the tests
+    #[cfg(test)]
+    _owned: Box<[u32]>,
+}
+
+// SAFETY: the registers are only accessed with volatile reads and writes,
+// and the read-modify-writes are serialized by the backend
+unsafe impl Send for Block {}
+unsafe impl Sync for Block {}
+
+impl Drop for Block {
+    fn drop(&mut self) {
+        if let Some((address, len)) = self.mapping {
+            // SAFETY: the pages were mapped by Block::map and nothing points into them anymore
+            unsafe { libc::munmap(address.as_ptr(), len) };
+        }
+    }
+}
+
+impl Block {
+    /// Map the register block at the given physical address from the memory device.
+    fn map(device: &impl AsRawFd, physical: usize, size: usize) -> Result<Self> {
+        // SAFETY: sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let page = physical & !(page_size - 1);
+        let len = physical - page + size;
+
+        // SAFETY: mapping a new region, checked for failure before use
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                device.as_raw_fd(),
+                page as libc::off_t,
+            )
+        };
+        if address == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let address = NonNull::new(address).ok_or_else(io::Error::last_os_error)?;
+        // SAFETY: the offset of the block is within the mapped pages
+        let registers = unsafe { address.cast::<u8>().add(physical - page) }.cast();
+        Ok(Self {
+            registers,
+            mapping: Some((address, len)),
+            #[cfg(test)]
+            _owned: Box::new([]),
+        })
+    }
+
+    /// Create a block in plain memory, to test the register accesses.
+    #[cfg(test)]
+    fn owned(size: usize) -> Self {
+        let mut memory = vec![0u32; size / 4].into_boxed_slice();
+        let registers = NonNull::new(memory.as_mut_ptr()).expect("Boxed memory is not null");
+        Self {
+            registers,
+            mapping: None,
+            _owned: memory,
+        }
+    }
+
+    /// Read the register at the given offset in bytes.
+    fn read(&self, offset: usize) -> u32 {
+        // SAFETY: the offsets are within the block and aligned on 4 bytes
+        unsafe { self.registers.add(offset / 4).read_volatile() }
+    }
+
+    /// Write the register at the given offset in bytes.
+    fn write(&self, offset: usize, value: u32) {
+        // SAFETY: the offsets are within the block and aligned on 4 bytes
+        unsafe { self.registers.add(offset / 4).write_volatile(value) }
+    }
+
+    /// Replace the bits of the register under the mask.
+    fn modify(&self, offset: usize, mask: u32, bits: u32) {
+        self.write(offset, (self.read(offset) & !mask) | (bits & mask));
+    }
+}
+
+/// Register blocks of the SoC, shared with the watch streams.
+#[derive(Debug)]
+struct Registers {
+    soc: Soc,
+    blocks: Vec<Block>,
+}
+
+impl Registers {
+    /// Get the block and the index in its bank of a pin.
+    /// The Allwinner banks are all in one block, the Rockchip ones each in their own.
+    fn locate(&self, pin_number: u8) -> Result<(&Block, usize, usize)> {
+        let (bank, index) = (pin_number as usize / 32, pin_number as usize % 32);
+        if self.soc.banks() & (1 << bank) == 0 {
+            return Err(GpioError::InvalidValue(format!(
+                "Pin {} is not a GPIO of the {:?}",
+                pin_number, self.soc
+            )));
+        }
+
+        if self.soc.is_allwinner() {
+            Ok((&self.blocks[0], bank * ALLWINNER_BANK_SIZE, index))
+        } else {
+            Ok((&self.blocks[bank], 0, index))
+        }
+    }
+
+    /// Read the level of the line.
+    fn level(&self, pin_number: u8) -> Result<u8> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        let data = if self.soc.is_allwinner() {
+            block.read(bank + ALLWINNER_DATA)
+        } else {
+            block.read(ROCKCHIP_INPUT)
+        };
+        Ok((data >> index & 1) as u8)
+    }
+
+    /// Drive the level of the line, when it's an output.
+    fn set_level(&self, pin_number: u8, level: u8) -> Result<()> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        if self.soc.is_allwinner() {
+            block.modify(bank + ALLWINNER_DATA, 1 << index, (level as u32) << index);
+        } else {
+            write_masked(block, ROCKCHIP_DATA, index, level as u32);
+        }
+        Ok(())
+    }
+
+    /// Get the direction of the pin, failing for an Allwinner pin muxed to another function.
+    fn direction(&self, pin_number: u8) -> Result<Direction> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        if !self.soc.is_allwinner() {
+            let half = ROCKCHIP_DIRECTION + index / 16 * 4;
+            let output = block.read(half) >> (index % 16) & 1 == 1;
+            return Ok(if output {
+                Direction::Out
+            } else {
+                Direction::In
+            });
+        }
+
+        let (offset, shift) = (bank + index / 8 * 4, index % 8 * 4);
+        match block.read(offset) >> shift & 0x7 {
+            ALLWINNER_INPUT => Ok(Direction::In),
+            ALLWINNER_OUTPUT => Ok(Direction::Out),
+            ALLWINNER_DISABLED => Err(GpioError::NotExported(pin_number)),
+            function => Err(GpioError::InvalidValue(format!(
+                "Pin {} is muxed to function {}",
+                pin_number, function
+            ))),
+        }
+    }
+
+    /// Make the pin an input or an output.
+    fn set_direction(&self, pin_number: u8, direction: Direction) -> Result<()> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        let output = direction == Direction::Out;
+        if self.soc.is_allwinner() {
+            let function = if output {
+                ALLWINNER_OUTPUT
+            } else {
+                ALLWINNER_INPUT
+            };
+            set_allwinner_function(block, bank, index, function);
+        } else {
+            write_masked(block, ROCKCHIP_DIRECTION, index, output as u32);
+        }
+        Ok(())
+    }
+
+    /// Disconnect an Allwinner pin, or make a Rockchip pin an input.
+    fn disable(&self, pin_number: u8) -> Result<()> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        if self.soc.is_allwinner() {
+            set_allwinner_function(block, bank, index, ALLWINNER_DISABLED);
+            Ok(())
+        } else {
+            self.set_direction(pin_number, Direction::In)
+        }
+    }
+
+    /// Set a 2 bit field of an Allwinner pin, its pull or drive level.
+    fn set_allwinner_field(&self, pin_number: u8, first: usize, value: u32) -> Result<()> {
+        let (block, bank, index) = self.locate(pin_number)?;
+        if !self.soc.is_allwinner() {
+            return Err(GpioError::Unsupported(format!(
+                "Pull resistors and drive strength through the registers of the {:?}",
+                self.soc
+            )));
+        }
+
+        let (offset, shift) = (bank + first + index / 16 * 4, index % 16 * 2);
+        block.modify(offset, 0x3 << shift, value << shift);
+        Ok(())
+    }
+}
+
+/// Set the function of an Allwinner pin, 4 bits per pin in 4 registers of 8 pins.
+fn set_allwinner_function(block: &Block, bank: usize, index: usize, function: u32) {
+    let (offset, shift) = (bank + index / 8 * 4, index % 8 * 4);
+    block.modify(offset, 0xF << shift, function << shift);
+}
+
+/// Write the bit of a Rockchip pin in a pair of registers of 16 pins,
+/// the upper half of the register masking the bits written.
+fn write_masked(block: &Block, first: usize, index: usize, bit: u32) {
+    let (offset, shift) = (first + index / 16 * 4, index % 16);
+    block.write(offset, 1 << (shift + 16) | bit << shift);
+}
+
+/// Backend accessing the pins through the GPIO registers of the SoC mapped in memory.
+#[derive(Debug)]
+pub struct MmioBackend {
+    registers: Arc<Registers>,
+    /// Serializes the read-modify-writes of registers shared by several pins
+    lock: Mutex<()>,
+    active_low: Mutex<HashSet<u8>>,
+    /// Edges set on the pins, only reported as changes are polled on any edge
+    edges: Mutex<HashMap<u8, Edge>>,
+}
+
+impl MmioBackend {
+    /// Map the GPIO registers of the SoC from `/dev/mem`.
+    pub fn new(soc: Soc) -> Result<Self> {
+        Self::with_device(soc, "/dev/mem")
+    }
+
+    /// Map the GPIO registers of the SoC from a device exposing the physical memory.
+    pub fn with_device(soc: Soc, device: impl AsRef<Path>) -> Result<Self> {
+        let device = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_SYNC)
+            .open(device)?;
+        let blocks = soc
+            .blocks()
+            .iter()
+            .map(|&physical| Block::map(&device, physical, soc.block_size()))
+            .collect::<Result<_>>()?;
+        Ok(Self::from_blocks(soc, blocks))
+    }
+
+    /// Create a backend on registers in plain memory, to test the register accesses.
+    #[cfg(test)]
+    pub(crate) fn in_memory(soc: Soc) -> Self {
+        let blocks = soc
+            .blocks()
+            .iter()
+            .map(|_| Block::owned(soc.block_size()))
+            .collect();
+        Self::from_blocks(soc, blocks)
+    }
+
+    /// Read a register, to test the register accesses.
+    #[cfg(test)]
+    pub(crate) fn register(&self, block: usize, offset: usize) -> u32 {
+        self.registers.blocks[block].read(offset)
+    }
+
+    fn from_blocks(soc: Soc, blocks: Vec<Block>) -> Self {
+        Self {
+            registers: Arc::new(Registers { soc, blocks }),
+            lock: Mutex::new(()),
+            active_low: Mutex::new(HashSet::new()),
+            edges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the SoC whose registers are accessed.
+    pub fn soc(&self) -> Soc {
+        self.registers.soc
+    }
+
+    /// Run a function on the registers, without other read-modify-write in between.
+    fn locked<T>(&self, f: impl FnOnce(&Registers) -> Result<T>) -> Result<T> {
+        let _guard = self.lock.lock().unwrap();
+        f(&self.registers)
+    }
+
+    /// Convert between the level of the line and the value of the pin.
+    fn logical(&self, pin_number: u8, value: u8) -> u8 {
+        value ^ self.active_low.lock().unwrap().contains(&pin_number) as u8
+    }
+}
+
+#[async_trait]
+impl GpioBackend for MmioBackend {
+    /// Nothing is exported, the pin is only made an input.
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        self.active_low.lock().unwrap().remove(&pin_number);
+        self.locked(|registers| registers.set_direction(pin_number, Direction::In))
+    }
+
+    /// The level is set before the direction, so the line doesn't glitch.
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        self.active_low.lock().unwrap().remove(&pin_number);
+        self.locked(|registers| {
+            registers.set_level(pin_number, default)?;
+            registers.set_direction(pin_number, Direction::Out)
+        })
+    }
+
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        self.registers.direction(pin_number)
+    }
+
+    /// The pin is disconnected, or made an input on the SoCs that can't disconnect it.
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        self.active_low.lock().unwrap().remove(&pin_number);
+        self.edges.lock().unwrap().remove(&pin_number);
+        self.locked(|registers| registers.disable(pin_number))
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        let level = self.registers.level(pin_number)?;
+        Ok(self.logical(pin_number, level))
+    }
+
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        let level = self.logical(pin_number, value);
+        self.locked(|registers| registers.set_level(pin_number, level))
+    }
+
+    /// Changes are polled, so any edge is accepted.
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        self.registers.locate(pin_number)?;
+        let mut edges = self.edges.lock().unwrap();
+        match edge {
+            Some(edge) => edges.insert(pin_number, edge),
+            None => edges.remove(&pin_number),
+        };
+        Ok(())
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        self.registers.locate(pin_number)?;
+        Ok(self.edges.lock().unwrap().get(&pin_number).copied())
+    }
+
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()> {
+        let pull = match bias {
+            Bias::Disabled => 0,
+            Bias::PullUp => 1,
+            Bias::PullDown => 2,
+        };
+        self.locked(|registers| registers.set_allwinner_field(pin_number, ALLWINNER_PULL, pull))
+    }
+
+    async fn set_drive_strength(&self, pin_number: u8, milliamps: u8) -> Result<()> {
+        let level = drive_level(milliamps)? as u32;
+        self.locked(|registers| registers.set_allwinner_field(pin_number, ALLWINNER_DRIVE, level))
+    }
+
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        self.registers.locate(pin_number)?;
+        let mut pins = self.active_low.lock().unwrap();
+        if active_low {
+            pins.insert(pin_number);
+        } else {
+            pins.remove(&pin_number);
+        }
+        Ok(())
+    }
+
+    /// Poll the data register of the pin, yielding when its level changes.
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        let registers = self.registers.clone();
+        let level = registers.level(pin_number)?;
+
+        Ok(Box::pin(futures::stream::unfold(
+            (registers, None, level),
+            move |(registers, interval, mut level)| async move {
+                let mut interval: time::Interval = interval.unwrap_or_else(|| {
+                    let mut interval = time::interval(WATCH_POLL_INTERVAL);
+                    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                    interval
+                });
+                let change = loop {
+                    interval.tick().await;
+                    match registers.level(pin_number) {
+                        Ok(current) if current == level => {}
+                        Ok(current) => {
+                            level = current;
+                            break Ok(());
+                        }
+                        Err(e) => break Err(e),
+                    }
+                };
+                Some((change, (registers, Some(interval), level)))
+            },
+        )))
+    }
+}
@@ -0,0 +1,494 @@
+//
+// This file provides an in-memory backend to test GPIO logic without any pin.
+// Each exported pin holds its level and a watch channel notifying its changes, so changes
+// can be driven from the test and picked up by a GpioWatcher like real edges.
+// Like the kernel, an input pin only notifies the edges it's configured to detect.
+// It's available with the `mock` feature, so downstream crates can test their GPIO logic
+// on machines without GPIO pins, e.g. in CI.
+//
+// The fake pin constructors (e.g. `GpioPin::new_fake_input`) use a backend shared by the
+// whole process, driven with [set_value] and checked with [get_value].
+//
+// An output pin can be wired to input pins like with jumpers, so the levels it drives are
+// seen by the inputs and their watchers, to test both ends of a protocol in software.
+//
+// Faults can be injected into the operations of the backend, e.g. a write failing with EACCES
+// or a slow read, to exercise the error handling and retries of the code using the pins.
+//
+// The writes to the pins can be recorded, even those leaving the level unchanged,
+// to check the waveforms generated by the code driving the outputs.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use super::recorder::Recorder;
+use super::sysfs;
+use super::watcher::GpioEvent;
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::sync::watch;
+use tokio_stream::{StreamExt, wrappers::WatchStream};
+
+/// Backend simulating the pins in memory.
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    pins: Mutex<HashMap<u8, MockPin>>,
+    latency: Mutex<Duration>,
+    /// Input pins wired to each output pin
+    loopbacks: Mutex<HashMap<u8, HashSet<u8>>>,
+    faults: Mutex<Vec<InjectedFault>>,
+    write_recorders: Mutex<Vec<Recorder>>,
+}
+
+/// Operation of a [MockBackend] a fault can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockOperation {
+    /// Exports, as an input or an output, and direction changes
+    Export,
+    /// Unexports
+    Unexport,
+    /// Reads of the value
+    Read,
+    /// Writes of the value
+    Write,
+    /// Edge, bias, drive strength and active-low settings
+    Configure,
+    /// Watches of the changes, which can't be delayed
+    Watch,
+}
+
+/// Fault injected into an operation of a [MockBackend].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// Fail with [GpioError::PermissionDenied], like EACCES on a sysfs file
+    PermissionDenied,
+    /// Fail with [GpioError::NotExported], like ENOENT on a sysfs file
+    NotFound,
+    /// Fail with [GpioError::ExportFailed]
+    ExportFailed,
+    /// Fail with the I/O error of an errno, e.g. `libc::EBUSY`
+    Errno(i32),
+    /// Run the operation after a delay
+    Delay(Duration),
+}
+
+impl Fault {
+    /// Get the error of the fault, `None` for a delay.
+    fn error(self, pin_number: u8) -> Option<GpioError> {
+        match self {
+            Self::PermissionDenied => Some(GpioError::PermissionDenied(io::Error::from(
+                io::ErrorKind::PermissionDenied,
+            ))),
+            Self::NotFound => Some(GpioError::NotExported(pin_number)),
+            Self::ExportFailed => Some(GpioError::ExportFailed(format!(
+                "Injected export failure of pin {}",
+                pin_number
+            ))),
+            Self::Errno(errno) => Some(io::Error::from_raw_os_error(errno).into()),
+            Self::Delay(_) => None,
+        }
+    }
+}
+
+/// Fault injected with [MockBackend::inject_fault].
+#[derive(Debug)]
+struct InjectedFault {
+    pin_number: Option<u8>,
+    operation: MockOperation,
+    fault: Fault,
+    remaining: Option<usize>,
+}
+
+/// Simulated pin, its level is the value seen on the wire.
+#[derive(Debug)]
+struct MockPin {
+    level: u8,
+    /// Notified on the changes watched, every write for an output and the edges for an input
+    events: watch::Sender<()>,
+    direction: Direction,
+    active_low: bool,
+    bias: Bias,
+    drive_strength: Option<u8>,
+    edge: Option<Edge>,
+    stuck: bool,
+}
+
+impl MockPin {
+    /// Convert between the level of the pin and the value read or written by the pin.
+    fn logical(&self, value: u8) -> u8 {
+        if self.active_low { value ^ 1 } else { value }
+    }
+
+    /// Set the level seen on the wire, notifying the watchers if the pin detects the change.
+    /// An output notifies every level it drives, an input the edges it's configured for.
+    fn set_level(&mut self, level: u8) {
+        let changed = std::mem::replace(&mut self.level, level) != level;
+        let detected = match (self.direction, self.edge) {
+            (Direction::Out, _) => true,
+            (Direction::In, Some(Edge::Both)) => changed,
+            (Direction::In, Some(Edge::Rising)) => changed && self.logical(level) == 1,
+            (Direction::In, Some(Edge::Falling)) => changed && self.logical(level) == 0,
+            (Direction::In, None) => false,
+        };
+        if detected {
+            self.events.send_replace(());
+        }
+    }
+}
+
+impl MockBackend {
+    /// Create a new backend without any exported pin.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Simulate an external signal changing the level of an exported pin.
+    /// Setting the level the pin already has notifies no change, like a real line.
+    pub fn set_value(&self, pin_number: u8, value: u8) -> Result<()> {
+        check_level(value)?;
+        self.with_pin(pin_number, |pin| {
+            if pin.level != value {
+                pin.set_level(value);
+            }
+        })
+    }
+
+    /// Simulate a pin stuck at a level, e.g. shorted or muxed to another function,
+    /// so writes don't change it, or release it with `None`.
+    pub fn set_stuck(&self, pin_number: u8, level: Option<u8>) -> Result<()> {
+        level.map(check_level).transpose()?;
+        self.with_pin(pin_number, |pin| {
+            pin.stuck = level.is_some();
+            if let Some(level) = level {
+                pin.set_level(level);
+            }
+        })
+    }
+
+    /// Simulate slow reads and writes, e.g. a hung sysfs mount, taking the given time.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.latency.lock().unwrap() = latency;
+    }
+
+    /// Make an operation fail or be delayed, on a pin or on any pin with `None`,
+    /// the next `times` times or until the faults are cleared with `None`.
+    /// The first fault injected matching an operation applies, a fault injected 0 times never does.
+    pub fn inject_fault(
+        &self,
+        pin_number: Option<u8>,
+        operation: MockOperation,
+        fault: Fault,
+        times: Option<usize>,
+    ) {
+        if times == Some(0) {
+            return;
+        }
+        self.faults.lock().unwrap().push(InjectedFault {
+            pin_number,
+            operation,
+            fault,
+            remaining: times,
+        });
+    }
+
+    /// Remove all the injected faults.
+    pub fn clear_faults(&self) {
+        self.faults.lock().unwrap().clear();
+    }
+
+    /// Take the fault injected into an operation on a pin, if any.
+    fn take_fault(&self, pin_number: u8, operation: MockOperation) -> Option<Fault> {
+        let mut faults = self.faults.lock().unwrap();
+        let index = faults.iter().position(|injected| {
+            injected.operation == operation
+                && injected.pin_number.is_none_or(|pin| pin == pin_number)
+        })?;
+        let injected = &mut faults[index];
+        let fault = injected.fault;
+        if let Some(remaining) = &mut injected.remaining {
+            *remaining -= 1;
+            if *remaining == 0 {
+                faults.remove(index);
+            }
+        }
+        Some(fault)
+    }
+
+    /// Apply the fault injected into an operation on a pin, failing or waiting.
+    async fn inject(&self, pin_number: u8, operation: MockOperation) -> Result<()> {
+        match self.take_fault(pin_number, operation) {
+            Some(Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+            Some(fault) => return Err(fault.error(pin_number).unwrap()),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Wait for the simulated latency of the reads and writes.
+    async fn delay(&self) {
+        let latency = *self.latency.lock().unwrap();
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+    }
+
+    /// Record every write to the pins into a recorder, with the level driven on the wire,
+    /// including the writes leaving the level unchanged.
+    pub fn record_writes(&self, recorder: &Recorder) {
+        self.write_recorders.lock().unwrap().push(recorder.clone());
+    }
+
+    /// Wire an output pin to an input pin, so the input sees the levels driven by the output.
+    /// The wire is kept when the pins are exported again, and applies while the output
+    /// pin is exported as an output and the input pin as an input.
+    pub fn connect(&self, output: u8, input: u8) -> Result<()> {
+        if output == input {
+            return Err(GpioError::InvalidValue(format!(
+                "Pin {} can't be wired to itself",
+                output
+            )));
+        }
+        let mut pins = self.pins.lock().unwrap();
+        self.loopbacks
+            .lock()
+            .unwrap()
+            .entry(output)
+            .or_default()
+            .insert(input);
+        self.propagate(&mut pins, output);
+        Ok(())
+    }
+
+    /// Remove the wire between an output pin and an input pin, the input keeps its level.
+    pub fn disconnect(&self, output: u8, input: u8) {
+        if let Some(inputs) = self.loopbacks.lock().unwrap().get_mut(&output) {
+            inputs.remove(&input);
+        }
+    }
+
+    /// Drive the input pins wired to a pin at its level, if it's an output.
+    /// Inputs already at the level see no change, like on a real wire.
+    fn propagate(&self, pins: &mut HashMap<u8, MockPin>, output: u8) {
+        let level = match pins.get(&output) {
+            Some(pin) if pin.direction == Direction::Out => pin.level,
+            _ => return,
+        };
+        let loopbacks = self.loopbacks.lock().unwrap();
+        for input in loopbacks.get(&output).into_iter().flatten() {
+            if let Some(pin) = pins.get_mut(input)
+                && pin.direction == Direction::In
+                && !pin.stuck
+            {
+                pin.set_level(level);
+            }
+        }
+    }
+
+    /// Get the outputs wired to a pin.
+    fn wired_outputs(&self, input: u8) -> Vec<u8> {
+        let loopbacks = self.loopbacks.lock().unwrap();
+        loopbacks
+            .iter()
+            .filter(|(_, inputs)| inputs.contains(&input))
+            .map(|(output, _)| *output)
+            .collect()
+    }
+
+    /// Get the current level of an exported pin, e.g. to check what an output pin drives.
+    pub fn get_value(&self, pin_number: u8) -> Result<u8> {
+        self.with_pin(pin_number, |pin| pin.level)
+    }
+
+    /// Get the drive strength set on an exported pin in mA, `None` if never set.
+    pub fn get_drive_strength(&self, pin_number: u8) -> Result<Option<u8>> {
+        self.with_pin(pin_number, |pin| pin.drive_strength)
+    }
+
+    /// Run a function on an exported pin.
+    fn with_pin<T>(&self, pin_number: u8, f: impl FnOnce(&mut MockPin) -> T) -> Result<T> {
+        let mut pins = self.pins.lock().unwrap();
+        let pin = pins
+            .get_mut(&pin_number)
+            .ok_or(GpioError::NotExported(pin_number))?;
+        Ok(f(pin))
+    }
+}
+
+#[async_trait]
+impl GpioBackend for MockBackend {
+    /// A pin that stops driving the line keeps its level, unless a pull resistor sets it.
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        self.inject(pin_number, MockOperation::Export).await?;
+        let mut pins = self.pins.lock().unwrap();
+        let pin = pins.entry(pin_number).or_insert_with(|| MockPin {
+            level: 0,
+            events: watch::Sender::new(()),
+            direction: Direction::In,
+            active_low: false,
+            bias: Bias::Disabled,
+            drive_strength: None,
+            edge: None,
+            stuck: false,
+        });
+        pin.direction = Direction::In;
+        pin.active_low = false;
+        let level = match pin.bias {
+            Bias::PullUp => Some(1),
+            Bias::PullDown => Some(0),
+            Bias::Disabled => None,
+        };
+        if let Some(level) = level {
+            pin.set_level(level);
+        }
+        for output in self.wired_outputs(pin_number) {
+            self.propagate(&mut pins, output);
+        }
+        Ok(())
+    }
+
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        self.inject(pin_number, MockOperation::Export).await?;
+        let mut pins = self.pins.lock().unwrap();
+        let pin = pins.entry(pin_number).or_insert_with(|| MockPin {
+            level: default,
+            events: watch::Sender::new(()),
+            direction: Direction::Out,
+            active_low: false,
+            bias: Bias::Disabled,
+            drive_strength: None,
+            edge: None,
+            stuck: false,
+        });
+        pin.direction = Direction::Out;
+        pin.active_low = false;
+        pin.set_level(default);
+        self.propagate(&mut pins, pin_number);
+        Ok(())
+    }
+
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        self.with_pin(pin_number, |pin| pin.direction)
+    }
+
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        self.inject(pin_number, MockOperation::Unexport).await?;
+        match self.pins.lock().unwrap().remove(&pin_number) {
+            Some(_) => Ok(()),
+            None => Err(GpioError::NotExported(pin_number)),
+        }
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        self.inject(pin_number, MockOperation::Read).await?;
+        self.delay().await;
+        self.with_pin(pin_number, |pin| pin.logical(pin.level))
+    }
+
+    /// The inputs wired to the pin see the new level.
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        self.inject(pin_number, MockOperation::Write).await?;
+        self.delay().await;
+        let mut pins = self.pins.lock().unwrap();
+        let pin = pins
+            .get_mut(&pin_number)
+            .ok_or(GpioError::NotExported(pin_number))?;
+        let level = pin.logical(value);
+        if !pin.stuck {
+            pin.set_level(level);
+        }
+        self.propagate(&mut pins, pin_number);
+        let event = GpioEvent::new(pin_number, level.into(), tokio::time::Instant::now());
+        for recorder in self.write_recorders.lock().unwrap().iter() {
+            recorder.record(event);
+        }
+        Ok(())
+    }
+
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        self.inject(pin_number, MockOperation::Configure).await?;
+        self.with_pin(pin_number, |pin| pin.edge = edge)
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        self.with_pin(pin_number, |pin| pin.edge)
+    }
+
+    async fn exported(&self) -> Result<Vec<u8>> {
+        let mut pins: Vec<u8> = self.pins.lock().unwrap().keys().copied().collect();
+        pins.sort_unstable();
+        Ok(pins)
+    }
+
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()> {
+        self.inject(pin_number, MockOperation::Configure).await?;
+        self.with_pin(pin_number, |pin| pin.bias = bias)
+    }
+
+    /// The strengths are checked against the ones of the Allwinner SoCs, like the sysfs backend.
+    async fn set_drive_strength(&self, pin_number: u8, milliamps: u8) -> Result<()> {
+        sysfs::drive_level(milliamps)?;
+        self.inject(pin_number, MockOperation::Configure).await?;
+        self.with_pin(pin_number, |pin| pin.drive_strength = Some(milliamps))
+    }
+
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        self.inject(pin_number, MockOperation::Configure).await?;
+        self.with_pin(pin_number, |pin| pin.active_low = active_low)
+    }
+
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        if let Some(error) = self
+            .take_fault(pin_number, MockOperation::Watch)
+            .and_then(|fault| fault.error(pin_number))
+        {
+            return Err(error);
+        }
+        let receiver = self.with_pin(pin_number, |pin| pin.events.subscribe())?;
+        Ok(Box::pin(
+            WatchStream::from_changes(receiver).map(|_| Ok(())),
+        ))
+    }
+}
+
+/// Check that a level can be set on a pin.
+fn check_level(level: u8) -> Result<()> {
+    if level > 1 {
+        return Err(GpioError::InvalidValue(format!(
+            "Value must be 0 or 1, got {}",
+            level
+        )));
+    }
+    Ok(())
+}
+
+/// Get the backend shared by the fake pins.
+pub fn backend() -> Arc<MockBackend> {
+    static BACKEND: OnceLock<Arc<MockBackend>> = OnceLock::new();
+    BACKEND.get_or_init(Arc::default).clone()
+}
+
+/// Simulate an external signal changing the value of a fake pin.
+pub fn set_value(pin_number: u8, value: u8) -> Result<()> {
+    backend().set_value(pin_number, value)
+}
+
+/// Get the current value of a fake pin.
+pub fn get_value(pin_number: u8) -> Result<u8> {
+    backend().get_value(pin_number)
+}
+
+/// Wire a fake output pin to a fake input pin, see [MockBackend::connect].
+pub fn connect(output: u8, input: u8) -> Result<()> {
+    backend().connect(output, input)
+}
+
+/// Remove the wire between two fake pins, see [MockBackend::disconnect].
+pub fn disconnect(output: u8, input: u8) {
+    backend().disconnect(output, input)
+}
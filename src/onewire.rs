@@ -0,0 +1,205 @@
+//
+// This file provides a 1-Wire bus master over a single open-drain pin,
+// for devices like DS18B20 temperature sensors and iButtons.
+// Every transfer starts with a reset pulse, answered by the presence pulse of the devices,
+// then a ROM command selects one device (or all of them) before the device commands.
+//
+// The time slots of the protocol are a few microseconds long, far below the tokio timer
+// resolution, so the delays are busy waits blocking the thread for up to half a millisecond.
+// Changing the direction of a pin must also be fast enough, which in practice
+// requires the character device backend.
+//
+
+use super::error::{GpioError, Result};
+use super::opendrain::OpenDrain;
+use super::pin::{Bias, GpioPin};
+use std::time::{Duration, Instant};
+
+/// ROM command addressing a single device by its ROM code.
+const MATCH_ROM: u8 = 0x55;
+/// ROM command addressing every device on the bus.
+const SKIP_ROM: u8 = 0xcc;
+/// ROM command starting the search for the ROM codes of the devices.
+const SEARCH_ROM: u8 = 0xf0;
+
+/// 1-Wire bus master over a single pin.
+pub struct OneWireBus {
+    line: OpenDrain,
+}
+
+impl OneWireBus {
+    /// Create a bus on the given pin.
+    /// The internal pull-up resistor is enabled, an external 4.7k pull-up is still recommended.
+    pub async fn new(pin: GpioPin) -> Result<Self> {
+        let mut line = OpenDrain::new(pin);
+        line.set_bias(Bias::PullUp).await?;
+        line.release().await?;
+        Ok(Self { line })
+    }
+
+    /// Send a reset pulse and return whether a device answered with a presence pulse.
+    pub async fn reset(&mut self) -> Result<bool> {
+        self.line.drive().await?;
+        delay_us(480);
+        self.line.release().await?;
+        delay_us(70);
+        let presence = !self.line.read().await?;
+        delay_us(410);
+        Ok(presence)
+    }
+
+    /// Write a bit in its own time slot.
+    pub async fn write_bit(&mut self, bit: bool) -> Result<()> {
+        self.line.drive().await?;
+        if bit {
+            delay_us(6);
+            self.line.release().await?;
+            delay_us(64);
+        } else {
+            delay_us(60);
+            self.line.release().await?;
+            delay_us(10);
+        }
+        Ok(())
+    }
+
+    /// Read a bit in its own time slot.
+    pub async fn read_bit(&mut self) -> Result<bool> {
+        self.line.drive().await?;
+        delay_us(6);
+        self.line.release().await?;
+        delay_us(9);
+        let bit = self.line.read().await?;
+        delay_us(55);
+        Ok(bit)
+    }
+
+    /// Write a byte, least significant bit first.
+    pub async fn write_byte(&mut self, byte: u8) -> Result<()> {
+        for i in 0..8 {
+            self.write_bit(byte >> i & 1 == 1).await?;
+        }
+        Ok(())
+    }
+
+    /// Read a byte, least significant bit first.
+    pub async fn read_byte(&mut self) -> Result<u8> {
+        let mut byte = 0;
+        for i in 0..8 {
+            byte |= (self.read_bit().await? as u8) << i;
+        }
+        Ok(byte)
+    }
+
+    /// Reset the bus and select the device with the given ROM code for the next command.
+    pub async fn match_rom(&mut self, rom: u64) -> Result<()> {
+        self.reset_with_presence().await?;
+        self.write_byte(MATCH_ROM).await?;
+        for byte in rom.to_le_bytes() {
+            self.write_byte(byte).await?;
+        }
+        Ok(())
+    }
+
+    /// Reset the bus and select every device for the next command,
+    /// e.g. to start a temperature conversion on all the sensors at once.
+    pub async fn skip_rom(&mut self) -> Result<()> {
+        self.reset_with_presence().await?;
+        self.write_byte(SKIP_ROM).await
+    }
+
+    /// Find the ROM codes of all the devices on the bus.
+    /// The first byte of a ROM code (its least significant byte) is the family of the device,
+    /// e.g. 0x28 for a DS18B20.
+    pub async fn search(&mut self) -> Result<Vec<u64>> {
+        let mut roms = Vec::new();
+        let mut rom = 0u64;
+        let mut last_discrepancy = None;
+
+        loop {
+            if !self.reset().await? {
+                return Ok(roms);
+            }
+            self.write_byte(SEARCH_ROM).await?;
+
+            // Walk down the tree of ROM codes, taking the 1 branch at the last discrepancy
+            let mut discrepancy = None;
+            for i in 0..64 {
+                let bit = self.read_bit().await?;
+                let complement = self.read_bit().await?;
+                let direction = match (bit, complement) {
+                    (true, true) => return Ok(roms),
+                    (bit, complement) if bit != complement => bit,
+                    _ => {
+                        let direction = match last_discrepancy {
+                            Some(last) if i < last => rom >> i & 1 == 1,
+                            Some(last) => i == last,
+                            None => false,
+                        };
+                        if !direction {
+                            discrepancy = Some(i);
+                        }
+                        direction
+                    }
+                };
+
+                rom = rom & !(1 << i) | (direction as u64) << i;
+                self.write_bit(direction).await?;
+            }
+
+            let bytes = rom.to_le_bytes();
+            if crc8(&bytes[..7]) != bytes[7] {
+                return Err(GpioError::InvalidValue(format!(
+                    "CRC mismatch in ROM code {:016x}",
+                    rom
+                )));
+            }
+            roms.push(rom);
+
+            if discrepancy.is_none() {
+                return Ok(roms);
+            }
+            last_discrepancy = discrepancy;
+        }
+    }
+
+    /// Give the pin back.
+    pub fn into_pin(self) -> Result<GpioPin> {
+        self.line.into_pin()
+    }
+
+    /// Reset the bus and fail if no device answered.
+    async fn reset_with_presence(&mut self) -> Result<()> {
+        if !self.reset().await? {
+            return Err(GpioError::InvalidValue(
+                "No device answered the reset pulse".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Compute the Dallas/Maxim CRC-8 of the given bytes, as found in ROM codes and scratchpads.
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix == 1 {
+                crc ^= 0x8c;
+            }
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+/// Busy wait for the given number of microseconds, sleeping isn't precise enough.
+fn delay_us(us: u64) {
+    let deadline = Instant::now() + Duration::from_micros(us);
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
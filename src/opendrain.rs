@@ -0,0 +1,121 @@
+//
+// This file provides open-drain lines for the bit-banged buses sharing a wire between
+// several devices, like I2C and 1-Wire. A line is driven low by making its pin an output,
+// and released by making it an input so the pull-up resistor sets it high.
+// Open-source lines are the opposite, driven high and released to a pull-down resistor.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{Bias, GpioPin};
+
+/// Level a line is driven to, the other one being set by a pull resistor when released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputMode {
+    /// Drive the line low and release it high, e.g. for I2C and 1-Wire
+    #[default]
+    OpenDrain,
+    /// Drive the line high and release it low
+    OpenSource,
+}
+
+impl OutputMode {
+    /// Get the value the line is driven to.
+    fn driven(self) -> u8 {
+        match self {
+            Self::OpenDrain => 0,
+            Self::OpenSource => 1,
+        }
+    }
+}
+
+/// Line shared with other devices, either driven or released.
+/// The pin switches between an output driving the line and a high impedance input.
+#[derive(Debug)]
+pub struct OpenDrain {
+    pin_number: u8,
+    mode: OutputMode,
+    pin: Option<GpioPin>,
+}
+
+impl OpenDrain {
+    /// Wrap a pin as an open-drain line, left as it is until it's driven or released.
+    pub fn new(pin: GpioPin) -> Self {
+        Self::with_mode(pin, OutputMode::OpenDrain)
+    }
+
+    /// Wrap a pin as a line of the given mode, left as it is until it's driven or released.
+    pub fn with_mode(pin: GpioPin, mode: OutputMode) -> Self {
+        Self {
+            pin_number: pin.get_pin_number(),
+            mode,
+            pin: Some(pin),
+        }
+    }
+
+    /// Get the level the line is driven to.
+    pub fn mode(&self) -> OutputMode {
+        self.mode
+    }
+
+    /// Get the pin number of the line.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Stop driving the line, so the pull resistor sets its level.
+    pub async fn release(&mut self) -> Result<()> {
+        let pin = self.take()?;
+        self.pin = Some(pin.into_input().await?);
+        Ok(())
+    }
+
+    /// Drive the line, low if open-drain or high if open-source.
+    pub async fn drive(&mut self) -> Result<()> {
+        let pin = self.take()?;
+        self.pin = Some(pin.into_output(self.mode.driven()).await?);
+        Ok(())
+    }
+
+    /// Set the line to the given bit, driving it or releasing it depending on the mode.
+    pub async fn set(&mut self, bit: bool) -> Result<()> {
+        if bit as u8 == self.mode.driven() {
+            self.drive().await
+        } else {
+            self.release().await
+        }
+    }
+
+    /// Check if the pin drives the line.
+    pub fn is_driven(&self) -> bool {
+        matches!(self.pin, Some(GpioPin::Output(_)))
+    }
+
+    /// Read the level of the line.
+    pub async fn read(&self) -> Result<bool> {
+        Ok(self.pin()?.read().await? == 1)
+    }
+
+    /// Configure the pull resistors of the pin, setting the level of the released line.
+    pub async fn set_bias(&self, bias: Bias) -> Result<()> {
+        self.pin()?.set_bias(bias).await
+    }
+
+    /// Give the pin back.
+    pub fn into_pin(mut self) -> Result<GpioPin> {
+        self.take()
+    }
+
+    /// Get the pin of the line.
+    fn pin(&self) -> Result<&GpioPin> {
+        self.pin
+            .as_ref()
+            .ok_or(GpioError::NotExported(self.pin_number))
+    }
+
+    /// Take the pin out of the line, a failed direction change leaves the line without pin.
+    fn take(&mut self) -> Result<GpioPin> {
+        self.pin
+            .take()
+            .ok_or(GpioError::NotExported(self.pin_number))
+    }
+}
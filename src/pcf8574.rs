@@ -0,0 +1,220 @@
+//
+// This file provides a driver for the PCF8574 and PCF8574A I2C port expanders, adding 8 lines
+// per chip, common on relay boards and on the backpacks of character LCDs. They only differ by
+// their addresses, 0x20 to 0x27 for the PCF8574 and 0x38 to 0x3F for the PCF8574A.
+//
+// The lines are quasi-bidirectional: a line written low is driven low, and a line written high
+// is only pulled up by a weak current source, so it can be pulled low from the outside and read
+// as an input. Relays and LEDs are therefore wired active low, the chip sinking their current.
+//
+// The chip pulls its INT pin low when an input changes, until the lines are read or written.
+// Wired to an input pin, it triggers reading the lines, and the changes are delivered as events
+// through a notifier, like with the MCP23017.
+//
+
+use super::error::{GpioError, Result};
+use super::i2c::I2cBus;
+use super::pin::InputPin;
+use super::pinlike::PinLike;
+use super::watcher::{self, Notifier};
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// Number of lines of a chip.
+const LINES: u8 = 8;
+
+struct State {
+    bus: Box<dyn I2cBus>,
+    /// Levels written to the lines, the inputs being written high
+    latch: u8,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("latch", &self.latch)
+            .finish_non_exhaustive()
+    }
+}
+
+/// PCF8574 or PCF8574A port expander on an I2C bus.
+#[derive(Debug)]
+pub struct Pcf8574 {
+    address: u8,
+    state: Mutex<State>,
+}
+
+/// Line of a [Pcf8574], used like a pin.
+#[derive(Debug, Clone)]
+pub struct Pcf8574Pin {
+    chip: Arc<Pcf8574>,
+    line: u8,
+}
+
+/// Watch of the inputs of a [Pcf8574] through its interrupt pin, stopped on drop.
+#[derive(Debug)]
+pub struct Pcf8574Watch {
+    task: JoinHandle<()>,
+}
+
+impl Pcf8574 {
+    /// Create a driver for the chip at the given address, depending on its model and its A0 to A2
+    /// pins, and release all its lines high, to be used as inputs.
+    pub async fn new(bus: impl I2cBus + 'static, address: u8) -> Result<Self> {
+        if !matches!(address, 0x20..=0x27 | 0x38..=0x3F) {
+            return Err(GpioError::InvalidValue(format!(
+                "PCF8574 address must be between 0x20 and 0x27, or 0x38 and 0x3F for the PCF8574A, got {:#04x}",
+                address
+            )));
+        }
+
+        let mut bus: Box<dyn I2cBus> = Box::new(bus);
+        bus.write(address, &[0xFF]).await?;
+        Ok(Self {
+            address,
+            state: Mutex::new(State { bus, latch: 0xFF }),
+        })
+    }
+
+    /// Get the I2C address of the chip.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Read the levels of all the lines, line 0 being the lowest bit.
+    /// Lines written low read low, whatever drives them.
+    pub async fn read_all(&self) -> Result<u8> {
+        let mut state = self.state.lock().await;
+        let mut levels = [0u8];
+        state.bus.read(self.address, &mut levels).await?;
+        Ok(levels[0])
+    }
+
+    /// Read the level of a line.
+    pub async fn read(&self, line: u8) -> Result<u8> {
+        check_line(line)?;
+        Ok(self.read_all().await? >> line & 1)
+    }
+
+    /// Write the values of all the lines at once, line 0 being the lowest bit.
+    /// The inputs must be written high.
+    pub async fn write_all(&self, values: u8) -> Result<()> {
+        let mut state = self.state.lock().await;
+        state.bus.write(self.address, &[values]).await?;
+        state.latch = values;
+        Ok(())
+    }
+
+    /// Write the value of a line, a line written high being usable as an input.
+    pub async fn write(&self, line: u8, value: u8) -> Result<()> {
+        let bit = check_line(line)?;
+        if value > 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Value must be 0 or 1, got {}",
+                value
+            )));
+        }
+
+        let mut state = self.state.lock().await;
+        let latch = if value == 1 {
+            state.latch | bit
+        } else {
+            state.latch & !bit
+        };
+        state.bus.write(self.address, &[latch]).await?;
+        state.latch = latch;
+        Ok(())
+    }
+
+    /// Make a line an input, by releasing it high.
+    pub async fn set_input(&self, line: u8) -> Result<()> {
+        self.write(line, 1).await
+    }
+
+    /// Get a handle to a single line, used like a pin.
+    pub fn pin(self: &Arc<Self>, line: u8) -> Result<Pcf8574Pin> {
+        check_line(line)?;
+        Ok(Pcf8574Pin {
+            chip: self.clone(),
+            line,
+        })
+    }
+
+    /// Watch the input lines of the mask, line 0 being the lowest bit, through the interrupt pin
+    /// of the chip wired to an input pin. The changes are delivered to the notifier as events
+    /// numbered by their line, until the returned watch is dropped.
+    pub async fn watch(
+        self: &Arc<Self>,
+        interrupt: InputPin,
+        lines: u8,
+        notifier: impl Into<Notifier>,
+    ) -> Result<Pcf8574Watch> {
+        let levels = self.read_all().await?;
+        let chip = self.clone();
+        let read = move || {
+            let chip = chip.clone();
+            async move { Ok(chip.read_all().await?.into()) }
+        };
+        let task = watcher::spawn_interrupt_watch(
+            "PCF8574",
+            interrupt,
+            lines.into(),
+            levels.into(),
+            notifier.into(),
+            read,
+        );
+        Ok(Pcf8574Watch { task })
+    }
+}
+
+impl Pcf8574Pin {
+    /// Get the line of the chip.
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    /// Make the line an input, by releasing it high.
+    pub async fn set_input(&self) -> Result<()> {
+        self.chip.set_input(self.line).await
+    }
+
+    /// Read the level of the line.
+    pub async fn read(&self) -> Result<u8> {
+        self.chip.read(self.line).await
+    }
+
+    /// Write the value of the line.
+    pub async fn write(&self, value: u8) -> Result<()> {
+        self.chip.write(self.line, value).await
+    }
+}
+
+#[async_trait]
+impl PinLike for Pcf8574Pin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        self.write(value).await
+    }
+}
+
+impl Drop for Pcf8574Watch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Check that a line is one of the chip, and get its bit.
+fn check_line(line: u8) -> Result<u8> {
+    if line >= LINES {
+        return Err(GpioError::InvalidValue(format!(
+            "PCF8574 line must be between 0 and {}, got {}",
+            LINES - 1,
+            line
+        )));
+    }
+    Ok(1 << line)
+}
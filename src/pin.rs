@@ -1,122 +1,788 @@
 //
 // This file provides a representation of a GPIO pin which can be either an input or an output.
-// It helps to ensure that the pin is properly initialized and exported to sysfs interface.
-// This module uses a combination of the `gpio` command for export operations and direct
-// sysfs interface for reading, writing, and mode operations.
+// It helps to ensure that the pin is properly initialized and exported.
+// Pins are created from a [Gpio] context, whose backend does the actual access to the pin.
 //
+// [InputPin] and [OutputPin] only expose the operations that make sense for their direction,
+// so misuse is caught at compile time. [GpioPin] wraps either of them when the direction
+// is only known at runtime, e.g. in heterogeneous collections.
+//
+// Waiting for an input pin to reach a value or for its next edge uses its changes
+// when watch is enabled, and polls it otherwise.
+//
+// With the `mock` feature, fake pins can be created to test GPIO logic without hardware.
+// With the `tracing` feature, exports, reads and writes are recorded as spans holding
+// the pin number and value, their duration being the latency of the access.
+//
+
+use super::backend::ChangeStream;
+use super::error::{GpioError, Result};
+use super::gpio::{Clock, Gpio};
+use super::sysfs;
+use super::watcher::GpioEvent;
+#[cfg(any(test, feature = "mock"))]
+use super::{gpio::GpioConfig, mock};
+use futures::future::join_all;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicU8, Ordering},
+    time::Duration,
+};
+#[cfg(test)]
+use tokio::fs;
+use tokio::time::{self, Instant};
+use tokio_stream::StreamExt;
+
+/// Time between two reads of a pin waiting for a value without watch.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Internal pull resistor configuration of a pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Bias {
+    /// Enable the internal pull-up resistor
+    PullUp,
+    /// Enable the internal pull-down resistor
+    PullDown,
+    /// Disable the internal pull resistors (tri-state)
+    Disabled,
+}
+
+impl Bias {
+    /// Get the mode name used by the `gpio mode` command.
+    pub(crate) fn as_mode(&self) -> &'static str {
+        match self {
+            Self::PullUp => "up",
+            Self::PullDown => "down",
+            Self::Disabled => "tri",
+        }
+    }
+}
+
+/// Direction of a pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Direction {
+    /// Input pin, reading the line
+    In,
+    /// Output pin, driving the line
+    Out,
+}
 
-use anyhow::{Context, Result, bail};
-use std::env;
-use tokio::{fs, process::Command};
+/// Signal edges that trigger a notification on a watched input pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Edge {
+    /// Notify when the value goes from 0 to 1
+    Rising,
+    /// Notify when the value goes from 1 to 0
+    Falling,
+    /// Notify on any value change
+    Both,
+}
+
+impl Edge {
+    /// Get the edge name used by the `gpio edge` command.
+    pub(crate) fn as_edge(&self) -> &'static str {
+        match self {
+            Self::Rising => "rising",
+            Self::Falling => "falling",
+            Self::Both => "both",
+        }
+    }
+}
+
+/// Logical level of a pin, the 0 and 1 values of the pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Level {
+    /// Value 0
+    Low,
+    /// Value 1
+    High,
+}
+
+impl Level {
+    /// Get the edge leading to this level, [Edge::Rising] for high and [Edge::Falling] for low.
+    pub fn edge(self) -> Edge {
+        match self {
+            Self::Low => Edge::Falling,
+            Self::High => Edge::Rising,
+        }
+    }
+}
+
+impl From<u8> for Level {
+    /// Any non-zero value is high.
+    fn from(value: u8) -> Self {
+        if value == 0 { Self::Low } else { Self::High }
+    }
+}
+
+impl From<Level> for u8 {
+    fn from(level: Level) -> Self {
+        level as u8
+    }
+}
+
+impl From<bool> for Level {
+    fn from(high: bool) -> Self {
+        if high { Self::High } else { Self::Low }
+    }
+}
+
+/// Represents a GPIO pin configured as an input.
+/// Use [InputPin::new] to ensure the pin is properly initialized.
+/// Pins are compared and hashed by their pin number.
+#[derive(Debug)]
+pub struct InputPin {
+    pin_number: u8,
+    edge: Option<Edge>,
+    active_low: bool,
+    gpio: Gpio,
+}
+
+/// Represents a GPIO pin configured as an output.
+/// Use [OutputPin::new] to ensure the pin is properly initialized.
+/// Pins are compared and hashed by their pin number.
+#[derive(Debug)]
+pub struct OutputPin {
+    pin_number: u8,
+    gpio: Gpio,
+    active_low: bool,
+    last_value: AtomicU8,
+}
 
 /// Represents a GPIO pin which can either be an input or an output but not both.
+/// This is a type-erased wrapper around [InputPin] and [OutputPin].
 /// Creating [GpioPin] directly is not recommended, use [GpioPin::new_input] or
 /// [GpioPin::new_output] instead to ensure the pin is properly initialized.
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub enum GpioPin {
-    Input { pin_number: u8, support_watch: bool },
-    Output { pin_number: u8 },
+    Input(InputPin),
+    Output(OutputPin),
 }
 
-impl GpioPin {
+impl InputPin {
     /// Initialize a new input pin
-    pub async fn new_input(pin_number: u8) -> Result<Self> {
-        // If watch support is disabled, call export
-        let output = Command::new("gpio")
-            .args(["export", &pin_number.to_string(), "in"])
-            .output()
-            .await
-            .context("Failed to export the pin with gpio command")?;
-        if !output.status.success() {
-            let error_message = String::from_utf8_lossy(&output.stderr);
-            bail!("Failed to export the input pin: {}", error_message);
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(gpio), err)
+    )]
+    pub async fn new(gpio: &Gpio, pin_number: u8) -> Result<Self> {
+        gpio.retry(pin_number, || gpio.backend().export_input(pin_number))
+            .await?;
+
+        Ok(Self {
+            pin_number,
+            edge: None,
+            active_low: false,
+            gpio: gpio.clone(),
+        })
+    }
+
+    /// Enable edge notification for the pin on the given edge.
+    /// After calling this, [InputPin::support_watch] will return true.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), fields(pin = self.pin_number), err))]
+    pub async fn enable_watch(&mut self, edge: Edge) -> Result<()> {
+        self.gpio
+            .backend()
+            .set_edge(self.pin_number, Some(edge))
+            .await?;
+        self.edge = Some(edge);
+        Ok(())
+    }
+
+    /// Disable edge notification for the pin.
+    /// After calling this, [InputPin::support_watch] will return false
+    /// and the pin can be handed to a consumer that doesn't watch it.
+    pub async fn disable_watch(&mut self) -> Result<()> {
+        self.gpio.backend().set_edge(self.pin_number, None).await?;
+        self.edge = None;
+        Ok(())
+    }
+
+    /// Check if the pin supports watch.
+    /// Calling [InputPin::enable_watch] will enable watch support.
+    pub fn support_watch(&self) -> bool {
+        self.edge.is_some()
+    }
+
+    /// Get the edge the pin is watched on, if watch is enabled.
+    pub fn edge(&self) -> Option<Edge> {
+        self.edge
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Get the clock of the context the pin was created from.
+    pub(crate) fn clock(&self) -> Clock {
+        self.gpio.config().clock
+    }
+
+    /// Get the sysfs path to the value of the pin.
+    /// This does NOT guarantee that the pin is exported nor that the path exists.
+    pub fn get_value_path(&self) -> String {
+        value_path(&self.gpio, self.pin_number)
+    }
+
+    /// Read the value from the pin.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), fields(pin = self.pin_number), ret, err))]
+    pub async fn read(&self) -> Result<u8> {
+        let result = self
+            .gpio
+            .timeout(
+                self.pin_number,
+                "Read",
+                self.gpio.backend().read(self.pin_number),
+            )
+            .await;
+        self.gpio
+            .metrics()
+            .record_access(self.pin_number, &result, |m| &mut m.reads);
+        result
+    }
+
+    /// Configure the internal pull resistors of the pin,
+    /// e.g. to read a button without external resistors.
+    /// The sysfs interface has no bias setting, so the sysfs backend runs the mode subcommand
+    /// of the `gpio` command. The character device backend requests the line again with the bias
+    /// flags, and the memory-mapped backend writes the pull registers of the SoC.
+    /// Backends without pull resistors fail with [GpioError::Unsupported].
+    pub async fn set_bias(&self, bias: Bias) -> Result<()> {
+        self.gpio.backend().set_bias(self.pin_number, bias).await
+    }
+
+    /// Invert the logic of the pin, so reading 1 means the line is low.
+    /// This is useful for buttons pulling the line to ground when pressed.
+    pub async fn set_active_low(&mut self, active_low: bool) -> Result<()> {
+        self.gpio
+            .backend()
+            .set_active_low(self.pin_number, active_low)
+            .await?;
+        self.active_low = active_low;
+        Ok(())
+    }
+
+    /// Check if the logic of the pin is inverted.
+    pub fn is_active_low(&self) -> bool {
+        self.active_low
+    }
+
+    /// Turn the pin into an output pin driving the given value, without unexporting it.
+    /// The active-low setting is kept, edge notification is not.
+    pub async fn into_output(self, default: u8) -> Result<OutputPin> {
+        if default != 0 && default != 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Default value must be 0 or 1, got {}",
+                default
+            )));
+        }
+
+        // Drive the physical level of the default value, so the line doesn't glitch
+        let level = default ^ self.active_low as u8;
+        let backend = self.gpio.backend();
+        backend.set_output(self.pin_number, level).await?;
+        if self.active_low {
+            backend.set_active_low(self.pin_number, true).await?;
+        }
+
+        Ok(OutputPin {
+            pin_number: self.pin_number,
+            gpio: self.gpio,
+            active_low: self.active_low,
+            last_value: AtomicU8::new(default),
+        })
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), fields(pin = self.pin_number), err))]
+    pub async fn release(self) -> Result<()> {
+        self.gpio.backend().unexport(self.pin_number).await
+    }
+
+    /// Wait for the pin to have the given value, e.g. until a limit switch closes.
+    /// Returns whether it had the value before the timeout.
+    /// The changes of the pin are used if watch is enabled, otherwise the pin is polled.
+    pub async fn wait_for_value(&self, value: u8, timeout: Duration) -> Result<bool> {
+        if value != 0 && value != 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Value must be 0 or 1, got {}",
+                value
+            )));
+        }
+
+        match time::timeout(timeout, self.wait_until(value)).await {
+            Ok(result) => result.map(|()| true),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Wait for the next edge of the pin, e.g. the press of a button, and get its event.
+    /// Waiting for [Edge::Both] returns on either edge.
+    /// The changes of the pin are used if watch is enabled, otherwise the pin is polled,
+    /// which misses pulses shorter than the polling interval.
+    pub async fn wait_for_edge(&self, edge: Edge) -> Result<GpioEvent> {
+        let mut changes = self.changes().ok();
+        let mut level = self.read().await?;
+        loop {
+            self.next_change(&mut changes).await;
+            let now = Instant::now();
+            let value = self.read().await?;
+            if value != level {
+                level = value;
+                let event = GpioEvent::new(self.pin_number, value.into(), now);
+                if edge == Edge::Both || edge == event.edge {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+
+    /// Wait for the pin to have the given value, without timeout.
+    async fn wait_until(&self, value: u8) -> Result<()> {
+        // Subscribe before the first read so a change right after it isn't missed
+        let mut changes = self.changes().ok();
+        while self.read().await? != value {
+            self.next_change(&mut changes).await;
+        }
+        Ok(())
+    }
+
+    /// Wait for the next change of the pin, or the next poll without changes.
+    /// The changes are dropped once they end, falling back to polling.
+    async fn next_change(&self, changes: &mut Option<ChangeStream>) {
+        match changes {
+            Some(stream) => match stream.next().await {
+                Some(Ok(())) => {}
+                Some(Err(e)) => log::warn!("Error watching pin {}: {}", self.pin_number, e),
+                None => *changes = None,
+            },
+            None => time::sleep(WAIT_POLL_INTERVAL).await,
+        }
+    }
+
+    /// Get a stream of the changes of the pin's value.
+    /// Watch must be enabled for the pin.
+    pub(crate) fn changes(&self) -> Result<ChangeStream> {
+        if !self.support_watch() {
+            return Err(GpioError::WatchUnsupported(self.pin_number));
         }
 
-        Ok(Self::Input {
+        self.gpio.backend().watch(self.pin_number)
+    }
+
+    /// Initialize a **FAKE** input pin with watch enabled.
+    /// The pin lives in the shared [mock] backend, drive its value with [mock::set_value].
+    /// Only used for testing on devices without actual GPIO pins.
+    #[cfg(any(test, feature = "mock"))]
+    pub async fn new_fake(pin_number: u8) -> Result<Self> {
+        let mut pin = Self::new(&fake_gpio(), pin_number).await?;
+        pin.enable_watch(Edge::Both).await?;
+        Ok(pin)
+    }
+
+    #[cfg(test)]
+    /// Initialize a **FAKE** input pin backed by files under the sysfs root of the context.
+    /// Only used for testing the sysfs backend.
+    pub(crate) async fn new_fake_sysfs(gpio: &Gpio, pin_number: u8) -> Result<Self> {
+        let pin_dir = gpio.config().sysfs_root.join(format!("gpio{}", pin_number));
+
+        println!(
+            "Creating fake input pin {} at {}",
             pin_number,
-            support_watch: false,
+            pin_dir.display()
+        );
+
+        // Create a new directory and some files to simulate the pin export
+        fs::create_dir_all(&pin_dir).await?;
+
+        // set the pin as input
+        fs::write(pin_dir.join("direction"), "in".as_bytes()).await?;
+
+        // set the pin as down
+        fs::write(pin_dir.join("value"), "0".as_bytes()).await?;
+
+        Ok(Self {
+            pin_number,
+            edge: Some(Edge::Both),
+            active_low: false,
+            gpio: gpio.clone(),
         })
     }
+}
 
+impl OutputPin {
     /// Initialize a new output pin
-    pub async fn new_output(pin_number: u8, default: u8) -> Result<Self> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(gpio), err)
+    )]
+    pub async fn new(gpio: &Gpio, pin_number: u8, default: u8) -> Result<Self> {
         if default != 0 && default != 1 {
-            bail!("Default value must be 0 or 1, got {}", default);
+            return Err(GpioError::InvalidValue(format!(
+                "Default value must be 0 or 1, got {}",
+                default
+            )));
         }
 
-        // Export the pin
-        let output = Command::new("gpio")
-            .args(["export", &pin_number.to_string(), "out"])
-            .output()
-            .await
-            .context("Failed to export the pin with gpio command")?;
-        if !output.status.success() {
-            let error_message = String::from_utf8_lossy(&output.stderr);
-            bail!("Failed to export the output pin: {}", error_message);
+        gpio.retry(pin_number, || {
+            gpio.backend().export_output(pin_number, default)
+        })
+        .await?;
+
+        Ok(Self {
+            pin_number,
+            gpio: gpio.clone(),
+            active_low: false,
+            last_value: AtomicU8::new(default),
+        })
+    }
+
+    /// Initialize a new active-low output pin, e.g. for a relay board switching on a low level.
+    /// The default value is the logical value, so a default of 0 keeps the line high.
+    /// The line is exported at the right level, so it doesn't glitch while being configured.
+    pub async fn new_active_low(gpio: &Gpio, pin_number: u8, default: u8) -> Result<Self> {
+        let physical = match default {
+            0 => 1,
+            1 => 0,
+            other => other,
+        };
+        let mut pin = Self::new(gpio, pin_number, physical).await?;
+        pin.gpio.backend().set_active_low(pin_number, true).await?;
+        pin.active_low = true;
+        pin.last_value.store(default, Ordering::Relaxed);
+        Ok(pin)
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Get the clock of the context the pin was created from.
+    pub(crate) fn clock(&self) -> Clock {
+        self.gpio.config().clock
+    }
+
+    /// Get the sysfs path to the value of the pin.
+    /// This does NOT guarantee that the pin is exported nor that the path exists.
+    pub fn get_value_path(&self) -> String {
+        value_path(&self.gpio, self.pin_number)
+    }
+
+    /// Write a value to the pin.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), fields(pin = self.pin_number), err))]
+    pub async fn write(&self, value: u8) -> Result<()> {
+        // Check if the value is valid
+        if value != 0 && value != 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Value must be 0 or 1, got {}",
+                value
+            )));
         }
 
-        // Set the default value
-        let gpio_dir = env::var("GPIO_DIR").context("GPIO_DIR environment variable not set")?;
-        let value_path = format!("{}/gpio{}/value", gpio_dir, pin_number);
-        fs::write(&value_path, default.to_string())
+        let write = self.gpio.retry(self.pin_number, || {
+            self.gpio.backend().write(self.pin_number, value)
+        });
+        let result = self.gpio.timeout(self.pin_number, "Write", write).await;
+        self.gpio
+            .metrics()
+            .record_access(self.pin_number, &result, |m| &mut m.writes);
+        result?;
+        self.last_value.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Write a value to the pin and read it back, failing if the pin doesn't have it,
+    /// e.g. because it's actually configured as an input or muxed to another function.
+    pub async fn write_verified(&self, value: u8) -> Result<()> {
+        self.write(value).await?;
+        let actual = self.read().await?;
+        if actual != value {
+            return Err(GpioError::VerifyFailed {
+                pin: self.pin_number,
+                expected: value,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Write a value to the pin only if it differs from the last written value.
+    /// Returns whether the value was written.
+    pub async fn write_if_changed(&self, value: u8) -> Result<bool> {
+        if value == self.last_value() {
+            return Ok(false);
+        }
+        self.write(value).await?;
+        Ok(true)
+    }
+
+    /// Get the last value written to the pin, or its default value if nothing was written yet.
+    /// Unlike [OutputPin::read], this doesn't access the pin.
+    pub fn last_value(&self) -> u8 {
+        self.last_value.load(Ordering::Relaxed)
+    }
+
+    /// Remember a value written to the pin without this handle, e.g. by a [FastPin](crate::fast::FastPin).
+    pub(crate) fn set_last_value(&self, value: u8) {
+        self.last_value.store(value, Ordering::Relaxed);
+    }
+
+    /// Invert the last written value of the pin, e.g. to blink an LED.
+    /// Returns the new value of the pin.
+    pub async fn toggle(&self) -> Result<u8> {
+        let value = if self.last_value() == 0 { 1 } else { 0 };
+        self.write(value).await?;
+        Ok(value)
+    }
+
+    /// Read the current value of the pin.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self), fields(pin = self.pin_number), ret, err))]
+    pub async fn read(&self) -> Result<u8> {
+        let result = self
+            .gpio
+            .timeout(
+                self.pin_number,
+                "Read",
+                self.gpio.backend().read(self.pin_number),
+            )
+            .await;
+        self.gpio
+            .metrics()
+            .record_access(self.pin_number, &result, |m| &mut m.reads);
+        result
+    }
+
+    /// Configure the internal pull resistors of the pin, setting the level of the line once
+    /// it's released, e.g. turned into an input. See [InputPin::set_bias] for the backends.
+    pub async fn set_bias(&self, bias: Bias) -> Result<()> {
+        self.gpio.backend().set_bias(self.pin_number, bias).await
+    }
+
+    /// Set the current the pin can drive in mA, where the SoC supports it, e.g. to drive
+    /// long cables or several LEDs. The strength is rounded up to one the SoC supports.
+    pub async fn set_drive_strength(&self, milliamps: u8) -> Result<()> {
+        self.gpio
+            .backend()
+            .set_drive_strength(self.pin_number, milliamps)
             .await
-            .context("Failed to set the pin default value")?;
+    }
 
-        Ok(Self::Output { pin_number })
+    /// Invert the logic of the pin, so writing 1 drives the line low.
+    /// The last written value is written again, so the pin keeps its logical value.
+    /// Use [OutputPin::new_active_low] to avoid driving the line while it's configured.
+    pub async fn set_active_low(&mut self, active_low: bool) -> Result<()> {
+        self.gpio
+            .backend()
+            .set_active_low(self.pin_number, active_low)
+            .await?;
+        self.active_low = active_low;
+        self.write(self.last_value()).await
     }
 
-    /// Enable edge notification for the pin.
+    /// Check if the logic of the pin is inverted.
+    pub fn is_active_low(&self) -> bool {
+        self.active_low
+    }
+
+    /// Turn the pin into an input pin, without unexporting it.
+    /// The pin stops driving the line, e.g. to let a pull-up resistor set its level.
+    /// The active-low setting is kept.
+    pub async fn into_input(self) -> Result<InputPin> {
+        let backend = self.gpio.backend();
+        backend.set_input(self.pin_number).await?;
+        if self.active_low {
+            backend.set_active_low(self.pin_number, true).await?;
+        }
+
+        Ok(InputPin {
+            pin_number: self.pin_number,
+            edge: None,
+            active_low: self.active_low,
+            gpio: self.gpio,
+        })
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self), fields(pin = self.pin_number), err))]
+    pub async fn release(self) -> Result<()> {
+        self.gpio.backend().unexport(self.pin_number).await
+    }
+
+    /// Initialize a **FAKE** output pin.
+    /// The pin lives in the shared [mock] backend, check its value with [mock::get_value].
+    /// Only used for testing on devices without actual GPIO pins.
+    #[cfg(any(test, feature = "mock"))]
+    pub async fn new_fake(pin_number: u8, default: u8) -> Result<Self> {
+        Self::new(&fake_gpio(), pin_number, default).await
+    }
+}
+
+impl PartialEq for InputPin {
+    fn eq(&self, other: &Self) -> bool {
+        self.pin_number == other.pin_number
+    }
+}
+
+impl Eq for InputPin {}
+
+impl Hash for InputPin {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pin_number.hash(state);
+    }
+}
+
+impl PartialEq for OutputPin {
+    fn eq(&self, other: &Self) -> bool {
+        self.pin_number == other.pin_number
+    }
+}
+
+impl Eq for OutputPin {}
+
+impl Hash for OutputPin {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pin_number.hash(state);
+    }
+}
+
+impl GpioPin {
+    /// Initialize a new input pin
+    pub async fn new_input(gpio: &Gpio, pin_number: u8) -> Result<Self> {
+        Ok(Self::Input(InputPin::new(gpio, pin_number).await?))
+    }
+
+    /// Initialize a new output pin
+    pub async fn new_output(gpio: &Gpio, pin_number: u8, default: u8) -> Result<Self> {
+        Ok(Self::Output(
+            OutputPin::new(gpio, pin_number, default).await?,
+        ))
+    }
+
+    /// Initialize a new input pin from its position on the header.
+    /// The position is translated with the pin map of the context.
+    pub async fn new_input_physical(gpio: &Gpio, physical: u8) -> Result<Self> {
+        let pin_number = gpio.config().pin_map.physical_to_soc(physical)?;
+        Self::new_input(gpio, pin_number).await
+    }
+
+    /// Initialize a new output pin from its position on the header.
+    /// The position is translated with the pin map of the context.
+    pub async fn new_output_physical(gpio: &Gpio, physical: u8, default: u8) -> Result<Self> {
+        let pin_number = gpio.config().pin_map.physical_to_soc(physical)?;
+        Self::new_output(gpio, pin_number, default).await
+    }
+
+    /// Take over a pin that is already exported, e.g. by an init script or another process,
+    /// as an input or output depending on its current direction, without exporting it again.
+    /// Edge notification and active-low settings are not picked up.
+    pub async fn adopt(gpio: &Gpio, pin_number: u8) -> Result<Self> {
+        let pin = match gpio.backend().direction(pin_number).await? {
+            Direction::In => Self::Input(InputPin {
+                pin_number,
+                edge: None,
+                active_low: false,
+                gpio: gpio.clone(),
+            }),
+            Direction::Out => {
+                let value = gpio.backend().read(pin_number).await?;
+                Self::Output(OutputPin {
+                    pin_number,
+                    gpio: gpio.clone(),
+                    active_low: false,
+                    last_value: AtomicU8::new(value),
+                })
+            }
+        };
+        Ok(pin)
+    }
+
+    /// Initialize many input pins at once, exporting them concurrently.
+    /// If any export fails, the pins already exported are released and the first error returned.
+    pub async fn new_inputs(gpio: &Gpio, pin_numbers: &[u8]) -> Result<Vec<Self>> {
+        check_unique(pin_numbers.iter().copied())?;
+        let results = join_all(
+            pin_numbers
+                .iter()
+                .map(|&pin_number| Self::new_input(gpio, pin_number)),
+        )
+        .await;
+        all_or_release(results).await
+    }
+
+    /// Initialize many output pins at once from their numbers and default values,
+    /// exporting them concurrently.
+    /// If any export fails, the pins already exported are released and the first error returned.
+    pub async fn new_outputs(gpio: &Gpio, pins: &[(u8, u8)]) -> Result<Vec<Self>> {
+        check_unique(pins.iter().map(|&(pin_number, _)| pin_number))?;
+        let results = join_all(
+            pins.iter()
+                .map(|&(pin_number, default)| Self::new_output(gpio, pin_number, default)),
+        )
+        .await;
+        all_or_release(results).await
+    }
+
+    /// Enable edge notification for the pin on the given edge.
     /// After calling this, [GpioPin::support_watch] will return true.
     /// Normally, edge command will automatically turn the pin into an input pin.
     /// To avoid confusion, this function is not allowed for output pins.
-    pub async fn enable_watch(&mut self) -> Result<()> {
-        // Call edge command
+    pub async fn enable_watch(&mut self, edge: Edge) -> Result<()> {
         match self {
-            Self::Input {
-                pin_number,
-                support_watch,
-            } => {
-                let output = Command::new("gpio")
-                    .args(["edge", &pin_number.to_string(), "both"])
-                    .output()
-                    .await
-                    .context("Failed to edge the pin with gpio command")?;
-                if output.status.success() {
-                    *support_watch = true;
-                    Ok(())
-                } else {
-                    let error_message = String::from_utf8_lossy(&output.stderr);
-                    bail!("Failed to edge the input pin: {}", error_message);
-                }
-            }
-            Self::Output { pin_number: _ } => {
-                bail!("Edge notification is not supported for output pins");
-            }
+            Self::Input(pin) => pin.enable_watch(edge).await,
+            Self::Output(pin) => Err(GpioError::WatchUnsupported(pin.get_pin_number())),
+        }
+    }
+
+    /// Disable edge notification for the pin.
+    /// After calling this, [GpioPin::support_watch] will return false.
+    pub async fn disable_watch(&mut self) -> Result<()> {
+        match self {
+            Self::Input(pin) => pin.disable_watch().await,
+            Self::Output(pin) => Err(GpioError::WatchUnsupported(pin.get_pin_number())),
         }
     }
 
-    /// Get the path to the value of the pin.
+    /// Get the sysfs path to the value of the pin.
     /// This does NOT guarantee that the pin is exported nor that the path exists.
     pub fn get_value_path(&self) -> String {
-        let gpio_dir = env::var("GPIO_DIR").expect("GPIO_DIR not set");
-
         match self {
-            Self::Input {
-                pin_number,
-                support_watch: _,
-            } => format!("{}/gpio{}/value", gpio_dir, pin_number),
-            Self::Output { pin_number } => format!("{}/gpio{}/value", gpio_dir, pin_number),
+            Self::Input(pin) => pin.get_value_path(),
+            Self::Output(pin) => pin.get_value_path(),
         }
     }
 
     /// Get the pin number of the pin.
     pub fn get_pin_number(&self) -> u8 {
         match self {
-            Self::Input {
-                pin_number,
-                support_watch: _,
-            } => *pin_number,
-            Self::Output { pin_number } => *pin_number,
+            Self::Input(pin) => pin.get_pin_number(),
+            Self::Output(pin) => pin.get_pin_number(),
         }
     }
 
@@ -125,73 +791,308 @@ impl GpioPin {
     /// Output pins always return false.
     pub fn support_watch(&self) -> bool {
         match self {
-            Self::Input {
-                pin_number: _,
-                support_watch,
-            } => *support_watch,
-            Self::Output { pin_number: _ } => false,
+            Self::Input(pin) => pin.support_watch(),
+            Self::Output(_) => false,
+        }
+    }
+
+    /// Get the edge the pin is watched on.
+    /// Output pins always return None.
+    pub fn edge(&self) -> Option<Edge> {
+        match self {
+            Self::Input(pin) => pin.edge(),
+            Self::Output(_) => None,
         }
     }
 
     /// Write a value to the pin.
+    /// Writing to an input pin is not allowed.
     pub async fn write(&self, value: u8) -> Result<()> {
-        // Check if the value is valid
-        if value != 0 && value != 1 {
-            bail!("Value must be 0 or 1");
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.write(value).await,
         }
+    }
 
-        // Write the value to the pin using sysfs interface
-        let value_path = self.get_value_path();
-        fs::write(&value_path, value.to_string())
-            .await
-            .context("Failed to write to the pin")?;
+    /// Write a value to the pin and read it back, failing if the pin doesn't have it.
+    /// Writing to an input pin is not allowed.
+    pub async fn write_verified(&self, value: u8) -> Result<()> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.write_verified(value).await,
+        }
+    }
 
-        Ok(())
+    /// Invert the value of the pin and return the new value.
+    /// Toggling an input pin is not allowed.
+    pub async fn toggle(&self) -> Result<u8> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.toggle().await,
+        }
+    }
+
+    /// Write a value to the pin only if it differs from the last written value.
+    /// Writing to an input pin is not allowed.
+    pub async fn write_if_changed(&self, value: u8) -> Result<bool> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.write_if_changed(value).await,
+        }
+    }
+
+    /// Get the last value written to the pin.
+    /// Input pins always return None.
+    pub fn last_value(&self) -> Option<u8> {
+        match self {
+            Self::Input(_) => None,
+            Self::Output(pin) => Some(pin.last_value()),
+        }
+    }
+
+    /// Get the context the pin was created from.
+    pub(crate) fn gpio(&self) -> &Gpio {
+        match self {
+            Self::Input(pin) => &pin.gpio,
+            Self::Output(pin) => &pin.gpio,
+        }
     }
 
     /// Read the value from the pin.
     pub async fn read(&self) -> Result<u8> {
-        // Read the value from the pin using sysfs interface
-        let value_path = self.get_value_path();
-        let content = fs::read_to_string(&value_path)
-            .await
-            .context("Failed to read from the pin")?;
+        match self {
+            Self::Input(pin) => pin.read().await,
+            Self::Output(pin) => pin.read().await,
+        }
+    }
 
-        let value = content
-            .trim()
-            .parse()
-            .context("Failed to parse the value from the pin")?;
-        Ok(value)
+    /// Wait for an input pin to have the given value, returning whether it had it
+    /// before the timeout. Waiting on an output pin is not allowed.
+    pub async fn wait_for_value(&self, value: u8, timeout: Duration) -> Result<bool> {
+        match self {
+            Self::Input(pin) => pin.wait_for_value(value, timeout).await,
+            Self::Output(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "input",
+            }),
+        }
     }
 
-    #[cfg(test)]
-    /// Initialize a **FAKE** input pin.
+    /// Wait for the next edge of an input pin and get its event.
+    /// Waiting on an output pin is not allowed.
+    pub async fn wait_for_edge(&self, edge: Edge) -> Result<GpioEvent> {
+        match self {
+            Self::Input(pin) => pin.wait_for_edge(edge).await,
+            Self::Output(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "input",
+            }),
+        }
+    }
+
+    /// Configure the internal pull resistors of the pin, see [InputPin::set_bias].
+    /// This is mostly useful for input pins, e.g. to read a button without external resistors.
+    pub async fn set_bias(&self, bias: Bias) -> Result<()> {
+        match self {
+            Self::Input(pin) => pin.set_bias(bias).await,
+            Self::Output(pin) => pin.set_bias(bias).await,
+        }
+    }
+
+    /// Set the current the pin can drive in mA, where the SoC supports it.
+    /// Setting the drive strength of an input pin is not allowed.
+    pub async fn set_drive_strength(&self, milliamps: u8) -> Result<()> {
+        match self {
+            Self::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            Self::Output(pin) => pin.set_drive_strength(milliamps).await,
+        }
+    }
+
+    /// Invert the logic of the pin, so 1 means the line is low.
+    pub async fn set_active_low(&mut self, active_low: bool) -> Result<()> {
+        match self {
+            Self::Input(pin) => pin.set_active_low(active_low).await,
+            Self::Output(pin) => pin.set_active_low(active_low).await,
+        }
+    }
+
+    /// Check if the logic of the pin is inverted.
+    pub fn is_active_low(&self) -> bool {
+        match self {
+            Self::Input(pin) => pin.is_active_low(),
+            Self::Output(pin) => pin.is_active_low(),
+        }
+    }
+
+    /// Turn the pin into an input pin, without unexporting it.
+    /// Input pins are returned as they are.
+    pub async fn into_input(self) -> Result<Self> {
+        match self {
+            Self::Input(pin) => Ok(Self::Input(pin)),
+            Self::Output(pin) => Ok(Self::Input(pin.into_input().await?)),
+        }
+    }
+
+    /// Turn the pin into an output pin driving the given value, without unexporting it.
+    /// Output pins are returned as they are, after writing the value.
+    pub async fn into_output(self, default: u8) -> Result<Self> {
+        match self {
+            Self::Input(pin) => Ok(Self::Output(pin.into_output(default).await?)),
+            Self::Output(pin) => {
+                pin.write(default).await?;
+                Ok(Self::Output(pin))
+            }
+        }
+    }
+
+    /// Unexport the pin and release it back to the system.
+    /// This consumes the pin so it can't be used afterwards.
+    /// Fails if the pin directory still exists after the unexport.
+    pub async fn release(self) -> Result<()> {
+        match self {
+            Self::Input(pin) => pin.release().await,
+            Self::Output(pin) => pin.release().await,
+        }
+    }
+
+    /// Get a stream of the changes of the pin's value.
+    /// Only input pins with watch enabled can be watched.
+    pub(crate) fn changes(&self) -> Result<ChangeStream> {
+        match self {
+            Self::Input(pin) => pin.changes(),
+            Self::Output(pin) => Err(GpioError::WatchUnsupported(pin.get_pin_number())),
+        }
+    }
+
+    /// Initialize a **FAKE** input pin with watch enabled.
+    /// The pin lives in the shared [mock] backend, drive its value with [mock::set_value].
     /// Only used for testing on devices without actual GPIO pins.
+    #[cfg(any(test, feature = "mock"))]
     pub async fn new_fake_input(pin_number: u8) -> Result<Self> {
-        let gpio_dir = env::var("GPIO_DIR").expect("GPIO_DIR not set");
+        Ok(Self::Input(InputPin::new_fake(pin_number).await?))
+    }
 
-        println!("Creating fake input pin {} at {}", pin_number, gpio_dir);
+    /// Initialize a **FAKE** output pin.
+    /// The pin lives in the shared [mock] backend, check its value with [mock::get_value].
+    /// Only used for testing on devices without actual GPIO pins.
+    #[cfg(any(test, feature = "mock"))]
+    pub async fn new_fake_output(pin_number: u8, default: u8) -> Result<Self> {
+        Ok(Self::Output(
+            OutputPin::new_fake(pin_number, default).await?,
+        ))
+    }
+}
 
-        // Create a new directory and some files to simulate the pin export
-        fs::create_dir_all(format!("{}/gpio{}", gpio_dir, pin_number)).await?;
+impl From<InputPin> for GpioPin {
+    fn from(pin: InputPin) -> Self {
+        Self::Input(pin)
+    }
+}
 
-        // set the pin as input
-        fs::write(
-            format!("{}/gpio{}/direction", gpio_dir, pin_number),
-            "in".as_bytes(),
-        )
-        .await?;
+impl From<OutputPin> for GpioPin {
+    fn from(pin: OutputPin) -> Self {
+        Self::Output(pin)
+    }
+}
 
-        // set the pin as down
-        fs::write(
-            format!("{}/gpio{}/value", gpio_dir, pin_number),
-            "0".as_bytes(),
-        )
-        .await?;
+impl TryFrom<GpioPin> for InputPin {
+    type Error = GpioError;
 
-        Ok(Self::Input {
-            pin_number,
-            support_watch: true,
-        })
+    fn try_from(pin: GpioPin) -> Result<Self> {
+        match pin {
+            GpioPin::Input(pin) => Ok(pin),
+            GpioPin::Output(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "input",
+            }),
+        }
+    }
+}
+
+impl TryFrom<GpioPin> for OutputPin {
+    type Error = GpioError;
+
+    fn try_from(pin: GpioPin) -> Result<Self> {
+        match pin {
+            GpioPin::Input(pin) => Err(GpioError::WrongDirection {
+                pin: pin.get_pin_number(),
+                expected: "output",
+            }),
+            GpioPin::Output(pin) => Ok(pin),
+        }
     }
 }
+
+/// Get the sysfs path to the value of the pin under the sysfs root of the context.
+fn value_path(gpio: &Gpio, pin_number: u8) -> String {
+    sysfs::value_path(&gpio.config().sysfs_root, pin_number)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Check that a batch of pins doesn't hold the same pin twice.
+pub(crate) fn check_unique(pin_numbers: impl Iterator<Item = u8>) -> Result<()> {
+    let mut seen = HashSet::new();
+    for pin_number in pin_numbers {
+        if !seen.insert(pin_number) {
+            return Err(GpioError::InvalidValue(format!(
+                "Pin {} is given more than once",
+                pin_number
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Get all the pins of a batch, or release the ones exported if any failed.
+pub(crate) async fn all_or_release<P: Into<GpioPin>>(results: Vec<Result<P>>) -> Result<Vec<P>> {
+    let mut pins = Vec::with_capacity(results.len());
+    let mut error = None;
+    for result in results {
+        match result {
+            Ok(pin) => pins.push(pin),
+            Err(e) => {
+                error.get_or_insert(e);
+            }
+        }
+    }
+
+    match error {
+        None => Ok(pins),
+        Some(error) => {
+            for pin in pins {
+                let pin: GpioPin = pin.into();
+                let pin_number = pin.get_pin_number();
+                if let Err(e) = pin.release().await {
+                    log::warn!(
+                        "Error releasing pin {} after a failed batch: {}",
+                        pin_number,
+                        e
+                    );
+                }
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Get the context of the fake pins, using the shared mock backend.
+#[cfg(any(test, feature = "mock"))]
+fn fake_gpio() -> Gpio {
+    Gpio::with_backend(GpioConfig::default(), mock::backend())
+}
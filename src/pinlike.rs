@@ -0,0 +1,112 @@
+//
+// This file provides the [PinLike] trait, the reads and writes shared by the native pins and the
+// lines of the port expanders and shift registers, so helpers like the LED, the push button and
+// the parallel bus drive any of them. The other features of the native pins, like the edge
+// notification or the pull resistors, stay on their own types.
+//
+// Pins without edge notification are polled by the helpers watching them,
+// with [poll_changes].
+//
+
+use super::backend::ChangeStream;
+use super::error::{GpioError, Result};
+use super::pin::{GpioPin, InputPin, OutputPin};
+use async_trait::async_trait;
+use std::{fmt, sync::Arc, time::Duration};
+use tokio::time::{self, MissedTickBehavior};
+
+/// Pin read and written by its level, native or behind a port expander or a shift register.
+#[async_trait]
+pub trait PinLike: fmt::Debug + Send + Sync {
+    /// Read the value of the pin.
+    async fn read(&self) -> Result<u8>;
+
+    /// Write a value (0 or 1) to the pin. Writing to an input fails.
+    async fn write(&self, value: u8) -> Result<()>;
+}
+
+#[async_trait]
+impl PinLike for InputPin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, _value: u8) -> Result<()> {
+        Err(GpioError::WrongDirection {
+            pin: self.get_pin_number(),
+            expected: "output",
+        })
+    }
+}
+
+#[async_trait]
+impl PinLike for OutputPin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        self.write(value).await
+    }
+}
+
+#[async_trait]
+impl PinLike for GpioPin {
+    async fn read(&self) -> Result<u8> {
+        self.read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        self.write(value).await
+    }
+}
+
+#[async_trait]
+impl<P: PinLike + ?Sized> PinLike for Box<P> {
+    async fn read(&self) -> Result<u8> {
+        (**self).read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        (**self).write(value).await
+    }
+}
+
+#[async_trait]
+impl<P: PinLike + ?Sized> PinLike for Arc<P> {
+    async fn read(&self) -> Result<u8> {
+        (**self).read().await
+    }
+
+    async fn write(&self, value: u8) -> Result<()> {
+        (**self).write(value).await
+    }
+}
+
+/// Poll a pin at the given interval, yielding when its value changes from the value read
+/// when this is called.
+pub async fn poll_changes(pin: Arc<dyn PinLike>, interval: Duration) -> Result<ChangeStream> {
+    let value = pin.read().await?;
+    Ok(Box::pin(futures::stream::unfold(
+        (pin, None, value),
+        move |(pin, ticks, mut value)| async move {
+            let mut ticks: time::Interval = ticks.unwrap_or_else(|| {
+                let mut ticks = time::interval(interval);
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                ticks
+            });
+            let change = loop {
+                ticks.tick().await;
+                match pin.read().await {
+                    Ok(current) if current == value => {}
+                    Ok(current) => {
+                        value = current;
+                        break Ok(());
+                    }
+                    Err(e) => break Err(e),
+                }
+            };
+            Some((change, (pin, Some(ticks), value)))
+        },
+    )))
+}
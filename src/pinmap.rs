@@ -0,0 +1,315 @@
+//
+// This file provides the translation between the numbering schemes of the pins.
+// Physical numbers are the positions on the header, wiringOP numbers are the ones
+// used by the `gpio` command without `-g`, and SoC numbers are the ones the kernel
+// uses, which is what every other API of the crate expects.
+//
+// Each supported board has its own table, selected through [Board]. The Raspberry Pi is
+// supported too, with the BCM numbers as SoC numbers, for fleets mixing both boards. Boards whose
+// SoC numbers don't fit the `u8` pin numbers of the crate (e.g. the Orange Pi 3 LTS)
+// are not supported.
+//
+// The board can be detected from the model in the device tree, so applications
+// don't need to know which board they run on.
+//
+
+use super::error::{GpioError, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Path to the model of the board in the device tree.
+const MODEL_PATH: &str = "/proc/device-tree/model";
+
+/// Mapping between the numbering schemes of the GPIO pins of a board header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PinMap {
+    /// Pairs of physical and SoC numbers of the GPIO pins, ordered by physical number.
+    /// The wiringOP number of a pin is its index in this list.
+    pins: &'static [(u8, u8)],
+}
+
+/// Pin map of the 26-pin header of the Orange Pi Zero 2.
+pub const ORANGE_PI_ZERO2: PinMap = PinMap {
+    pins: &[
+        (3, 229),
+        (5, 228),
+        (7, 73),
+        (8, 226),
+        (10, 227),
+        (11, 70),
+        (12, 75),
+        (13, 69),
+        (15, 72),
+        (16, 79),
+        (18, 78),
+        (19, 231),
+        (21, 232),
+        (22, 71),
+        (23, 230),
+        (24, 233),
+        (26, 74),
+    ],
+};
+
+/// Pin map of the 26-pin header of the Orange Pi Zero.
+pub const ORANGE_PI_ZERO: PinMap = PinMap {
+    pins: &[
+        (3, 12),
+        (5, 11),
+        (7, 6),
+        (8, 198),
+        (10, 199),
+        (11, 1),
+        (12, 7),
+        (13, 0),
+        (15, 3),
+        (16, 19),
+        (18, 18),
+        (19, 15),
+        (21, 16),
+        (22, 2),
+        (23, 14),
+        (24, 13),
+        (26, 10),
+    ],
+};
+
+/// Pin map of the 40-pin header of the Orange Pi PC.
+pub const ORANGE_PI_PC: PinMap = PinMap {
+    pins: &[
+        (3, 12),
+        (5, 11),
+        (7, 6),
+        (8, 13),
+        (10, 14),
+        (11, 1),
+        (12, 110),
+        (13, 0),
+        (15, 3),
+        (16, 68),
+        (18, 71),
+        (19, 64),
+        (21, 65),
+        (22, 2),
+        (23, 66),
+        (24, 67),
+        (26, 21),
+        (27, 19),
+        (28, 18),
+        (29, 7),
+        (31, 8),
+        (32, 200),
+        (33, 9),
+        (35, 10),
+        (36, 201),
+        (37, 20),
+        (38, 198),
+        (40, 199),
+    ],
+};
+
+/// Pin map of the 26-pin header of the Orange Pi 5.
+pub const ORANGE_PI_5: PinMap = PinMap {
+    pins: &[
+        (3, 47),
+        (5, 46),
+        (7, 54),
+        (8, 131),
+        (10, 132),
+        (11, 138),
+        (12, 29),
+        (13, 139),
+        (15, 28),
+        (16, 59),
+        (18, 58),
+        (19, 49),
+        (21, 48),
+        (22, 92),
+        (23, 50),
+        (24, 52),
+        (26, 35),
+    ],
+};
+
+/// Pin map of the 40-pin header of the Raspberry Pi, from the Model B+ on.
+/// The SoC numbers are the BCM numbers, and the wiring numbers follow the header like wiringOP,
+/// not the wiringPi numbers.
+pub const RASPBERRY_PI: PinMap = PinMap {
+    pins: &[
+        (3, 2),
+        (5, 3),
+        (7, 4),
+        (8, 14),
+        (10, 15),
+        (11, 17),
+        (12, 18),
+        (13, 27),
+        (15, 22),
+        (16, 23),
+        (18, 24),
+        (19, 10),
+        (21, 9),
+        (22, 25),
+        (23, 11),
+        (24, 8),
+        (26, 7),
+        (27, 0),
+        (28, 1),
+        (29, 5),
+        (31, 6),
+        (32, 12),
+        (33, 13),
+        (35, 19),
+        (36, 16),
+        (37, 26),
+        (38, 20),
+        (40, 21),
+    ],
+};
+
+/// Orange Pi models with a known pin map, and the Raspberry Pi.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Board {
+    /// Orange Pi Zero (Allwinner H2+)
+    OrangePiZero,
+    /// Orange Pi Zero 2 (Allwinner H616)
+    OrangePiZero2,
+    /// Orange Pi PC (Allwinner H3)
+    OrangePiPc,
+    /// Orange Pi 5 (Rockchip RK3588S)
+    OrangePi5,
+    /// Raspberry Pi with a 40-pin header, any model
+    RaspberryPi,
+}
+
+impl Board {
+    /// Detect the board from the model in the device tree.
+    pub fn detect() -> Result<Self> {
+        Self::detect_from(MODEL_PATH)
+    }
+
+    /// Detect the board from the model in the given file, e.g. a copy of the device tree model.
+    pub fn detect_from(path: impl AsRef<Path>) -> Result<Self> {
+        let model = fs::read_to_string(path)?;
+        Self::from_model(&model).ok_or(GpioError::UnknownBoard(
+            model.trim_end_matches('\0').trim().to_string(),
+        ))
+    }
+
+    /// Get the board from a model name, e.g. `Xunlong Orange Pi PC`, `OrangePi Zero2`
+    /// or `Raspberry Pi 4 Model B Rev 1.4`.
+    /// The vendor prefix, case and spacing of the name don't matter.
+    pub fn from_model(model: &str) -> Option<Self> {
+        let model: String = model
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if model.contains("raspberrypi") {
+            return Some(Self::RaspberryPi);
+        }
+        let (_, variant) = model.split_once("orangepi")?;
+        match variant {
+            "zero" => Some(Self::OrangePiZero),
+            "zero2" => Some(Self::OrangePiZero2),
+            "pc" => Some(Self::OrangePiPc),
+            "5" => Some(Self::OrangePi5),
+            _ => None,
+        }
+    }
+
+    /// Get the pin map of the header of the board.
+    pub fn pin_map(&self) -> PinMap {
+        match self {
+            Self::OrangePiZero => ORANGE_PI_ZERO,
+            Self::OrangePiZero2 => ORANGE_PI_ZERO2,
+            Self::OrangePiPc => ORANGE_PI_PC,
+            Self::OrangePi5 => ORANGE_PI_5,
+            Self::RaspberryPi => RASPBERRY_PI,
+        }
+    }
+}
+
+impl From<Board> for PinMap {
+    fn from(board: Board) -> Self {
+        board.pin_map()
+    }
+}
+
+impl Default for PinMap {
+    fn default() -> Self {
+        ORANGE_PI_ZERO2
+    }
+}
+
+impl PinMap {
+    /// Get the pin map of the board detected from the device tree.
+    pub fn detect() -> Result<Self> {
+        Board::detect().map(|board| board.pin_map())
+    }
+
+    /// Get the SoC number of the pin at the given position on the header.
+    pub fn physical_to_soc(&self, physical: u8) -> Result<u8> {
+        self.find(|(p, _)| p == physical, "physical", physical)
+            .map(|(_, (_, soc))| soc)
+    }
+
+    /// Get the position on the header of the pin with the given SoC number.
+    pub fn soc_to_physical(&self, soc: u8) -> Result<u8> {
+        self.find(|(_, s)| s == soc, "SoC", soc)
+            .map(|(_, (physical, _))| physical)
+    }
+
+    /// Get the SoC number of the pin with the given wiringOP number.
+    pub fn wiring_to_soc(&self, wiring: u8) -> Result<u8> {
+        self.pins
+            .get(wiring as usize)
+            .map(|&(_, soc)| soc)
+            .ok_or(GpioError::UnmappedPin {
+                scheme: "wiringOP",
+                pin: wiring,
+            })
+    }
+
+    /// Get the wiringOP number of the pin with the given SoC number.
+    pub fn soc_to_wiring(&self, soc: u8) -> Result<u8> {
+        self.find(|(_, s)| s == soc, "SoC", soc)
+            .map(|(wiring, _)| wiring)
+    }
+
+    /// Get the wiringOP number of the pin at the given position on the header.
+    pub fn physical_to_wiring(&self, physical: u8) -> Result<u8> {
+        self.find(|(p, _)| p == physical, "physical", physical)
+            .map(|(wiring, _)| wiring)
+    }
+
+    /// Get the position on the header of the pin with the given wiringOP number.
+    pub fn wiring_to_physical(&self, wiring: u8) -> Result<u8> {
+        self.soc_to_physical(self.wiring_to_soc(wiring)?)
+    }
+
+    /// Iterate over the physical and SoC numbers of the GPIO pins, ordered by physical number.
+    pub fn pins(&self) -> impl Iterator<Item = (u8, u8)> {
+        self.pins.iter().copied()
+    }
+
+    /// Find the wiringOP number and the entry of the first pin matching the predicate.
+    fn find(
+        &self,
+        predicate: impl Fn((u8, u8)) -> bool,
+        scheme: &'static str,
+        pin: u8,
+    ) -> Result<(u8, (u8, u8))> {
+        self.pins()
+            .enumerate()
+            .find(|&(_, entry)| predicate(entry))
+            .map(|(wiring, entry)| (wiring as u8, entry))
+            .ok_or(GpioError::UnmappedPin { scheme, pin })
+    }
+}
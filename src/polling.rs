@@ -0,0 +1,157 @@
+//
+// This file provides a watcher sampling the values of pins at a fixed interval,
+// as a fallback for kernels and filesystems where the sysfs value file doesn't
+// notify its changes reliably.
+// It emits the same events as the GpioWatcher, with the time of the sample that
+// saw the change, so changes shorter than the interval are missed.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::GpioPin;
+use super::watcher::{GpioEvent, Notifier};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{
+    sync::Mutex,
+    task::JoinHandle,
+    time::{self, Instant, MissedTickBehavior},
+};
+
+/// A pin being sampled along with its notifier and last value.
+struct PolledPin {
+    pin: Arc<GpioPin>,
+    notifier: Notifier,
+    last_value: u8,
+}
+
+/// Watcher sampling GPIO pins at a fixed interval and notifying their changes,
+/// for pins whose changes can't be watched.
+/// Pins can be added or removed while the watcher is running.
+///
+/// Dropping this will abort the watcher.
+pub struct PollingWatcher {
+    interval: Duration,
+    pins: Arc<Mutex<HashMap<u8, PolledPin>>>,
+    watcher_thread: JoinHandle<()>,
+}
+
+impl Drop for PollingWatcher {
+    fn drop(&mut self) {
+        self.watcher_thread.abort();
+    }
+}
+
+impl PollingWatcher {
+    /// Create a new [PollingWatcher] sampling the given pins at the interval
+    /// and sending their changes to the notifiers.
+    pub async fn new<P, N>(pin_map: HashMap<P, N>, interval: Duration) -> Result<Self>
+    where
+        P: Into<Arc<GpioPin>>,
+        N: Into<Notifier>,
+    {
+        if interval.is_zero() {
+            return Err(GpioError::InvalidValue(
+                "Polling interval must not be zero".to_string(),
+            ));
+        }
+
+        let pins = Arc::new(Mutex::new(HashMap::new()));
+        let watcher_thread = tokio::spawn(run_polling(pins.clone(), interval));
+        let watcher = Self {
+            interval,
+            pins,
+            watcher_thread,
+        };
+        for (pin, notifier) in pin_map {
+            watcher.add_pin(pin, notifier).await?;
+        }
+
+        Ok(watcher)
+    }
+
+    /// Get the time between two samples.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Start sampling another pin while the watcher is running.
+    /// The initial value of the pin is sent to the notifier right away.
+    pub async fn add_pin(
+        &self,
+        pin: impl Into<Arc<GpioPin>>,
+        notifier: impl Into<Notifier>,
+    ) -> Result<()> {
+        let pin = pin.into();
+        let notifier = notifier.into();
+        let pin_number = pin.get_pin_number();
+        let mut pins = self.pins.lock().await;
+        if pins.contains_key(&pin_number) {
+            return Err(GpioError::AlreadyWatched(pin_number));
+        }
+
+        // Send the initial value of the pin
+        let timestamp = Instant::now();
+        let value = pin.read().await?;
+        let result = notifier.notify(GpioEvent::new(pin_number, value.into(), timestamp));
+        pin.gpio().metrics().record_event(pin_number, &result);
+        result?;
+
+        pins.insert(
+            pin_number,
+            PolledPin {
+                pin,
+                notifier,
+                last_value: value,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop sampling a pin while the watcher is running.
+    /// The pin is given back so it can be used elsewhere.
+    pub async fn remove_pin(&self, pin_number: u8) -> Result<Arc<GpioPin>> {
+        match self.pins.lock().await.remove(&pin_number) {
+            Some(polled) => Ok(polled.pin),
+            None => Err(GpioError::NotWatched(pin_number)),
+        }
+    }
+
+    /// Get the numbers of the pins currently being sampled.
+    pub async fn pin_numbers(&self) -> Vec<u8> {
+        self.pins.lock().await.keys().copied().collect()
+    }
+}
+
+/// Sample the pins at every interval and notify the values that changed.
+async fn run_polling(pins: Arc<Mutex<HashMap<u8, PolledPin>>>, interval: Duration) {
+    let mut interval = time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        interval.tick().await;
+        let timestamp = Instant::now();
+        let mut pins = pins.lock().await;
+        for (&pin_number, polled) in pins.iter_mut() {
+            let value = match polled.pin.read().await {
+                Ok(value) => value,
+                Err(e) => {
+                    log::error!("Error reading GPIO value: {}", e);
+                    continue;
+                }
+            };
+            if value == polled.last_value {
+                continue;
+            }
+
+            polled.last_value = value;
+            let event = GpioEvent::new(pin_number, value.into(), timestamp);
+            let result = polled.notifier.notify(event);
+            polled
+                .pin
+                .gpio()
+                .metrics()
+                .record_event(pin_number, &result);
+            if let Err(e) = result {
+                log::warn!("Error sending message: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//
+// This file provides pulse measurements on watched input pins, for sensors reporting
+// through pulses like anemometers, rain gauges and flow sensors.
+// The counter counts the edges of a pin and computes the frequency from the edges
+// seen over a sliding window, which trades responsiveness for a steadier value.
+// The meter times the high and low parts of the last pulse, e.g. to read the PWM output
+// of another device.
+//
+// Edges are detected from the changes of the pin, so pulses shorter than the latency
+// of the backend are missed.
+//
+
+use super::backend::ChangeStream;
+use super::error::{GpioError, Result};
+use super::pin::{Edge, InputPin, Level};
+use super::watcher::GpioEvent;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{task::JoinHandle, time::Instant};
+use tokio_stream::StreamExt;
+
+/// Edges counted by a [PulseCounter].
+#[derive(Debug, Default)]
+struct CounterState {
+    count: u64,
+    edges: VecDeque<Instant>,
+}
+
+/// Counter of the edges of an input pin.
+///
+/// Dropping this will stop the counting.
+pub struct PulseCounter {
+    edge: Edge,
+    window: Duration,
+    state: Arc<Mutex<CounterState>>,
+    counter_thread: JoinHandle<()>,
+}
+
+impl Drop for PulseCounter {
+    fn drop(&mut self) {
+        self.counter_thread.abort();
+    }
+}
+
+impl PulseCounter {
+    /// Start counting the given edges of the pin, enabling edge notification on it.
+    /// The frequency is computed over the sliding window.
+    pub async fn new(mut pin: InputPin, edge: Edge, window: Duration) -> Result<Self> {
+        if window.is_zero() {
+            return Err(GpioError::InvalidValue(
+                "Frequency window must not be zero".to_string(),
+            ));
+        }
+
+        pin.enable_watch(Edge::Both).await?;
+        let changes = pin.changes()?;
+        let level = pin.read().await?;
+
+        let state = Arc::new(Mutex::new(CounterState::default()));
+        let counter_state = state.clone();
+        let counter_thread = tokio::spawn(run_edges(pin, changes, level, move |event| {
+            if edge == Edge::Both || edge == event.edge {
+                let mut state = counter_state.lock().unwrap();
+                state.count += 1;
+                state.edges.push_back(event.timestamp);
+                prune(&mut state.edges, window);
+            }
+        }));
+
+        Ok(Self {
+            edge,
+            window,
+            state,
+            counter_thread,
+        })
+    }
+
+    /// Get the edges that are counted.
+    pub fn edge(&self) -> Edge {
+        self.edge
+    }
+
+    /// Get the window the frequency is computed over.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Get the number of edges counted since the counter started or was reset.
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap().count
+    }
+
+    /// Reset the count and the frequency to 0.
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = CounterState::default();
+    }
+
+    /// Get the frequency of the pulses in Hz, from the edges seen during the last window.
+    /// When counting both edges, a pulse is two edges.
+    pub fn frequency_hz(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        prune(&mut state.edges, self.window);
+        let pulses = match self.edge {
+            Edge::Both => state.edges.len() as f64 / 2.0,
+            Edge::Rising | Edge::Falling => state.edges.len() as f64,
+        };
+        pulses / self.window.as_secs_f64()
+    }
+}
+
+/// Last pulse timings measured by a [PulseMeter].
+#[derive(Debug, Default)]
+struct MeterState {
+    last_rise: Option<Instant>,
+    last_fall: Option<Instant>,
+    high: Option<Duration>,
+    low: Option<Duration>,
+}
+
+/// Meter of the high and low times of the pulses of an input pin, e.g. a PWM signal.
+///
+/// Dropping this will stop the measurements.
+pub struct PulseMeter {
+    state: Arc<Mutex<MeterState>>,
+    meter_thread: JoinHandle<()>,
+}
+
+impl Drop for PulseMeter {
+    fn drop(&mut self) {
+        self.meter_thread.abort();
+    }
+}
+
+impl PulseMeter {
+    /// Start timing the edges of the pin, enabling edge notification on it.
+    pub async fn new(mut pin: InputPin) -> Result<Self> {
+        pin.enable_watch(Edge::Both).await?;
+        let changes = pin.changes()?;
+        let level = pin.read().await?;
+
+        let state = Arc::new(Mutex::new(MeterState::default()));
+        let meter_state = state.clone();
+        let meter_thread = tokio::spawn(run_edges(pin, changes, level, move |event| {
+            let now = event.timestamp;
+            let mut state = meter_state.lock().unwrap();
+            if event.level == Level::High {
+                state.low = state.last_fall.map(|fall| now - fall);
+                state.last_rise = Some(now);
+            } else {
+                state.high = state.last_rise.map(|rise| now - rise);
+                state.last_fall = Some(now);
+            }
+        }));
+
+        Ok(Self {
+            state,
+            meter_thread,
+        })
+    }
+
+    /// Get the time the pin stayed high during the last pulse, `None` until measured.
+    pub fn high_time(&self) -> Option<Duration> {
+        self.state.lock().unwrap().high
+    }
+
+    /// Get the time the pin stayed low between the last two pulses, `None` until measured.
+    pub fn low_time(&self) -> Option<Duration> {
+        self.state.lock().unwrap().low
+    }
+
+    /// Get the period of the signal from the last high and low times.
+    pub fn period(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        Some(state.high? + state.low?)
+    }
+
+    /// Get the duty cycle of the signal from the last high and low times,
+    /// as a ratio between 0.0 and 1.0.
+    pub fn duty_cycle(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        let (high, low) = (state.high?, state.low?);
+        Some(high.as_secs_f64() / (high + low).as_secs_f64())
+    }
+
+    /// Get the time of the last edge, to tell if the measurements are stale,
+    /// e.g. when the signal stopped at a fixed level.
+    pub fn last_edge(&self) -> Option<Instant> {
+        let state = self.state.lock().unwrap();
+        state.last_rise.max(state.last_fall)
+    }
+}
+
+/// Call the function with the event of each edge of the pin.
+async fn run_edges(
+    pin: InputPin,
+    mut changes: ChangeStream,
+    mut level: u8,
+    mut on_edge: impl FnMut(GpioEvent),
+) {
+    while let Some(change) = changes.next().await {
+        let now = Instant::now();
+        if let Err(e) = change {
+            log::error!("Error watching pulses: {}", e);
+            continue;
+        }
+        let value = match pin.read().await {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("Error reading pulses: {}", e);
+                continue;
+            }
+        };
+        if value != level {
+            level = value;
+            on_edge(GpioEvent::new(pin.get_pin_number(), value.into(), now));
+        }
+    }
+}
+
+/// Drop the edges older than the window.
+fn prune(edges: &mut VecDeque<Instant>, window: Duration) {
+    while edges.front().is_some_and(|edge| edge.elapsed() > window) {
+        edges.pop_front();
+    }
+}
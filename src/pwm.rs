@@ -0,0 +1,211 @@
+//
+// This file provides a representation of a hardware PWM channel.
+// It drives the SoC's PWM controller through the sysfs pwmchip interface
+// by exporting the channel and writing its period, duty cycle and enable files.
+// The sysfs root is taken from the `pwm_root` of the [Gpio] context's configuration.
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Gpio;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, time};
+
+/// Represents a hardware PWM channel (`pwmchipN/pwmM`).
+/// Use [PwmPin::new] to ensure the channel is properly exported.
+/// The channel is disabled until [PwmPin::enable] is called.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct PwmPin {
+    root: PathBuf,
+    chip: u8,
+    channel: u8,
+    period_ns: u64,
+    duty_cycle_ns: u64,
+}
+
+impl PwmPin {
+    /// Initialize a PWM channel, exporting it if it's not exported yet.
+    pub async fn new(gpio: &Gpio, chip: u8, channel: u8) -> Result<Self> {
+        let root = gpio.config().pwm_root.clone();
+        let channel_dir = channel_dir(&root, chip, channel);
+
+        // Export the channel if needed
+        if !fs::try_exists(&channel_dir).await? {
+            fs::write(
+                root.join(format!("pwmchip{}/export", chip)),
+                channel.to_string(),
+            )
+            .await?;
+
+            // Wait for the channel directory to be created
+            let mut attempts = 0;
+            while !fs::try_exists(&channel_dir).await.unwrap_or(false) {
+                attempts += 1;
+                if attempts > 100 {
+                    return Err(GpioError::ExportFailed(format!(
+                        "PWM channel {} of chip {} did not appear",
+                        channel, chip
+                    )));
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+
+        // Read the current configuration
+        let mut pwm = Self {
+            root,
+            chip,
+            channel,
+            period_ns: 0,
+            duty_cycle_ns: 0,
+        };
+        pwm.period_ns = pwm.read_attribute("period").await?;
+        pwm.duty_cycle_ns = pwm.read_attribute("duty_cycle").await?;
+
+        Ok(pwm)
+    }
+
+    /// Get the chip number of the channel.
+    pub fn get_chip(&self) -> u8 {
+        self.chip
+    }
+
+    /// Get the channel number within the chip.
+    pub fn get_channel(&self) -> u8 {
+        self.channel
+    }
+
+    /// Get the current period.
+    pub fn period(&self) -> Duration {
+        Duration::from_nanos(self.period_ns)
+    }
+
+    /// Get the current duty cycle (active time per period).
+    pub fn duty_cycle(&self) -> Duration {
+        Duration::from_nanos(self.duty_cycle_ns)
+    }
+
+    /// Set the period. The duty cycle is shortened if it doesn't fit anymore.
+    pub async fn set_period(&mut self, period: Duration) -> Result<()> {
+        let period_ns = period.as_nanos() as u64;
+        self.configure(period_ns, self.duty_cycle_ns.min(period_ns))
+            .await
+    }
+
+    /// Set the duty cycle (active time per period).
+    /// It must not be longer than the period.
+    pub async fn set_duty_cycle(&mut self, duty_cycle: Duration) -> Result<()> {
+        let duty_cycle_ns = duty_cycle.as_nanos() as u64;
+        if duty_cycle_ns > self.period_ns {
+            return Err(GpioError::InvalidValue(format!(
+                "Duty cycle {}ns is longer than the period {}ns",
+                duty_cycle_ns, self.period_ns
+            )));
+        }
+        self.configure(self.period_ns, duty_cycle_ns).await
+    }
+
+    /// Set the frequency in Hz, keeping the current duty ratio.
+    pub async fn set_frequency(&mut self, frequency: f64) -> Result<()> {
+        if !(frequency > 0.0 && frequency.is_finite()) {
+            return Err(GpioError::InvalidValue(format!(
+                "Frequency must be positive, got {}",
+                frequency
+            )));
+        }
+        let ratio = self.duty();
+        let period_ns = (1_000_000_000.0 / frequency) as u64;
+        self.configure(period_ns, (period_ns as f64 * ratio) as u64)
+            .await
+    }
+
+    /// Get the duty ratio between 0.0 and 1.0.
+    pub fn duty(&self) -> f64 {
+        if self.period_ns == 0 {
+            0.0
+        } else {
+            self.duty_cycle_ns as f64 / self.period_ns as f64
+        }
+    }
+
+    /// Set the duty ratio between 0.0 and 1.0.
+    pub async fn set_duty(&mut self, ratio: f64) -> Result<()> {
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(GpioError::InvalidValue(format!(
+                "Duty ratio must be between 0.0 and 1.0, got {}",
+                ratio
+            )));
+        }
+        let duty_cycle_ns = (self.period_ns as f64 * ratio) as u64;
+        self.configure(self.period_ns, duty_cycle_ns).await
+    }
+
+    /// Start generating the signal.
+    pub async fn enable(&self) -> Result<()> {
+        self.write_attribute("enable", 1).await
+    }
+
+    /// Stop generating the signal.
+    pub async fn disable(&self) -> Result<()> {
+        self.write_attribute("enable", 0).await
+    }
+
+    /// Check if the channel is currently generating the signal.
+    pub async fn is_enabled(&self) -> Result<bool> {
+        Ok(self.read_attribute("enable").await? == 1)
+    }
+
+    /// Disable and unexport the channel.
+    /// This consumes the channel so it can't be used afterwards.
+    pub async fn release(self) -> Result<()> {
+        self.disable().await?;
+        fs::write(
+            self.root.join(format!("pwmchip{}/unexport", self.chip)),
+            self.channel.to_string(),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Write the period and duty cycle in an order the kernel accepts,
+    /// as the duty cycle must never be longer than the period.
+    async fn configure(&mut self, period_ns: u64, duty_cycle_ns: u64) -> Result<()> {
+        if period_ns >= self.duty_cycle_ns {
+            self.write_attribute("period", period_ns).await?;
+            self.period_ns = period_ns;
+            self.write_attribute("duty_cycle", duty_cycle_ns).await?;
+            self.duty_cycle_ns = duty_cycle_ns;
+        } else {
+            self.write_attribute("duty_cycle", duty_cycle_ns).await?;
+            self.duty_cycle_ns = duty_cycle_ns;
+            self.write_attribute("period", period_ns).await?;
+            self.period_ns = period_ns;
+        }
+
+        Ok(())
+    }
+
+    /// Read a numeric attribute file of the channel.
+    async fn read_attribute(&self, name: &str) -> Result<u64> {
+        let path = channel_dir(&self.root, self.chip, self.channel).join(name);
+        let content = fs::read_to_string(&path).await?;
+
+        content.trim().parse().map_err(|_| {
+            GpioError::InvalidValue(format!("Failed to parse the PWM {} {:?}", name, content))
+        })
+    }
+
+    /// Write a numeric attribute file of the channel.
+    async fn write_attribute(&self, name: &str, value: u64) -> Result<()> {
+        let path = channel_dir(&self.root, self.chip, self.channel).join(name);
+        Ok(fs::write(&path, value.to_string()).await?)
+    }
+}
+
+/// Get the sysfs directory of a PWM channel under the PWM root.
+fn channel_dir(root: &Path, chip: u8, channel: u8) -> PathBuf {
+    root.join(format!("pwmchip{}/pwm{}", chip, channel))
+}
@@ -0,0 +1,240 @@
+//
+// This file provides a recorder of the transitions of pins, a poor man's logic analyzer
+// for debugging timing issues. It's fed by a watcher through its notifier, and keeps the last
+// transitions in a ring buffer, with their time since the recorder started, so they can be
+// queried after the fact, e.g. to measure the pulses of a bit-banged protocol.
+// The transitions can also be appended to a file, one `time_us,pin,value` line each,
+// which keeps the whole capture when the buffer wraps around.
+//
+// The capture can be dumped as a Value Change Dump (VCD) file, with a signal per pin,
+// to inspect it in a waveform viewer like GTKWave or PulseView.
+//
+
+use super::error::Result;
+use super::pin::Level;
+use super::watcher::{GpioEvent, Notifier};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::File,
+    io::{BufWriter, LineWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Change of the level of a pin recorded by a [Recorder].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Transition {
+    /// Number of the pin
+    pub pin: u8,
+    /// New level of the pin
+    pub level: Level,
+    /// Time of the change since the recorder started
+    pub time: Duration,
+}
+
+/// Transitions recorded so far.
+#[derive(Debug)]
+struct Capture {
+    transitions: VecDeque<Transition>,
+    dropped: u64,
+    file: Option<LineWriter<File>>,
+}
+
+/// Recorder of the transitions of watched pins, keeping the last ones in memory.
+/// Clones share the same capture.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    start: Instant,
+    capacity: usize,
+    capture: Arc<Mutex<Capture>>,
+}
+
+impl Recorder {
+    /// Create a recorder keeping up to `capacity` transitions, the oldest being dropped first.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            capacity: capacity.max(1),
+            capture: Arc::new(Mutex::new(Capture {
+                transitions: VecDeque::with_capacity(capacity.min(4096)),
+                dropped: 0,
+                file: None,
+            })),
+        }
+    }
+
+    /// Create a recorder also appending every transition to a file, replaced if it exists.
+    pub fn with_file(path: impl AsRef<Path>, capacity: usize) -> Result<Self> {
+        let recorder = Self::new(capacity);
+        let file = LineWriter::new(File::create(path)?);
+        recorder.capture.lock().unwrap().file = Some(file);
+        Ok(recorder)
+    }
+
+    /// Get the time the recorder started, the origin of the times of the transitions.
+    pub fn start(&self) -> Instant {
+        self.start
+    }
+
+    /// Get a notifier recording the events of a pin, to watch it with a
+    /// [GpioWatcher](crate::watcher::GpioWatcher).
+    pub fn notifier(&self) -> Notifier {
+        let recorder = self.clone();
+        Notifier::event_callback(move |event| recorder.record(event))
+    }
+
+    /// Record an event of a watcher.
+    /// Events from before the recorder started are recorded at its start.
+    pub fn record(&self, event: GpioEvent) {
+        let transition = Transition {
+            pin: event.pin,
+            level: event.level,
+            time: event.timestamp.saturating_duration_since(self.start),
+        };
+
+        let mut capture = self.capture.lock().unwrap();
+        if let Some(file) = &mut capture.file
+            && let Err(e) = writeln!(
+                file,
+                "{},{},{}",
+                transition.time.as_micros(),
+                transition.pin,
+                u8::from(transition.level)
+            )
+        {
+            log::error!("Error writing the recorded transitions: {}", e);
+        }
+        if capture.transitions.len() == self.capacity {
+            capture.transitions.pop_front();
+            capture.dropped += 1;
+        }
+        capture.transitions.push_back(transition);
+    }
+
+    /// Get the recorded transitions, oldest first.
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.capture
+            .lock()
+            .unwrap()
+            .transitions
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Get the recorded transitions of a pin, oldest first.
+    pub fn transitions_of(&self, pin: u8) -> Vec<Transition> {
+        self.filter(|transition| transition.pin == pin)
+    }
+
+    /// Get the transitions recorded between two times since the start, both included.
+    pub fn between(&self, from: Duration, to: Duration) -> Vec<Transition> {
+        self.filter(|transition| (from..=to).contains(&transition.time))
+    }
+
+    /// Get how long a pin stayed at each level between its recorded transitions,
+    /// e.g. the widths of the pulses it received.
+    pub fn durations(&self, pin: u8) -> Vec<(Level, Duration)> {
+        self.transitions_of(pin)
+            .windows(2)
+            .map(|pair| (pair[0].level, pair[1].time.saturating_sub(pair[0].time)))
+            .collect()
+    }
+
+    /// Write the recorded transitions as a VCD file, with a `gpioN` signal per pin.
+    /// The pins are unknown until their first recorded transition.
+    pub fn write_vcd(&self, mut writer: impl Write) -> Result<()> {
+        let mut transitions = self.transitions();
+        transitions.sort_by_key(|transition| transition.time);
+        let pins: BTreeSet<u8> = transitions.iter().map(|t| t.pin).collect();
+        let ids: Vec<(u8, String)> = pins
+            .into_iter()
+            .enumerate()
+            .map(|(index, pin)| (pin, vcd_id(index)))
+            .collect();
+        let id = |pin| &ids.iter().find(|(p, _)| *p == pin).unwrap().1;
+
+        writeln!(
+            writer,
+            "$version opi_gpio_rs {} $end",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module gpio $end")?;
+        for (pin, id) in &ids {
+            writeln!(writer, "$var wire 1 {} gpio{} $end", id, pin)?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+        writeln!(writer, "#0")?;
+        writeln!(writer, "$dumpvars")?;
+        for (_, id) in &ids {
+            writeln!(writer, "x{}", id)?;
+        }
+        writeln!(writer, "$end")?;
+
+        let mut time = 0;
+        for transition in transitions {
+            let nanos = transition.time.as_nanos();
+            if nanos != time {
+                writeln!(writer, "#{}", nanos)?;
+                time = nanos;
+            }
+            writeln!(
+                writer,
+                "{}{}",
+                u8::from(transition.level),
+                id(transition.pin)
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Save the recorded transitions to a VCD file, replaced if it exists.
+    pub fn save_vcd(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.write_vcd(BufWriter::new(File::create(path)?))
+    }
+
+    /// Get the number of transitions dropped from the buffer because it was full.
+    pub fn dropped(&self) -> u64 {
+        self.capture.lock().unwrap().dropped
+    }
+
+    /// Forget the recorded transitions, the file keeps them.
+    pub fn clear(&self) {
+        let mut capture = self.capture.lock().unwrap();
+        capture.transitions.clear();
+        capture.dropped = 0;
+    }
+
+    fn filter(&self, predicate: impl Fn(&Transition) -> bool) -> Vec<Transition> {
+        self.capture
+            .lock()
+            .unwrap()
+            .transitions
+            .iter()
+            .filter(|transition| predicate(transition))
+            .copied()
+            .collect()
+    }
+}
+
+/// Get the VCD identifier of the signal at the given index, in base 94 over the printable
+/// characters, e.g. `!` for the first one.
+fn vcd_id(mut index: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
@@ -0,0 +1,245 @@
+//
+// This file provides a bank of relays with named channels, on native pins or on the lines of
+// a port expander, for loads like motorized valves and reversing contactors.
+// Most relay boards energize their relays when the input is low, so the channels are active low
+// by default and every channel is switched off when it's added.
+//
+// Channels can be interlocked so they're never on together, e.g. the open and close windings of a
+// valve: turning a channel on fails while another channel of one of its interlocks is on, and
+// `switch_to` turns the others off first. A channel is only turned on once it, and the channels
+// interlocked with it, have been off for the minimum off time, waiting for the rest,
+// so contactors have dropped out and motors stopped before reversing.
+//
+
+use super::error::{GpioError, Result};
+use super::pinlike::PinLike;
+use std::time::Duration;
+use tokio::{
+    sync::Mutex,
+    time::{self, Instant},
+};
+
+/// Settings of a [RelayBank].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RelayConfig {
+    /// The relays are energized by a low level, true by default
+    pub active_low: bool,
+    /// Time a channel and the channels interlocked with it must be off before it's turned on,
+    /// none by default
+    pub min_off_time: Duration,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            active_low: true,
+            min_off_time: Duration::ZERO,
+        }
+    }
+}
+
+/// Channel of a [RelayBank].
+#[derive(Debug)]
+struct Channel {
+    name: String,
+    pin: Box<dyn PinLike>,
+    on: bool,
+    /// Time the channel was last turned off, None if it was never on
+    off_since: Option<Instant>,
+}
+
+/// Bank of relays with named channels, interlocks and a minimum off time.
+/// The channels are switched one at a time, waiting for the minimum off time
+/// blocks the other switches of the bank.
+#[derive(Debug)]
+pub struct RelayBank {
+    config: RelayConfig,
+    channels: Mutex<Vec<Channel>>,
+    /// Groups of channels never on together, by index
+    interlocks: Vec<Vec<usize>>,
+}
+
+impl RelayBank {
+    /// Create a bank without channels.
+    pub fn new(config: RelayConfig) -> Self {
+        Self {
+            config,
+            channels: Mutex::new(Vec::new()),
+            interlocks: Vec::new(),
+        }
+    }
+
+    /// Get the settings of the bank.
+    pub fn config(&self) -> RelayConfig {
+        self.config
+    }
+
+    /// Add a channel on the given pin, and switch it off.
+    pub async fn add_channel(&mut self, name: &str, pin: impl PinLike + 'static) -> Result<()> {
+        let off = self.level(false);
+        let channels = self.channels.get_mut();
+        if channels.iter().any(|channel| channel.name == name) {
+            return Err(GpioError::InvalidValue(format!(
+                "Relay {} is already in the bank",
+                name
+            )));
+        }
+
+        pin.write(off).await?;
+        channels.push(Channel {
+            name: name.to_string(),
+            pin: Box::new(pin),
+            on: false,
+            off_since: None,
+        });
+        Ok(())
+    }
+
+    /// Interlock channels, so at most one of them is on at any time.
+    /// A channel can be in several interlocks.
+    pub fn add_interlock(&mut self, names: &[&str]) -> Result<()> {
+        if names.len() < 2 {
+            return Err(GpioError::InvalidValue(
+                "An interlock needs at least 2 relays".to_string(),
+            ));
+        }
+
+        let channels = self.channels.get_mut();
+        let group = names
+            .iter()
+            .map(|name| index_of(channels, name))
+            .collect::<Result<Vec<_>>>()?;
+        if group.iter().filter(|&&index| channels[index].on).count() > 1 {
+            return Err(GpioError::InvalidValue(format!(
+                "Relays {:?} can't be interlocked while several of them are on",
+                names
+            )));
+        }
+        self.interlocks.push(group);
+        Ok(())
+    }
+
+    /// Get the names of the channels, in the order they were added.
+    pub async fn names(&self) -> Vec<String> {
+        let channels = self.channels.lock().await;
+        channels
+            .iter()
+            .map(|channel| channel.name.clone())
+            .collect()
+    }
+
+    /// Check if a channel is on.
+    pub async fn is_on(&self, name: &str) -> Result<bool> {
+        let channels = self.channels.lock().await;
+        Ok(channels[index_of(&channels, name)?].on)
+    }
+
+    /// Turn a channel on, failing if a channel interlocked with it is on.
+    /// Waits for the channel and the channels interlocked with it to be off
+    /// for the minimum off time.
+    pub async fn on(&self, name: &str) -> Result<()> {
+        let mut channels = self.channels.lock().await;
+        let index = index_of(&channels, name)?;
+        if let Some(active) = self.partners(index).find(|&other| channels[other].on) {
+            return Err(GpioError::Interlocked {
+                channel: name.to_string(),
+                active: channels[active].name.clone(),
+            });
+        }
+        self.energize(&mut channels, index).await
+    }
+
+    /// Turn a channel off.
+    pub async fn off(&self, name: &str) -> Result<()> {
+        let mut channels = self.channels.lock().await;
+        let index = index_of(&channels, name)?;
+        self.release(&mut channels, index).await
+    }
+
+    /// Turn a channel on or off.
+    pub async fn set(&self, name: &str, on: bool) -> Result<()> {
+        if on {
+            self.on(name).await
+        } else {
+            self.off(name).await
+        }
+    }
+
+    /// Turn the channels interlocked with a channel off, then the channel on,
+    /// after the minimum off time, e.g. to reverse a motor.
+    pub async fn switch_to(&self, name: &str) -> Result<()> {
+        let mut channels = self.channels.lock().await;
+        let index = index_of(&channels, name)?;
+        let partners: Vec<usize> = self.partners(index).collect();
+        for other in partners {
+            self.release(&mut channels, other).await?;
+        }
+        self.energize(&mut channels, index).await
+    }
+
+    /// Turn all the channels off.
+    pub async fn all_off(&self) -> Result<()> {
+        let mut channels = self.channels.lock().await;
+        for index in 0..channels.len() {
+            self.release(&mut channels, index).await?;
+        }
+        Ok(())
+    }
+
+    /// Turn a channel on once it and its partners have been off for the minimum off time.
+    async fn energize(&self, channels: &mut [Channel], index: usize) -> Result<()> {
+        if channels[index].on {
+            return Ok(());
+        }
+
+        let last_off = self
+            .partners(index)
+            .chain([index])
+            .filter_map(|other| channels[other].off_since)
+            .max();
+        if let Some(last_off) = last_off {
+            time::sleep_until(last_off + self.config.min_off_time).await;
+        }
+
+        let channel = &mut channels[index];
+        channel.pin.write(self.level(true)).await?;
+        channel.on = true;
+        Ok(())
+    }
+
+    /// Turn a channel off if it's on.
+    async fn release(&self, channels: &mut [Channel], index: usize) -> Result<()> {
+        let channel = &mut channels[index];
+        if !channel.on {
+            return Ok(());
+        }
+
+        channel.pin.write(self.level(false)).await?;
+        channel.on = false;
+        channel.off_since = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Get the channels interlocked with a channel.
+    fn partners(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.interlocks
+            .iter()
+            .filter(move |group| group.contains(&index))
+            .flatten()
+            .copied()
+            .filter(move |&other| other != index)
+    }
+
+    /// Get the level of the pins of the channels in the given state.
+    fn level(&self, on: bool) -> u8 {
+        (on != self.config.active_low) as u8
+    }
+}
+
+/// Get the index of a channel by its name.
+fn index_of(channels: &[Channel], name: &str) -> Result<usize> {
+    channels
+        .iter()
+        .position(|channel| channel.name == name)
+        .ok_or_else(|| GpioError::InvalidValue(format!("No relay {} in the bank", name)))
+}
@@ -0,0 +1,125 @@
+//
+// This file provides the replay of recorded waveforms onto the pins of the mock backend,
+// so protocol decoders can be tested against traffic captured on real hardware.
+// A waveform is taken from a [Recorder] or loaded from a CSV file, either the
+// `time_us,pin,value` lines written by a recorder or plain `time_us,value` pairs for a
+// single pin. Replaying it sets the levels of the exported mock pins at the recorded times
+// relative to the start of the replay, using the tokio timer so tests can pause the time.
+//
+
+use super::error::{GpioError, Result};
+use super::mock::{self, MockBackend};
+use super::pin::Level;
+use super::recorder::{Recorder, Transition};
+use std::{path::Path, time::Duration};
+use tokio::{fs, time};
+
+/// Sequence of transitions of pins, ordered by time.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Waveform {
+    transitions: Vec<Transition>,
+}
+
+impl From<&Recorder> for Waveform {
+    fn from(recorder: &Recorder) -> Self {
+        Self::new(recorder.transitions())
+    }
+}
+
+impl Waveform {
+    /// Create a waveform from transitions, sorted by time.
+    pub fn new(mut transitions: Vec<Transition>) -> Self {
+        transitions.sort_by_key(|transition| transition.time);
+        Self { transitions }
+    }
+
+    /// Parse a CSV capture, with `time_us,pin,value` or `time_us,value` lines,
+    /// the latter being transitions of the given pin.
+    /// Empty lines, lines starting with `#` and a header line are skipped.
+    pub fn from_csv(content: &str, pin: u8) -> Result<Self> {
+        let mut transitions = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let is_header = index == 0 && fields[0].parse::<u64>().is_err();
+            let invalid = || GpioError::InvalidValue(format!("Invalid waveform line {:?}", line));
+            let (time, pin, value) = match fields.as_slice() {
+                _ if is_header => continue,
+                [time, value] => (*time, pin, *value),
+                [time, pin, value] => (*time, pin.parse().map_err(|_| invalid())?, *value),
+                _ => return Err(invalid()),
+            };
+            let time: u64 = time.parse().map_err(|_| invalid())?;
+            let level = match value {
+                "0" => Level::Low,
+                "1" => Level::High,
+                _ => return Err(invalid()),
+            };
+            transitions.push(Transition {
+                pin,
+                level,
+                time: Duration::from_micros(time),
+            });
+        }
+        Ok(Self::new(transitions))
+    }
+
+    /// Read and parse a CSV capture, see [Waveform::from_csv].
+    pub async fn load(path: impl AsRef<Path>, pin: u8) -> Result<Self> {
+        Self::from_csv(&fs::read_to_string(path).await?, pin)
+    }
+
+    /// Get the transitions, ordered by time.
+    pub fn transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
+
+    /// Get the pins of the waveform, by increasing number.
+    pub fn pins(&self) -> Vec<u8> {
+        let mut pins: Vec<u8> = self.transitions.iter().map(|t| t.pin).collect();
+        pins.sort_unstable();
+        pins.dedup();
+        pins
+    }
+
+    /// Get the time of the last transition.
+    pub fn duration(&self) -> Duration {
+        self.transitions
+            .last()
+            .map_or(Duration::ZERO, |transition| transition.time)
+    }
+
+    /// Move the transitions of a pin to another one, e.g. to replay a capture onto a fake pin.
+    pub fn map_pin(mut self, from: u8, to: u8) -> Self {
+        for transition in &mut self.transitions {
+            if transition.pin == from {
+                transition.pin = to;
+            }
+        }
+        self
+    }
+
+    /// Set the levels of the pins of a mock backend at the times of the transitions,
+    /// relative to now. The pins must be exported.
+    /// Transitions to the current level of a pin are skipped, so watchers don't see a glitch.
+    pub async fn replay(&self, backend: &MockBackend) -> Result<()> {
+        let start = time::Instant::now();
+        for transition in &self.transitions {
+            time::sleep_until(start + transition.time).await;
+            let value = transition.level.into();
+            if backend.get_value(transition.pin)? != value {
+                backend.set_value(transition.pin, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay the waveform onto the fake pins, see [Waveform::replay].
+    pub async fn replay_fake(&self) -> Result<()> {
+        self.replay(&mock::backend()).await
+    }
+}
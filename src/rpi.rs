@@ -0,0 +1,148 @@
+//
+// This file provides a backend for the Raspberry Pi, so fleets mixing Orange Pi and Raspberry Pi
+// boards can share the code built on this crate. It's available with the `rpi` feature.
+//
+// The pins are numbered by their BCM numbers, like on the Raspberry Pi, and accessed through the
+// GPIO character device of the controller driving the header: the BCM2835 one up to the Pi 3,
+// the BCM2711 one on the Pi 4 and the RP1 one on the Pi 5, whatever the number of its chip.
+// The sysfs numbers of these lines start at a base like 512, which doesn't fit the `u8` pin
+// numbers of the crate, so sysfs isn't used. Only the GPIOs of the header, BCM 0 to 27,
+// can be used, the other lines driving the peripherals of the board.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::cdev::{self, CdevBackend};
+use super::error::{GpioError, Result};
+use super::pin::{Bias, Direction, Edge};
+use async_trait::async_trait;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Labels of the GPIO controllers driving the header of the Raspberry Pi models.
+const HEADER_CONTROLLERS: &[&str] = &["pinctrl-bcm2835", "pinctrl-bcm2711", "pinctrl-rp1"];
+/// Number of GPIOs on the header, BCM 0 to 27.
+const HEADER_GPIOS: u8 = 28;
+
+/// Find the GPIO chip of the header among the `gpiochipN` devices of a directory,
+/// getting the label of each chip with the given function.
+pub(crate) fn find_chip(
+    directory: &Path,
+    label_of: impl Fn(&Path) -> Result<String>,
+) -> Result<PathBuf> {
+    let mut chips: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("gpiochip"))
+        })
+        .collect();
+    chips.sort();
+
+    let is_header = |label: String| HEADER_CONTROLLERS.contains(&label.as_str());
+    chips
+        .into_iter()
+        .find(|chip| label_of(chip).is_ok_and(is_header))
+        .ok_or_else(|| {
+            GpioError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No GPIO chip of a Raspberry Pi header",
+            ))
+        })
+}
+
+/// Backend accessing the GPIOs of the Raspberry Pi header by their BCM numbers.
+#[derive(Debug)]
+pub struct RpiBackend {
+    cdev: CdevBackend,
+}
+
+impl RpiBackend {
+    /// Open the GPIO chip of the header, found among the chips in `/dev`.
+    pub fn new() -> Result<Self> {
+        let chip = find_chip(Path::new("/dev"), |chip| cdev::chip_label(chip))?;
+        Ok(Self::with_chip(chip))
+    }
+
+    /// Use the given GPIO chip, e.g. `/dev/gpiochip0`.
+    pub fn with_chip(chip: impl Into<PathBuf>) -> Self {
+        Self {
+            cdev: CdevBackend::new(chip),
+        }
+    }
+
+    /// Get the path of the GPIO chip of the header.
+    pub fn chip(&self) -> &Path {
+        self.cdev.chip()
+    }
+}
+
+/// Check that the pin is a GPIO of the header.
+fn check_header(pin_number: u8) -> Result<()> {
+    if pin_number >= HEADER_GPIOS {
+        return Err(GpioError::UnmappedPin {
+            scheme: "BCM",
+            pin: pin_number,
+        });
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl GpioBackend for RpiBackend {
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        check_header(pin_number)?;
+        self.cdev.export_input(pin_number).await
+    }
+
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        check_header(pin_number)?;
+        self.cdev.export_output(pin_number, default).await
+    }
+
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        self.cdev.direction(pin_number).await
+    }
+
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        self.cdev.unexport(pin_number).await
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        self.cdev.read(pin_number).await
+    }
+
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        self.cdev.write(pin_number, value).await
+    }
+
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        self.cdev.set_edge(pin_number, edge).await
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        self.cdev.edge(pin_number).await
+    }
+
+    async fn exported(&self) -> Result<Vec<u8>> {
+        self.cdev.exported().await
+    }
+
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()> {
+        self.cdev.set_bias(pin_number, bias).await
+    }
+
+    async fn set_drive_strength(&self, pin_number: u8, milliamps: u8) -> Result<()> {
+        self.cdev.set_drive_strength(pin_number, milliamps).await
+    }
+
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        self.cdev.set_active_low(pin_number, active_low).await
+    }
+
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        self.cdev.watch(pin_number)
+    }
+}
@@ -0,0 +1,195 @@
+//
+// This file provides hobby servo control on top of a hardware or software PWM.
+// A servo reads its angle from the width of a pulse repeated at the refresh rate,
+// usually 50Hz, the pulse widths of both ends of the range varying between models.
+// The calibration maps the angles to the pulse widths of a given servo.
+//
+// Software PWM jitters with the tokio timer, which makes the servo twitch,
+// so hardware PWM should be preferred when a channel is available.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::OutputPin;
+use super::pwm::PwmPin;
+use super::softpwm::SoftPwm;
+use std::time::Duration;
+
+/// Default refresh rate of the pulses in Hz.
+const DEFAULT_REFRESH_RATE: f64 = 50.0;
+
+/// Pulse widths at both ends of the range of a servo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoCalibration {
+    /// Pulse width at 0°, 1ms by default
+    pub min_pulse: Duration,
+    /// Pulse width at the maximum angle, 2ms by default
+    pub max_pulse: Duration,
+    /// Angle of the end of the range in degrees, 180° by default
+    pub max_angle: f64,
+}
+
+impl Default for ServoCalibration {
+    fn default() -> Self {
+        Self {
+            min_pulse: Duration::from_micros(1000),
+            max_pulse: Duration::from_micros(2000),
+            max_angle: 180.0,
+        }
+    }
+}
+
+/// PWM generating the pulses of a [Servo].
+#[derive(Debug)]
+enum ServoOutput {
+    Hardware(PwmPin),
+    Software(SoftPwm),
+}
+
+/// Hobby servo driven by a PWM signal.
+/// The servo is not driven until an angle is set.
+#[derive(Debug)]
+pub struct Servo {
+    output: ServoOutput,
+    calibration: ServoCalibration,
+    refresh_rate: f64,
+    pulse: Option<Duration>,
+}
+
+impl Servo {
+    /// Create a servo driven by a hardware PWM channel.
+    pub async fn new_hardware(mut pwm: PwmPin, calibration: ServoCalibration) -> Result<Self> {
+        check_calibration(&calibration)?;
+        pwm.disable().await?;
+        pwm.set_duty_cycle(Duration::ZERO).await?;
+        pwm.set_period(Duration::from_secs_f64(1.0 / DEFAULT_REFRESH_RATE))
+            .await?;
+
+        Ok(Self {
+            output: ServoOutput::Hardware(pwm),
+            calibration,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            pulse: None,
+        })
+    }
+
+    /// Create a servo driven by a software PWM on the given pin.
+    pub fn new_software(pin: OutputPin, calibration: ServoCalibration) -> Result<Self> {
+        check_calibration(&calibration)?;
+        let pwm = SoftPwm::new(pin, DEFAULT_REFRESH_RATE, 0.0)?;
+
+        Ok(Self {
+            output: ServoOutput::Software(pwm),
+            calibration,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            pulse: None,
+        })
+    }
+
+    /// Get the calibration of the servo.
+    pub fn calibration(&self) -> ServoCalibration {
+        self.calibration
+    }
+
+    /// Get the refresh rate of the pulses in Hz.
+    pub fn refresh_rate(&self) -> f64 {
+        self.refresh_rate
+    }
+
+    /// Set the refresh rate of the pulses in Hz, the period must be longer than the pulses.
+    pub async fn set_refresh_rate(&mut self, refresh_rate: f64) -> Result<()> {
+        let period = match Duration::try_from_secs_f64(1.0 / refresh_rate) {
+            Ok(period) if period > self.calibration.max_pulse => period,
+            _ => {
+                return Err(GpioError::InvalidValue(format!(
+                    "Refresh rate {}Hz must give a period longer than the pulses of {:?}",
+                    refresh_rate, self.calibration.max_pulse
+                )));
+            }
+        };
+
+        self.refresh_rate = refresh_rate;
+        match &mut self.output {
+            ServoOutput::Hardware(pwm) => pwm.set_period(period).await?,
+            ServoOutput::Software(pwm) => pwm.set_frequency(refresh_rate)?,
+        }
+        match self.pulse {
+            Some(pulse) => self.set_pulse_width(pulse).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Move the servo to the given angle in degrees, between 0 and the maximum angle.
+    pub async fn set_angle(&mut self, angle: f64) -> Result<()> {
+        let calibration = self.calibration;
+        if !(0.0..=calibration.max_angle).contains(&angle) {
+            return Err(GpioError::InvalidValue(format!(
+                "Angle must be between 0 and {}, got {}",
+                calibration.max_angle, angle
+            )));
+        }
+
+        let range = calibration.max_pulse - calibration.min_pulse;
+        let pulse = calibration.min_pulse + range.mul_f64(angle / calibration.max_angle);
+        self.set_pulse_width(pulse).await
+    }
+
+    /// Get the current angle in degrees, `None` while the servo is not driven.
+    pub fn angle(&self) -> Option<f64> {
+        let calibration = self.calibration;
+        let range = (calibration.max_pulse - calibration.min_pulse).as_secs_f64();
+        self.pulse.map(|pulse| {
+            (pulse - calibration.min_pulse).as_secs_f64() / range * calibration.max_angle
+        })
+    }
+
+    /// Send pulses of the given width, e.g. to find the ends of the range of a servo.
+    pub async fn set_pulse_width(&mut self, pulse: Duration) -> Result<()> {
+        let period = Duration::from_secs_f64(1.0 / self.refresh_rate);
+        if pulse >= period {
+            return Err(GpioError::InvalidValue(format!(
+                "Pulse width {:?} is longer than the period {:?}",
+                pulse, period
+            )));
+        }
+
+        match &mut self.output {
+            ServoOutput::Hardware(pwm) => {
+                pwm.set_duty_cycle(pulse).await?;
+                pwm.enable().await?;
+            }
+            ServoOutput::Software(pwm) => {
+                pwm.set_duty(pulse.as_secs_f64() * self.refresh_rate)?;
+            }
+        }
+        self.pulse = Some(pulse);
+        Ok(())
+    }
+
+    /// Get the current pulse width, `None` while the servo is not driven.
+    pub fn pulse_width(&self) -> Option<Duration> {
+        self.pulse
+    }
+
+    /// Stop sending pulses, most servos then stop holding their position.
+    pub async fn disable(&mut self) -> Result<()> {
+        match &mut self.output {
+            ServoOutput::Hardware(pwm) => pwm.disable().await?,
+            ServoOutput::Software(pwm) => pwm.set_duty(0.0)?,
+        }
+        self.pulse = None;
+        Ok(())
+    }
+}
+
+/// Check that the calibration maps a non-empty range of angles to increasing pulses.
+fn check_calibration(calibration: &ServoCalibration) -> Result<()> {
+    if calibration.min_pulse >= calibration.max_pulse
+        || !(calibration.max_angle > 0.0 && calibration.max_angle.is_finite())
+    {
+        return Err(GpioError::InvalidValue(format!(
+            "Invalid servo calibration {:?}",
+            calibration
+        )));
+    }
+    Ok(())
+}
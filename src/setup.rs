@@ -0,0 +1,224 @@
+//
+// This file provides the setup of pins from a TOML description of the wiring, so
+// deployments can change it without rebuilding the application.
+// Each pin is described by a label, its number, direction, default value, and for inputs
+// the edge to watch and the debounce time. Building the setup creates all the pins and
+// a single watcher for the watched inputs, and gives the pins back by label.
+//
+// [[pin]]
+// label = "button"
+// number = 7
+// direction = "in"
+// edge = "both"
+// debounce_ms = 20
+//
+// [[pin]]
+// label = "led"
+// number = 8
+// direction = "out"
+// default = 1
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Gpio;
+pub use super::pin::Direction;
+use super::pin::{Edge, GpioPin, InputPin, OutputPin};
+use super::watcher::{GpioEvent, GpioWatcher};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+use tokio::{fs, sync::mpsc};
+
+/// Description of a pin in a [PinSetup].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinConfig {
+    /// Name the pin is given back by
+    pub label: String,
+    /// SoC GPIO number of the pin
+    pub number: u8,
+    /// Direction of the pin
+    pub direction: Direction,
+    /// Initial value of an output pin
+    #[serde(default)]
+    pub default: u8,
+    /// Edge watched on an input pin, `None` to not watch it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<Edge>,
+    /// Debounce time of a watched input pin, in milliseconds in the description
+    #[serde(
+        rename = "debounce_ms",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "millis"
+    )]
+    pub debounce: Option<Duration>,
+}
+
+/// Description of the pins of an application, see the top of this file for the format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinSetup {
+    /// Pins to create, in order
+    #[serde(rename = "pin", default)]
+    pub pins: Vec<PinConfig>,
+}
+
+impl PinSetup {
+    /// Parse a TOML description of the pins, checking that it's consistent.
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let setup: Self = toml::from_str(toml)
+            .map_err(|e| GpioError::InvalidValue(format!("Invalid pin setup: {}", e.message())))?;
+        setup.check()?;
+        Ok(setup)
+    }
+
+    /// Write the TOML description of the pins.
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self)
+            .map_err(|e| GpioError::InvalidValue(format!("Invalid pin setup: {}", e)))
+    }
+
+    /// Read and parse a TOML description of the pins.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_toml(&fs::read_to_string(path).await?)
+    }
+
+    /// Check that labels and numbers are unique and that the settings fit the directions.
+    pub fn check(&self) -> Result<()> {
+        let mut labels = HashMap::new();
+        let mut numbers = HashMap::new();
+        for pin in &self.pins {
+            if labels.insert(pin.label.as_str(), pin.number).is_some() {
+                return Err(invalid(pin, "the label is used twice"));
+            }
+            if let Some(other) = numbers.insert(pin.number, pin.label.as_str()) {
+                return Err(invalid(
+                    pin,
+                    &format!("pin {} is also {}", pin.number, other),
+                ));
+            }
+            if pin.default > 1 {
+                return Err(invalid(pin, "the default value must be 0 or 1"));
+            }
+            if pin.direction == Direction::Out && (pin.edge.is_some() || pin.debounce.is_some()) {
+                return Err(invalid(pin, "outputs can't be watched"));
+            }
+            if pin.edge.is_none() && pin.debounce.is_some() {
+                return Err(invalid(pin, "only watched inputs can be debounced"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create all the pins, and a watcher sending the events of the watched inputs.
+    pub async fn build(&self, gpio: &Gpio) -> Result<PinSet> {
+        self.check()?;
+
+        let mut pins = HashMap::new();
+        let mut labels = HashMap::new();
+        let mut watched = Vec::new();
+        for config in &self.pins {
+            let pin: GpioPin = match config.direction {
+                Direction::In => {
+                    let mut pin = InputPin::new(gpio, config.number).await?;
+                    if let Some(edge) = config.edge {
+                        pin.enable_watch(edge).await?;
+                    }
+                    pin.into()
+                }
+                Direction::Out => OutputPin::new(gpio, config.number, config.default)
+                    .await?
+                    .into(),
+            };
+            let pin = Arc::new(pin);
+            if config.edge.is_some() {
+                watched.push((pin.clone(), config.debounce));
+            }
+            pins.insert(config.label.clone(), pin);
+            labels.insert(config.number, config.label.clone());
+        }
+
+        // Watch the inputs with an edge, all sending to the same channel
+        let (sender, events) = mpsc::unbounded_channel();
+        let pin_map: HashMap<Arc<GpioPin>, _> = watched
+            .iter()
+            .map(|(pin, _)| (pin.clone(), sender.clone()))
+            .collect();
+        let watcher = GpioWatcher::new(pin_map).await?;
+        for (pin, debounce) in watched {
+            watcher.set_debounce(pin.get_pin_number(), debounce).await?;
+        }
+
+        Ok(PinSet {
+            pins,
+            labels,
+            watcher,
+            events,
+        })
+    }
+}
+
+/// Pins created from a [PinSetup], by label, with the watcher of the watched inputs.
+///
+/// Dropping this will stop the watcher.
+pub struct PinSet {
+    pins: HashMap<String, Arc<GpioPin>>,
+    labels: HashMap<u8, String>,
+    watcher: GpioWatcher,
+    events: mpsc::UnboundedReceiver<GpioEvent>,
+}
+
+impl PinSet {
+    /// Get a pin by its label.
+    pub fn pin(&self, label: &str) -> Option<&Arc<GpioPin>> {
+        self.pins.get(label)
+    }
+
+    /// Get the label of a pin by its number.
+    pub fn label(&self, pin_number: u8) -> Option<&str> {
+        self.labels.get(&pin_number).map(String::as_str)
+    }
+
+    /// Get the labels of all the pins.
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.pins.keys().map(String::as_str)
+    }
+
+    /// Get the watcher of the watched inputs, e.g. to change their debounce time.
+    pub fn watcher(&self) -> &GpioWatcher {
+        &self.watcher
+    }
+
+    /// Wait for the next event of the watched inputs, starting with their initial values.
+    /// Use [PinSet::label] to tell which pin it's from.
+    pub async fn next_event(&mut self) -> Option<GpioEvent> {
+        self.events.recv().await
+    }
+}
+
+/// Create the error of an inconsistent pin description.
+fn invalid(pin: &PinConfig, reason: &str) -> GpioError {
+    GpioError::InvalidValue(format!("Invalid pin setup for {:?}: {}", pin.label, reason))
+}
+
+/// (De)serialization of optional durations as a number of milliseconds.
+mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_u64(duration.as_millis() as u64),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+    }
+}
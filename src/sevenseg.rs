@@ -0,0 +1,237 @@
+//
+// This file provides a driver for multiplexed seven-segment displays, whose digits share
+// the segment lines and each have a common pin. Only one digit is lit at a time,
+// cycling fast enough from a background task for the eye to see all of them at once.
+//
+// The segment pins are given in the order a, b, c, d, e, f, g and optionally dp,
+// and the digit pins from left to right, so any wiring maps to the display.
+//
+
+use super::error::{GpioError, Result};
+use super::group::PinGroup;
+use std::time::Duration;
+use tokio::{sync::watch, task::JoinHandle, time};
+
+/// Segment bit of the decimal point, bits 0 to 6 being segments a to g.
+const DECIMAL_POINT: u8 = 0x80;
+
+/// Wiring and timing of a [SevenSegment] display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SevenSegmentConfig {
+    /// Whether the digits share their anode, lighting segments driven low,
+    /// false by default for a common cathode
+    pub common_anode: bool,
+    /// Number of times per second the whole display is refreshed, 100Hz by default
+    pub refresh_rate: f64,
+}
+
+impl Default for SevenSegmentConfig {
+    fn default() -> Self {
+        Self {
+            common_anode: false,
+            refresh_rate: 100.0,
+        }
+    }
+}
+
+/// Multiplexed seven-segment display.
+///
+/// Dropping this will stop the refresh, leaving the last digit lit.
+pub struct SevenSegment {
+    digits: usize,
+    segments: watch::Sender<Vec<u8>>,
+    display_thread: JoinHandle<()>,
+}
+
+impl Drop for SevenSegment {
+    fn drop(&mut self) {
+        self.display_thread.abort();
+    }
+}
+
+impl SevenSegment {
+    /// Start refreshing a blank display on the segment pins (a to g, then dp if wired)
+    /// and the digit pins, from left to right.
+    pub async fn new(
+        segment_pins: PinGroup,
+        digit_pins: PinGroup,
+        config: SevenSegmentConfig,
+    ) -> Result<Self> {
+        if !(7..=8).contains(&segment_pins.len()) || digit_pins.is_empty() {
+            return Err(GpioError::InvalidValue(format!(
+                "A display needs 7 or 8 segment pins and at least one digit pin, got {} and {}",
+                segment_pins.len(),
+                digit_pins.len()
+            )));
+        }
+        if !(config.refresh_rate > 0.0 && config.refresh_rate.is_finite()) {
+            return Err(GpioError::InvalidValue(format!(
+                "Refresh rate must be positive, got {}",
+                config.refresh_rate
+            )));
+        }
+
+        let digits = digit_pins.len();
+        digit_pins.write_all(!config.common_anode as u8).await?;
+        let (segments, receiver) = watch::channel(vec![0; digits]);
+        let display_thread = tokio::spawn(run_display(segment_pins, digit_pins, config, receiver));
+
+        Ok(Self {
+            digits,
+            segments,
+            display_thread,
+        })
+    }
+
+    /// Get the number of digits of the display.
+    pub fn digit_count(&self) -> usize {
+        self.digits
+    }
+
+    /// Show raw segment patterns, one per digit from the left,
+    /// bits 0 to 6 being segments a to g and bit 7 the decimal point.
+    pub fn set_segments(&self, segments: &[u8]) -> Result<()> {
+        if segments.len() != self.digits {
+            return Err(GpioError::InvalidValue(format!(
+                "Got {} patterns for a display of {} digits",
+                segments.len(),
+                self.digits
+            )));
+        }
+        self.segments.send_replace(segments.to_vec());
+        Ok(())
+    }
+
+    /// Get the segment patterns currently shown, one per digit from the left.
+    pub fn segments(&self) -> Vec<u8> {
+        self.segments.borrow().clone()
+    }
+
+    /// Show text aligned to the left, see [encode_char] for the characters that can be shown.
+    /// A `.` lights the decimal point of the previous character.
+    pub fn set_text(&self, text: &str) -> Result<()> {
+        let mut segments: Vec<u8> = Vec::with_capacity(self.digits);
+        for c in text.chars() {
+            match segments.last_mut() {
+                Some(last) if c == '.' && *last & DECIMAL_POINT == 0 => *last |= DECIMAL_POINT,
+                _ => segments.push(encode_char(c).ok_or_else(|| {
+                    GpioError::InvalidValue(format!("Character {:?} can't be shown", c))
+                })?),
+            }
+        }
+        if segments.len() > self.digits {
+            return Err(GpioError::InvalidValue(format!(
+                "Text {:?} doesn't fit on {} digits",
+                text, self.digits
+            )));
+        }
+
+        segments.resize(self.digits, 0);
+        self.segments.send_replace(segments);
+        Ok(())
+    }
+
+    /// Show a number aligned to the right.
+    pub fn set_number(&self, number: i64) -> Result<()> {
+        let text = number.to_string();
+        if text.len() > self.digits {
+            return Err(GpioError::InvalidValue(format!(
+                "Number {} doesn't fit on {} digits",
+                number, self.digits
+            )));
+        }
+        self.set_text(&format!("{:>width$}", text, width = self.digits))
+    }
+
+    /// Turn all the segments off.
+    pub fn clear(&self) {
+        self.segments.send_replace(vec![0; self.digits]);
+    }
+}
+
+/// Get the segment pattern of a character, bits 0 to 6 being segments a to g.
+/// Digits, space, `-`, `_`, `=`, `.` and the letters that are readable on 7 segments
+/// can be shown, lowercase when only that form is, e.g. `b` and `n`.
+pub fn encode_char(c: char) -> Option<u8> {
+    let segments = match c {
+        ' ' => 0x00,
+        '0' | 'O' => 0x3f,
+        '1' => 0x06,
+        '2' => 0x5b,
+        '3' => 0x4f,
+        '4' => 0x66,
+        '5' | 'S' | 's' => 0x6d,
+        '6' => 0x7d,
+        '7' => 0x07,
+        '8' => 0x7f,
+        '9' => 0x6f,
+        'A' | 'a' => 0x77,
+        'B' | 'b' => 0x7c,
+        'C' => 0x39,
+        'c' => 0x58,
+        'D' | 'd' => 0x5e,
+        'E' | 'e' => 0x79,
+        'F' | 'f' => 0x71,
+        'G' | 'g' => 0x3d,
+        'H' => 0x76,
+        'h' => 0x74,
+        'I' | 'i' => 0x30,
+        'J' | 'j' => 0x1e,
+        'L' | 'l' => 0x38,
+        'N' | 'n' => 0x54,
+        'o' => 0x5c,
+        'P' | 'p' => 0x73,
+        'Q' | 'q' => 0x67,
+        'R' | 'r' => 0x50,
+        'T' | 't' => 0x78,
+        'U' => 0x3e,
+        'u' => 0x1c,
+        'Y' | 'y' => 0x6e,
+        '-' => 0x40,
+        '_' => 0x08,
+        '=' => 0x48,
+        '.' => DECIMAL_POINT,
+        _ => return None,
+    };
+    Some(segments)
+}
+
+/// Light the digits one after the other with their segments.
+async fn run_display(
+    segment_pins: PinGroup,
+    digit_pins: PinGroup,
+    config: SevenSegmentConfig,
+    receiver: watch::Receiver<Vec<u8>>,
+) {
+    let digit_time = Duration::from_secs_f64(1.0 / (config.refresh_rate * digit_pins.len() as f64));
+    // A common cathode is selected low and lights segments driven high, the reverse for an anode
+    let selected = config.common_anode as u8;
+    let lit = 1 - selected;
+    let mut pattern = vec![0; segment_pins.len()];
+
+    loop {
+        let segments = receiver.borrow().clone();
+        for (digit, (pin, &digit_segments)) in digit_pins.pins().iter().zip(&segments).enumerate() {
+            for (bit, level) in pattern.iter_mut().enumerate() {
+                *level = if digit_segments >> bit & 1 == 1 {
+                    lit
+                } else {
+                    1 - lit
+                };
+            }
+
+            // Turn the previous digit off before changing the segments, so they don't bleed
+            let previous = &digit_pins.pins()[(digit + digit_pins.len() - 1) % digit_pins.len()];
+            let result = async {
+                previous.write(1 - selected).await?;
+                segment_pins.write_pattern(&pattern).await?;
+                pin.write(selected).await
+            }
+            .await;
+            if let Err(e) = result {
+                log::error!("Error refreshing seven-segment display: {}", e);
+            }
+            time::sleep(digit_time).await;
+        }
+    }
+}
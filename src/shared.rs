@@ -0,0 +1,96 @@
+//
+// This file provides a pin handle shared between tokio tasks, e.g. a web handler and a control
+// loop driving the same output. Clones share the pin behind an async mutex, so accesses are
+// serialized: a toggle or a sequence of writes done under the lock isn't interleaved with
+// the writes of another task.
+//
+
+use super::error::Result;
+use super::pin::GpioPin;
+use std::sync::Arc;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Pin that can be cloned and used from several tasks.
+/// Clones are compared by the pin they share.
+#[derive(Debug, Clone)]
+pub struct SharedPin {
+    pin_number: u8,
+    pin: Arc<Mutex<GpioPin>>,
+}
+
+impl PartialEq for SharedPin {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.pin, &other.pin)
+    }
+}
+
+impl Eq for SharedPin {}
+
+impl<P: Into<GpioPin>> From<P> for SharedPin {
+    fn from(pin: P) -> Self {
+        Self::new(pin)
+    }
+}
+
+impl SharedPin {
+    /// Share an input or output pin.
+    pub fn new(pin: impl Into<GpioPin>) -> Self {
+        let pin = pin.into();
+        Self {
+            pin_number: pin.get_pin_number(),
+            pin: Arc::new(Mutex::new(pin)),
+        }
+    }
+
+    /// Get the pin number of the pin.
+    pub fn get_pin_number(&self) -> u8 {
+        self.pin_number
+    }
+
+    /// Read the value from the pin.
+    pub async fn read(&self) -> Result<u8> {
+        self.pin.lock().await.read().await
+    }
+
+    /// Write a value to the pin, once the writes of the other clones are done.
+    /// Writing to an input pin is not allowed.
+    pub async fn write(&self, value: u8) -> Result<()> {
+        self.pin.lock().await.write(value).await
+    }
+
+    /// Invert the value of the pin and return the new value,
+    /// without any other write in between.
+    pub async fn toggle(&self) -> Result<u8> {
+        self.pin.lock().await.toggle().await
+    }
+
+    /// Write a value to the pin only if it differs from the last value written.
+    /// Returns whether a write happened.
+    pub async fn write_if_changed(&self, value: u8) -> Result<bool> {
+        self.pin.lock().await.write_if_changed(value).await
+    }
+
+    /// Get the last value written to the pin, `None` for an input pin.
+    pub async fn last_value(&self) -> Option<u8> {
+        self.pin.lock().await.last_value()
+    }
+
+    /// Lock the pin for a sequence of operations, e.g. a pulse that no other clone
+    /// may interrupt. The other clones wait until the guard is dropped.
+    pub async fn lock(&self) -> MutexGuard<'_, GpioPin> {
+        self.pin.lock().await
+    }
+
+    /// Get the number of clones sharing the pin.
+    pub fn handles(&self) -> usize {
+        Arc::strong_count(&self.pin)
+    }
+
+    /// Take the pin back, or get the handle back if other clones still share it.
+    pub fn into_inner(self) -> std::result::Result<GpioPin, Self> {
+        let pin_number = self.pin_number;
+        Arc::try_unwrap(self.pin)
+            .map(Mutex::into_inner)
+            .map_err(|pin| Self { pin_number, pin })
+    }
+}
@@ -0,0 +1,105 @@
+//
+// This file provides a signal generator for the fake pins, to test watchers, debouncing
+// and pulse counting deterministically without hardware.
+// A signal is built as a script of levels held for some time, with helpers for square waves
+// and bursts of pulses, and is driven onto the mock pins as a [Waveform]. Tests pausing the
+// tokio time run it instantly, the time advancing from one transition to the next.
+//
+
+use super::error::{GpioError, Result};
+use super::mock::{self, MockBackend};
+use super::pin::Level;
+use super::recorder::Transition;
+use super::replay::Waveform;
+use std::time::Duration;
+
+/// Signal driven onto a fake pin, as a sequence of levels held for some time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalGenerator {
+    pin: u8,
+    steps: Vec<(Level, Duration)>,
+}
+
+impl SignalGenerator {
+    /// Start an empty signal on the given pin.
+    pub fn new(pin: u8) -> Self {
+        Self {
+            pin,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Get the pin the signal is driven onto.
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Set the pin to a level and hold it for the given time.
+    pub fn hold(mut self, level: impl Into<Level>, duration: Duration) -> Self {
+        self.steps.push((level.into(), duration));
+        self
+    }
+
+    /// Add a scripted sequence of values held for the given times.
+    pub fn script(self, steps: &[(u8, Duration)]) -> Self {
+        steps.iter().fold(self, |signal, &(value, duration)| {
+            signal.hold(value, duration)
+        })
+    }
+
+    /// Add pulses, each high for `high` then low for `low`.
+    pub fn pulses(self, count: usize, high: Duration, low: Duration) -> Self {
+        (0..count).fold(self, |signal, _| {
+            signal.hold(Level::High, high).hold(Level::Low, low)
+        })
+    }
+
+    /// Add cycles of a square wave of the given period, high for the duty ratio of each period.
+    pub fn square(self, period: Duration, duty: f64, cycles: usize) -> Result<Self> {
+        if !(0.0..=1.0).contains(&duty) {
+            return Err(GpioError::InvalidValue(format!(
+                "Duty ratio must be between 0.0 and 1.0, got {}",
+                duty
+            )));
+        }
+        let high = period.mul_f64(duty);
+        Ok(self.pulses(cycles, high, period - high))
+    }
+
+    /// Get the total time of the signal.
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// Get the transitions of the signal, from its start.
+    /// Consecutive steps at the same level make a single transition.
+    pub fn waveform(&self) -> Waveform {
+        let mut transitions: Vec<Transition> = Vec::new();
+        let mut time = Duration::ZERO;
+        for &(level, duration) in &self.steps {
+            if transitions.last().is_none_or(|last| last.level != level) {
+                transitions.push(Transition {
+                    pin: self.pin,
+                    level,
+                    time,
+                });
+            }
+            time += duration;
+        }
+        Waveform::new(transitions)
+    }
+
+    /// Drive the signal onto an exported pin of a mock backend, returning at its end.
+    /// The pin keeps the last level afterwards.
+    pub async fn run(&self, backend: &MockBackend) -> Result<()> {
+        let start = tokio::time::Instant::now();
+        self.waveform().replay(backend).await?;
+        tokio::time::sleep_until(start + self.duration()).await;
+        Ok(())
+    }
+
+    /// Drive the signal onto a fake pin, see [SignalGenerator::run].
+    pub async fn run_fake(&self) -> Result<()> {
+        self.run(&mock::backend()).await
+    }
+}
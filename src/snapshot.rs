@@ -0,0 +1,134 @@
+//
+// This file provides snapshots of the state of the pins, like the `gpio readall` command:
+// the direction, value and edge of the pins of the board header and of the other exported pins.
+// They're meant for diagnostics, e.g. a status endpoint, and for checking the state of
+// several pins at once in tests.
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Gpio;
+use super::pin::{Direction, Edge};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt};
+
+/// State of a pin when a [Snapshot] was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PinState {
+    /// SoC number of the pin
+    pub pin_number: u8,
+    /// Position of the pin on the board header, `None` if it's not on the header
+    pub physical: Option<u8>,
+    /// wiringOP number of the pin, `None` if it's not on the header
+    pub wiring: Option<u8>,
+    /// Direction of the pin, `None` if it's not exported
+    pub direction: Option<Direction>,
+    /// Value of the pin, `None` if it's not exported
+    pub value: Option<u8>,
+    /// Edge notification is enabled on, `None` if disabled or not exported
+    pub edge: Option<Edge>,
+}
+
+impl PinState {
+    /// Check if the pin was exported.
+    pub fn is_exported(&self) -> bool {
+        self.direction.is_some()
+    }
+}
+
+/// State of the pins of the board header and of the other exported pins, by SoC number.
+/// It's displayed as a table like `gpio readall`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    /// States of the pins, by increasing SoC number
+    pub pins: Vec<PinState>,
+}
+
+impl Snapshot {
+    /// Read the state of the pins of the header of the context's pin map,
+    /// and of the other exported pins if the backend can list them.
+    pub async fn take(gpio: &Gpio) -> Result<Self> {
+        let pin_map = gpio.config().pin_map;
+        let mut pin_numbers: BTreeSet<u8> = pin_map.pins().map(|(_, soc)| soc).collect();
+        match gpio.backend().exported().await {
+            Ok(exported) => pin_numbers.extend(exported),
+            Err(GpioError::Unsupported(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut pins = Vec::with_capacity(pin_numbers.len());
+        for pin_number in pin_numbers {
+            let mut state = PinState {
+                pin_number,
+                physical: pin_map.soc_to_physical(pin_number).ok(),
+                wiring: pin_map.soc_to_wiring(pin_number).ok(),
+                direction: None,
+                value: None,
+                edge: None,
+            };
+            match read_state(gpio, &mut state).await {
+                Ok(()) | Err(GpioError::NotExported(_)) => pins.push(state),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Self { pins })
+    }
+
+    /// Get the state of a pin by SoC number.
+    pub fn get(&self, pin_number: u8) -> Option<&PinState> {
+        self.pins.iter().find(|pin| pin.pin_number == pin_number)
+    }
+
+    /// Iterate over the states of the exported pins.
+    pub fn exported(&self) -> impl Iterator<Item = &PinState> {
+        self.pins.iter().filter(|pin| pin.is_exported())
+    }
+}
+
+impl fmt::Display for Snapshot {
+    /// Print a row per pin, with `-` for the unknown fields.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn column(value: Option<impl ToString>) -> String {
+            value.map_or("-".to_string(), |value| value.to_string())
+        }
+
+        write!(f, " Physical | wPi | SoC | Mode | Value | Edge")?;
+        for pin in &self.pins {
+            let mode = pin.direction.map(|direction| match direction {
+                Direction::In => "in",
+                Direction::Out => "out",
+            });
+            let edge = match (pin.direction, pin.edge) {
+                (None, _) => None,
+                (Some(_), edge) => Some(edge.map_or("none", |edge| edge.as_edge())),
+            };
+            write!(
+                f,
+                "\n {:>8} | {:>3} | {:>3} | {:>4} | {:>5} | {}",
+                column(pin.physical),
+                column(pin.wiring),
+                pin.pin_number,
+                column(mode),
+                column(pin.value),
+                column(edge)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the direction, value and edge of a pin, stopping at the first failure.
+/// Backends without edges leave it unknown.
+async fn read_state(gpio: &Gpio, state: &mut PinState) -> Result<()> {
+    let backend = gpio.backend();
+    state.direction = Some(backend.direction(state.pin_number).await?);
+    state.value = Some(backend.read(state.pin_number).await?);
+    state.edge = match backend.edge(state.pin_number).await {
+        Ok(edge) => edge,
+        Err(GpioError::Unsupported(_)) => None,
+        Err(e) => return Err(e),
+    };
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//
+// This file provides a software PWM for pins without a hardware PWM channel.
+// A tokio task toggles an output pin at the configured frequency and duty cycle.
+// The timing relies on the tokio timer, so it's only suitable for low frequencies
+// such as dimming LEDs or driving slow actuators.
+// A precise PWM runs on a dedicated OS thread instead, sleeping until shortly before each
+// edge and busy-waiting the rest, for signals like servo pulses that need a steady timing.
+// With the virtual clock of the context, the precise PWM falls back to a tokio task,
+// so tests on paused time see the same signal.
+// Any [PinLike] pin can carry the signal of a tokio task, e.g. the line of a port expander,
+// at the rate its writes allow.
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Clock;
+use super::pin::OutputPin;
+use super::pinlike::PinLike;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use tokio::{
+    runtime::Handle,
+    sync::watch,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+/// Time before an edge the timing thread stops sleeping and busy-waits,
+/// covering the wake-up latency of the OS scheduler.
+const SPIN_MARGIN: Duration = Duration::from_micros(200);
+
+/// Longest sleep of the timing thread, so it sees setting changes and stops promptly.
+const MAX_SLEEP: Duration = Duration::from_millis(10);
+
+/// Frequency and duty cycle of a [SoftPwm].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PwmSettings {
+    frequency: f64,
+    duty: f64,
+}
+
+/// Settings of a precise [SoftPwm], read by its timing thread without locking.
+#[derive(Debug)]
+struct AtomicSettings {
+    /// Bits of the frequency in Hz
+    frequency: AtomicU64,
+    /// Bits of the duty ratio
+    duty: AtomicU64,
+    stopped: AtomicBool,
+}
+
+impl AtomicSettings {
+    fn new(settings: PwmSettings) -> Self {
+        Self {
+            frequency: AtomicU64::new(settings.frequency.to_bits()),
+            duty: AtomicU64::new(settings.duty.to_bits()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    fn load(&self) -> PwmSettings {
+        PwmSettings {
+            frequency: f64::from_bits(self.frequency.load(Ordering::Relaxed)),
+            duty: f64::from_bits(self.duty.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn store(&self, settings: PwmSettings) {
+        self.frequency
+            .store(settings.frequency.to_bits(), Ordering::Relaxed);
+        self.duty.store(settings.duty.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Task or thread generating the signal of a [SoftPwm].
+#[derive(Debug)]
+enum PwmThread<P> {
+    /// tokio task following the settings channel
+    Task(JoinHandle<P>),
+    /// OS thread following the atomic settings
+    Precise(Arc<AtomicSettings>, thread::JoinHandle<P>),
+}
+
+/// Software PWM driving an [OutputPin], or any [PinLike] pin, from a tokio task,
+/// or from an OS thread if precise.
+/// Settings can be changed at any time while the signal is running.
+///
+/// Dropping this will stop the signal and leave the pin low.
+#[derive(Debug)]
+pub struct SoftPwm<P = OutputPin> {
+    settings: watch::Sender<Option<PwmSettings>>,
+    pwm_thread: Option<PwmThread<P>>,
+}
+
+impl<P> Drop for SoftPwm<P> {
+    fn drop(&mut self) {
+        // The task or thread drives the pin low and exits once it sees the stop signal
+        let _ = self.settings.send(None);
+        if let Some(PwmThread::Precise(settings, _)) = &self.pwm_thread {
+            settings.stopped.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl<P: PinLike + 'static> SoftPwm<P> {
+    /// Start a software PWM on the given pin.
+    /// The frequency is in Hz and the duty is a ratio between 0.0 and 1.0.
+    pub fn new(pin: P, frequency: f64, duty: f64) -> Result<Self> {
+        check_frequency(frequency)?;
+        check_duty(duty)?;
+
+        let (settings, receiver) = watch::channel(Some(PwmSettings { frequency, duty }));
+        let pwm_thread = tokio::spawn(run_pwm(pin, receiver));
+
+        Ok(Self {
+            settings,
+            pwm_thread: Some(PwmThread::Task(pwm_thread)),
+        })
+    }
+}
+
+impl SoftPwm {
+    /// Start a software PWM on the given pin, generated by a dedicated OS thread.
+    /// The edges are timed to a few microseconds instead of the millisecond of the tokio timer,
+    /// at the cost of a thread busy-waiting before each edge, e.g. for servos.
+    /// The writes should be fast, e.g. with the `mmio` backend, as their latency delays the edges.
+    /// With [Clock::Virtual], the signal is generated by a tokio task like [SoftPwm::new].
+    pub fn precise(pin: OutputPin, frequency: f64, duty: f64) -> Result<Self> {
+        if pin.clock() == Clock::Virtual {
+            return Self::new(pin, frequency, duty);
+        }
+        check_frequency(frequency)?;
+        check_duty(duty)?;
+
+        let current = PwmSettings { frequency, duty };
+        let (settings, _) = watch::channel(Some(current));
+        let shared = Arc::new(AtomicSettings::new(current));
+        let runtime = Handle::current();
+        let pwm_thread = thread::Builder::new()
+            .name(format!("soft-pwm-{}", pin.get_pin_number()))
+            .spawn({
+                let shared = shared.clone();
+                move || run_precise_pwm(pin, shared, runtime)
+            })?;
+
+        Ok(Self {
+            settings,
+            pwm_thread: Some(PwmThread::Precise(shared, pwm_thread)),
+        })
+    }
+}
+
+impl<P: PinLike + 'static> SoftPwm<P> {
+    /// Check if the signal is generated by a dedicated OS thread.
+    pub fn is_precise(&self) -> bool {
+        matches!(self.pwm_thread, Some(PwmThread::Precise(..)))
+    }
+
+    /// Get the current frequency in Hz.
+    pub fn frequency(&self) -> f64 {
+        self.settings.borrow().map_or(0.0, |s| s.frequency)
+    }
+
+    /// Get the current duty ratio.
+    pub fn duty(&self) -> f64 {
+        self.settings.borrow().map_or(0.0, |s| s.duty)
+    }
+
+    /// Change the frequency in Hz.
+    pub fn set_frequency(&self, frequency: f64) -> Result<()> {
+        check_frequency(frequency)?;
+        self.settings.send_modify(|settings| {
+            if let Some(settings) = settings {
+                settings.frequency = frequency;
+            }
+        });
+        self.update_precise();
+        Ok(())
+    }
+
+    /// Change the duty ratio (0.0 to 1.0).
+    pub fn set_duty(&self, duty: f64) -> Result<()> {
+        check_duty(duty)?;
+        self.settings.send_modify(|settings| {
+            if let Some(settings) = settings {
+                settings.duty = duty;
+            }
+        });
+        self.update_precise();
+        Ok(())
+    }
+
+    /// Stop the signal, leave the pin low and give the pin back.
+    pub async fn stop(mut self) -> Result<P> {
+        let _ = self.settings.send(None);
+        match self.pwm_thread.take() {
+            Some(PwmThread::Task(pwm_thread)) => pwm_thread
+                .await
+                .map_err(|e| GpioError::TaskFailed(e.to_string())),
+            Some(PwmThread::Precise(settings, pwm_thread)) => {
+                settings.stopped.store(true, Ordering::Release);
+                tokio::task::spawn_blocking(move || pwm_thread.join())
+                    .await
+                    .map_err(|e| GpioError::TaskFailed(e.to_string()))?
+                    .map_err(|_| GpioError::TaskFailed("Software PWM thread panicked".to_string()))
+            }
+            None => Err(GpioError::TaskFailed(
+                "Software PWM is already stopped".to_string(),
+            )),
+        }
+    }
+
+    /// Pass the current settings to the timing thread of a precise PWM.
+    fn update_precise(&self) {
+        if let (Some(PwmThread::Precise(shared, _)), Some(current)) =
+            (&self.pwm_thread, *self.settings.borrow())
+        {
+            shared.store(current);
+        }
+    }
+}
+
+/// Toggle the pin according to the settings until the stop signal is received.
+async fn run_pwm<P: PinLike>(pin: P, mut settings: watch::Receiver<Option<PwmSettings>>) -> P {
+    loop {
+        let Some(current) = *settings.borrow_and_update() else {
+            break;
+        };
+
+        let period = Duration::from_secs_f64(1.0 / current.frequency);
+        let high = period.mul_f64(current.duty);
+
+        // Fully off or fully on, hold the level until the settings change
+        if high.is_zero() || high >= period {
+            write_level(&pin, if high.is_zero() { 0 } else { 1 }).await;
+            if settings.changed().await.is_err() {
+                break;
+            }
+            continue;
+        }
+
+        // Use absolute deadlines so the period doesn't drift with the write latency
+        let start = Instant::now();
+        write_level(&pin, 1).await;
+        tokio::select! {
+            _ = time::sleep_until(start + high) => {}
+            _ = settings.changed() => continue,
+        }
+        write_level(&pin, 0).await;
+        tokio::select! {
+            _ = time::sleep_until(start + period) => {}
+            _ = settings.changed() => continue,
+        }
+    }
+
+    write_level(&pin, 0).await;
+    pin
+}
+
+/// Toggle the pin according to the atomic settings until they're stopped.
+/// The writes are run on the runtime the PWM was started from, blocking the thread.
+fn run_precise_pwm(pin: OutputPin, settings: Arc<AtomicSettings>, runtime: Handle) -> OutputPin {
+    let write = |value| runtime.block_on(write_level(&pin, value));
+    let mut held = None;
+    let mut start = std::time::Instant::now();
+
+    while !settings.stopped.load(Ordering::Acquire) {
+        let current = settings.load();
+        let period = Duration::from_secs_f64(1.0 / current.frequency);
+        let high = period.mul_f64(current.duty);
+
+        // Fully off or fully on, hold the level until the settings change
+        if high.is_zero() || high >= period {
+            let level = if high.is_zero() { 0 } else { 1 };
+            if held != Some(level) {
+                write(level);
+                held = Some(level);
+            }
+            thread::sleep(MAX_SLEEP);
+            start = std::time::Instant::now();
+            continue;
+        }
+        held = None;
+
+        // Use absolute deadlines so the period doesn't drift with the write latency,
+        // restarting from now if the thread fell more than a period behind
+        let now = std::time::Instant::now();
+        if now > start + period {
+            start = now;
+        }
+        write(1);
+        if !wait_until(start + high, &settings.stopped) {
+            break;
+        }
+        write(0);
+        if !wait_until(start + period, &settings.stopped) {
+            break;
+        }
+        start += period;
+    }
+
+    write(0);
+    pin
+}
+
+/// Sleep until shortly before the deadline and busy-wait the rest.
+/// Returns false if the PWM was stopped in the meantime.
+fn wait_until(deadline: std::time::Instant, stopped: &AtomicBool) -> bool {
+    loop {
+        if stopped.load(Ordering::Acquire) {
+            return false;
+        }
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining <= SPIN_MARGIN {
+            break;
+        }
+        thread::sleep((remaining - SPIN_MARGIN).min(MAX_SLEEP));
+    }
+    while std::time::Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+    true
+}
+
+/// Write a level to the pin, logging failures instead of stopping the signal.
+async fn write_level(pin: &impl PinLike, value: u8) {
+    if let Err(e) = pin.write(value).await {
+        log::error!("Error writing software PWM level: {}", e);
+    }
+}
+
+/// Check that the frequency is usable for a signal.
+fn check_frequency(frequency: f64) -> Result<()> {
+    if !(frequency > 0.0 && frequency.is_finite())
+        || Duration::try_from_secs_f64(1.0 / frequency).is_err()
+    {
+        return Err(GpioError::InvalidValue(format!(
+            "Frequency must be positive with a period fitting a Duration, got {}",
+            frequency
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the duty ratio is between 0.0 and 1.0.
+fn check_duty(duty: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&duty) {
+        return Err(GpioError::InvalidValue(format!(
+            "Duty ratio must be between 0.0 and 1.0, got {}",
+            duty
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+//
+// This file provides a software SPI master, for devices like ADCs, displays and
+// shift registers on boards without the kernel SPI overlays enabled.
+// Data is shifted out on MOSI and in from MISO at the same time, one bit per clock
+// cycle, while the chip select line is held low.
+//
+// The timing relies on the tokio timer, so the clock is limited to a few hundred Hz.
+//
+
+use super::bus::BitOrder;
+use super::error::{GpioError, Result};
+use super::pin::{InputPin, OutputPin};
+use std::time::Duration;
+use tokio::time;
+
+/// Clock polarity and phase of a [Spi] bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpiMode {
+    /// Clock idle low, data sampled on the rising edge
+    Mode0,
+    /// Clock idle low, data sampled on the falling edge
+    Mode1,
+    /// Clock idle high, data sampled on the falling edge
+    Mode2,
+    /// Clock idle high, data sampled on the rising edge
+    Mode3,
+}
+
+impl SpiMode {
+    /// Get the level of the clock when idle (CPOL).
+    fn idle_level(&self) -> u8 {
+        match self {
+            Self::Mode0 | Self::Mode1 => 0,
+            Self::Mode2 | Self::Mode3 => 1,
+        }
+    }
+
+    /// Check if data is sampled on the second edge of each clock cycle (CPHA).
+    fn sample_on_trailing_edge(&self) -> bool {
+        matches!(self, Self::Mode1 | Self::Mode3)
+    }
+}
+
+/// Software SPI master over output pins for the clock, data and chip select,
+/// and an optional input pin for the data sent back by the device.
+#[derive(Debug)]
+pub struct Spi {
+    sclk: OutputPin,
+    mosi: OutputPin,
+    miso: Option<InputPin>,
+    cs: Option<OutputPin>,
+    mode: SpiMode,
+    order: BitOrder,
+    half_period: Duration,
+}
+
+impl Spi {
+    /// Create an SPI master with the clock frequency in Hz.
+    /// Without MISO, the bytes read are 0. Without CS, the device must be always selected.
+    /// The clock is set to its idle level and the device is deselected.
+    pub async fn new(
+        sclk: OutputPin,
+        mosi: OutputPin,
+        miso: Option<InputPin>,
+        cs: Option<OutputPin>,
+        mode: SpiMode,
+        frequency: f64,
+    ) -> Result<Self> {
+        if !frequency.is_finite() || frequency <= 0.0 {
+            return Err(GpioError::InvalidValue(format!(
+                "Frequency must be positive, got {}",
+                frequency
+            )));
+        }
+
+        sclk.write(mode.idle_level()).await?;
+        if let Some(cs) = &cs {
+            cs.write(1).await?;
+        }
+
+        Ok(Self {
+            sclk,
+            mosi,
+            miso,
+            cs,
+            mode,
+            order: BitOrder::MsbFirst,
+            half_period: Duration::from_secs_f64(0.5 / frequency),
+        })
+    }
+
+    /// Get the mode of the bus.
+    pub fn mode(&self) -> SpiMode {
+        self.mode
+    }
+
+    /// Get the order in which the bits of each byte are shifted, most significant first by default.
+    pub fn bit_order(&self) -> BitOrder {
+        self.order
+    }
+
+    /// Set the order in which the bits of each byte are shifted.
+    pub fn set_bit_order(&mut self, order: BitOrder) {
+        self.order = order;
+    }
+
+    /// Send the bytes of the buffer and replace them with the bytes received at the same time.
+    /// The device is selected for the whole transfer.
+    pub async fn transfer(&self, buffer: &mut [u8]) -> Result<()> {
+        self.select(true).await?;
+        for byte in buffer.iter_mut() {
+            *byte = self.exchange(*byte).await?;
+        }
+        self.select(false).await
+    }
+
+    /// Send bytes, ignoring the bytes received.
+    pub async fn write(&self, data: &[u8]) -> Result<()> {
+        self.transfer(&mut data.to_vec()).await
+    }
+
+    /// Give the pins back.
+    pub fn into_pins(self) -> (OutputPin, OutputPin, Option<InputPin>, Option<OutputPin>) {
+        (self.sclk, self.mosi, self.miso, self.cs)
+    }
+
+    /// Select or deselect the device with the chip select line, which is active low.
+    async fn select(&self, selected: bool) -> Result<()> {
+        if let Some(cs) = &self.cs {
+            cs.write(if selected { 0 } else { 1 }).await?;
+            self.delay().await;
+        }
+        Ok(())
+    }
+
+    /// Shift a byte out and another in.
+    async fn exchange(&self, byte: u8) -> Result<u8> {
+        let mut received = 0;
+        for i in 0..8 {
+            let shift = match self.order {
+                BitOrder::MsbFirst => 7 - i,
+                BitOrder::LsbFirst => i,
+            };
+            received |= self.exchange_bit(byte >> shift & 1).await? << shift;
+        }
+        Ok(received)
+    }
+
+    /// Run a clock cycle, writing a bit on MOSI and sampling MISO on the edges of the mode.
+    async fn exchange_bit(&self, bit: u8) -> Result<u8> {
+        let idle = self.mode.idle_level();
+        let received = if self.mode.sample_on_trailing_edge() {
+            self.sclk.write(1 - idle).await?;
+            self.mosi.write(bit).await?;
+            self.delay().await;
+            self.sclk.write(idle).await?;
+            let received = self.sample().await?;
+            self.delay().await;
+            received
+        } else {
+            self.mosi.write(bit).await?;
+            self.delay().await;
+            self.sclk.write(1 - idle).await?;
+            let received = self.sample().await?;
+            self.delay().await;
+            self.sclk.write(idle).await?;
+            received
+        };
+        Ok(received)
+    }
+
+    /// Read the bit sent by the device, 0 without MISO.
+    async fn sample(&self) -> Result<u8> {
+        match &self.miso {
+            Some(miso) => miso.read().await,
+            None => Ok(0),
+        }
+    }
+
+    /// Wait for half a clock period.
+    async fn delay(&self) {
+        time::sleep(self.half_period).await;
+    }
+}
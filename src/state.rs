@@ -0,0 +1,195 @@
+//
+// This file provides a store of the state of the pins in a TOML file, so an application
+// restarting after a crash or a power cut can bring the pins back as it left them, e.g. with
+// the relays in the intended state instead of the default one.
+// Each recorded pin keeps its direction, active-low setting and watched edge, and for outputs
+// the last value written through the store. The file is rewritten after each change, by
+// replacing it so a crash while saving leaves the previous state.
+//
+// [[pin]]
+// number = 8
+// direction = "out"
+// value = 1
+// active_low = true
+//
+
+use super::error::{GpioError, Result};
+use super::gpio::Gpio;
+use super::pin::{self, Direction, Edge, GpioPin, InputPin, OutputPin};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, sync::Mutex};
+
+/// State of a pin recorded in a [StateStore].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinRecord {
+    /// SoC GPIO number of the pin
+    pub number: u8,
+    /// Direction of the pin
+    pub direction: Direction,
+    /// Last value written to an output pin
+    #[serde(default)]
+    pub value: u8,
+    /// Whether the logic of the pin is inverted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub active_low: bool,
+    /// Edge watched on an input pin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<Edge>,
+}
+
+impl PinRecord {
+    /// Get the record of the current state of a pin.
+    pub fn of(pin: &GpioPin) -> Self {
+        Self {
+            number: pin.get_pin_number(),
+            direction: match pin {
+                GpioPin::Input(_) => Direction::In,
+                GpioPin::Output(_) => Direction::Out,
+            },
+            value: pin.last_value().unwrap_or(0),
+            active_low: pin.is_active_low(),
+            edge: pin.edge(),
+        }
+    }
+
+    /// Export the pin and configure it as recorded, driving an output at its last value.
+    pub async fn restore(&self, gpio: &Gpio) -> Result<GpioPin> {
+        match self.direction {
+            Direction::In => {
+                let mut pin = InputPin::new(gpio, self.number).await?;
+                pin.set_active_low(self.active_low).await?;
+                if let Some(edge) = self.edge {
+                    pin.enable_watch(edge).await?;
+                }
+                Ok(pin.into())
+            }
+            Direction::Out if self.active_low => {
+                Ok(OutputPin::new_active_low(gpio, self.number, self.value)
+                    .await?
+                    .into())
+            }
+            Direction::Out => Ok(OutputPin::new(gpio, self.number, self.value).await?.into()),
+        }
+    }
+}
+
+/// Content of the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StateFile {
+    #[serde(rename = "pin", default)]
+    pins: Vec<PinRecord>,
+}
+
+/// Store of the state of the pins, saved to a file after each change.
+#[derive(Debug)]
+pub struct StateStore {
+    path: PathBuf,
+    pins: Mutex<BTreeMap<u8, PinRecord>>,
+}
+
+impl StateStore {
+    /// Open the store saved at the given path, empty if the file doesn't exist yet.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file: StateFile = match fs::read_to_string(&path).await {
+            Ok(content) => toml::from_str(&content).map_err(|e| {
+                GpioError::InvalidValue(format!("Invalid pin state: {}", e.message()))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => StateFile::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        for record in &file.pins {
+            if record.value > 1 {
+                return Err(GpioError::InvalidValue(format!(
+                    "Invalid pin state: the value of pin {} must be 0 or 1",
+                    record.number
+                )));
+            }
+        }
+        Ok(Self {
+            path,
+            pins: Mutex::new(file.pins.into_iter().map(|r| (r.number, r)).collect()),
+        })
+    }
+
+    /// Get the path of the state file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the records of the pins, by increasing number.
+    pub async fn pins(&self) -> Vec<PinRecord> {
+        self.pins.lock().await.values().copied().collect()
+    }
+
+    /// Get the record of a pin.
+    pub async fn get(&self, pin_number: u8) -> Option<PinRecord> {
+        self.pins.lock().await.get(&pin_number).copied()
+    }
+
+    /// Record the current state of a pin, replacing its previous record.
+    pub async fn record(&self, pin: &GpioPin) -> Result<()> {
+        let mut pins = self.pins.lock().await;
+        pins.insert(pin.get_pin_number(), PinRecord::of(pin));
+        self.save(&pins).await
+    }
+
+    /// Write a value to an output pin and record it, so it's driven again once restored.
+    pub async fn write(&self, pin: &OutputPin, value: u8) -> Result<()> {
+        let mut pins = self.pins.lock().await;
+        pin.write(value).await?;
+        let record = pins.entry(pin.get_pin_number()).or_insert(PinRecord {
+            number: pin.get_pin_number(),
+            direction: Direction::Out,
+            value,
+            active_low: pin.is_active_low(),
+            edge: None,
+        });
+        record.direction = Direction::Out;
+        record.value = value;
+        self.save(&pins).await
+    }
+
+    /// Stop recording a pin, e.g. once it's released.
+    pub async fn forget(&self, pin_number: u8) -> Result<()> {
+        let mut pins = self.pins.lock().await;
+        if pins.remove(&pin_number).is_some() {
+            self.save(&pins).await?;
+        }
+        Ok(())
+    }
+
+    /// Export and configure all the recorded pins, driving the outputs at their last values.
+    /// If any pin fails, the ones already exported are released.
+    pub async fn restore(&self, gpio: &Gpio) -> Result<Vec<GpioPin>> {
+        let records = self.pins().await;
+        let mut results = Vec::with_capacity(records.len());
+        for record in &records {
+            results.push(record.restore(gpio).await);
+        }
+        pin::all_or_release(results).await
+    }
+
+    /// Write the records to a temporary file replacing the state file.
+    async fn save(&self, pins: &BTreeMap<u8, PinRecord>) -> Result<()> {
+        let file = StateFile {
+            pins: pins.values().copied().collect(),
+        };
+        let content = toml::to_string(&file)
+            .map_err(|e| GpioError::InvalidValue(format!("Invalid pin state: {}", e)))?;
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, content).await?;
+        fs::rename(&temporary, &self.path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//
+// This file provides a stepper motor driver, either driving the 4 coil wires of a motor
+// through a transistor array (e.g. ULN2003 with a 28BYJ-48) or the step and direction
+// inputs of a driver board (e.g. A4988, DRV8825).
+// Steps are timed with absolute deadlines so the speed doesn't drift with the write latency,
+// and the speed can be ramped up and down with a constant acceleration so the motor
+// doesn't stall or skip steps when starting and stopping.
+//
+
+use super::error::{GpioError, Result};
+use super::group::PinGroup;
+use super::pin::OutputPin;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+/// Coil patterns of the full-step sequence, two coils energized at a time.
+const FULL_STEP: [[u8; 4]; 4] = [[1, 1, 0, 0], [0, 1, 1, 0], [0, 0, 1, 1], [1, 0, 0, 1]];
+
+/// Coil patterns of the half-step sequence, alternating one and two coils energized.
+const HALF_STEP: [[u8; 4]; 8] = [
+    [1, 0, 0, 0],
+    [1, 1, 0, 0],
+    [0, 1, 0, 0],
+    [0, 1, 1, 0],
+    [0, 0, 1, 0],
+    [0, 0, 1, 1],
+    [0, 0, 0, 1],
+    [1, 0, 0, 1],
+];
+
+/// Step sequence of a 4-wire [Stepper].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepMode {
+    /// Full steps, with the most torque
+    FullStep,
+    /// Half steps, with twice the resolution and smoother motion
+    HalfStep,
+}
+
+impl StepMode {
+    /// Get the coil patterns of the sequence.
+    fn sequence(&self) -> &'static [[u8; 4]] {
+        match self {
+            Self::FullStep => &FULL_STEP,
+            Self::HalfStep => &HALF_STEP,
+        }
+    }
+}
+
+/// Pins driving a [Stepper].
+#[derive(Debug)]
+enum StepperDriver {
+    FourWire { coils: PinGroup, mode: StepMode },
+    StepDir { step: OutputPin, dir: OutputPin },
+}
+
+/// Stepper motor driven step by step.
+#[derive(Debug)]
+pub struct Stepper {
+    driver: StepperDriver,
+    position: i64,
+    acceleration: Option<f64>,
+}
+
+impl Stepper {
+    /// Create a driver for a motor whose 4 coil wires are driven by the pins of the group,
+    /// in the order of the coils.
+    pub fn new_four_wire(coils: PinGroup, mode: StepMode) -> Result<Self> {
+        if coils.len() != 4 {
+            return Err(GpioError::InvalidValue(format!(
+                "A 4-wire stepper needs 4 pins, got {}",
+                coils.len()
+            )));
+        }
+
+        Ok(Self::new(StepperDriver::FourWire { coils, mode }))
+    }
+
+    /// Create a driver for a driver board with step and direction inputs.
+    /// The step pin is set low.
+    pub async fn new_step_dir(step: OutputPin, dir: OutputPin) -> Result<Self> {
+        step.write(0).await?;
+        Ok(Self::new(StepperDriver::StepDir { step, dir }))
+    }
+
+    /// Create a driver at position 0 without acceleration.
+    fn new(driver: StepperDriver) -> Self {
+        Self {
+            driver,
+            position: 0,
+            acceleration: None,
+        }
+    }
+
+    /// Get the position in steps since the driver was created.
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Set the acceleration in steps per second squared, or start at full speed with `None`.
+    pub fn set_acceleration(&mut self, acceleration: Option<f64>) -> Result<()> {
+        // The first step of a ramp, the slowest, must be timed too
+        if let Some(acceleration) = acceleration
+            && !(acceleration > 0.0
+                && acceleration.is_finite()
+                && check_speed((2.0 * acceleration).sqrt()).is_ok())
+        {
+            return Err(GpioError::InvalidValue(format!(
+                "Acceleration must be positive with a first step time fitting a Duration, got {}",
+                acceleration
+            )));
+        }
+        self.acceleration = acceleration;
+        Ok(())
+    }
+
+    /// Get the acceleration in steps per second squared.
+    pub fn acceleration(&self) -> Option<f64> {
+        self.acceleration
+    }
+
+    /// Rotate by the given number of steps, backwards if negative,
+    /// at a speed in steps per second.
+    /// With an acceleration, the speed ramps up at the start and down before the end.
+    pub async fn rotate(&mut self, steps: i64, speed: f64) -> Result<()> {
+        check_speed(speed)?;
+        let forward = steps >= 0;
+        let total = steps.unsigned_abs();
+
+        let mut deadline = Instant::now();
+        for i in 0..total {
+            // Limit the speed by the distance from both ends of the move
+            let speed = self.ramp(speed, i.min(total - 1 - i));
+            self.step(forward).await?;
+            deadline += Duration::from_secs_f64(1.0 / speed);
+            time::sleep_until(deadline).await;
+        }
+        Ok(())
+    }
+
+    /// Rotate without end at a speed in steps per second, backwards if negative.
+    /// With an acceleration, the speed ramps up at the start.
+    /// This never returns on success, drop the future to stop, e.g. with `tokio::time::timeout`.
+    /// The position stays accurate as it's updated at every step.
+    pub async fn rotate_continuous(&mut self, speed: f64) -> Result<()> {
+        check_speed(speed.abs())?;
+        let forward = speed > 0.0;
+
+        let mut deadline = Instant::now();
+        for i in 0.. {
+            let speed = self.ramp(speed.abs(), i);
+            self.step(forward).await?;
+            deadline += Duration::from_secs_f64(1.0 / speed);
+            time::sleep_until(deadline).await;
+        }
+        Ok(())
+    }
+
+    /// Stop energizing the coils, so the motor doesn't heat up while idle.
+    /// This does nothing for driver boards, whose enable pin isn't handled here.
+    pub async fn disable(&self) -> Result<()> {
+        match &self.driver {
+            StepperDriver::FourWire { coils, .. } => coils.write_all(0).await,
+            StepperDriver::StepDir { .. } => Ok(()),
+        }
+    }
+
+    /// Get the speed reachable after the given number of steps from a stop.
+    fn ramp(&self, speed: f64, steps: u64) -> f64 {
+        match self.acceleration {
+            // v² = 2·a·d, starting from the speed of the first step
+            Some(acceleration) => speed.min((2.0 * acceleration * (steps + 1) as f64).sqrt()),
+            None => speed,
+        }
+    }
+
+    /// Move one step forward or backward.
+    async fn step(&mut self, forward: bool) -> Result<()> {
+        let position = if forward {
+            self.position + 1
+        } else {
+            self.position - 1
+        };
+
+        match &self.driver {
+            StepperDriver::FourWire { coils, mode } => {
+                let sequence = mode.sequence();
+                let phase = position.rem_euclid(sequence.len() as i64) as usize;
+                coils.write_pattern(&sequence[phase]).await?;
+            }
+            StepperDriver::StepDir { step, dir } => {
+                dir.write_if_changed(forward as u8).await?;
+                step.write(1).await?;
+                step.write(0).await?;
+            }
+        }
+
+        self.position = position;
+        Ok(())
+    }
+}
+
+/// Check that the speed is usable for timing steps.
+fn check_speed(speed: f64) -> Result<()> {
+    if !(speed > 0.0 && speed.is_finite()) || Duration::try_from_secs_f64(1.0 / speed).is_err() {
+        return Err(GpioError::InvalidValue(format!(
+            "Speed must be positive with a step time fitting a Duration, got {}",
+            speed
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,505 @@
+//
+// This file provides the sysfs backend, the default way of accessing the pins.
+// It uses a combination of the `gpio` command for export operations and direct
+// sysfs interface for reading, writing, and mode operations.
+// Direction changes of exported pins write the direction file, which is much faster than
+// exporting the pin again, e.g. for protocols turning the line around mid-transaction.
+// The drive strength is set with `gpio drive`, as one of the 4 levels of the Allwinner SoCs.
+// Images without the `gpio` command can export the pins and set their direction and edge
+// by writing the sysfs files directly, which is done by default when the command isn't found.
+// The pull resistors and drive strength have no sysfs file, so they need the command.
+// Changes of the pins' values are detected by polling the sysfs value files for POLLPRI,
+// which the kernel raises on the edges set with `edge`, including transitions driven
+// by external hardware. Files that can't be polled, e.g. regular files standing in for
+// sysfs, are watched with inotify instead, which only sees writes through the filesystem.
+// The `gpio` command can be given a timeout, after which it's killed, so a hung command
+// doesn't block its caller forever.
+//
+
+use super::backend::{ChangeStream, GpioBackend};
+use super::error::{GpioError, Result};
+use super::gpio::{GpioCommand, Subcommand};
+use super::pin::{Bias, Direction, Edge};
+use async_trait::async_trait;
+use inotify::{EventMask, Inotify, WatchMask};
+use std::{
+    env,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{Interest, unix::AsyncFd},
+    process::Command,
+    time,
+};
+use tokio_stream::StreamExt;
+
+/// Current added by each drive level of the Allwinner SoCs, from 10mA at level 0.
+const DRIVE_STEP_MA: u8 = 10;
+/// Highest drive level of the Allwinner SoCs, 40mA.
+const MAX_DRIVE_LEVEL: u8 = 3;
+
+/// How the [SysfsBackend] detects the changes of the pins' values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SysfsWatchMode {
+    /// Poll the value file for interrupts, falling back to inotify if it can't be polled
+    #[default]
+    Auto,
+    /// Poll the value file for interrupts, failing if it can't be polled
+    Interrupt,
+    /// Watch the value file for modifications with inotify
+    Inotify,
+}
+
+/// How the [SysfsBackend] exports the pins and sets their direction, edge and bias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SysfsExportMode {
+    /// Use the `gpio` command if it's installed, the sysfs files otherwise
+    #[default]
+    Auto,
+    /// Run the `gpio` command
+    Command,
+    /// Write the sysfs files, without setting the bias nor the drive strength
+    Files,
+}
+
+/// Backend accessing the pins through the sysfs interface found under a root directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysfsBackend {
+    root: PathBuf,
+    watch_mode: SysfsWatchMode,
+    export_mode: SysfsExportMode,
+    command: GpioCommand,
+    command_timeout: Option<Duration>,
+    command_found: OnceLock<bool>,
+}
+
+impl SysfsBackend {
+    /// Create a backend for the sysfs interface under the given root, e.g. `/sys/class/gpio`.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self::with_watch_mode(root, SysfsWatchMode::default())
+    }
+
+    /// Create a backend for the sysfs interface under the given root,
+    /// detecting the changes of the pins with the given mode.
+    pub fn with_watch_mode(root: impl AsRef<Path>, watch_mode: SysfsWatchMode) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            watch_mode,
+            export_mode: SysfsExportMode::default(),
+            command: GpioCommand::default(),
+            command_timeout: None,
+            command_found: OnceLock::new(),
+        }
+    }
+
+    /// Set how the pins are exported and configured.
+    pub fn with_export_mode(mut self, export_mode: SysfsExportMode) -> Self {
+        self.export_mode = export_mode;
+        self
+    }
+
+    /// Get how the pins are exported and configured.
+    pub fn export_mode(&self) -> SysfsExportMode {
+        self.export_mode
+    }
+
+    /// Set the external command exporting and configuring the pins.
+    pub fn with_command(mut self, command: GpioCommand) -> Self {
+        self.command = command;
+        self.command_found = OnceLock::new();
+        self
+    }
+
+    /// Get the external command exporting and configuring the pins.
+    pub fn command(&self) -> &GpioCommand {
+        &self.command
+    }
+
+    /// Kill the `gpio` command and fail with [GpioError::Timeout] when it runs longer
+    /// than the timeout, or wait for it forever with `None`, the default.
+    pub fn with_command_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    /// Get the longest time the `gpio` command may run.
+    pub fn command_timeout(&self) -> Option<Duration> {
+        self.command_timeout
+    }
+
+    /// Get how the changes of the pins are detected.
+    pub fn watch_mode(&self) -> SysfsWatchMode {
+        self.watch_mode
+    }
+
+    /// Get the sysfs path to the value of the pin.
+    fn value_path(&self, pin_number: u8) -> PathBuf {
+        value_path(&self.root, pin_number)
+    }
+
+    /// Check if the pins are configured with the `gpio` command rather than the sysfs files.
+    fn uses_command(&self) -> bool {
+        match self.export_mode {
+            SysfsExportMode::Auto => *self
+                .command_found
+                .get_or_init(|| find_command(&self.command.program)),
+            SysfsExportMode::Command => true,
+            SysfsExportMode::Files => false,
+        }
+    }
+
+    /// Read a file of the pin.
+    async fn read_pin_file(&self, pin_number: u8, name: &str) -> Result<String> {
+        let path = self.root.join(format!("gpio{}/{}", pin_number, name));
+        fs::read_to_string(path)
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))
+    }
+
+    /// Write a file of the pin.
+    async fn write_pin_file(&self, pin_number: u8, name: &str, content: &str) -> Result<()> {
+        let path = self.root.join(format!("gpio{}/{}", pin_number, name));
+        fs::write(path, content)
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))
+    }
+
+    /// Write the direction file of the pin.
+    async fn write_direction(&self, pin_number: u8, direction: &str) -> Result<()> {
+        self.write_pin_file(pin_number, "direction", direction)
+            .await
+    }
+
+    /// Convert an error accessing the files of a pin, a missing file means the pin isn't exported.
+    fn pin_error(pin_number: u8, error: io::Error) -> GpioError {
+        match error.kind() {
+            io::ErrorKind::NotFound => GpioError::NotExported(pin_number),
+            _ => error.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl GpioBackend for SysfsBackend {
+    async fn export_input(&self, pin_number: u8) -> Result<()> {
+        self.export(pin_number, "in").await
+    }
+
+    async fn export_output(&self, pin_number: u8, default: u8) -> Result<()> {
+        if !self.uses_command() {
+            let direction = if default == 0 { "low" } else { "high" };
+            return self.export_files(pin_number, direction).await;
+        }
+        self.export(pin_number, "out").await?;
+
+        // Set the default value
+        fs::write(self.value_path(pin_number), default.to_string())
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))
+    }
+
+    /// Write the direction file instead of running the gpio command.
+    async fn set_input(&self, pin_number: u8) -> Result<()> {
+        self.write_direction(pin_number, "in").await
+    }
+
+    /// Write the level to the direction file, so the line doesn't glitch while it turns
+    /// into an output.
+    async fn set_output(&self, pin_number: u8, level: u8) -> Result<()> {
+        let direction = if level == 0 { "low" } else { "high" };
+        self.write_direction(pin_number, direction).await
+    }
+
+    async fn direction(&self, pin_number: u8) -> Result<Direction> {
+        let content = self.read_pin_file(pin_number, "direction").await?;
+        match content.trim() {
+            "in" => Ok(Direction::In),
+            "out" | "low" | "high" => Ok(Direction::Out),
+            other => Err(GpioError::InvalidValue(format!(
+                "Failed to parse the direction {:?} of pin {}",
+                other, pin_number
+            ))),
+        }
+    }
+
+    async fn unexport(&self, pin_number: u8) -> Result<()> {
+        if self.uses_command() {
+            self.run_gpio(Subcommand::Unexport, pin_number, None)
+                .await?;
+        } else {
+            self.unexport_files(pin_number).await?;
+        }
+
+        // Verify that the pin directory is gone
+        let pin_dir = self.root.join(format!("gpio{}", pin_number));
+        if fs::try_exists(&pin_dir).await? {
+            return Err(GpioError::ExportFailed(format!(
+                "Pin {} is still exported after unexport",
+                pin_number
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn read(&self, pin_number: u8) -> Result<u8> {
+        let content = fs::read_to_string(self.value_path(pin_number))
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))?;
+
+        content.trim().parse().map_err(|_| {
+            GpioError::InvalidValue(format!(
+                "Failed to parse the value {:?} of pin {}",
+                content, pin_number
+            ))
+        })
+    }
+
+    async fn write(&self, pin_number: u8, value: u8) -> Result<()> {
+        fs::write(self.value_path(pin_number), value.to_string())
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))
+    }
+
+    /// Set the edge of the pin using the gpio command, or its edge file.
+    async fn set_edge(&self, pin_number: u8, edge: Option<Edge>) -> Result<()> {
+        let edge = edge.map_or("none", |edge| edge.as_edge());
+        if !self.uses_command() {
+            return self.write_pin_file(pin_number, "edge", edge).await;
+        }
+        self.run_gpio(Subcommand::Edge, pin_number, Some(edge))
+            .await
+    }
+
+    async fn edge(&self, pin_number: u8) -> Result<Option<Edge>> {
+        let content = self.read_pin_file(pin_number, "edge").await?;
+        match content.trim() {
+            "none" => Ok(None),
+            "rising" => Ok(Some(Edge::Rising)),
+            "falling" => Ok(Some(Edge::Falling)),
+            "both" => Ok(Some(Edge::Both)),
+            other => Err(GpioError::InvalidValue(format!(
+                "Failed to parse the edge {:?} of pin {}",
+                other, pin_number
+            ))),
+        }
+    }
+
+    /// List the `gpioN` directories of the root, ignoring the `gpiochipN` ones.
+    async fn exported(&self) -> Result<Vec<u8>> {
+        let mut pins = Vec::new();
+        let mut entries = fs::read_dir(&self.root).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            if let Some(pin_number) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("gpio"))
+                .and_then(|number| number.parse().ok())
+            {
+                pins.push(pin_number);
+            }
+        }
+        pins.sort_unstable();
+        Ok(pins)
+    }
+
+    /// Set the bias of the pin using the gpio command.
+    /// The `-g` flag makes the `gpio` command use the same numbering as export.
+    async fn set_bias(&self, pin_number: u8, bias: Bias) -> Result<()> {
+        if !self.uses_command() {
+            return Err(GpioError::Unsupported(
+                "Bias without the gpio command".to_string(),
+            ));
+        }
+        self.run_gpio(Subcommand::Mode, pin_number, Some(bias.as_mode()))
+            .await
+    }
+
+    /// Set the drive level of the pin using the gpio command, the lowest level
+    /// driving at least the given current.
+    async fn set_drive_strength(&self, pin_number: u8, milliamps: u8) -> Result<()> {
+        let level = drive_level(milliamps)?;
+        if !self.uses_command() {
+            return Err(GpioError::Unsupported(
+                "Drive strength without the gpio command".to_string(),
+            ));
+        }
+        self.run_gpio(Subcommand::Drive, pin_number, Some(&level.to_string()))
+            .await
+    }
+
+    /// Write the `active_low` attribute of the pin, the kernel then inverts the value file.
+    async fn set_active_low(&self, pin_number: u8, active_low: bool) -> Result<()> {
+        let path = self.root.join(format!("gpio{}/active_low", pin_number));
+        fs::write(path, if active_low { "1" } else { "0" })
+            .await
+            .map_err(|e| Self::pin_error(pin_number, e))
+    }
+
+    /// Watch the sysfs value file of the pin for interrupts or modifications,
+    /// depending on the watch mode.
+    fn watch(&self, pin_number: u8) -> Result<ChangeStream> {
+        match self.watch_mode {
+            SysfsWatchMode::Auto => self.watch_interrupts(pin_number).or_else(|e| {
+                log::debug!(
+                    "Watching pin {} with inotify, interrupts unavailable: {}",
+                    pin_number,
+                    e
+                );
+                self.watch_inotify(pin_number)
+            }),
+            SysfsWatchMode::Interrupt => self.watch_interrupts(pin_number),
+            SysfsWatchMode::Inotify => self.watch_inotify(pin_number),
+        }
+    }
+}
+
+impl SysfsBackend {
+    /// Keep the value file of the pin open and wait for POLLPRI on it,
+    /// which the kernel raises on each edge of the pin.
+    fn watch_interrupts(&self, pin_number: u8) -> Result<ChangeStream> {
+        let file =
+            File::open(self.value_path(pin_number)).map_err(|e| Self::pin_error(pin_number, e))?;
+        let fd = AsyncFd::with_interest(file, Interest::PRIORITY)?;
+
+        // The value must be read to acknowledge the interrupt, including the one pending on open
+        read_value_file(fd.get_ref())?;
+
+        Ok(Box::pin(futures::stream::unfold(fd, |fd| async move {
+            let change = async {
+                let mut guard = fd.ready(Interest::PRIORITY).await?;
+                guard.clear_ready();
+                read_value_file(fd.get_ref())
+            };
+            Some((change.await.map_err(Into::into), fd))
+        })))
+    }
+
+    /// Watch the sysfs value file of the pin for modifications with inotify.
+    fn watch_inotify(&self, pin_number: u8) -> Result<ChangeStream> {
+        let inotify = Inotify::init()?;
+        inotify
+            .watches()
+            .add(self.value_path(pin_number), WatchMask::MODIFY)
+            .map_err(|e| Self::pin_error(pin_number, e))?;
+        let event_stream = inotify.into_event_stream([0u8; 1024])?;
+
+        Ok(Box::pin(event_stream.filter_map(
+            move |event| match event {
+                Ok(event) if event.mask.contains(EventMask::MODIFY) => Some(Ok(())),
+                Ok(event) if event.mask.contains(EventMask::Q_OVERFLOW) => {
+                    Some(Err(GpioError::WatchOverflow(pin_number)))
+                }
+                Ok(_) => None,
+                Err(e) => Some(Err(e.into())),
+            },
+        )))
+    }
+
+    /// Export the pin with the given direction ("in" or "out") using the gpio command,
+    /// or the sysfs files if the command isn't used.
+    async fn export(&self, pin_number: u8, direction: &str) -> Result<()> {
+        if !self.uses_command() {
+            return self.export_files(pin_number, direction).await;
+        }
+        self.run_gpio(Subcommand::Export, pin_number, Some(direction))
+            .await
+            .map_err(|e| match e {
+                GpioError::CommandFailed { stderr, .. } => GpioError::ExportFailed(format!(
+                    "Failed to export pin {} as {}: {}",
+                    pin_number, direction, stderr
+                )),
+                e => e,
+            })
+    }
+
+    /// Export the pin by writing its number to the export file, then set its direction
+    /// ("in", "out", "low" or "high").
+    async fn export_files(&self, pin_number: u8, direction: &str) -> Result<()> {
+        match fs::write(self.root.join("export"), pin_number.to_string()).await {
+            // The kernel refuses to export a pin twice
+            Err(e) if e.kind() == io::ErrorKind::ResourceBusy => {}
+            result => result?,
+        }
+        self.write_direction(pin_number, direction).await
+    }
+
+    /// Unexport the pin by writing its number to the unexport file.
+    async fn unexport_files(&self, pin_number: u8) -> Result<()> {
+        match fs::write(self.root.join("unexport"), pin_number.to_string()).await {
+            // The kernel refuses to unexport a pin that isn't exported
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// Run a subcommand of the gpio command on a pin, killing it after the command timeout.
+    async fn run_gpio(
+        &self,
+        subcommand: Subcommand,
+        pin_number: u8,
+        value: Option<&str>,
+    ) -> Result<()> {
+        let args = self.command.args(subcommand, pin_number, value);
+        let mut command = Command::new(&self.command.program);
+        let output = command.args(&args).kill_on_drop(true).output();
+        let output = match self.command_timeout {
+            Some(timeout) => time::timeout(timeout, output).await.map_err(|_| {
+                GpioError::Timeout(format!(
+                    "{} took more than {:?}",
+                    self.command.describe(&args),
+                    timeout
+                ))
+            })??,
+            None => output.await?,
+        };
+        if !output.status.success() {
+            return Err(GpioError::CommandFailed {
+                command: self.command.describe(&args),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the lowest drive level of the Allwinner SoCs driving at least the given current.
+pub(crate) fn drive_level(milliamps: u8) -> Result<u8> {
+    let level = milliamps.div_ceil(DRIVE_STEP_MA).saturating_sub(1);
+    if milliamps == 0 || level > MAX_DRIVE_LEVEL {
+        return Err(GpioError::InvalidValue(format!(
+            "Drive strength must be between 1 and {}mA, got {}mA",
+            (MAX_DRIVE_LEVEL + 1) * DRIVE_STEP_MA,
+            milliamps
+        )));
+    }
+    Ok(level)
+}
+
+/// Check if the command can be run, looking it up in the `PATH` unless it's a path.
+fn find_command(command: &Path) -> bool {
+    if command.components().count() > 1 {
+        return command.is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
+
+/// Read an open value file from its start, acknowledging its pending interrupt.
+fn read_value_file(mut file: &File) -> io::Result<()> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut content)?;
+    Ok(())
+}
+
+/// Get the sysfs path to the value of the pin under the given root.
+pub(crate) fn value_path(root: &Path, pin_number: u8) -> PathBuf {
+    root.join(format!("gpio{}/value", pin_number))
+}
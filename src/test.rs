@@ -1,31 +1,88 @@
-#[cfg(test)]
+#[cfg(all(test, feature = "async"))]
 mod gpio_util_tests {
-    use super::super::pin::GpioPin;
-    use super::super::watcher::GpioWatcher;
-    use std::{collections::HashMap, env};
-    use tokio::sync::watch;
+    use super::super::adc::{Mcp3008, Mcp3008Model};
+    use super::super::backend::{ChangeStream, GpioBackend};
+    use super::super::blinker::{BlinkPattern, Blinker};
+    use super::super::bus::{BitOrder, PinBus};
+    use super::super::button::{Button, ButtonConfig, ButtonEvent};
+    use super::super::buzzer::{Buzzer, Note};
+    use super::super::dht::{Dht, DhtModel};
+    use super::super::encoder::{RotaryEncoder, RotaryEvent};
+    use super::super::error::GpioError;
+    use super::super::fast::FastPin;
+    use super::super::gpio::{Clock, Gpio, GpioCommand, GpioConfig, RetryPolicy, Subcommand};
+    use super::super::group::PinGroup;
+    use super::super::harness::GpioTestHarness;
+    use super::super::hbridge::{HBridge, HBridgeConfig, MotorState};
+    use super::super::hc165::Hc165;
+    use super::super::hc595::Hc595;
+    use super::super::hd44780::Hd44780;
+    use super::super::heartbeat::{Heartbeat, HeartbeatConfig};
+    use super::super::hx711::{Hx711, Hx711Gain};
+    use super::super::i2c::{I2c, I2cBus};
+    use super::super::ir::{IrReceiver, NecEvent};
+    use super::super::keypad::{Key, Keypad, KeypadConfig, KeypadEvent};
+    use super::super::led::Led;
+    use super::super::manager::GpioManager;
+    use super::super::mcp23017::Mcp23017;
+    use super::super::mock::{self, Fault, MockBackend, MockOperation};
+    use super::super::onewire::{self, OneWireBus};
+    use super::super::opendrain::{OpenDrain, OutputMode};
+    use super::super::pcf8574::Pcf8574;
+    use super::super::pin::{Bias, Direction, Edge, GpioPin, InputPin, Level, OutputPin};
+    use super::super::pinlike::PinLike;
+    use super::super::pinmap::{Board, ORANGE_PI_ZERO2};
+    use super::super::polling::PollingWatcher;
+    use super::super::pulse::{PulseCounter, PulseMeter};
+    use super::super::pwm::PwmPin;
+    use super::super::recorder::Recorder;
+    use super::super::relay::{RelayBank, RelayConfig};
+    use super::super::replay::Waveform;
+    use super::super::servo::{Servo, ServoCalibration};
+    #[cfg(feature = "config")]
+    use super::super::setup::PinSetup;
+    use super::super::sevenseg::{self, SevenSegment, SevenSegmentConfig};
+    use super::super::shared::SharedPin;
+    use super::super::signal::SignalGenerator;
+    use super::super::softpwm::SoftPwm;
+    use super::super::spi::{Spi, SpiMode};
+    #[cfg(feature = "config")]
+    use super::super::state::StateStore;
+    use super::super::stepper::{StepMode, Stepper};
+    use super::super::sysfs::{SysfsBackend, SysfsExportMode, SysfsWatchMode};
+    use super::super::uart::SoftUart;
+    use super::super::ultrasonic::HcSr04;
+    use super::super::watcher::{GpioEvent, GpioWatcher, Notifier, WatcherError};
+    use super::super::ws2812::{self, Rgb, Ws2812};
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Arc,
+    };
+    use tokio::sync::{broadcast, mpsc, watch};
     use tokio::{fs, time};
+    use tokio_stream::StreamExt;
 
     #[tokio::test]
     async fn gpio_watcher_test() {
-        unsafe {
-            env::set_var("GPIO_DIR", "test_assets/output/gpio");
-        }
+        let gpio = Gpio::new(GpioConfig {
+            sysfs_root: "test_assets/output/gpio_watcher_test".into(),
+            ..Default::default()
+        });
 
         // Remove old test outputs
-        fs::remove_dir_all("test_assets/output/gpio")
+        fs::remove_dir_all("test_assets/output/gpio_watcher_test")
             .await
             .unwrap_or_default();
 
         // Create a fake GPIO pins
-        let gpio1 = GpioPin::new_fake_input(1).await.unwrap();
+        let gpio1 = InputPin::new_fake_sysfs(&gpio, 1).await.unwrap();
 
         // Create a watch channel to check the callback
         let (tx, mut rx) = watch::channel::<u8>(0);
 
         // Set up the pin-to-callback map
         let mut pin_map = HashMap::new();
-        pin_map.insert(gpio1, tx);
+        pin_map.insert(GpioPin::from(gpio1), tx);
 
         // Initialize the GPIO watcher
         let _watcher = GpioWatcher::new(pin_map).await.unwrap();
@@ -39,9 +96,12 @@ mod gpio_util_tests {
         assert!(result == 0);
 
         // Simulate value changes in both pins
-        fs::write("test_assets/output/gpio/gpio1/value", "1".as_bytes())
-            .await
-            .unwrap();
+        fs::write(
+            "test_assets/output/gpio_watcher_test/gpio1/value",
+            "1".as_bytes(),
+        )
+        .await
+        .unwrap();
 
         // Check if something is sent to the rx (meaning that the callback is triggered)
         time::timeout(time::Duration::from_secs(1), rx.changed())
@@ -51,4 +111,3326 @@ mod gpio_util_tests {
         let result = *rx.borrow();
         assert!(result == 1);
     }
+
+    #[tokio::test]
+    async fn pwm_pin_test() {
+        let gpio = Gpio::new(GpioConfig {
+            pwm_root: "test_assets/output/pwm_pin_test".into(),
+            ..Default::default()
+        });
+
+        // Create a fake, already exported PWM channel
+        let channel_dir = "test_assets/output/pwm_pin_test/pwmchip0/pwm0";
+        fs::remove_dir_all(channel_dir).await.unwrap_or_default();
+        fs::create_dir_all(channel_dir).await.unwrap();
+        for name in ["period", "duty_cycle", "enable"] {
+            fs::write(format!("{}/{}", channel_dir, name), "0")
+                .await
+                .unwrap();
+        }
+
+        // Configure a 1 kHz signal with a 25% duty ratio
+        let mut pwm = PwmPin::new(&gpio, 0, 0).await.unwrap();
+        pwm.set_frequency(1000.0).await.unwrap();
+        pwm.set_duty(0.25).await.unwrap();
+        pwm.enable().await.unwrap();
+
+        let read = |name: &'static str| async move {
+            fs::read_to_string(format!("{}/{}", channel_dir, name))
+                .await
+                .unwrap()
+        };
+        assert_eq!(read("period").await, "1000000");
+        assert_eq!(read("duty_cycle").await, "250000");
+        assert_eq!(read("enable").await, "1");
+
+        // Shortening the period must keep the duty ratio
+        pwm.set_frequency(4000.0).await.unwrap();
+        assert_eq!(read("period").await, "250000");
+        assert_eq!(read("duty_cycle").await, "62500");
+        assert!(pwm.is_enabled().await.unwrap());
+
+        // Frequencies without a period are rejected, keeping the signal
+        for frequency in [0.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                pwm.set_frequency(frequency).await,
+                Err(GpioError::InvalidValue(_))
+            ));
+        }
+        assert_eq!(read("period").await, "250000");
+    }
+
+    #[tokio::test]
+    async fn soft_pwm_test() {
+        // A full duty ratio holds the pin high
+        let pin = OutputPin::new_fake(2, 0).await.unwrap();
+        let pwm = SoftPwm::new(pin, 100.0, 1.0).unwrap();
+        time::sleep(time::Duration::from_millis(50)).await;
+        assert_eq!(mock::get_value(2).unwrap(), 1);
+
+        // A zero duty ratio holds the pin low
+        pwm.set_duty(0.0).unwrap();
+        time::sleep(time::Duration::from_millis(50)).await;
+        assert_eq!(mock::get_value(2).unwrap(), 0);
+
+        // Invalid settings are rejected
+        assert!(pwm.set_duty(1.5).is_err());
+        assert!(pwm.set_frequency(0.0).is_err());
+        assert!(matches!(
+            pwm.set_frequency(1e-300),
+            Err(GpioError::InvalidValue(_))
+        ));
+
+        // Stopping gives the pin back in the low state
+        pwm.set_duty(0.5).unwrap();
+        time::sleep(time::Duration::from_millis(50)).await;
+        let pin = pwm.stop().await.unwrap();
+        assert_eq!(pin.get_pin_number(), 2);
+        assert_eq!(mock::get_value(2).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_add_remove_test() {
+        // Start watching a single pin
+        let gpio3 = GpioPin::new_fake_input(3).await.unwrap();
+        let (tx3, mut rx3) = watch::channel::<u8>(0);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio3, tx3);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        time::timeout(time::Duration::from_secs(1), rx3.changed())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Add a second pin to the running watcher, once its initial value is sent
+        let gpio4 = GpioPin::new_fake_input(4).await.unwrap();
+        let (tx4, mut rx4) = watch::channel::<u8>(0);
+        watcher.add_pin(gpio4, tx4).await.unwrap();
+        time::timeout(time::Duration::from_secs(1), rx4.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(*rx4.borrow_and_update() == 0);
+        mock::set_value(4, 1).unwrap();
+        time::timeout(time::Duration::from_secs(1), rx4.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(*rx4.borrow() == 1);
+
+        // Remove the first pin, its changes must not be notified anymore
+        let gpio3 = watcher.remove_pin(3).await.unwrap();
+        assert_eq!(gpio3.get_pin_number(), 3);
+        assert_eq!(watcher.pin_numbers().await, vec![4]);
+        mock::set_value(3, 1).unwrap();
+        time::sleep(time::Duration::from_millis(200)).await;
+        assert!(*rx3.borrow() == 0);
+
+        // Removing or adding the same pin twice fails
+        assert!(matches!(
+            watcher.remove_pin(3).await,
+            Err(GpioError::NotWatched(3))
+        ));
+        let (tx4, _rx4) = watch::channel::<u8>(0);
+        let gpio4 = GpioPin::new_fake_input(4).await.unwrap();
+        assert!(matches!(
+            watcher.add_pin(gpio4, tx4).await,
+            Err(GpioError::AlreadyWatched(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_callback_test() {
+        // Forward the callback values into a channel to check them
+        let (tx, mut rx) = mpsc::unbounded_channel::<(u8, u8)>();
+        let gpio5 = GpioPin::new_fake_input(5).await.unwrap();
+        let callback_tx = tx.clone();
+        let mut callback_map = HashMap::new();
+        callback_map.insert(gpio5, move |value| callback_tx.send((5, value)).unwrap());
+        let watcher = GpioWatcher::with_callbacks(callback_map).await.unwrap();
+
+        // The callback is called with the initial value
+        let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(received.unwrap(), Some((5, 0)));
+
+        // Callbacks can also be used when adding pins
+        let gpio6 = GpioPin::new_fake_input(6).await.unwrap();
+        let callback = Notifier::callback(move |value| tx.send((6, value)).unwrap());
+        watcher.add_pin(gpio6, callback).await.unwrap();
+        let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(received.unwrap(), Some((6, 0)));
+
+        // Changes are delivered through the callback
+        mock::set_value(5, 1).unwrap();
+        let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(received.unwrap(), Some((5, 1)));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_broadcast_test() {
+        // Watch a pin through a broadcast channel with two subscribers
+        let gpio7 = GpioPin::new_fake_input(7).await.unwrap();
+        let (_watcher, senders) = GpioWatcher::with_broadcast(vec![gpio7], 16).await.unwrap();
+        let mut rx1 = senders[&7].subscribe();
+        let mut rx2 = senders[&7].subscribe();
+
+        // Both subscribers get the change
+        mock::set_value(7, 1).unwrap();
+        for rx in [&mut rx1, &mut rx2] {
+            let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+            assert_eq!(received.unwrap().unwrap(), 1);
+        }
+
+        // Broadcast senders can also be used directly as notifiers
+        let (tx, _) = broadcast::channel::<u8>(16);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(GpioPin::new_fake_input(8).await.unwrap(), tx);
+        assert!(GpioWatcher::new(pin_map).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_debounce_test() {
+        // Watch a pin with a debounce time
+        let (tx, mut rx) = mpsc::unbounded_channel::<u8>();
+        let mut callback_map = HashMap::new();
+        callback_map.insert(GpioPin::new_fake_input(9).await.unwrap(), move |value| {
+            tx.send(value).unwrap()
+        });
+        let watcher = GpioWatcher::with_callbacks(callback_map).await.unwrap();
+        assert_eq!(rx.recv().await, Some(0));
+        watcher
+            .set_debounce(9, Some(time::Duration::from_millis(100)))
+            .await
+            .unwrap();
+
+        // A bouncing switch only forwards the settled value
+        for value in [1, 0, 1, 0, 1] {
+            mock::set_value(9, value).unwrap();
+            time::sleep(time::Duration::from_millis(10)).await;
+        }
+        let received = time::timeout(time::Duration::from_secs(1), rx.recv()).await;
+        assert_eq!(received.unwrap(), Some(1));
+        time::sleep(time::Duration::from_millis(200)).await;
+        assert!(rx.try_recv().is_err());
+
+        // A glitch settling back to the previous value is not forwarded
+        mock::set_value(9, 0).unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        mock::set_value(9, 1).unwrap();
+        time::sleep(time::Duration::from_millis(200)).await;
+        assert!(rx.try_recv().is_err());
+
+        // Unknown pins can't be configured
+        assert!(watcher.set_debounce(10, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mock_backend_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Output pins write through the backend
+        let output = GpioPin::new_output(&gpio, 1, 1).await.unwrap();
+        assert_eq!(output.read().await.unwrap(), 1);
+        output.write(0).await.unwrap();
+        assert_eq!(output.read().await.unwrap(), 0);
+
+        // Toggling inverts the value
+        assert_eq!(output.toggle().await.unwrap(), 1);
+        assert_eq!(output.toggle().await.unwrap(), 0);
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+
+        // Unchanged values are not written again
+        backend.set_value(1, 1).unwrap();
+        assert_eq!(output.last_value(), Some(0));
+        assert!(!output.write_if_changed(0).await.unwrap());
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert!(output.write_if_changed(1).await.unwrap());
+
+        // Active-low pins invert the level of the line
+        let relay = OutputPin::new_active_low(&gpio, 3, 0).await.unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 1);
+        relay.write(1).await.unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 0);
+        assert_eq!(relay.read().await.unwrap(), 1);
+
+        // Watched input pins get the values driven on the backend
+        let mut input = GpioPin::new_input(&gpio, 2).await.unwrap();
+        input.enable_watch(Edge::Both).await.unwrap();
+        let (tx, mut rx) = watch::channel::<u8>(0);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(input, tx);
+        let _watcher = GpioWatcher::new(pin_map).await.unwrap();
+        rx.borrow_and_update();
+        backend.set_value(2, 1).unwrap();
+        time::timeout(time::Duration::from_secs(1), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(*rx.borrow() == 1);
+
+        // Only levels can be set on the lines
+        assert!(matches!(
+            backend.set_value(2, 2),
+            Err(GpioError::InvalidValue(_))
+        ));
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+
+        // Released pins are gone from the backend
+        output.release().await.unwrap();
+        assert!(matches!(
+            backend.set_value(1, 1),
+            Err(GpioError::NotExported(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn pin_group_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let group = PinGroup::new_outputs(&gpio, &[1, 2, 3], 0).await.unwrap();
+        assert_eq!(group.pin_numbers(), vec![1, 2, 3]);
+
+        // All the pins get the same value
+        group.write_all(1).await.unwrap();
+        assert_eq!(group.read_all().await.unwrap(), vec![1, 1, 1]);
+
+        // Patterns map to the pins in order
+        group.write_pattern(&[1, 0, 1]).await.unwrap();
+        assert_eq!(backend.get_value(2).unwrap(), 0);
+        assert_eq!(group.read_all().await.unwrap(), vec![1, 0, 1]);
+        assert!(matches!(
+            group.write_pattern(&[1, 0]).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+
+        // A failed export releases the pins of the group
+        backend.inject_fault(
+            Some(5),
+            MockOperation::Export,
+            Fault::PermissionDenied,
+            None,
+        );
+        assert!(matches!(
+            PinGroup::new_outputs(&gpio, &[4, 5, 6], 0).await,
+            Err(GpioError::PermissionDenied(_))
+        ));
+        for pin_number in [4, 5, 6] {
+            assert!(backend.get_value(pin_number).is_err());
+        }
+
+        // Duplicate pins are rejected before exporting any pin
+        assert!(matches!(
+            PinGroup::new_outputs(&gpio, &[7, 8, 7], 0).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+        assert!(backend.get_value(7).is_err());
+        assert!(backend.get_value(8).is_err());
+    }
+
+    #[tokio::test]
+    async fn pin_bus_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // The first pin carries the most significant bit
+        let group = PinGroup::new_outputs(&gpio, &[1, 2, 3, 4], 0)
+            .await
+            .unwrap();
+        let bus = PinBus::<4>::new(group, BitOrder::MsbFirst).unwrap();
+        bus.write_u8(0b1010).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(4).unwrap(), 0);
+        assert_eq!(bus.read_u8().await.unwrap(), 0b1010);
+        assert!(bus.write_u8(0b10000).await.is_err());
+
+        // The first pin carries the least significant bit
+        let bus = PinBus::<4>::new(bus.into_group(), BitOrder::LsbFirst).unwrap();
+        bus.write_u8(0b0001).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(4).unwrap(), 0);
+        assert_eq!(bus.read_u8().await.unwrap(), 0b0001);
+
+        // The bus width must match the group
+        assert!(PinBus::<3>::new(bus.into_group(), BitOrder::MsbFirst).is_err());
+    }
+
+    #[tokio::test]
+    async fn pin_map_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Translate between the numbering schemes
+        assert_eq!(ORANGE_PI_ZERO2.physical_to_soc(7).unwrap(), 73);
+        assert_eq!(ORANGE_PI_ZERO2.soc_to_physical(73).unwrap(), 7);
+        assert_eq!(ORANGE_PI_ZERO2.wiring_to_soc(2).unwrap(), 73);
+        assert_eq!(ORANGE_PI_ZERO2.physical_to_wiring(26).unwrap(), 16);
+        assert_eq!(ORANGE_PI_ZERO2.wiring_to_physical(16).unwrap(), 26);
+
+        // Power and ground pins have no GPIO number
+        assert!(matches!(
+            ORANGE_PI_ZERO2.physical_to_soc(1),
+            Err(GpioError::UnmappedPin { pin: 1, .. })
+        ));
+        assert!(ORANGE_PI_ZERO2.wiring_to_soc(17).is_err());
+
+        // Pins created by their position use the SoC number
+        let output = GpioPin::new_output_physical(&gpio, 7, 1).await.unwrap();
+        assert_eq!(output.get_pin_number(), 73);
+        assert_eq!(backend.get_value(73).unwrap(), 1);
+
+        // Other boards have their own tables
+        let gpio = Gpio::with_backend(
+            GpioConfig {
+                pin_map: Board::OrangePiPc.pin_map(),
+                ..Default::default()
+            },
+            backend.clone(),
+        );
+        let output = GpioPin::new_output_physical(&gpio, 40, 1).await.unwrap();
+        assert_eq!(output.get_pin_number(), 199);
+        assert!(Board::OrangePi5.pin_map().physical_to_soc(40).is_err());
+
+        // Boards are detected from their device tree model
+        assert_eq!(
+            Board::from_model("Xunlong Orange Pi PC\0"),
+            Some(Board::OrangePiPc)
+        );
+        assert_eq!(
+            Board::from_model("OrangePi Zero2"),
+            Some(Board::OrangePiZero2)
+        );
+        assert_eq!(Board::from_model("Orange Pi 5 Plus"), None);
+        assert_eq!(Board::from_model("Banana Pi BPI-M2 Zero"), None);
+        assert_eq!(
+            Board::from_model("Raspberry Pi 4 Model B Rev 1.4\0"),
+            Some(Board::RaspberryPi)
+        );
+    }
+
+    #[tokio::test]
+    async fn i2c_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // The bus is idle with both lines released
+        let sda = GpioPin::new_output(&gpio, 1, 0).await.unwrap();
+        let scl = GpioPin::new_output(&gpio, 2, 0).await.unwrap();
+        let mut i2c = I2c::new(sda, scl, 1000.0).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+
+        // Without a device, the pull-up leaves the address unacknowledged
+        assert!(matches!(
+            i2c.write(0x42, &[0x01]).await,
+            Err(GpioError::Nack { address: 0x42 })
+        ));
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+
+        // A clock held low times out
+        i2c.set_stretch_timeout(time::Duration::from_millis(10));
+        backend.set_bias(2, Bias::PullDown).await.unwrap();
+        assert!(matches!(
+            i2c.read(0x42, &mut [0; 1]).await,
+            Err(GpioError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn spi_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // MISO reads the MOSI line, so every mode must read back what it writes
+        for mode in [
+            SpiMode::Mode0,
+            SpiMode::Mode1,
+            SpiMode::Mode2,
+            SpiMode::Mode3,
+        ] {
+            let sclk = OutputPin::new(&gpio, 1, 0).await.unwrap();
+            let mosi = OutputPin::new(&gpio, 2, 0).await.unwrap();
+            let miso = InputPin::new(&gpio, 2).await.unwrap();
+            let cs = OutputPin::new(&gpio, 3, 0).await.unwrap();
+            let mut spi = Spi::new(sclk, mosi, Some(miso), Some(cs), mode, 2000.0)
+                .await
+                .unwrap();
+            assert_eq!(backend.get_value(3).unwrap(), 1);
+
+            let mut buffer = [0xa5, 0x3c];
+            spi.transfer(&mut buffer).await.unwrap();
+            assert_eq!(buffer, [0xa5, 0x3c]);
+
+            spi.set_bit_order(BitOrder::LsbFirst);
+            spi.transfer(&mut buffer).await.unwrap();
+            assert_eq!(buffer, [0xa5, 0x3c]);
+
+            // The clock is idle and the device deselected after the transfer
+            let idle = if matches!(mode, SpiMode::Mode2 | SpiMode::Mode3) {
+                1
+            } else {
+                0
+            };
+            assert_eq!(backend.get_value(1).unwrap(), idle);
+            assert_eq!(backend.get_value(3).unwrap(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn soft_uart_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // RX reads the TX line, so the UART receives what it sends
+        let tx = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let rx = InputPin::new(&gpio, 1).await.unwrap();
+        let mut uart = SoftUart::new(tx, rx, 50).await.unwrap();
+        uart.write(b"ok").await.unwrap();
+
+        let mut received = Vec::new();
+        let mut buffer = [0; 8];
+        while received.len() < 2 {
+            let len = time::timeout(time::Duration::from_secs(1), uart.read(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            received.extend_from_slice(&buffer[..len]);
+        }
+        assert_eq!(received, b"ok");
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn one_wire_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Without a device, nothing answers the reset pulse
+        let pin = GpioPin::new_output(&gpio, 1, 0).await.unwrap();
+        let mut bus = OneWireBus::new(pin).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert!(!bus.reset().await.unwrap());
+        assert!(bus.search().await.unwrap().is_empty());
+        assert!(bus.skip_rom().await.is_err());
+
+        // ROM codes end with the CRC of their first 7 bytes
+        assert_eq!(
+            onewire::crc8(&[0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00]),
+            0xa2
+        );
+    }
+
+    #[tokio::test]
+    async fn dht_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Decode the values of both models
+        let reading = DhtModel::Dht22
+            .decode([0x02, 0x8c, 0x01, 0x5f, 0xee])
+            .unwrap();
+        assert_eq!(reading.humidity, 65.2);
+        assert_eq!(reading.temperature, 35.1);
+        let reading = DhtModel::Dht22
+            .decode([0x02, 0x8c, 0x80, 0x65, 0x73])
+            .unwrap();
+        assert_eq!(reading.temperature, -10.1);
+        let reading = DhtModel::Dht11.decode([55, 0, 24, 0, 79]).unwrap();
+        assert_eq!(reading.humidity, 55.0);
+        assert_eq!(reading.temperature, 24.0);
+        assert!(DhtModel::Dht11.decode([55, 0, 24, 0, 80]).is_err());
+
+        // Without a sensor, the line stays high
+        let pin = GpioPin::new_output(&gpio, 1, 0).await.unwrap();
+        let mut dht = Dht::new(pin, DhtModel::Dht22).await.unwrap();
+        assert!(matches!(dht.read().await, Err(GpioError::Timeout(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hc_sr04_test() {
+        let backend = Arc::new(MockBackend::new());
+        let config = GpioConfig {
+            clock: Clock::Virtual,
+            ..Default::default()
+        };
+        let gpio = Gpio::with_backend(config, backend.clone());
+        let trigger = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let echo = InputPin::new(&gpio, 2).await.unwrap();
+        let mut sensor = HcSr04::new(trigger, echo).await.unwrap();
+
+        // Simulate a 10ms echo after the trigger pulse, 1715mm at the speed of sound
+        let simulator = backend.clone();
+        let mut trigger_changes = backend.watch(1).unwrap();
+        let echo_thread = tokio::spawn(async move {
+            trigger_changes.next().await;
+            simulator.set_value(2, 1).unwrap();
+            time::sleep(time::Duration::from_millis(10)).await;
+            simulator.set_value(2, 0).unwrap();
+        });
+        let distance = sensor.distance_mm().await.unwrap();
+        assert_eq!(distance, 1715);
+        echo_thread.await.unwrap();
+
+        // Without an echo, the measurement times out
+        sensor.set_timeout(time::Duration::from_millis(5));
+        assert!(matches!(
+            sensor.distance_mm().await,
+            Err(GpioError::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rotary_encoder_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let a = InputPin::new(&gpio, 1).await.unwrap();
+        let b = InputPin::new(&gpio, 2).await.unwrap();
+        backend.set_value(1, 1).unwrap();
+        backend.set_value(2, 1).unwrap();
+        let (encoder, mut events) = RotaryEncoder::new(a, b).await.unwrap();
+
+        // Step the pins through the Gray code, from rest to rest
+        async fn turn(backend: &MockBackend, states: &[(u8, u8)]) {
+            for &(a, b) in states {
+                backend.set_value(1, a).unwrap();
+                backend.set_value(2, b).unwrap();
+                time::sleep(time::Duration::from_millis(5)).await;
+            }
+        }
+        turn(&backend, &[(0, 1), (0, 0), (1, 0), (1, 1)]).await;
+        assert_eq!(events.recv().await, Some(RotaryEvent::Increment));
+        turn(&backend, &[(1, 0), (0, 0), (0, 1), (1, 1)]).await;
+        assert_eq!(events.recv().await, Some(RotaryEvent::Decrement));
+        turn(&backend, &[(0, 1), (0, 0), (1, 0), (1, 1)]).await;
+        assert_eq!(events.recv().await, Some(RotaryEvent::Increment));
+
+        // Bounces back to rest are not detents
+        turn(&backend, &[(0, 1), (1, 1), (0, 1), (1, 1)]).await;
+        assert!(events.try_recv().is_err());
+        assert_eq!(encoder.position(), 1);
+    }
+
+    #[tokio::test]
+    async fn button_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = InputPin::new(&gpio, 1).await.unwrap();
+        let config = ButtonConfig {
+            debounce: time::Duration::from_millis(5),
+            long_press: time::Duration::from_millis(100),
+            double_click: time::Duration::from_millis(100),
+        };
+        let (_button, mut events) = Button::new(pin, config).await.unwrap();
+        let set = |value| {
+            backend.set_value(1, value).unwrap();
+            time::sleep(time::Duration::from_millis(20))
+        };
+
+        // Bounces are filtered out
+        backend.set_value(1, 1).unwrap();
+        backend.set_value(1, 0).unwrap();
+        set(1).await;
+        set(0).await;
+        assert_eq!(events.recv().await, Some(ButtonEvent::Pressed));
+        assert_eq!(events.recv().await, Some(ButtonEvent::Released));
+
+        // A second click right after is a double click
+        set(1).await;
+        set(0).await;
+        assert_eq!(events.recv().await, Some(ButtonEvent::Pressed));
+        assert_eq!(events.recv().await, Some(ButtonEvent::Released));
+        assert_eq!(events.recv().await, Some(ButtonEvent::DoubleClick));
+
+        // Holding the button is a long press
+        time::sleep(time::Duration::from_millis(150)).await;
+        set(1).await;
+        time::sleep(time::Duration::from_millis(100)).await;
+        set(0).await;
+        assert_eq!(events.recv().await, Some(ButtonEvent::Pressed));
+        assert_eq!(events.recv().await, Some(ButtonEvent::Released));
+        assert!(matches!(
+            events.recv().await,
+            Some(ButtonEvent::LongPress(held)) if held >= config.long_press
+        ));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn servo_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let mut servo = Servo::new_software(pin, ServoCalibration::default()).unwrap();
+        assert_eq!(servo.angle(), None);
+
+        // Angles map linearly to the pulse widths of the calibration
+        servo.set_angle(90.0).await.unwrap();
+        assert_eq!(servo.pulse_width(), Some(time::Duration::from_micros(1500)));
+        assert_eq!(servo.angle(), Some(90.0));
+        servo.set_angle(180.0).await.unwrap();
+        assert_eq!(servo.pulse_width(), Some(time::Duration::from_micros(2000)));
+        assert!(servo.set_angle(181.0).await.is_err());
+
+        // The period must stay longer than the pulses
+        assert!(servo.set_refresh_rate(1000.0).await.is_err());
+        for refresh_rate in [1e-300, 0.0, -50.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                servo.set_refresh_rate(refresh_rate).await,
+                Err(GpioError::InvalidValue(_))
+            ));
+        }
+        servo.set_refresh_rate(100.0).await.unwrap();
+        servo.disable().await.unwrap();
+        assert_eq!(servo.angle(), None);
+    }
+
+    #[tokio::test]
+    async fn stepper_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // 4-wire motors step through the coil patterns
+        let coils = PinGroup::new_outputs(&gpio, &[1, 2, 3, 4], 0)
+            .await
+            .unwrap();
+        let mut stepper = Stepper::new_four_wire(coils, StepMode::HalfStep).unwrap();
+        stepper.rotate(3, 1000.0).await.unwrap();
+        assert_eq!(stepper.position(), 3);
+        let coils: Vec<u8> = (1..=4).map(|pin| backend.get_value(pin).unwrap()).collect();
+        assert_eq!(coils, vec![0, 1, 1, 0]);
+        stepper.set_acceleration(Some(2000.0)).unwrap();
+        stepper.rotate(-3, 1000.0).await.unwrap();
+        assert_eq!(stepper.position(), 0);
+        let coils: Vec<u8> = (1..=4).map(|pin| backend.get_value(pin).unwrap()).collect();
+        assert_eq!(coils, vec![1, 0, 0, 0]);
+
+        // Driver boards get the direction and a pulse per step
+        let step = OutputPin::new(&gpio, 5, 0).await.unwrap();
+        let dir = OutputPin::new(&gpio, 6, 0).await.unwrap();
+        let mut stepper = Stepper::new_step_dir(step, dir).await.unwrap();
+        stepper.rotate(-2, 1000.0).await.unwrap();
+        assert_eq!(stepper.position(), -2);
+        assert_eq!(backend.get_value(6).unwrap(), 0);
+        let _ = time::timeout(
+            time::Duration::from_millis(50),
+            stepper.rotate_continuous(500.0),
+        )
+        .await;
+        assert!(stepper.position() > 0);
+        assert_eq!(backend.get_value(6).unwrap(), 1);
+        assert!(stepper.rotate(1, 0.0).await.is_err());
+        assert!(matches!(
+            stepper.rotate(1, 1e-300).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            stepper.rotate_continuous(-1e-300).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            stepper.set_acceleration(Some(1e-300)),
+            Err(GpioError::InvalidValue(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn hc595_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let data = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let clock = OutputPin::new(&gpio, 2, 0).await.unwrap();
+        let latch = OutputPin::new(&gpio, 3, 0).await.unwrap();
+        let chain = Arc::new(Hc595::new(data, clock, latch, 2).await.unwrap());
+        assert_eq!(chain.output_count(), 16);
+        assert_eq!(chain.bytes().await, vec![0, 0]);
+
+        // Single outputs change without touching the others
+        chain
+            .write_bytes(&[0b1010_0000, 0b0000_0001])
+            .await
+            .unwrap();
+        let led = chain.pin(9).unwrap();
+        led.write(1).await.unwrap();
+        assert_eq!(chain.bytes().await, vec![0b1010_0000, 0b0000_0011]);
+        assert_eq!(led.toggle().await.unwrap(), 0);
+        assert_eq!(chain.read(7).await.unwrap(), 1);
+
+        // The last bit shifted is Q0 of the first chip, and the latch ends low
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+        assert_eq!(backend.get_value(3).unwrap(), 0);
+        assert!(chain.pin(16).is_err());
+        assert!(chain.write_bytes(&[0]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn hc165_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let load = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let clock = OutputPin::new(&gpio, 2, 1).await.unwrap();
+        let data = InputPin::new(&gpio, 3).await.unwrap();
+        let chain = Arc::new(Hc165::new(load, clock, data, 2).await.unwrap());
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 0);
+
+        // Every bit shifted out is read from the data pin
+        assert_eq!(chain.read_bytes().await.unwrap(), vec![0, 0]);
+        backend.set_value(3, 1).unwrap();
+        assert_eq!(chain.read_bytes().await.unwrap(), vec![0xff, 0xff]);
+        assert_eq!(chain.read_mask().await.unwrap(), 0xffff);
+        assert_eq!(chain.pin(15).unwrap().read().await.unwrap(), 1);
+        assert!(chain.pin(16).is_err());
+    }
+
+    #[tokio::test]
+    async fn ws2812_test() {
+        // Each bit of green, red then blue becomes 110 or 100
+        let bytes = ws2812::encode_spi(&[Rgb::new(0xff, 0, 0)]);
+        assert_eq!(
+            bytes[..9],
+            [0x92, 0x49, 0x24, 0xdb, 0x6d, 0xb6, 0x92, 0x49, 0x24]
+        );
+        assert!(bytes[9..].iter().all(|&byte| byte == 0));
+
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 1, 1).await.unwrap();
+        let strip = Ws2812::new_pin(pin).await.unwrap();
+        strip.set_pixels(&[Rgb::new(1, 2, 3)]).await.unwrap();
+
+        // The line is left low to latch the colors
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ir_receiver_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = InputPin::new(&gpio, 1).await.unwrap();
+        backend.set_value(1, 1).unwrap();
+        let (_receiver, mut events) = IrReceiver::new(pin).await.unwrap();
+
+        // Each pulse is timed on the paused clock, once the receiver saw the change
+        let pulse = |value, micros| {
+            backend.set_value(1, value).unwrap();
+            async move {
+                tokio::task::yield_now().await;
+                time::advance(time::Duration::from_micros(micros)).await;
+            }
+        };
+
+        // Address 0x04 and command 0x08, LSB first with their inverses
+        pulse(0, 9000).await;
+        pulse(1, 4500).await;
+        let data = u32::from_le_bytes([0x04, !0x04, 0x08, !0x08]);
+        for bit in 0..32 {
+            pulse(0, 562).await;
+            pulse(1, if data >> bit & 1 == 1 { 1687 } else { 562 }).await;
+        }
+        pulse(0, 562).await;
+        pulse(1, 40000).await;
+        let key = NecEvent {
+            address: 0x04,
+            command: 0x08,
+            repeat: false,
+        };
+        assert_eq!(events.recv().await, Some(key));
+
+        // A repeat frame repeats the last key
+        pulse(0, 9000).await;
+        pulse(1, 2250).await;
+        pulse(0, 562).await;
+        pulse(1, 0).await;
+        assert_eq!(
+            events.recv().await,
+            Some(NecEvent {
+                repeat: true,
+                ..key
+            })
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn hx711_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let clock = OutputPin::new(&gpio, 1, 1).await.unwrap();
+        let data = InputPin::new(&gpio, 2).await.unwrap();
+        let mut hx711 = Hx711::new(clock, data, Hx711Gain::A64).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+
+        // Conversions are ready while the data line is low
+        assert_eq!(hx711.read_raw().await.unwrap(), 0);
+        hx711.set_offset(-10.0);
+        hx711.set_scale(2.0).unwrap();
+        assert_eq!(hx711.weight(3).await.unwrap(), 5.0);
+        hx711.tare(2).await.unwrap();
+        assert_eq!(hx711.weight(1).await.unwrap(), 0.0);
+        assert!(hx711.set_scale(0.0).is_err());
+
+        backend.set_value(2, 1).unwrap();
+        assert!(!hx711.is_ready().await.unwrap());
+        assert!(matches!(hx711.read_raw().await, Err(GpioError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn keypad_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let rows = vec![
+            OutputPin::new(&gpio, 1, 0).await.unwrap(),
+            OutputPin::new(&gpio, 2, 0).await.unwrap(),
+        ];
+        let columns = vec![
+            InputPin::new(&gpio, 3).await.unwrap(),
+            InputPin::new(&gpio, 4).await.unwrap(),
+        ];
+        let config = KeypadConfig {
+            scan_interval: time::Duration::from_millis(2),
+            debounce: time::Duration::from_millis(10),
+        };
+        backend.set_value(3, 1).unwrap();
+        backend.set_value(4, 1).unwrap();
+        let (_keypad, mut events) = Keypad::new(rows, columns, config).await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+
+        // A low column reads as pressed on every row of the mock
+        backend.set_value(3, 0).unwrap();
+        let key = |row, column| Key { row, column };
+        assert_eq!(events.recv().await, Some(KeypadEvent::Pressed(key(0, 0))));
+        assert_eq!(events.recv().await, Some(KeypadEvent::Pressed(key(1, 0))));
+
+        // Both columns on both rows is ambiguous and ignored
+        backend.set_value(4, 0).unwrap();
+        time::sleep(time::Duration::from_millis(30)).await;
+        assert!(events.try_recv().is_err());
+
+        backend.set_value(3, 1).unwrap();
+        backend.set_value(4, 1).unwrap();
+        assert_eq!(events.recv().await, Some(KeypadEvent::Released(key(0, 0))));
+        assert_eq!(events.recv().await, Some(KeypadEvent::Released(key(1, 0))));
+
+        // The rows must be scanned at some interval
+        let config = KeypadConfig {
+            scan_interval: time::Duration::ZERO,
+            ..config
+        };
+        let rows = vec![OutputPin::new(&gpio, 5, 0).await.unwrap()];
+        let columns = vec![InputPin::new(&gpio, 6).await.unwrap()];
+        assert!(matches!(
+            Keypad::new(rows, columns, config).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn seven_segment_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let segment_pins = PinGroup::new_outputs(&gpio, &[1, 2, 3, 4, 5, 6, 7, 8], 0)
+            .await
+            .unwrap();
+        let digit_pins = PinGroup::new_outputs(&gpio, &[9], 0).await.unwrap();
+        let display = SevenSegment::new(segment_pins, digit_pins, SevenSegmentConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(sevenseg::encode_char('7'), Some(0x07));
+        assert_eq!(sevenseg::encode_char('%'), None);
+        assert!(display.set_number(10).is_err());
+        assert!(display.set_text("%").is_err());
+
+        // The single digit is selected low with the segments of a 7 and its decimal point
+        display.set_text("7.").unwrap();
+        assert_eq!(display.segments(), vec![0x87]);
+        time::sleep(time::Duration::from_millis(30)).await;
+        let segments: Vec<u8> = (1..=8).map(|pin| backend.get_value(pin).unwrap()).collect();
+        assert_eq!(segments, vec![1, 1, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(backend.get_value(9).unwrap(), 0);
+
+        assert!(display.set_number(-1).is_err());
+        display.clear();
+        assert_eq!(display.segments(), vec![0]);
+    }
+
+    #[tokio::test]
+    async fn hd44780_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let rs = OutputPin::new(&gpio, 1, 1).await.unwrap();
+        let enable = OutputPin::new(&gpio, 2, 1).await.unwrap();
+        let data = PinGroup::new_outputs(&gpio, &[3, 4, 5, 6], 0)
+            .await
+            .unwrap();
+        let mut lcd = Hd44780::new(rs, enable, data, 16, 2).await.unwrap();
+        assert_eq!(lcd.size(), (16, 2));
+        assert!(lcd.set_cursor(16, 0).await.is_err());
+        assert!(lcd.write_str("é").await.is_err());
+        assert!(lcd.create_char(8, [0; 8]).await.is_err());
+
+        // The low nibble of the last character stays on D4 to D7, with RS selecting data
+        lcd.set_cursor(0, 1).await.unwrap();
+        lcd.write_str("Hi").await.unwrap();
+        let data: Vec<u8> = (3..=6).map(|pin| backend.get_value(pin).unwrap()).collect();
+        assert_eq!(data, vec![1, 0, 0, 1]);
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn led_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let mut led = Led::new(pin).unwrap();
+        assert!(led.set_brightness(1.5).await.is_err());
+
+        // Fading ends at the target brightness
+        led.fade_to(1.0, time::Duration::from_millis(60))
+            .await
+            .unwrap();
+        assert!(led.is_effect_running());
+        led.wait_effect().await.unwrap();
+        assert_eq!(led.brightness(), 1.0);
+        time::sleep(time::Duration::from_millis(20)).await;
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+
+        // Blinking runs until stopped, a new brightness replaces it
+        led.blink(
+            time::Duration::from_millis(10),
+            time::Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+        time::sleep(time::Duration::from_millis(25)).await;
+        assert!(led.is_effect_running());
+        led.off().await.unwrap();
+        assert!(!led.is_effect_running());
+        assert_eq!(led.brightness(), 0.0);
+
+        led.breathe(time::Duration::from_millis(100)).await.unwrap();
+        let pin = led.into_pin().await.unwrap();
+        assert_eq!(pin.last_value(), 0);
+    }
+
+    #[tokio::test]
+    async fn blinker_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let blinker = Blinker::new(pin);
+        let ms = time::Duration::from_millis;
+        assert!(!blinker.is_playing());
+        assert!(blinker.play(BlinkPattern::new().repeat(true)).is_err());
+
+        let sos = BlinkPattern::sos(ms(100));
+        assert_eq!(sos.steps().len(), 18);
+        assert_eq!(sos.duration(), ms(100 * 34));
+        assert_eq!(BlinkPattern::code(3, ms(100), ms(500)).duration(), ms(1100));
+
+        // A pattern played once ends with the pin off
+        blinker.play(BlinkPattern::new().on(ms(30))).unwrap();
+        time::sleep(ms(10)).await;
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        blinker.wait().await;
+        assert!(!blinker.is_playing());
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+
+        // A repeating pattern plays until replaced or stopped
+        blinker
+            .play(BlinkPattern::new().on(ms(10)).off(ms(10)).repeat(true))
+            .unwrap();
+        time::sleep(ms(50)).await;
+        assert!(blinker.is_playing());
+        blinker.play(BlinkPattern::new().on(ms(1000))).unwrap();
+        time::sleep(ms(10)).await;
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        blinker.stop();
+        time::sleep(ms(10)).await;
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+
+        let pin = blinker.into_pin().await.unwrap();
+        assert_eq!(pin.last_value(), 0);
+    }
+
+    #[tokio::test]
+    async fn heartbeat_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let config = HeartbeatConfig {
+            interval: time::Duration::from_millis(10),
+            pet_timeout: Some(time::Duration::from_millis(50)),
+        };
+        let heartbeat = Heartbeat::new(pin, config).unwrap();
+        let mut changes = backend.watch(1).unwrap();
+
+        // The pin toggles while petted
+        for _ in 0..5 {
+            heartbeat.pet();
+            changes.next().await.unwrap().unwrap();
+        }
+        assert!(heartbeat.is_beating());
+
+        // Without pets, the toggling stops until the next pet
+        time::sleep(time::Duration::from_millis(100)).await;
+        assert!(!heartbeat.is_beating());
+        let value = backend.get_value(1).unwrap();
+        time::sleep(time::Duration::from_millis(30)).await;
+        assert_eq!(backend.get_value(1).unwrap(), value);
+        heartbeat.pet();
+        time::sleep(time::Duration::from_millis(30)).await;
+        assert!(heartbeat.is_beating());
+
+        let pin = heartbeat.stop().await.unwrap();
+        assert_eq!(pin.last_value(), 0);
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn pulse_counter_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = InputPin::new(&gpio, 1).await.unwrap();
+        let counter = PulseCounter::new(pin, Edge::Rising, time::Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        // Only the rising edges are counted
+        for _ in 0..10 {
+            backend.set_value(1, 1).unwrap();
+            time::sleep(time::Duration::from_millis(5)).await;
+            backend.set_value(1, 0).unwrap();
+            time::sleep(time::Duration::from_millis(5)).await;
+        }
+        assert_eq!(counter.count(), 10);
+        assert_eq!(counter.frequency_hz(), 10.0);
+
+        counter.reset();
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.frequency_hz(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn pulse_meter_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = InputPin::new(&gpio, 1).await.unwrap();
+        let meter = PulseMeter::new(pin).await.unwrap();
+        assert_eq!(meter.duty_cycle(), None);
+
+        // 10ms high and 30ms low is a 25% duty cycle
+        for _ in 0..3 {
+            backend.set_value(1, 1).unwrap();
+            time::sleep(time::Duration::from_millis(10)).await;
+            backend.set_value(1, 0).unwrap();
+            time::sleep(time::Duration::from_millis(30)).await;
+        }
+        backend.set_value(1, 1).unwrap();
+        time::sleep(time::Duration::from_millis(1)).await;
+
+        let high = meter.high_time().unwrap().as_secs_f64();
+        let low = meter.low_time().unwrap().as_secs_f64();
+        assert!((0.009..0.02).contains(&high), "high time {}", high);
+        assert!((0.029..0.04).contains(&low), "low time {}", low);
+        assert!((0.2..0.3).contains(&meter.duty_cycle().unwrap()));
+        assert!(meter.last_edge().is_some());
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_timestamp_test() {
+        // Watch a pin with timestamped values
+        let gpio10 = GpioPin::new_fake_input(10).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel::<GpioEvent>(16);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio10, sender);
+        let start = time::Instant::now();
+        let _watcher = GpioWatcher::new(pin_map).await.unwrap();
+        let initial = receiver.recv().await.unwrap();
+        assert_eq!(initial.pin, 10);
+        assert_eq!(initial.level, Level::Low);
+        assert!(initial.timestamp >= start);
+
+        // Each change is stamped when it arrives
+        time::sleep(time::Duration::from_millis(20)).await;
+        mock::set_value(10, 1).unwrap();
+        let event = time::timeout(time::Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.value(), 1);
+        assert!(event.timestamp - initial.timestamp >= time::Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_edge_test() {
+        let gpio11 = GpioPin::new_fake_input(11).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(
+            gpio11,
+            Notifier::event_callback(move |event| tx.send((event.value(), event.edge)).unwrap()),
+        );
+        let _watcher = GpioWatcher::new(pin_map).await.unwrap();
+        assert_eq!(rx.recv().await, Some((0, Edge::Falling)));
+
+        mock::set_value(11, 1).unwrap();
+        assert_eq!(rx.recv().await, Some((1, Edge::Rising)));
+
+        // A pulse shorter than the read still notifies both edges
+        mock::set_value(11, 0).unwrap();
+        mock::set_value(11, 1).unwrap();
+        assert_eq!(rx.recv().await, Some((0, Edge::Falling)));
+        assert_eq!(rx.recv().await, Some((1, Edge::Rising)));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_stream_test() {
+        let gpio12 = GpioPin::new_fake_input(12).await.unwrap();
+        let gpio13 = GpioPin::new_fake_input(13).await.unwrap();
+        let (sender, _) = broadcast::channel::<u8>(16);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio12, sender.clone());
+        pin_map.insert(gpio13, sender);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        let mut events = watcher.into_stream().await;
+
+        // The stream starts with the value of each pin
+        let mut initial = [events.next().await.unwrap(), events.next().await.unwrap()];
+        initial.sort_by_key(|event| event.pin);
+        assert_eq!(initial[0].pin, 12);
+        assert_eq!(initial[1].pin, 13);
+        assert!(initial.iter().all(|event| event.level == Level::Low));
+
+        // Changes of all the pins come through the stream
+        mock::set_value(13, 1).unwrap();
+        let event = time::timeout(time::Duration::from_secs(1), events.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((event.pin, event.edge), (13, Edge::Rising));
+    }
+
+    #[tokio::test]
+    async fn polling_watcher_test() {
+        let gpio14 = GpioPin::new_fake_input(14).await.unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<GpioEvent>();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio14, sender);
+        let watcher = PollingWatcher::new(pin_map, time::Duration::from_millis(5))
+            .await
+            .unwrap();
+        assert_eq!(watcher.pin_numbers().await, vec![14]);
+
+        // Initial value
+        let event = receiver.recv().await.unwrap();
+        assert_eq!((event.pin, event.level), (14, Level::Low));
+
+        // Changes are seen at the next sample
+        mock::set_value(14, 1).unwrap();
+        let event = time::timeout(time::Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((event.level, event.edge), (Level::High, Edge::Rising));
+
+        // Nothing is sent while the value stays the same
+        time::sleep(time::Duration::from_millis(20)).await;
+        assert!(receiver.try_recv().is_err());
+
+        // Removed pins are given back
+        let pin = watcher.remove_pin(14).await.unwrap();
+        assert_eq!(pin.get_pin_number(), 14);
+        assert!(watcher.pin_numbers().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sysfs_watch_mode_test() {
+        let root = "test_assets/output/sysfs_watch_mode_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(format!("{}/gpio1", root)).await.unwrap();
+        fs::write(format!("{}/gpio1/value", root), "0")
+            .await
+            .unwrap();
+
+        // Regular files can't be polled for interrupts
+        let backend = SysfsBackend::with_watch_mode(root, SysfsWatchMode::Interrupt);
+        assert_eq!(backend.watch_mode(), SysfsWatchMode::Interrupt);
+        assert!(backend.watch(1).is_err());
+
+        // The default mode falls back to inotify
+        let backend = SysfsBackend::new(root);
+        assert_eq!(backend.watch_mode(), SysfsWatchMode::Auto);
+        let mut changes = backend.watch(1).unwrap();
+        fs::write(format!("{}/gpio1/value", root), "1")
+            .await
+            .unwrap();
+        time::timeout(time::Duration::from_secs(1), changes.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // Missing pins are not exported
+        assert!(matches!(backend.watch(2), Err(GpioError::NotExported(2))));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_shutdown_test() {
+        let gpio15 = GpioPin::new_fake_input(15).await.unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<GpioEvent>();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio15, sender);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap().level, Level::Low);
+
+        // Start a change that would only settle long after the shutdown
+        watcher
+            .set_debounce(15, Some(time::Duration::from_secs(60)))
+            .await
+            .unwrap();
+        mock::set_value(15, 1).unwrap();
+        time::sleep(time::Duration::from_millis(20)).await;
+        assert!(receiver.try_recv().is_err());
+
+        // The pending value is notified before the watcher stops
+        time::timeout(time::Duration::from_secs(1), watcher.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        let event = receiver.recv().await.unwrap();
+        assert_eq!((event.pin, event.level), (15, Level::High));
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_status_test() {
+        let gpio16 = GpioPin::new_fake_input(16).await.unwrap();
+        let (sender, receiver) = watch::channel::<u8>(0);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio16, sender);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        let mut errors = watcher.errors();
+
+        let status = watcher.status().await;
+        assert!(status.running);
+        assert_eq!(status.error_count, 0);
+        assert!(status.last_error.is_none());
+
+        // Values can't be delivered once the receiver is dropped
+        drop(receiver);
+        mock::set_value(16, 1).unwrap();
+        let error = time::timeout(time::Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            error,
+            WatcherError::NotifyFailed { pin: 16, ref error } if matches!(**error, GpioError::ChannelClosed)
+        ));
+
+        let status = watcher.status().await;
+        assert!(status.running);
+        assert_eq!(status.error_count, 1);
+        assert!(matches!(
+            status.last_error,
+            Some(WatcherError::NotifyFailed { pin: 16, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_throttle_test() {
+        let gpio17 = GpioPin::new_fake_input(17).await.unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<GpioEvent>();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio17, sender);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        watcher
+            .set_throttle(17, Some(time::Duration::from_millis(200)))
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().level, Level::Low);
+
+        // The first change is delivered right away
+        mock::set_value(17, 1).unwrap();
+        let event = time::timeout(time::Duration::from_millis(100), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.level, Level::High);
+
+        // The next changes are coalesced into the latest one at the end of the throttle time
+        for value in [0, 1, 0] {
+            time::sleep(time::Duration::from_millis(10)).await;
+            mock::set_value(17, value).unwrap();
+        }
+        time::sleep(time::Duration::from_millis(50)).await;
+        assert!(receiver.try_recv().is_err());
+        let event = time::timeout(time::Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.level, Level::Low);
+        time::sleep(time::Duration::from_millis(250)).await;
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_channel_test() {
+        let gpio18 = GpioPin::new_fake_input(18).await.unwrap();
+        let gpio19 = GpioPin::new_fake_input(19).await.unwrap();
+        let (_watcher, mut receiver) = GpioWatcher::with_channel(vec![gpio18, gpio19], 16)
+            .await
+            .unwrap();
+
+        // Initial values of both pins
+        let mut initial = [
+            receiver.recv().await.unwrap(),
+            receiver.recv().await.unwrap(),
+        ];
+        initial.sort();
+        assert_eq!(initial, [(18, 0), (19, 0)]);
+
+        // Changes of both pins come through the same channel
+        mock::set_value(19, 1).unwrap();
+        let value = time::timeout(time::Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap();
+        assert_eq!(value, Some((19, 1)));
+        mock::set_value(18, 1).unwrap();
+        let value = time::timeout(time::Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap();
+        assert_eq!(value, Some((18, 1)));
+    }
+
+    #[tokio::test]
+    async fn gpio_watcher_shared_pin_test() {
+        let gpio20 = Arc::new(GpioPin::new_fake_input(20).await.unwrap());
+        let (tx, mut rx) = watch::channel::<u8>(0);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(gpio20.clone(), tx);
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+
+        // The pin can still be read while it's watched
+        mock::set_value(20, 1).unwrap();
+        time::timeout(
+            time::Duration::from_secs(1),
+            rx.wait_for(|value| *value == 1),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(gpio20.read().await.unwrap(), 1);
+
+        // The removed pin is the same as the one kept
+        let removed = watcher.remove_pin(20).await.unwrap();
+        assert!(Arc::ptr_eq(&removed, &gpio20));
+    }
+
+    #[tokio::test]
+    async fn gpio_manager_test() {
+        let backend = Arc::new(MockBackend::new());
+        let manager = GpioManager::new(Gpio::with_backend(GpioConfig::default(), backend.clone()));
+
+        // Claimed pins can't be claimed again, even through a clone
+        let led = manager.output(5, "led", 1).await.unwrap();
+        assert_eq!(backend.get_value(5).unwrap(), 1);
+        led.write(0).await.unwrap();
+        assert_eq!(led.claim().owner(), "led");
+        assert!(matches!(
+            manager.clone().input(5, "button").await,
+            Err(GpioError::PinClaimed { pin: 5, ref owner }) if owner == "led"
+        ));
+        assert_eq!(manager.claims(), vec![(5, "led".to_string())]);
+
+        // Waiting claims get the pin once it's released
+        let waiter = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.claim_wait(5, "button").await }
+        });
+        time::sleep(time::Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+        drop(led);
+        let claim = time::timeout(time::Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(manager.owner(5).as_deref(), Some("button"));
+        drop(claim);
+        assert!(manager.owner(5).is_none());
+    }
+
+    #[cfg(feature = "config")]
+    #[tokio::test]
+    async fn pin_setup_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let setup = PinSetup::from_toml(
+            r#"
+            [[pin]]
+            label = "button"
+            number = 7
+            direction = "in"
+            edge = "both"
+            debounce_ms = 20
+
+            [[pin]]
+            label = "led"
+            number = 8
+            direction = "out"
+            default = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(setup.pins[0].edge, Some(Edge::Both));
+        assert_eq!(
+            setup.pins[0].debounce,
+            Some(time::Duration::from_millis(20))
+        );
+        assert_eq!(setup.pins[1].direction, Direction::Out);
+
+        // Pins are created and given back by label
+        let mut pins = setup.build(&gpio).await.unwrap();
+        assert_eq!(backend.get_value(8).unwrap(), 1);
+        pins.pin("led").unwrap().write(0).await.unwrap();
+        assert_eq!(backend.get_value(8).unwrap(), 0);
+
+        // Watched inputs send their events
+        let event = pins.next_event().await.unwrap();
+        assert_eq!(pins.label(event.pin), Some("button"));
+        backend.set_value(7, 1).unwrap();
+        let event = time::timeout(time::Duration::from_secs(1), pins.next_event())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((event.pin, event.level), (7, Level::High));
+
+        // Inconsistent setups are rejected
+        let duplicate = "[[pin]]\nlabel = \"a\"\nnumber = 1\ndirection = \"in\"\n\
+            [[pin]]\nlabel = \"b\"\nnumber = 1\ndirection = \"in\"\n";
+        assert!(matches!(
+            PinSetup::from_toml(duplicate),
+            Err(GpioError::InvalidValue(_))
+        ));
+        let watched_output =
+            "[[pin]]\nlabel = \"a\"\nnumber = 1\ndirection = \"out\"\nedge = \"both\"\n";
+        assert!(PinSetup::from_toml(watched_output).is_err());
+    }
+
+    #[cfg(feature = "config")]
+    #[tokio::test]
+    async fn serde_test() {
+        // Events are forwarded without their timestamp
+        let event = GpioEvent::new(7, Level::High, time::Instant::now());
+        let text = toml::to_string(&event).unwrap();
+        assert_eq!(text, "pin = 7\nlevel = \"high\"\nedge = \"rising\"\n");
+        let received: GpioEvent = toml::from_str(&text).unwrap();
+        assert_eq!(
+            (received.pin, received.level, received.edge),
+            (7, Level::High, Edge::Rising)
+        );
+
+        // Pin settings and setups round trip
+        let bias: Bias =
+            toml::from_str::<HashMap<String, Bias>>("bias = \"pull_up\"").unwrap()["bias"];
+        assert_eq!(bias, Bias::PullUp);
+        let setup = PinSetup::from_toml(
+            "[[pin]]\nlabel = \"button\"\nnumber = 7\ndirection = \"in\"\nedge = \"falling\"\ndebounce_ms = 20\n",
+        )
+        .unwrap();
+        assert_eq!(
+            PinSetup::from_toml(&setup.to_toml().unwrap()).unwrap(),
+            setup
+        );
+    }
+
+    #[tokio::test]
+    async fn gpio_metrics_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let output = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let mut input = InputPin::new(&gpio, 2).await.unwrap();
+        input.enable_watch(Edge::Both).await.unwrap();
+
+        output.write(1).await.unwrap();
+        output.toggle().await.unwrap();
+        output.read().await.unwrap();
+        assert!(output.write(2).await.is_err());
+        let metrics = gpio.metrics().pin(1);
+        assert_eq!((metrics.writes, metrics.reads, metrics.errors), (2, 1, 0));
+
+        // Events delivered by the watcher and the channel overflows are counted
+        let (watcher, _receiver) = GpioWatcher::with_channel(vec![GpioPin::from(input)], 1)
+            .await
+            .unwrap();
+        backend.set_value(2, 1).unwrap();
+        let mut errors = watcher.errors();
+        time::timeout(time::Duration::from_secs(1), errors.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let metrics = gpio.metrics().pin(2);
+        assert_eq!(
+            (metrics.events, metrics.errors, metrics.overflows),
+            (1, 1, 1)
+        );
+        assert!(metrics.reads >= 2);
+
+        assert_eq!(gpio.metrics().pins().len(), 2);
+        gpio.metrics().reset();
+        assert_eq!(gpio.metrics().pin(1), Default::default());
+    }
+
+    #[tokio::test]
+    async fn write_verified_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = GpioPin::new_output(&gpio, 3, 0).await.unwrap();
+        pin.write_verified(1).await.unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 1);
+
+        // A pin that doesn't follow the writes fails the check
+        backend.set_stuck(3, Some(0)).unwrap();
+        assert!(matches!(
+            pin.write_verified(1).await,
+            Err(GpioError::VerifyFailed {
+                pin: 3,
+                expected: 1,
+                actual: 0
+            })
+        ));
+        backend.set_stuck(3, None).unwrap();
+        pin.write_verified(1).await.unwrap();
+
+        let input = GpioPin::new_input(&gpio, 4).await.unwrap();
+        assert!(matches!(
+            input.write_verified(1).await,
+            Err(GpioError::WrongDirection { pin: 4, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn retry_policy_test() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0), time::Duration::from_millis(2));
+        assert_eq!(policy.delay(2), time::Duration::from_millis(8));
+        assert_eq!(policy.delay(10), time::Duration::from_millis(50));
+
+        // Writes are retried while the value file is missing
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 5, 0).await.unwrap();
+        backend.unexport(5).await.unwrap();
+        let exporter = tokio::spawn({
+            let backend = backend.clone();
+            async move {
+                time::sleep(time::Duration::from_millis(10)).await;
+                backend.export_output(5, 0).await.unwrap();
+            }
+        });
+        pin.write(1).await.unwrap();
+        exporter.await.unwrap();
+        assert_eq!(backend.get_value(5).unwrap(), 1);
+
+        // Without retries, the first error is returned
+        let gpio = Gpio::with_backend(
+            GpioConfig {
+                retry: RetryPolicy::none(),
+                ..Default::default()
+            },
+            backend.clone(),
+        );
+        let pin = OutputPin::new(&gpio, 6, 0).await.unwrap();
+        backend.unexport(6).await.unwrap();
+        assert!(matches!(pin.write(1).await, Err(GpioError::NotExported(6))));
+    }
+
+    #[tokio::test]
+    async fn timeout_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(
+            GpioConfig {
+                timeout: Some(time::Duration::from_millis(20)),
+                ..Default::default()
+            },
+            backend.clone(),
+        );
+        let pin = OutputPin::new(&gpio, 5, 0).await.unwrap();
+        pin.write(1).await.unwrap();
+        assert_eq!(pin.read().await.unwrap(), 1);
+
+        // Operations slower than the timeout fail instead of blocking
+        backend.set_latency(time::Duration::from_secs(5));
+        assert!(matches!(pin.read().await, Err(GpioError::Timeout(_))));
+        assert!(matches!(pin.write(0).await, Err(GpioError::Timeout(_))));
+        assert_eq!(backend.get_value(5).unwrap(), 1);
+        assert_eq!(gpio.metrics().pin(5).errors, 2);
+    }
+
+    #[tokio::test]
+    async fn wait_for_value_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let mut pin = InputPin::new(&gpio, 5).await.unwrap();
+        let timeout = time::Duration::from_secs(1);
+
+        // Already at the value
+        assert!(pin.wait_for_value(0, timeout).await.unwrap());
+        // Polled without watch
+        let setter = tokio::spawn({
+            let backend = backend.clone();
+            async move {
+                time::sleep(time::Duration::from_millis(30)).await;
+                backend.set_value(5, 1).unwrap();
+            }
+        });
+        assert!(pin.wait_for_value(1, timeout).await.unwrap());
+        setter.await.unwrap();
+
+        // Watched changes
+        pin.enable_watch(Edge::Both).await.unwrap();
+        let setter = tokio::spawn({
+            let backend = backend.clone();
+            async move {
+                time::sleep(time::Duration::from_millis(30)).await;
+                backend.set_value(5, 0).unwrap();
+            }
+        });
+        assert!(pin.wait_for_value(0, timeout).await.unwrap());
+        setter.await.unwrap();
+
+        // Timed out
+        let short = time::Duration::from_millis(30);
+        assert!(!pin.wait_for_value(1, short).await.unwrap());
+        assert!(pin.wait_for_value(2, short).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_for_edge_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let mut pin = InputPin::new(&gpio, 5).await.unwrap();
+        pin.enable_watch(Edge::Both).await.unwrap();
+
+        // The falling edge is skipped while waiting for a rising one
+        let setter = tokio::spawn({
+            let backend = backend.clone();
+            async move {
+                for value in [1, 0, 1] {
+                    time::sleep(time::Duration::from_millis(20)).await;
+                    backend.set_value(5, value).unwrap();
+                }
+            }
+        });
+        let event = pin.wait_for_edge(Edge::Falling).await.unwrap();
+        assert_eq!((event.pin, event.level), (5, Level::Low));
+        let event = pin.wait_for_edge(Edge::Rising).await.unwrap();
+        assert_eq!(event.edge, Edge::Rising);
+        setter.await.unwrap();
+
+        // Polled without watch
+        pin.disable_watch().await.unwrap();
+        let setter = tokio::spawn({
+            let backend = backend.clone();
+            async move {
+                time::sleep(time::Duration::from_millis(30)).await;
+                backend.set_value(5, 0).unwrap();
+            }
+        });
+        let event = pin.wait_for_edge(Edge::Both).await.unwrap();
+        assert_eq!(event.edge, Edge::Falling);
+        setter.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn drive_strength_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = GpioPin::new_output(&gpio, 5, 0).await.unwrap();
+        assert_eq!(backend.get_drive_strength(5).unwrap(), None);
+        pin.set_drive_strength(20).await.unwrap();
+        assert_eq!(backend.get_drive_strength(5).unwrap(), Some(20));
+        assert!(matches!(
+            pin.set_drive_strength(41).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+        assert_eq!(backend.get_drive_strength(5).unwrap(), Some(20));
+
+        let pin = GpioPin::new_input(&gpio, 6).await.unwrap();
+        assert!(matches!(
+            pin.set_drive_strength(20).await,
+            Err(GpioError::WrongDirection { pin: 6, .. })
+        ));
+
+        // Strengths beyond the SoC's are rejected before running the gpio command
+        let sysfs = SysfsBackend::new("/tmp");
+        for milliamps in [0, 41] {
+            assert!(matches!(
+                sysfs.set_drive_strength(5, milliamps).await,
+                Err(GpioError::InvalidValue(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn output_mode_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Open-drain drives 0 and releases 1 to the pull-up
+        let pin = GpioPin::new_input(&gpio, 5).await.unwrap();
+        let mut line = OpenDrain::new(pin);
+        line.set_bias(Bias::PullUp).await.unwrap();
+        line.set(false).await.unwrap();
+        assert!(line.is_driven());
+        assert_eq!(backend.get_value(5).unwrap(), 0);
+        line.set(true).await.unwrap();
+        assert!(!line.is_driven());
+        assert!(line.read().await.unwrap());
+
+        // Open-source drives 1 and releases 0 to the pull-down
+        let pin = GpioPin::new_input(&gpio, 6).await.unwrap();
+        let mut line = OpenDrain::with_mode(pin, OutputMode::OpenSource);
+        line.set_bias(Bias::PullDown).await.unwrap();
+        line.set(true).await.unwrap();
+        assert!(line.is_driven());
+        assert_eq!(backend.get_value(6).unwrap(), 1);
+        line.set(false).await.unwrap();
+        assert!(!line.is_driven());
+        assert!(!line.read().await.unwrap());
+        assert!(matches!(line.into_pin(), Ok(GpioPin::Input(_))));
+    }
+
+    #[tokio::test]
+    async fn direction_change_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // The active-low setting survives the direction changes
+        let mut pin = InputPin::new(&gpio, 5).await.unwrap();
+        pin.set_active_low(true).await.unwrap();
+        let pin = pin.into_output(1).await.unwrap();
+        assert!(pin.is_active_low());
+        assert_eq!(backend.get_value(5).unwrap(), 0);
+        let pin = pin.into_input().await.unwrap();
+        assert!(pin.is_active_low());
+        backend.set_value(5, 1).unwrap();
+        assert_eq!(pin.read().await.unwrap(), 0);
+
+        // The sysfs backend writes the direction file instead of exporting the pin again
+        let root = "test_assets/output/direction_change_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(format!("{}/gpio3", root)).await.unwrap();
+        let sysfs = SysfsBackend::new(root);
+        let direction = format!("{}/gpio3/direction", root);
+        sysfs.set_output(3, 1).await.unwrap();
+        assert_eq!(fs::read_to_string(&direction).await.unwrap(), "high");
+        sysfs.set_input(3).await.unwrap();
+        assert_eq!(fs::read_to_string(&direction).await.unwrap(), "in");
+        assert!(matches!(
+            sysfs.set_input(4).await,
+            Err(GpioError::NotExported(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn shared_pin_test() {
+        use futures::FutureExt;
+
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = SharedPin::new(OutputPin::new(&gpio, 5, 0).await.unwrap());
+
+        // Toggles from several tasks are serialized, so none is lost
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let pin = pin.clone();
+                tokio::spawn(async move { pin.toggle().await.unwrap() })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(backend.get_value(5).unwrap(), 0);
+        assert_eq!(pin.last_value().await, Some(0));
+
+        // A sequence under the lock isn't interrupted
+        {
+            let guard = pin.lock().await;
+            guard.write(1).await.unwrap();
+            assert!(pin.write(0).now_or_never().is_none());
+            assert_eq!(guard.read().await.unwrap(), 1);
+        }
+
+        let clone = pin.clone();
+        assert_eq!(pin.handles(), 2);
+        let pin = pin.into_inner().unwrap_err();
+        drop(clone);
+        assert!(matches!(pin.into_inner(), Ok(GpioPin::Output(_))));
+    }
+
+    #[tokio::test]
+    async fn batch_export_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        let pins = GpioPin::new_outputs(&gpio, &[(5, 1), (6, 0), (7, 1)])
+            .await
+            .unwrap();
+        assert_eq!(pins.len(), 3);
+        assert_eq!(backend.get_value(7).unwrap(), 1);
+        let pins = GpioPin::new_inputs(&gpio, &[8, 9]).await.unwrap();
+        assert!(pins.iter().all(|pin| matches!(pin, GpioPin::Input(_))));
+
+        // A failed export releases the pins of the batch
+        assert!(
+            GpioPin::new_outputs(&gpio, &[(10, 0), (11, 2), (12, 0)])
+                .await
+                .is_err()
+        );
+        for pin_number in [10, 11, 12] {
+            assert!(backend.get_value(pin_number).is_err());
+        }
+        assert!(GpioPin::new_inputs(&gpio, &[13, 13]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn adopt_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // Pins exported by someone else keep their direction and value
+        backend.export_input(5).await.unwrap();
+        backend.export_output(6, 1).await.unwrap();
+        assert!(matches!(
+            GpioPin::adopt(&gpio, 5).await,
+            Ok(GpioPin::Input(_))
+        ));
+        let pin = GpioPin::adopt(&gpio, 6).await.unwrap();
+        assert_eq!(pin.last_value(), Some(1));
+        assert_eq!(backend.get_value(6).unwrap(), 1);
+        assert!(matches!(
+            GpioPin::adopt(&gpio, 7).await,
+            Err(GpioError::NotExported(7))
+        ));
+
+        // The sysfs backend reads the direction file
+        let root = "test_assets/output/adopt_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(format!("{}/gpio3", root)).await.unwrap();
+        fs::write(format!("{}/gpio3/direction", root), "out\n")
+            .await
+            .unwrap();
+        let sysfs = SysfsBackend::new(root);
+        assert_eq!(sysfs.direction(3).await.unwrap(), Direction::Out);
+        assert!(matches!(
+            sysfs.direction(4).await,
+            Err(GpioError::NotExported(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn sysfs_export_files_test() {
+        let root = "test_assets/output/sysfs_export_files_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(format!("{}/gpio3", root)).await.unwrap();
+        let backend = SysfsBackend::new(root).with_export_mode(SysfsExportMode::Files);
+        assert_eq!(backend.export_mode(), SysfsExportMode::Files);
+
+        // Exports and edges are written to the sysfs files
+        backend.export_output(3, 1).await.unwrap();
+        let read = |name: &str| fs::read_to_string(format!("{}/{}", root, name));
+        assert_eq!(read("export").await.unwrap(), "3");
+        assert_eq!(read("gpio3/direction").await.unwrap(), "high");
+        backend.export_input(3).await.unwrap();
+        backend.set_edge(3, Some(Edge::Falling)).await.unwrap();
+        assert_eq!(read("gpio3/direction").await.unwrap(), "in");
+        assert_eq!(read("gpio3/edge").await.unwrap(), "falling");
+
+        // The pull resistors can only be set with the gpio command
+        assert!(matches!(
+            backend.set_bias(3, Bias::PullUp).await,
+            Err(GpioError::Unsupported(_))
+        ));
+
+        // Unexports are written to the sysfs files too, the kernel removing the pin directory
+        assert!(matches!(
+            backend.unexport(3).await,
+            Err(GpioError::ExportFailed(_))
+        ));
+        assert_eq!(read("unexport").await.unwrap(), "3");
+        fs::remove_dir_all(format!("{}/gpio3", root)).await.unwrap();
+        backend.unexport(3).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn gpio_command_test() {
+        let command = GpioCommand::default();
+        assert_eq!(
+            command.args(Subcommand::Mode, 5, Some("up")),
+            ["-g", "mode", "5", "up"]
+        );
+        assert_eq!(
+            command.args(Subcommand::Unexport, 5, None),
+            ["unexport", "5"]
+        );
+
+        // Other tools are run with their own program and subcommands
+        let command = GpioCommand {
+            program: "false".into(),
+            edge: "set-edge".to_string(),
+            numbering: Vec::new(),
+            ..Default::default()
+        };
+        let backend = SysfsBackend::new("test_assets/output/gpio_command_test")
+            .with_export_mode(SysfsExportMode::Command)
+            .with_command(command);
+        match backend.set_edge(5, Some(Edge::Both)).await {
+            Err(GpioError::CommandFailed { command, .. }) => {
+                assert_eq!(command, "false set-edge 5 both")
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "mmio")]
+    #[tokio::test]
+    async fn mmio_backend_test() {
+        use super::super::backend::GpioBackend;
+        use super::super::mmio::{MmioBackend, Soc};
+
+        assert_eq!(Soc::of(Board::OrangePiZero2).unwrap(), Soc::AllwinnerH616);
+
+        // PC6 is pin 70, the 7th pin of the 3rd bank
+        let backend = MmioBackend::in_memory(Soc::AllwinnerH616);
+        backend.export_output(70, 1).await.unwrap();
+        assert_eq!(backend.register(0, 2 * 0x24) >> 24 & 0xF, 1);
+        assert_eq!(backend.register(0, 2 * 0x24 + 0x10), 1 << 6);
+        assert_eq!(backend.direction(70).await.unwrap(), Direction::Out);
+        backend.set_active_low(70, true).await.unwrap();
+        assert_eq!(backend.read(70).await.unwrap(), 0);
+        backend.write(70, 1).await.unwrap();
+        assert_eq!(backend.register(0, 2 * 0x24 + 0x10), 0);
+
+        backend.set_bias(70, Bias::PullUp).await.unwrap();
+        assert_eq!(backend.register(0, 2 * 0x24 + 0x1C) >> 12 & 0x3, 1);
+        backend.set_drive_strength(70, 40).await.unwrap();
+        assert_eq!(backend.register(0, 2 * 0x24 + 0x14) >> 12 & 0x3, 3);
+        backend.unexport(70).await.unwrap();
+        assert!(matches!(
+            backend.direction(70).await,
+            Err(GpioError::NotExported(70))
+        ));
+        // The H616 has no PA bank
+        assert!(backend.export_input(5).await.is_err());
+
+        // Rockchip writes are masked, GPIO1_B2 is pin 42 in the 2nd controller
+        let backend = MmioBackend::in_memory(Soc::RockchipRk3588);
+        backend.export_output(42, 1).await.unwrap();
+        assert_eq!(backend.register(1, 0x00), 1 << 26 | 1 << 10);
+        assert_eq!(backend.register(1, 0x08), 1 << 26 | 1 << 10);
+        assert!(matches!(
+            backend.set_bias(42, Bias::PullUp).await,
+            Err(GpioError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn fast_pin_test() {
+        let root = "test_assets/output/fast_pin_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(format!("{}/gpio3", root)).await.unwrap();
+        let config = GpioConfig {
+            sysfs_root: root.into(),
+            ..Default::default()
+        };
+        let backend = SysfsBackend::new(root).with_export_mode(SysfsExportMode::Files);
+        let gpio = Gpio::with_backend(config, Arc::new(backend));
+        let pin = OutputPin::new(&gpio, 3, 0).await.unwrap();
+
+        // The value file must exist to be kept open
+        let value_path = pin.get_value_path();
+        fs::write(&value_path, "0").await.unwrap();
+        let fast = FastPin::new(pin).unwrap();
+
+        // Writes go through the open file
+        fast.write(1).unwrap();
+        assert_eq!(fs::read_to_string(&value_path).await.unwrap(), "1");
+        assert_eq!(fast.toggle().unwrap(), 0);
+        assert_eq!(fast.read().unwrap(), 0);
+        assert!(fast.write(2).is_err());
+
+        // The pin given back remembers the last value
+        fast.write(1).unwrap();
+        let pin = fast.into_pin();
+        assert_eq!(pin.last_value(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn precise_soft_pwm_test() {
+        // A full duty ratio holds the pin high
+        let pin = OutputPin::new_fake(27, 0).await.unwrap();
+        let pwm = SoftPwm::precise(pin, 100.0, 1.0).unwrap();
+        assert!(pwm.is_precise());
+        time::sleep(time::Duration::from_millis(50)).await;
+        assert_eq!(mock::get_value(27).unwrap(), 1);
+
+        // Duty changes reach the timing thread, which toggles the pin
+        pwm.set_duty(0.5).unwrap();
+        assert_eq!(pwm.duty(), 0.5);
+        let mut levels = HashSet::new();
+        for _ in 0..100 {
+            levels.insert(mock::get_value(27).unwrap());
+            time::sleep(time::Duration::from_millis(1)).await;
+        }
+        assert_eq!(levels.len(), 2);
+
+        // Stopping gives the pin back in the low state
+        let pin = pwm.stop().await.unwrap();
+        assert_eq!(pin.get_pin_number(), 27);
+        assert_eq!(mock::get_value(27).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn snapshot_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let _output = OutputPin::new(&gpio, 229, 1).await.unwrap();
+        let mut input = InputPin::new(&gpio, 5).await.unwrap();
+        input.enable_watch(Edge::Rising).await.unwrap();
+
+        // Header pins are listed with their positions, exported or not
+        let snapshot = gpio.snapshot().await.unwrap();
+        let output = snapshot.get(229).unwrap();
+        assert_eq!((output.physical, output.wiring), (Some(3), Some(0)));
+        assert_eq!(output.direction, Some(Direction::Out));
+        assert_eq!(output.value, Some(1));
+        assert!(!snapshot.get(228).unwrap().is_exported());
+
+        // Other exported pins are added
+        let input = snapshot.get(5).unwrap();
+        assert_eq!(input.physical, None);
+        assert_eq!(input.edge, Some(Edge::Rising));
+        let exported: Vec<u8> = snapshot.exported().map(|pin| pin.pin_number).collect();
+        assert_eq!(exported, [5, 229]);
+
+        // It's displayed like gpio readall
+        let table = snapshot.to_string();
+        assert!(table.contains("        3 |   0 | 229 |  out |     1 | none"));
+        assert!(table.contains("        - |   - |   5 |   in |     0 | rising"));
+    }
+
+    #[cfg(feature = "config")]
+    #[tokio::test]
+    async fn state_store_test() {
+        let root = "test_assets/output/state_store_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(root).await.unwrap();
+        let path = format!("{}/state.toml", root);
+
+        // Record an active-low output and a watched input
+        let gpio = Gpio::with_backend(GpioConfig::default(), Arc::new(MockBackend::new()));
+        let store = StateStore::open(&path).await.unwrap();
+        assert!(store.pins().await.is_empty());
+        let relay = OutputPin::new_active_low(&gpio, 8, 0).await.unwrap();
+        store.write(&relay, 1).await.unwrap();
+        let mut button = GpioPin::new_input(&gpio, 7).await.unwrap();
+        button.enable_watch(Edge::Both).await.unwrap();
+        store.record(&button).await.unwrap();
+        store.write(&relay, 0).await.unwrap();
+        store.write(&relay, 1).await.unwrap();
+
+        // After a restart, the pins come back as they were left
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let store = StateStore::open(&path).await.unwrap();
+        let pins = store.restore(&gpio).await.unwrap();
+        assert_eq!(pins.len(), 2);
+        assert_eq!(pins[0].edge(), Some(Edge::Both));
+        assert_eq!(pins[1].last_value(), Some(1));
+        assert!(pins[1].is_active_low());
+        assert_eq!(backend.get_value(8).unwrap(), 0);
+
+        // Forgotten pins are not restored anymore
+        store.forget(7).await.unwrap();
+        let store = StateStore::open(&path).await.unwrap();
+        assert_eq!(store.pins().await.len(), 1);
+        assert!(store.get(7).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn recorder_test() {
+        let root = "test_assets/output/recorder_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(root).await.unwrap();
+        let path = format!("{}/capture.csv", root);
+
+        // Record a pulse on a watched pin
+        let recorder = Recorder::with_file(&path, 16).unwrap();
+        let mut pin = GpioPin::new_fake_input(41).await.unwrap();
+        pin.enable_watch(Edge::Both).await.unwrap();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(pin, recorder.notifier());
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        time::sleep(time::Duration::from_millis(20)).await;
+        mock::set_value(41, 1).unwrap();
+        time::sleep(time::Duration::from_millis(30)).await;
+        mock::set_value(41, 0).unwrap();
+        time::sleep(time::Duration::from_millis(20)).await;
+        watcher.shutdown().await.unwrap();
+
+        // The initial value and both edges are recorded in order
+        let levels: Vec<Level> = recorder.transitions().iter().map(|t| t.level).collect();
+        assert_eq!(levels, [Level::Low, Level::High, Level::Low]);
+        let (level, width) = recorder.durations(41)[1];
+        assert_eq!(level, Level::High);
+        assert!(width >= time::Duration::from_millis(25));
+        assert!(recorder.transitions_of(42).is_empty());
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        assert!(lines.lines().nth(1).unwrap().ends_with(",41,1"));
+
+        // The oldest transitions are dropped once the buffer is full
+        let recorder = Recorder::new(2);
+        for value in [0, 1, 0] {
+            recorder.record(GpioEvent::new(41, value.into(), time::Instant::now()));
+        }
+        assert_eq!(recorder.dropped(), 1);
+        assert_eq!(recorder.transitions()[0].level, Level::High);
+    }
+
+    #[tokio::test]
+    async fn recorder_vcd_test() {
+        let recorder = Recorder::new(16);
+        let at = |millis| recorder.start() + time::Duration::from_millis(millis);
+        recorder.record(GpioEvent::new(9, Level::High, at(0)));
+        recorder.record(GpioEvent::new(5, Level::Low, at(1)));
+        recorder.record(GpioEvent::new(9, Level::Low, at(1)));
+        recorder.record(GpioEvent::new(5, Level::High, at(3)));
+
+        // A signal per pin, unknown until its first transition
+        let mut vcd = Vec::new();
+        recorder.write_vcd(&mut vcd).unwrap();
+        let vcd = String::from_utf8(vcd).unwrap();
+        assert!(vcd.contains("$timescale 1ns $end"));
+        assert!(vcd.contains("$var wire 1 ! gpio5 $end\n$var wire 1 \" gpio9 $end"));
+        assert!(vcd.ends_with("$dumpvars\nx!\nx\"\n$end\n1\"\n#1000000\n0!\n0\"\n#3000000\n1!\n"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waveform_replay_test() {
+        // Time and value pairs go to the given pin, recorder lines keep theirs
+        let waveform = Waveform::from_csv("time_us,value\n0,1\n1500,0\n4000,1\n", 3).unwrap();
+        assert_eq!(waveform.pins(), [3]);
+        assert_eq!(waveform.duration(), time::Duration::from_millis(4));
+        let waveform = Waveform::from_csv("# capture\n0,7,1\n10,7,0\n", 0)
+            .unwrap()
+            .map_pin(7, 4);
+        assert_eq!(waveform.pins(), [4]);
+        assert!(Waveform::from_csv("0,2\n", 3).is_err());
+
+        // The levels are set at the recorded times
+        let backend = Arc::new(MockBackend::new());
+        backend.export_input(3).await.unwrap();
+        let waveform = Waveform::from_csv("0,1\n2000,0\n4000,1\n", 3).unwrap();
+        let replay = tokio::spawn({
+            let backend = backend.clone();
+            async move { waveform.replay(&backend).await }
+        });
+        time::sleep(time::Duration::from_millis(1)).await;
+        assert_eq!(backend.get_value(3).unwrap(), 1);
+        time::sleep(time::Duration::from_millis(2)).await;
+        assert_eq!(backend.get_value(3).unwrap(), 0);
+        replay.await.unwrap().unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 1);
+
+        // Recorded captures replay the same way
+        let recorder = Recorder::new(8);
+        recorder.record(GpioEvent::new(3, Level::Low, recorder.start()));
+        Waveform::from(&recorder).replay(&backend).await.unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn signal_generator_test() {
+        let ms = time::Duration::from_millis;
+        let signal = SignalGenerator::new(42)
+            .hold(0, ms(5))
+            .pulses(2, ms(2), ms(3))
+            .square(ms(10), 0.2, 1)
+            .unwrap()
+            .script(&[(1, ms(4)), (1, ms(1))]);
+        assert_eq!(signal.duration(), ms(30));
+        let times: Vec<_> = signal
+            .waveform()
+            .transitions()
+            .iter()
+            .map(|t| t.time)
+            .collect();
+        assert_eq!(
+            times,
+            [ms(0), ms(5), ms(7), ms(10), ms(12), ms(15), ms(17), ms(25)]
+        );
+        assert!(SignalGenerator::new(42).square(ms(10), 2.0, 1).is_err());
+
+        // A watcher on the fake pin sees every transition at its time
+        let recorder = Recorder::new(16);
+        let mut pin = GpioPin::new_fake_input(42).await.unwrap();
+        pin.enable_watch(Edge::Both).await.unwrap();
+        let mut pin_map = HashMap::new();
+        pin_map.insert(pin, recorder.notifier());
+        let _watcher = GpioWatcher::new(pin_map).await.unwrap();
+        time::sleep(ms(1)).await;
+        recorder.clear();
+        let start = time::Instant::now();
+        signal.run_fake().await.unwrap();
+        assert_eq!(start.elapsed(), ms(30));
+        assert_eq!(mock::get_value(42).unwrap(), 1);
+        let widths: Vec<_> = recorder
+            .durations(42)
+            .iter()
+            .map(|(_, width)| *width)
+            .collect();
+        assert_eq!(widths, [ms(2), ms(3), ms(2), ms(3), ms(2), ms(8)]);
+    }
+
+    #[tokio::test]
+    async fn loopback_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let output = OutputPin::new(&gpio, 1, 1).await.unwrap();
+        let mut input = InputPin::new(&gpio, 2).await.unwrap();
+        input.enable_watch(Edge::Both).await.unwrap();
+        assert!(backend.connect(1, 1).is_err());
+
+        // The input takes the level of the output as soon as they're wired
+        backend.connect(1, 2).unwrap();
+        assert_eq!(input.read().await.unwrap(), 1);
+
+        // Writes are seen by a watcher on the input
+        let recorder = Recorder::new(16);
+        let mut pin_map = HashMap::new();
+        pin_map.insert(GpioPin::from(input), recorder.notifier());
+        let watcher = GpioWatcher::new(pin_map).await.unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        output.write(0).await.unwrap();
+        output.write(0).await.unwrap();
+        output.write(1).await.unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+
+        // Setting the level the input already has is no edge
+        backend.set_value(2, 1).unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        let levels: Vec<Level> = recorder.transitions().iter().map(|t| t.level).collect();
+        assert_eq!(levels, [Level::High, Level::Low, Level::High]);
+
+        // Once disconnected, the input keeps its level
+        backend.disconnect(1, 2);
+        output.write(0).await.unwrap();
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+        watcher.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fault_injection_test() {
+        let backend = Arc::new(MockBackend::new());
+        let config = GpioConfig {
+            timeout: Some(time::Duration::from_millis(500)),
+            ..Default::default()
+        };
+        let gpio = Gpio::with_backend(config, backend.clone());
+
+        // Failed exports are reported
+        backend.inject_fault(Some(3), MockOperation::Export, Fault::ExportFailed, Some(1));
+        assert!(matches!(
+            OutputPin::new(&gpio, 3, 0).await,
+            Err(GpioError::ExportFailed(_))
+        ));
+        let pin = OutputPin::new(&gpio, 3, 0).await.unwrap();
+
+        // A fault injected 0 times never applies
+        backend.inject_fault(Some(3), MockOperation::Read, Fault::NotFound, Some(0));
+        assert_eq!(pin.read().await.unwrap(), 0);
+        assert_eq!(pin.read().await.unwrap(), 0);
+
+        // Transient EACCES on writes are retried, persistent ones are not
+        let write = MockOperation::Write;
+        backend.inject_fault(None, write, Fault::PermissionDenied, Some(2));
+        pin.write(1).await.unwrap();
+        assert_eq!(backend.get_value(3).unwrap(), 1);
+        backend.inject_fault(Some(3), write, Fault::PermissionDenied, None);
+        assert!(matches!(
+            pin.write(0).await,
+            Err(GpioError::PermissionDenied(_))
+        ));
+        backend.clear_faults();
+
+        // Reads can fail with ENOENT, any errno, or be too slow
+        let read = MockOperation::Read;
+        backend.inject_fault(Some(3), read, Fault::NotFound, Some(1));
+        backend.inject_fault(Some(3), read, Fault::Errno(16), Some(1));
+        assert!(matches!(pin.read().await, Err(GpioError::NotExported(3))));
+        assert!(matches!(pin.read().await, Err(GpioError::Io(_))));
+        let delay = Fault::Delay(time::Duration::from_secs(1));
+        backend.inject_fault(Some(3), read, delay, Some(1));
+        assert!(matches!(pin.read().await, Err(GpioError::Timeout(_))));
+        assert_eq!(pin.read().await.unwrap(), 1);
+
+        // Watches fail without being delayed
+        backend.inject_fault(None, MockOperation::Watch, Fault::NotFound, None);
+        assert!(backend.watch(3).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn virtual_clock_test() {
+        let backend = Arc::new(MockBackend::new());
+        let config = GpioConfig {
+            clock: Clock::Virtual,
+            ..Default::default()
+        };
+        let gpio = Gpio::with_backend(config, backend.clone());
+        let real_start = std::time::Instant::now();
+
+        // A long press of the default second is seen without waiting for it
+        let pin = InputPin::new(&gpio, 1).await.unwrap();
+        let (_button, mut events) = Button::new(pin, ButtonConfig::default()).await.unwrap();
+        backend.set_value(1, 1).unwrap();
+        time::sleep(time::Duration::from_millis(1500)).await;
+        backend.set_value(1, 0).unwrap();
+        assert_eq!(events.recv().await, Some(ButtonEvent::Pressed));
+        assert_eq!(events.recv().await, Some(ButtonEvent::Released));
+        assert!(matches!(
+            events.recv().await,
+            Some(ButtonEvent::LongPress(held)) if held >= time::Duration::from_secs(1)
+        ));
+
+        // The echo is polled on the tokio time, to the millisecond
+        let trigger = OutputPin::new(&gpio, 2, 0).await.unwrap();
+        let echo = InputPin::new(&gpio, 3).await.unwrap();
+        let sensor = HcSr04::new(trigger, echo).await.unwrap();
+        let simulator = backend.clone();
+        tokio::spawn(async move {
+            time::sleep(time::Duration::from_millis(5)).await;
+            simulator.set_value(3, 1).unwrap();
+            time::sleep(time::Duration::from_millis(10)).await;
+            simulator.set_value(3, 0).unwrap();
+        });
+        let echo = sensor.measure_echo().await.unwrap();
+        assert!((9..=11).contains(&echo.as_millis()), "{:?}", echo);
+        let (_, echo) = sensor.into_pins();
+        let sensor = HcSr04::new(OutputPin::new(&gpio, 4, 0).await.unwrap(), echo)
+            .await
+            .unwrap();
+        assert!(matches!(
+            sensor.distance_mm().await,
+            Err(GpioError::Timeout(_))
+        ));
+
+        // The precise PWM falls back to a task
+        let pin = OutputPin::new(&gpio, 5, 0).await.unwrap();
+        let pwm = SoftPwm::precise(pin, 100.0, 0.25).unwrap();
+        assert!(!pwm.is_precise());
+        pwm.stop().await.unwrap();
+
+        assert!(real_start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_harness_test() {
+        let harness = GpioTestHarness::new();
+        let pin = OutputPin::new(harness.gpio(), 1, 0).await.unwrap();
+        let ms = time::Duration::from_millis;
+
+        // Every write is recorded, even when the level doesn't change
+        pin.write(1).await.unwrap();
+        time::sleep(ms(10)).await;
+        pin.write(0).await.unwrap();
+        pin.write(0).await.unwrap();
+        time::sleep(ms(5)).await;
+        pin.write(1).await.unwrap();
+        harness.assert_sequence(1, &[(1, ms(0)), (0, ms(10)), (0, ms(10)), (1, ms(15))]);
+        assert_eq!(harness.writes().len(), 4);
+
+        // Writes off by more than the tolerance fail the assertion
+        let late = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            harness.assert_sequence(1, &[(1, ms(0)), (0, ms(12)), (0, ms(12)), (1, ms(15))])
+        }));
+        assert!(late.is_err());
+        let harness = harness.with_tolerance(ms(2));
+        harness.assert_sequence(1, &[(1, ms(0)), (0, ms(12)), (0, ms(12)), (1, ms(15))]);
+
+        // The waveforms of the helpers can be checked, the PWM driving the pin low once stopped
+        harness.clear();
+        let pwm = SoftPwm::new(pin, 50.0, 0.25).unwrap();
+        time::sleep(ms(35)).await;
+        pwm.stop().await.unwrap();
+        harness.assert_sequence(
+            1,
+            &[
+                (1, ms(15)),
+                (0, ms(20)),
+                (1, ms(35)),
+                (0, ms(40)),
+                (0, ms(50)),
+            ],
+        );
+    }
+
+    #[cfg(feature = "ftdi")]
+    #[tokio::test]
+    async fn ftdi_backend_test() {
+        use super::super::backend::GpioBackend;
+        use super::super::ftdi::{self, FtdiBackend, FtdiChip};
+
+        // The adapter is found by its USB IDs and serial number
+        let root = "test_assets/output/ftdi_backend_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        for (device, product, serial, bus, address) in [
+            ("1-1", "6010", "FT2232", "1", "3"),
+            ("1-2", "6014", "FT232A", "1", "4"),
+            ("2-1", "6014", "FT232B", "2", "12"),
+        ] {
+            let dir = format!("{}/{}", root, device);
+            fs::create_dir_all(&dir).await.unwrap();
+            for (name, content) in [
+                ("idVendor", "0403\n"),
+                ("idProduct", product),
+                ("serial", serial),
+                ("busnum", bus),
+                ("devnum", address),
+            ] {
+                fs::write(format!("{}/{}", dir, name), content)
+                    .await
+                    .unwrap();
+            }
+        }
+        let find = |chip, serial| ftdi::find_device(root.as_ref(), chip, serial);
+        assert_eq!(
+            find(FtdiChip::Ft232h, None).unwrap().to_str(),
+            Some("/dev/bus/usb/001/004")
+        );
+        assert_eq!(
+            find(FtdiChip::Ft232h, Some("FT232B")).unwrap().to_str(),
+            Some("/dev/bus/usb/002/012")
+        );
+        assert!(find(FtdiChip::Ft2232h, Some("FT232B")).is_err());
+
+        // ADBUS3 and ACBUS1 are driven through the MPSSE
+        let backend = FtdiBackend::in_memory(FtdiChip::Ft232h);
+        backend.export_output(3, 1).await.unwrap();
+        backend.export_output(9, 0).await.unwrap();
+        assert_eq!(backend.fake_lines(), (1 << 3, 1 << 3 | 1 << 9));
+        backend.write(9, 1).await.unwrap();
+        assert_eq!(backend.read(9).await.unwrap(), 1);
+        backend.set_active_low(3, true).await.unwrap();
+        backend.write(3, 1).await.unwrap();
+        assert_eq!(backend.fake_lines(), (1 << 9, 1 << 3 | 1 << 9));
+        assert_eq!(backend.exported().await.unwrap(), vec![3, 9]);
+
+        // Inputs read the levels on the lines
+        backend.export_input(0).await.unwrap();
+        backend.set_fake_inputs(1);
+        assert_eq!(backend.read(0).await.unwrap(), 1);
+        assert_eq!(backend.direction(0).await.unwrap(), Direction::In);
+        backend.unexport(9).await.unwrap();
+        assert_eq!(backend.fake_lines(), (0, 1 << 3));
+        assert!(matches!(
+            backend.read(9).await,
+            Err(GpioError::NotExported(9))
+        ));
+        assert!(backend.export_input(16).await.is_err());
+        assert!(matches!(
+            backend.set_bias(0, Bias::PullUp).await,
+            Err(GpioError::Unsupported(_))
+        ));
+    }
+
+    #[cfg(feature = "rpi")]
+    #[tokio::test]
+    async fn rpi_backend_test() {
+        use super::super::backend::GpioBackend;
+        use super::super::rpi::{self, RpiBackend};
+
+        // The chip of the header is found by its label, here on a Pi 5
+        let root = "test_assets/output/rpi_backend_test";
+        fs::remove_dir_all(root).await.unwrap_or_default();
+        fs::create_dir_all(root).await.unwrap();
+        for (chip, label) in [
+            ("gpiochip0", "gpio-brcmstb@107d508500"),
+            ("gpiochip4", "pinctrl-rp1"),
+            ("gpiochip10", "raspberrypi-exp-gpio"),
+        ] {
+            fs::write(format!("{}/{}", root, chip), label)
+                .await
+                .unwrap();
+        }
+        let label_of = |chip: &std::path::Path| Ok(std::fs::read_to_string(chip)?);
+        let chip = rpi::find_chip(root.as_ref(), label_of).unwrap();
+        assert_eq!(chip, std::path::Path::new(root).join("gpiochip4"));
+        std::fs::remove_file(&chip).unwrap();
+        assert!(rpi::find_chip(root.as_ref(), label_of).is_err());
+
+        // Only the GPIOs of the header are accessible, by their BCM numbers
+        let backend = RpiBackend::with_chip(chip);
+        assert!(matches!(
+            backend.export_input(28).await,
+            Err(GpioError::UnmappedPin {
+                scheme: "BCM",
+                pin: 28
+            })
+        ));
+        assert_eq!(
+            Board::RaspberryPi.pin_map().physical_to_soc(11).unwrap(),
+            17
+        );
+        assert_eq!(
+            Board::RaspberryPi.pin_map().soc_to_physical(21).unwrap(),
+            40
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mcp23017_test() {
+        // Fake chip releasing its interrupt, wired to the pin 7, when its lines are read
+        struct FakeMcp23017 {
+            registers: Arc<std::sync::Mutex<[u8; 0x16]>>,
+            backend: Arc<MockBackend>,
+        }
+
+        #[async_trait::async_trait]
+        impl I2cBus for FakeMcp23017 {
+            async fn write(&mut self, address: u8, data: &[u8]) -> crate::error::Result<()> {
+                assert_eq!(address, 0x21);
+                let mut registers = self.registers.lock().unwrap();
+                let start = data[0] as usize;
+                registers[start..start + data.len() - 1].copy_from_slice(&data[1..]);
+                Ok(())
+            }
+
+            async fn read(&mut self, address: u8, _: &mut [u8]) -> crate::error::Result<()> {
+                Err(GpioError::Nack { address })
+            }
+
+            async fn write_read(
+                &mut self,
+                _: u8,
+                data: &[u8],
+                buffer: &mut [u8],
+            ) -> crate::error::Result<()> {
+                let start = data[0] as usize;
+                buffer.copy_from_slice(&self.registers.lock().unwrap()[start..][..buffer.len()]);
+                if data[0] == 0x12 {
+                    self.backend.set_value(7, 1).unwrap();
+                }
+                Ok(())
+            }
+        }
+
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let interrupt = InputPin::new(&gpio, 7).await.unwrap();
+        backend.set_value(7, 1).unwrap();
+        let registers = Arc::new(std::sync::Mutex::new([0u8; 0x16]));
+        let fake = || FakeMcp23017 {
+            registers: registers.clone(),
+            backend: backend.clone(),
+        };
+        assert!(Mcp23017::new(fake(), 0x40).await.is_err());
+        let chip = Arc::new(Mcp23017::new(fake(), 0x21).await.unwrap());
+
+        // The lines are reset to inputs, with mirrored interrupt pins
+        let register = |index: usize| registers.lock().unwrap()[index];
+        assert_eq!(register(0x0A), 0x40);
+        assert_eq!((register(0x00), register(0x01)), (0xFF, 0xFF));
+
+        // The lines are used like pins, GPB1 being the line 9
+        let line = chip.pin(9).unwrap();
+        line.set_output(1).await.unwrap();
+        assert_eq!((register(0x14), register(0x15)), (0x00, 0x02));
+        assert_eq!((register(0x00), register(0x01)), (0xFF, 0xFD));
+        chip.set_pull_up(0, true).await.unwrap();
+        assert_eq!(register(0x0C), 0x01);
+        registers.lock().unwrap()[0x13] = 0x02;
+        assert_eq!(line.read().await.unwrap(), 1);
+        assert!(chip.pin(16).is_err());
+
+        // The changes of the watched lines are notified when the interrupt pin falls
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let watch = chip.watch(interrupt, 0x0003, tx).await.unwrap();
+        assert_eq!((register(0x04), register(0x05)), (0x03, 0x00));
+        registers.lock().unwrap()[0x12] = 0x06;
+        backend.set_value(7, 0).unwrap();
+        let event = time::timeout(time::Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((event.pin, event.level), (1, Level::High));
+        assert!(rx.try_recv().is_err());
+        watch.stop().await.unwrap();
+        assert_eq!(register(0x04), 0x00);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pcf8574_test() {
+        // Fake chip whose lines are pulled low by the external levels, and whose interrupt,
+        // wired to the pin 5, is released when the lines are read or written
+        struct FakePcf8574 {
+            latch: Arc<std::sync::Mutex<u8>>,
+            external: Arc<std::sync::Mutex<u8>>,
+            backend: Arc<MockBackend>,
+        }
+
+        #[async_trait::async_trait]
+        impl I2cBus for FakePcf8574 {
+            async fn write(&mut self, _: u8, data: &[u8]) -> crate::error::Result<()> {
+                *self.latch.lock().unwrap() = data[0];
+                self.backend.set_value(5, 1)
+            }
+
+            async fn read(&mut self, _: u8, buffer: &mut [u8]) -> crate::error::Result<()> {
+                buffer[0] = *self.latch.lock().unwrap() & *self.external.lock().unwrap();
+                self.backend.set_value(5, 1)
+            }
+
+            async fn write_read(
+                &mut self,
+                address: u8,
+                _: &[u8],
+                _: &mut [u8],
+            ) -> crate::error::Result<()> {
+                Err(GpioError::Nack { address })
+            }
+        }
+
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let interrupt = InputPin::new(&gpio, 5).await.unwrap();
+        let latch = Arc::new(std::sync::Mutex::new(0));
+        let external = Arc::new(std::sync::Mutex::new(0xFF));
+        let fake = || FakePcf8574 {
+            latch: latch.clone(),
+            external: external.clone(),
+            backend: backend.clone(),
+        };
+        assert!(Pcf8574::new(fake(), 0x30).await.is_err());
+        let chip = Arc::new(Pcf8574::new(fake(), 0x3F).await.unwrap());
+        assert_eq!(*latch.lock().unwrap(), 0xFF);
+
+        // Lines written low are driven low, the others read the external levels
+        let relay = chip.pin(2).unwrap();
+        relay.write(0).await.unwrap();
+        assert_eq!(*latch.lock().unwrap(), 0xFB);
+        assert_eq!(relay.read().await.unwrap(), 0);
+        *external.lock().unwrap() = 0xFE;
+        assert_eq!(chip.read_all().await.unwrap(), 0xFA);
+        relay.set_input().await.unwrap();
+        assert_eq!(chip.read(2).await.unwrap(), 1);
+        assert!(chip.pin(8).is_err());
+
+        // The changes of the watched lines are notified when the interrupt pin falls
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let _watch = chip.watch(interrupt, 0x03, tx).await.unwrap();
+        *external.lock().unwrap() = 0xFD;
+        backend.set_value(5, 0).unwrap();
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            let event = time::timeout(time::Duration::from_secs(1), rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            events.push((event.pin, event.level));
+        }
+        assert_eq!(events, [(0, Level::High), (1, Level::Low)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn mcp3008_test() {
+        let harness = GpioTestHarness::new();
+        let gpio = harness.gpio();
+        let spi = |mode| async move {
+            let sclk = OutputPin::new(gpio, 1, 0).await.unwrap();
+            let mosi = OutputPin::new(gpio, 2, 0).await.unwrap();
+            let miso = InputPin::new(gpio, 3).await.unwrap();
+            let cs = OutputPin::new(gpio, 4, 1).await.unwrap();
+            Spi::new(sclk, mosi, Some(miso), Some(cs), mode, 1000.0)
+                .await
+                .unwrap()
+        };
+        assert!(Mcp3008::new(spi(SpiMode::Mode1).await, Mcp3008Model::Mcp3008).is_err());
+        let adc = Mcp3008::new(spi(SpiMode::Mode0).await, Mcp3008Model::Mcp3004).unwrap();
+        harness.clear();
+
+        // The start bit, single ended mode and channel are sent, then the result is read back
+        assert_eq!(adc.read_channel(3).await.unwrap(), 0);
+        let sent: Vec<u8> = harness
+            .writes_of(2)
+            .iter()
+            .map(|write| write.level.into())
+            .collect();
+        let command = u32::from_be_bytes([0, 0x01, 0xB0, 0x00]);
+        let expected: Vec<u8> = (0..24)
+            .rev()
+            .map(|bit| (command >> bit & 1) as u8)
+            .collect();
+        assert_eq!(sent, expected);
+
+        harness.backend().set_value(3, 1).unwrap();
+        assert_eq!(adc.read_channel(0).await.unwrap(), 1023);
+        assert_eq!(adc.read_differential(1).await.unwrap(), 1023);
+        assert_eq!(adc.read_voltage(2, 3.3).await.unwrap(), 3.3);
+        assert!(adc.read_channel(4).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pin_like_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let data = OutputPin::new(&gpio, 1, 0).await.unwrap();
+        let clock = OutputPin::new(&gpio, 2, 0).await.unwrap();
+        let latch = OutputPin::new(&gpio, 3, 0).await.unwrap();
+        let chain = Arc::new(Hc595::new(data, clock, latch, 2).await.unwrap());
+
+        // A bus on the outputs of a shift register
+        let pins = (0..4).map(|index| chain.pin(index).unwrap()).collect();
+        let bus = PinBus::<4, _>::new(PinGroup::new(pins), BitOrder::MsbFirst).unwrap();
+        bus.write_u8(0b1010).await.unwrap();
+        assert_eq!(chain.bytes().await, vec![0b0000_0101, 0]);
+        assert_eq!(bus.read_u8().await.unwrap(), 0b1010);
+
+        // Native and virtual pins mixed in a group
+        let pins: Vec<Box<dyn PinLike>> = vec![
+            Box::new(OutputPin::new(&gpio, 5, 0).await.unwrap()),
+            Box::new(chain.pin(15).unwrap()),
+        ];
+        PinGroup::new(pins).write_all(1).await.unwrap();
+        assert_eq!(backend.get_value(5).unwrap(), 1);
+        assert_eq!(chain.read(15).await.unwrap(), 1);
+
+        // An LED on an output of the shift register
+        let mut led = Led::new(chain.pin(8).unwrap()).unwrap();
+        led.on().await.unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        assert_eq!(chain.read(8).await.unwrap(), 1);
+        let pin = led.into_pin().await.unwrap();
+        assert_eq!((pin.index(), chain.read(8).await.unwrap()), (8, 0));
+
+        // A button on a pin without edge notification is polled
+        let pin = InputPin::new(&gpio, 4).await.unwrap();
+        let (_button, mut events) = Button::polled(pin, ButtonConfig::default()).await.unwrap();
+        backend.set_value(4, 1).unwrap();
+        time::sleep(time::Duration::from_millis(50)).await;
+        backend.set_value(4, 0).unwrap();
+        assert_eq!(events.recv().await, Some(ButtonEvent::Pressed));
+        assert_eq!(events.recv().await, Some(ButtonEvent::Released));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn relay_bank_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let mut valve = RelayBank::new(RelayConfig {
+            min_off_time: time::Duration::from_millis(500),
+            ..Default::default()
+        });
+        valve
+            .add_channel("open", OutputPin::new(&gpio, 1, 0).await.unwrap())
+            .await
+            .unwrap();
+        valve
+            .add_channel("close", OutputPin::new(&gpio, 2, 0).await.unwrap())
+            .await
+            .unwrap();
+        assert!(valve.add_interlock(&["open", "stop"]).is_err());
+        valve.add_interlock(&["open", "close"]).unwrap();
+
+        // The channels are switched off when added, active low
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+        valve.on("open").await.unwrap();
+        assert_eq!(backend.get_value(1).unwrap(), 0);
+        assert!(valve.is_on("open").await.unwrap());
+
+        // Interlocked channels are never on together
+        assert!(matches!(
+            valve.on("close").await,
+            Err(GpioError::Interlocked { channel, active }) if channel == "close" && active == "open"
+        ));
+        assert_eq!(backend.get_value(2).unwrap(), 1);
+
+        // Reversing waits for the minimum off time
+        let start = time::Instant::now();
+        valve.switch_to("close").await.unwrap();
+        assert_eq!(start.elapsed(), time::Duration::from_millis(500));
+        assert_eq!(backend.get_value(1).unwrap(), 1);
+        assert_eq!(backend.get_value(2).unwrap(), 0);
+
+        time::sleep(time::Duration::from_millis(200)).await;
+        valve.off("close").await.unwrap();
+        let start = time::Instant::now();
+        valve.on("close").await.unwrap();
+        assert_eq!(start.elapsed(), time::Duration::from_millis(500));
+        valve.all_off().await.unwrap();
+        assert_eq!(valve.names().await, ["open", "close"]);
+        assert!(valve.on("stop").await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hbridge_test() {
+        let harness = GpioTestHarness::new();
+        let gpio = harness.gpio();
+        let mut motor = HBridge::new_software(
+            OutputPin::new(gpio, 1, 0).await.unwrap(),
+            OutputPin::new(gpio, 2, 0).await.unwrap(),
+            OutputPin::new(gpio, 3, 0).await.unwrap(),
+            HBridgeConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert!(motor.forward(1.5).await.is_err());
+
+        // Changing direction or braking cuts the bridge for the dead time
+        motor.forward(1.0).await.unwrap();
+        time::sleep(time::Duration::from_millis(100)).await;
+        motor.reverse(0.5).await.unwrap();
+        assert_eq!(motor.state(), MotorState::Reverse(0.5));
+        motor.brake().await.unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        assert_eq!(harness.backend().get_value(3).unwrap(), 1);
+        motor.coast().await.unwrap();
+        time::sleep(time::Duration::from_millis(10)).await;
+        assert_eq!(harness.backend().get_value(3).unwrap(), 0);
+
+        // The dead time is also waited out when coasting between directions, but not when
+        // driving the same way again
+        let ms = time::Duration::from_millis;
+        time::sleep(ms(100)).await;
+        let start = time::Instant::now();
+        motor.forward(1.0).await.unwrap();
+        assert_eq!(start.elapsed(), ms(0));
+        motor.coast().await.unwrap();
+        motor.reverse(1.0).await.unwrap();
+        assert_eq!(start.elapsed(), ms(50));
+        motor.coast().await.unwrap();
+        motor.reverse(0.5).await.unwrap();
+        assert_eq!(start.elapsed(), ms(50));
+        assert_eq!(motor.state(), MotorState::Reverse(0.5));
+
+        harness.assert_sequence(
+            1,
+            &[
+                (0, ms(0)),
+                (1, ms(0)),
+                (0, ms(100)),
+                (0, ms(150)),
+                (0, ms(150)),
+                (1, ms(200)),
+                (0, ms(210)),
+                (1, ms(320)),
+                (0, ms(320)),
+                (0, ms(370)),
+                (0, ms(370)),
+                (0, ms(370)),
+            ],
+        );
+        harness.assert_sequence(
+            2,
+            &[
+                (0, ms(0)),
+                (0, ms(0)),
+                (0, ms(100)),
+                (1, ms(150)),
+                (0, ms(150)),
+                (1, ms(200)),
+                (0, ms(210)),
+                (0, ms(320)),
+                (0, ms(320)),
+                (1, ms(370)),
+                (0, ms(370)),
+                (1, ms(370)),
+            ],
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn buzzer_test() {
+        let harness = GpioTestHarness::new();
+        let pin = OutputPin::new(harness.gpio(), 1, 0).await.unwrap();
+        let mut buzzer = Buzzer::new(pin).unwrap();
+        let ms = time::Duration::from_millis;
+        assert!(buzzer.play(&[Note::tone(0.0, ms(10))]).await.is_err());
+
+        // Each note is a square wave, cut short so notes are heard apart
+        let start = time::Instant::now();
+        buzzer
+            .play(&[
+                Note::tone(100.0, ms(50)),
+                Note::rest(ms(20)),
+                Note::tone(50.0, ms(40)),
+            ])
+            .await
+            .unwrap();
+        assert!(buzzer.is_playing());
+        buzzer.wait().await.unwrap();
+        assert_eq!(start.elapsed(), ms(110));
+        let rises: Vec<time::Duration> = harness
+            .writes_of(1)
+            .iter()
+            .filter(|write| write.level == Level::High)
+            .map(|write| write.time)
+            .collect();
+        assert_eq!(rises, [ms(0), ms(10), ms(20), ms(30), ms(70), ms(90)]);
+        assert_eq!(harness.backend().get_value(1).unwrap(), 0);
+
+        // A melody is cancelled by stopping it
+        buzzer.play(&[Note::semitones(3, ms(500))]).await.unwrap();
+        time::sleep(ms(100)).await;
+        buzzer.stop().await.unwrap();
+        time::sleep(ms(10)).await;
+        assert!(!buzzer.is_playing());
+        assert_eq!(harness.backend().get_value(1).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn bias_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let pin = OutputPin::new(&gpio, 5, 0).await.unwrap();
+
+        // A released line is pulled to the level of its resistor
+        pin.set_bias(Bias::PullUp).await.unwrap();
+        let pin = pin.into_input().await.unwrap();
+        assert_eq!(pin.read().await.unwrap(), 1);
+        pin.set_bias(Bias::PullDown).await.unwrap();
+        let pin = pin
+            .into_output(1)
+            .await
+            .unwrap()
+            .into_input()
+            .await
+            .unwrap();
+        assert_eq!(pin.read().await.unwrap(), 0);
+
+        // Without a resistor, the line keeps the level it was driven to
+        pin.set_bias(Bias::Disabled).await.unwrap();
+        let pin = pin
+            .into_output(1)
+            .await
+            .unwrap()
+            .into_input()
+            .await
+            .unwrap();
+        assert_eq!(pin.read().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn pin_types_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // An input turned into an output drives its default value
+        let input = InputPin::new(&gpio, 5).await.unwrap();
+        assert_eq!(backend.direction(5).await.unwrap(), Direction::In);
+        let output = input.into_output(1).await.unwrap();
+        assert_eq!(output.get_pin_number(), 5);
+        assert_eq!(backend.direction(5).await.unwrap(), Direction::Out);
+        assert_eq!(backend.get_value(5).unwrap(), 1);
+
+        // An output turned back into an input reads the line
+        let input = output.into_input().await.unwrap();
+        assert_eq!(backend.direction(5).await.unwrap(), Direction::In);
+        backend.set_value(5, 0).unwrap();
+        assert_eq!(input.read().await.unwrap(), 0);
+        assert!(matches!(
+            input.into_output(2).await,
+            Err(GpioError::InvalidValue(_))
+        ));
+
+        // The type-erased pin follows the conversions, only writing as an output
+        let pin = GpioPin::from(OutputPin::new(&gpio, 6, 0).await.unwrap());
+        assert!(matches!(pin, GpioPin::Output(_)));
+        let pin = pin.into_input().await.unwrap();
+        assert!(matches!(pin, GpioPin::Input(_)));
+        assert!(matches!(
+            pin.write(1).await,
+            Err(GpioError::WrongDirection { pin: 6, .. })
+        ));
+        let pin = pin.into_output(1).await.unwrap();
+        assert!(matches!(pin, GpioPin::Output(_)));
+        pin.write(0).await.unwrap();
+        assert_eq!(backend.get_value(6).unwrap(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn edge_selection_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // The selected edge is configured on the line
+        let mut pin = InputPin::new(&gpio, 5).await.unwrap();
+        assert!(!pin.support_watch());
+        pin.enable_watch(Edge::Rising).await.unwrap();
+        assert!(pin.support_watch());
+        assert_eq!(pin.edge(), Some(Edge::Rising));
+        assert_eq!(backend.edge(5).await.unwrap(), Some(Edge::Rising));
+
+        // Only the changes on the selected edge are notified
+        let mut changes = pin.changes().unwrap();
+        for (edge, rising, falling) in [
+            (Edge::Rising, true, false),
+            (Edge::Falling, false, true),
+            (Edge::Both, true, true),
+        ] {
+            pin.enable_watch(edge).await.unwrap();
+            assert_eq!(backend.edge(5).await.unwrap(), Some(edge));
+            backend.set_value(5, 1).unwrap();
+            assert_eq!(notified(&mut changes).await, rising, "{:?}", edge);
+            backend.set_value(5, 0).unwrap();
+            assert_eq!(notified(&mut changes).await, falling, "{:?}", edge);
+        }
+
+        // The edges are the ones of the value, inverted on an active-low pin
+        pin.set_active_low(true).await.unwrap();
+        pin.enable_watch(Edge::Rising).await.unwrap();
+        backend.set_value(5, 1).unwrap();
+        assert!(!notified(&mut changes).await);
+        backend.set_value(5, 0).unwrap();
+        assert!(notified(&mut changes).await);
+
+        // Outputs can't be watched
+        let mut pin = GpioPin::new_output(&gpio, 6, 0).await.unwrap();
+        assert!(matches!(
+            pin.enable_watch(Edge::Both).await,
+            Err(GpioError::WatchUnsupported(6))
+        ));
+        assert_eq!(pin.edge(), None);
+    }
+
+    /// Check if a change is notified on a stream before a timeout.
+    async fn notified(changes: &mut ChangeStream) -> bool {
+        time::timeout(time::Duration::from_millis(10), changes.next())
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disable_watch_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+
+        // The edge detection of the line is turned off
+        let mut pin = InputPin::new(&gpio, 5).await.unwrap();
+        pin.enable_watch(Edge::Both).await.unwrap();
+        let mut changes = pin.changes().unwrap();
+        backend.set_value(5, 1).unwrap();
+        assert!(notified(&mut changes).await);
+        pin.disable_watch().await.unwrap();
+        assert!(!pin.support_watch());
+        assert_eq!(pin.edge(), None);
+        assert_eq!(backend.edge(5).await.unwrap(), None);
+        assert!(matches!(pin.changes(), Err(GpioError::WatchUnsupported(5))));
+
+        // No event arrives afterwards, on either edge
+        backend.set_value(5, 0).unwrap();
+        assert!(!notified(&mut changes).await);
+        backend.set_value(5, 1).unwrap();
+        assert!(!notified(&mut changes).await);
+
+        // Watch can be enabled again
+        let mut pin = GpioPin::from(pin);
+        pin.enable_watch(Edge::Falling).await.unwrap();
+        backend.set_value(5, 0).unwrap();
+        assert!(notified(&mut changes).await);
+        pin.disable_watch().await.unwrap();
+        assert!(!pin.support_watch());
+        backend.set_value(5, 1).unwrap();
+        backend.set_value(5, 0).unwrap();
+        assert!(!notified(&mut changes).await);
+
+        // Outputs are never watched
+        let mut pin = GpioPin::new_output(&gpio, 6, 0).await.unwrap();
+        assert!(matches!(
+            pin.disable_watch().await,
+            Err(GpioError::WatchUnsupported(6))
+        ));
+    }
+
+    #[tokio::test]
+    async fn release_test() {
+        let backend = Arc::new(MockBackend::new());
+        let gpio = Gpio::with_backend(GpioConfig::default(), backend.clone());
+        let input = InputPin::new(&gpio, 1).await.unwrap();
+        let output = OutputPin::new(&gpio, 2, 1).await.unwrap();
+        let pin = GpioPin::new_output(&gpio, 3, 0).await.unwrap();
+        assert_eq!(backend.exported().await.unwrap(), vec![1, 2, 3]);
+
+        // Released pins are unexported, the others stay exported
+        input.release().await.unwrap();
+        assert_eq!(backend.exported().await.unwrap(), vec![2, 3]);
+        assert!(matches!(
+            backend.read(1).await,
+            Err(GpioError::NotExported(1))
+        ));
+        output.release().await.unwrap();
+        pin.release().await.unwrap();
+        assert!(backend.exported().await.unwrap().is_empty());
+        assert!(matches!(
+            backend.write(3, 1).await,
+            Err(GpioError::NotExported(3))
+        ));
+
+        // A failed unexport is reported
+        let pin = GpioPin::new_input(&gpio, 4).await.unwrap();
+        backend.inject_fault(
+            Some(4),
+            MockOperation::Unexport,
+            Fault::ExportFailed,
+            Some(1),
+        );
+        assert!(matches!(
+            pin.release().await,
+            Err(GpioError::ExportFailed(_))
+        ));
+        assert_eq!(backend.exported().await.unwrap(), vec![4]);
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod blocking_tests {
+    use super::super::blocking::{GpioPin, InputPin, OutputPin};
+    use super::super::error::GpioError;
+    use super::super::gpio::{GpioCommand, GpioConfig};
+    use std::{fs, path::Path};
+
+    /// Create a sysfs root with scripts standing in for the export and unexport subcommands
+    /// of the gpio command, refusing pin 99.
+    fn fake_sysfs(root: &str) -> GpioConfig {
+        fs::remove_dir_all(root).unwrap_or_default();
+        fs::create_dir_all(root).unwrap();
+        let export = Path::new(root).join("export.sh");
+        fs::write(
+            &export,
+            "root=$(dirname \"$0\")\n\
+             [ \"$1\" = 99 ] && { echo \"Pin 99 is reserved\" >&2; exit 1; }\n\
+             mkdir -p \"$root/gpio$1\" && echo 0 > \"$root/gpio$1/value\"\n",
+        )
+        .unwrap();
+        let unexport = Path::new(root).join("unexport.sh");
+        fs::write(&unexport, "rm -r \"$(dirname \"$0\")/gpio$1\"\n").unwrap();
+
+        // The scripts are run by the shell, so they don't need to be executable
+        GpioConfig {
+            sysfs_root: root.into(),
+            command: GpioCommand {
+                program: "sh".into(),
+                export: export.to_string_lossy().into_owned(),
+                unexport: unexport.to_string_lossy().into_owned(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn blocking_pin_test() {
+        let root = "test_assets/output/blocking_pin_test";
+        let config = fake_sysfs(root);
+        let read = |name: &str| fs::read_to_string(format!("{}/{}", root, name)).unwrap();
+
+        // Inputs read the value file
+        let input = InputPin::new(&config, 1).unwrap();
+        assert_eq!(input.get_pin_number(), 1);
+        fs::write(format!("{}/gpio1/value", root), "1\n").unwrap();
+        assert_eq!(input.read().unwrap(), 1);
+        input.set_active_low(true).unwrap();
+        assert_eq!(read("gpio1/active_low"), "1");
+
+        // Outputs are written their default value, and toggled
+        let output = OutputPin::new(&config, 2, 1).unwrap();
+        assert_eq!(read("gpio2/value"), "1");
+        assert_eq!(output.toggle().unwrap(), 0);
+        assert_eq!(read("gpio2/value"), "0");
+        assert_eq!(output.read().unwrap(), 0);
+        assert!(matches!(output.write(2), Err(GpioError::InvalidValue(_))));
+
+        // Releasing unexports the pins
+        input.release().unwrap();
+        output.release().unwrap();
+        assert!(!Path::new(root).join("gpio1").exists());
+        assert!(!Path::new(root).join("gpio2").exists());
+
+        // The type-erased pins only write and toggle outputs
+        let pin = GpioPin::new_input(&config, 3).unwrap();
+        assert!(matches!(
+            pin.write(1),
+            Err(GpioError::WrongDirection { pin: 3, .. })
+        ));
+        assert!(matches!(
+            pin.toggle(),
+            Err(GpioError::WrongDirection { pin: 3, .. })
+        ));
+        assert_eq!(pin.read().unwrap(), 0);
+        let pin = GpioPin::new_output(&config, 4, 0).unwrap();
+        assert_eq!(pin.toggle().unwrap(), 1);
+        pin.write(0).unwrap();
+        assert_eq!(pin.read().unwrap(), 0);
+        assert_eq!(pin.get_pin_number(), 4);
+
+        // A pin unexported behind its back is reported as such
+        fs::remove_dir_all(format!("{}/gpio4", root)).unwrap();
+        assert!(matches!(pin.read(), Err(GpioError::NotExported(4))));
+    }
+
+    #[test]
+    fn blocking_command_error_test() {
+        let root = "test_assets/output/blocking_command_error_test";
+        let config = fake_sysfs(root);
+
+        // A failed export carries the error of the command
+        match InputPin::new(&config, 99) {
+            Err(GpioError::ExportFailed(message)) => {
+                assert!(message.contains("Pin 99 is reserved"), "{}", message)
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // A failed unexport carries the command run
+        let pin = OutputPin::new(&config, 5, 0).unwrap();
+        fs::remove_dir_all(format!("{}/gpio5", root)).unwrap();
+        match pin.release() {
+            Err(GpioError::CommandFailed { command, .. }) => {
+                assert!(command.starts_with("sh "), "{}", command);
+                assert!(command.ends_with("unexport.sh 5"), "{}", command);
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        // A pin still exported after unexport is an error
+        let noop = format!("{}/noop.sh", root);
+        fs::write(&noop, "").unwrap();
+        let config = GpioConfig {
+            command: GpioCommand {
+                unexport: noop,
+                ..config.command
+            },
+            ..config
+        };
+        let pin = OutputPin::new(&config, 6, 0).unwrap();
+        assert!(matches!(pin.release(), Err(GpioError::ExportFailed(_))));
+
+        // A missing command fails to run
+        let config = GpioConfig {
+            command: GpioCommand {
+                program: "test_assets/output/no_such_gpio".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            OutputPin::new(&config, 7, 0),
+            Err(GpioError::Io(_))
+        ));
+    }
 }
@@ -0,0 +1,161 @@
+//
+// This file provides a software UART for talking to serial devices, e.g. GPS modules,
+// when the hardware UARTs are occupied. Frames are 8N1: a low start bit, 8 data bits
+// least significant first, and a high stop bit.
+//
+// Bytes are sent by writing the TX pin at each bit time. Bytes are received by a task
+// watching the RX pin for the falling edge of a start bit, then sampling the middle
+// of each bit. The timing relies on the tokio timer, so only low baud rates work reliably.
+//
+
+use super::backend::ChangeStream;
+use super::error::{GpioError, Result};
+use super::pin::{Edge, InputPin, OutputPin};
+use futures::FutureExt;
+use std::time::Duration;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_stream::StreamExt;
+
+/// Software UART sending 8N1 frames on a TX pin and receiving them on an RX pin.
+///
+/// Dropping this will stop receiving.
+pub struct SoftUart {
+    tx: OutputPin,
+    bit_time: Duration,
+    received: mpsc::UnboundedReceiver<u8>,
+    rx_thread: JoinHandle<()>,
+}
+
+impl Drop for SoftUart {
+    fn drop(&mut self) {
+        self.rx_thread.abort();
+    }
+}
+
+impl SoftUart {
+    /// Create a UART at the given baud rate, e.g. 300 baud or less.
+    /// The TX line is set to its idle high level and edge notification is enabled on RX.
+    pub async fn new(tx: OutputPin, mut rx: InputPin, baud: u32) -> Result<Self> {
+        if baud == 0 {
+            return Err(GpioError::InvalidValue(
+                "Baud rate must be positive".to_string(),
+            ));
+        }
+
+        tx.write(1).await?;
+        rx.enable_watch(Edge::Both).await?;
+        let changes = rx.changes()?;
+
+        let bit_time = Duration::from_secs_f64(1.0 / baud as f64);
+        let (sender, received) = mpsc::unbounded_channel();
+        let rx_thread = tokio::spawn(run_receiver(rx, changes, bit_time, sender));
+
+        Ok(Self {
+            tx,
+            bit_time,
+            received,
+            rx_thread,
+        })
+    }
+
+    /// Send bytes, returning once the stop bit of the last byte is sent.
+    pub async fn write(&self, data: &[u8]) -> Result<()> {
+        let mut deadline = Instant::now();
+        for &byte in data {
+            // Start bit, data bits and stop bit
+            let bits = std::iter::once(0)
+                .chain((0..8).map(|i| byte >> i & 1))
+                .chain(std::iter::once(1));
+            for bit in bits {
+                self.tx.write(bit).await?;
+                deadline += self.bit_time;
+                time::sleep_until(deadline).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for at least one byte, then read as many received bytes as fit in the buffer.
+    /// Returns the number of bytes read.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let Some(first) = self.received.recv().await else {
+            return Err(GpioError::TaskFailed(
+                "Receiver thread is not running".to_string(),
+            ));
+        };
+        buffer[0] = first;
+
+        let mut len = 1;
+        while len < buffer.len() {
+            match self.received.try_recv() {
+                Ok(byte) => buffer[len] = byte,
+                Err(_) => break,
+            }
+            len += 1;
+        }
+        Ok(len)
+    }
+}
+
+/// Wait for start bits on the RX pin and sample the frames that follow.
+async fn run_receiver(
+    rx: InputPin,
+    mut changes: ChangeStream,
+    bit_time: Duration,
+    sender: mpsc::UnboundedSender<u8>,
+) {
+    while let Some(change) = changes.next().await {
+        if let Err(e) = change {
+            log::error!("Error watching RX pin {}: {}", rx.get_pin_number(), e);
+            continue;
+        }
+
+        // A falling edge starts a frame
+        let start = Instant::now();
+        match rx.read().await {
+            Ok(0) => {}
+            Ok(_) => continue,
+            Err(e) => {
+                log::error!("Error reading RX pin {}: {}", rx.get_pin_number(), e);
+                continue;
+            }
+        }
+
+        match receive_frame(&rx, start, bit_time).await {
+            Ok(Some(byte)) => {
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+            Ok(None) => log::warn!("Framing error on RX pin {}", rx.get_pin_number()),
+            Err(e) => log::error!("Error reading RX pin {}: {}", rx.get_pin_number(), e),
+        }
+
+        // Skip the changes of the frame, the next start bit can't have begun yet
+        while let Some(Some(_)) = changes.next().now_or_never() {}
+    }
+}
+
+/// Sample the data and stop bits of a frame in the middle of each bit.
+/// Returns `None` if the stop bit is missing.
+async fn receive_frame(rx: &InputPin, start: Instant, bit_time: Duration) -> Result<Option<u8>> {
+    let mut byte = 0;
+    for i in 0..8 {
+        time::sleep_until(start + bit_time.mul_f64(i as f64 + 1.5)).await;
+        byte |= rx.read().await? << i;
+    }
+
+    time::sleep_until(start + bit_time.mul_f64(9.5)).await;
+    match rx.read().await? {
+        1 => Ok(Some(byte)),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,97 @@
+//
+// This file provides a driver for the HC-SR04 ultrasonic distance sensor.
+// A 10µs pulse on the trigger pin starts a measurement, then the sensor raises its echo pin
+// for as long as the sound took to come back. The distance is half the way the sound
+// travelled in that time.
+//
+// The echo pulse is timed by polling the pin with monotonic timestamps, so the precision
+// depends on how fast the pin can be read, about a centimeter through sysfs.
+// With the virtual clock of the context, the pin is polled every millisecond of the tokio
+// time instead, so tests can simulate echoes on paused time.
+//
+
+use super::error::{GpioError, Result};
+use super::pin::{InputPin, OutputPin};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Speed of sound in air at 20°C, in millimeters per second.
+const SPEED_OF_SOUND: f64 = 343_000.0;
+/// Length of the pulse starting a measurement.
+const TRIGGER_PULSE: Duration = Duration::from_micros(10);
+/// Default time to wait for each edge of the echo, the sensor gives up after 38ms.
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(40);
+
+/// Driver of an HC-SR04 sensor on a trigger and an echo pin.
+#[derive(Debug)]
+pub struct HcSr04 {
+    trigger: OutputPin,
+    echo: InputPin,
+    timeout: Duration,
+}
+
+impl HcSr04 {
+    /// Create a driver on the given pins, the trigger pin is set low.
+    pub async fn new(trigger: OutputPin, echo: InputPin) -> Result<Self> {
+        trigger.write(0).await?;
+        Ok(Self {
+            trigger,
+            echo,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Set the time to wait for each edge of the echo before giving up.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the time to wait for each edge of the echo before giving up.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Measure the distance to the nearest obstacle in millimeters.
+    /// Fails with [GpioError::Timeout] when no echo comes back, e.g. nothing is in range.
+    pub async fn distance_mm(&self) -> Result<u32> {
+        let echo = self.measure_echo().await?;
+        Ok((echo.as_secs_f64() * SPEED_OF_SOUND / 2.0).round() as u32)
+    }
+
+    /// Trigger a measurement and return the length of the echo pulse.
+    pub async fn measure_echo(&self) -> Result<Duration> {
+        // Trigger pulse
+        self.trigger.write(1).await?;
+        self.trigger.clock().delay(TRIGGER_PULSE).await;
+        self.trigger.write(0).await?;
+
+        // Time the echo pulse
+        let start = self.wait_for(1).await?;
+        let end = self.wait_for(0).await?;
+        Ok(end - start)
+    }
+
+    /// Give the pins back.
+    pub fn into_pins(self) -> (OutputPin, InputPin) {
+        (self.trigger, self.echo)
+    }
+
+    /// Poll the echo pin until it reaches the given value and return when it did.
+    async fn wait_for(&self, value: u8) -> Result<Instant> {
+        let start = Instant::now();
+        loop {
+            if self.echo.read().await? == value {
+                return Ok(Instant::now());
+            }
+            if start.elapsed() > self.timeout {
+                return Err(GpioError::Timeout(format!(
+                    "No echo {} on pin {} after {:?}",
+                    if value == 1 { "start" } else { "end" },
+                    self.echo.get_pin_number(),
+                    self.timeout
+                )));
+            }
+            self.echo.clock().pause().await;
+        }
+    }
+}
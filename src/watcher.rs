@@ -1,25 +1,322 @@
 //
 // This file provides a way to watch for changes in GPIO pins' state.
-// It uses the `inotify` command line tool to watch for changes in the value of the GPIO pins
-// through the sysfs interface.
+// Each pin provides a stream of its changes, inotify events on the sysfs value file
+// or line events from the GPIO character device, and a single task waits on all of them.
+// Each change is timestamped as soon as it arrives, so consumers can compute intervals
+// between changes without the latency of reading the value and notifying them.
+// The previous value of each pin is tracked to tell the edge of each change, and a change
+// whose value is the same as the previous one means the pin went back and forth
+// before it was read, so both edges are notified.
+// Errors met by the watcher thread don't stop it: they are counted in its status and
+// published to subscribers, so supervising code can restart the watcher if needed.
 //
 
-use super::pin::GpioPin;
-use anyhow::{Context, Result, bail};
-use inotify::{EventMask, Inotify, WatchMask};
-use std::collections::HashMap;
-use tokio::{fs, sync::watch, task::JoinHandle};
-use tokio_stream::StreamExt;
+use super::backend::ChangeStream;
+use super::error::{GpioError, Result};
+use super::pin::{Edge, GpioPin, InputPin, Level};
+use futures::FutureExt;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::{Mutex, broadcast, mpsc, watch},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tokio_stream::{Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream};
+use tokio_util::sync::CancellationToken;
+
+/// Number of errors kept for the subscribers of [GpioWatcher::errors] that lag behind.
+const ERROR_CAPACITY: usize = 16;
+/// Longest wait for the interrupt pin of an expander before checking it again,
+/// in case a change was missed.
+const INTERRUPT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Time to wait after an error of an expander watch before trying again.
+const INTERRUPT_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Change of the level of a pin, with its direction and the time it was detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GpioEvent {
+    /// Number of the pin
+    pub pin: u8,
+    /// New level of the pin
+    pub level: Level,
+    /// Direction of the change, [Edge::Rising] or [Edge::Falling].
+    /// The initial value counts as a change from the opposite level.
+    pub edge: Edge,
+    /// Time the change arrived at the watcher, or the time of the last change
+    /// before the value settled when debouncing.
+    /// It's not serialized, a deserialized event is timestamped when it's deserialized
+    #[cfg_attr(feature = "serde", serde(skip, default = "Instant::now"))]
+    pub timestamp: Instant,
+}
+
+impl GpioEvent {
+    /// Create the event of a pin changing to the given level at the given time.
+    pub fn new(pin: u8, level: Level, timestamp: Instant) -> Self {
+        Self {
+            pin,
+            level,
+            edge: level.edge(),
+            timestamp,
+        }
+    }
+
+    /// Get the new value of the pin, as read from it.
+    pub fn value(&self) -> u8 {
+        self.level.into()
+    }
+}
+
+/// Destination of the values detected by a [GpioWatcher]
+/// or a [PollingWatcher](crate::polling::PollingWatcher).
+pub enum Notifier {
+    /// Send the value through a watch channel
+    Watch(watch::Sender<u8>),
+    /// Publish the value to every subscriber of a broadcast channel
+    Broadcast(broadcast::Sender<u8>),
+    /// Call a function with the value from the watcher thread
+    Callback(Box<dyn Fn(u8) + Send>),
+    /// Send the event through a watch channel
+    EventWatch(watch::Sender<GpioEvent>),
+    /// Publish the event to every subscriber of a broadcast channel
+    EventBroadcast(broadcast::Sender<GpioEvent>),
+    /// Call a function with the event from the watcher thread
+    EventCallback(Box<dyn Fn(GpioEvent) + Send>),
+    /// Send the event through an unbounded channel, which can be shared by several pins
+    EventChannel(mpsc::UnboundedSender<GpioEvent>),
+    /// Send the pin number and value through a channel, which can be shared by several pins.
+    /// Values are dropped while the channel is full
+    Channel(mpsc::Sender<(u8, u8)>),
+}
+
+impl Notifier {
+    /// Create a notifier calling the given function with each value.
+    /// The function runs on the watcher thread, so it should return quickly.
+    pub fn callback(callback: impl Fn(u8) + Send + 'static) -> Self {
+        Self::Callback(Box::new(callback))
+    }
+
+    /// Create a notifier calling the given function with each event.
+    /// The function runs on the watcher thread, so it should return quickly.
+    pub fn event_callback(callback: impl Fn(GpioEvent) + Send + 'static) -> Self {
+        Self::EventCallback(Box::new(callback))
+    }
+
+    /// Deliver an event to the notifier.
+    pub(crate) fn notify(&self, event: GpioEvent) -> Result<()> {
+        // Having no subscriber at the moment is not an error for broadcasts
+        match self {
+            Self::Watch(sender) => sender
+                .send(event.value())
+                .map_err(|_| GpioError::ChannelClosed)?,
+            Self::Broadcast(sender) => {
+                let _ = sender.send(event.value());
+            }
+            Self::Callback(callback) => callback(event.value()),
+            Self::EventWatch(sender) => sender.send(event).map_err(|_| GpioError::ChannelClosed)?,
+            Self::EventBroadcast(sender) => {
+                let _ = sender.send(event);
+            }
+            Self::EventCallback(callback) => callback(event),
+            Self::EventChannel(sender) => {
+                sender.send(event).map_err(|_| GpioError::ChannelClosed)?
+            }
+            Self::Channel(sender) => {
+                sender
+                    .try_send((event.pin, event.value()))
+                    .map_err(|e| match e {
+                        mpsc::error::TrySendError::Full(_) => GpioError::ChannelFull,
+                        mpsc::error::TrySendError::Closed(_) => GpioError::ChannelClosed,
+                    })?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<watch::Sender<u8>> for Notifier {
+    fn from(sender: watch::Sender<u8>) -> Self {
+        Self::Watch(sender)
+    }
+}
+
+impl From<broadcast::Sender<u8>> for Notifier {
+    fn from(sender: broadcast::Sender<u8>) -> Self {
+        Self::Broadcast(sender)
+    }
+}
+
+impl From<watch::Sender<GpioEvent>> for Notifier {
+    fn from(sender: watch::Sender<GpioEvent>) -> Self {
+        Self::EventWatch(sender)
+    }
+}
+
+impl From<broadcast::Sender<GpioEvent>> for Notifier {
+    fn from(sender: broadcast::Sender<GpioEvent>) -> Self {
+        Self::EventBroadcast(sender)
+    }
+}
+
+impl From<mpsc::UnboundedSender<GpioEvent>> for Notifier {
+    fn from(sender: mpsc::UnboundedSender<GpioEvent>) -> Self {
+        Self::EventChannel(sender)
+    }
+}
+
+impl From<mpsc::Sender<(u8, u8)>> for Notifier {
+    fn from(sender: mpsc::Sender<(u8, u8)>) -> Self {
+        Self::Channel(sender)
+    }
+}
+
+/// Spawn a task notifying the changes of the lines of a port expander, line 0 being the lowest
+/// bit of the mask, read with the given function each time its active low interrupt pin is
+/// asserted, until the notifier is closed. The events are numbered by their line.
+pub(crate) fn spawn_interrupt_watch<F, Fut>(
+    chip: &'static str,
+    interrupt: InputPin,
+    lines: u16,
+    mut levels: u16,
+    notifier: Notifier,
+    read: F,
+) -> JoinHandle<()>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u16>> + Send,
+{
+    tokio::spawn(async move {
+        loop {
+            // The interrupt pin stays low until the lines are read
+            if let Err(e) = interrupt.wait_for_value(0, INTERRUPT_TIMEOUT).await {
+                log::warn!("Error waiting for the {} interrupt: {}", chip, e);
+                time::sleep(INTERRUPT_RETRY_DELAY).await;
+                continue;
+            }
+            let timestamp = Instant::now();
+            let current = match read().await {
+                Ok(current) => current,
+                Err(e) => {
+                    log::warn!("Error reading the {} lines: {}", chip, e);
+                    time::sleep(INTERRUPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            let changed = (current ^ levels) & lines;
+            levels = current;
+            for line in (0..16).filter(|line| changed >> line & 1 == 1) {
+                let level = (current >> line & 1) as u8;
+                if let Err(e) = notifier.notify(GpioEvent::new(line, level.into(), timestamp)) {
+                    log::warn!("Stopping the {} watch: {}", chip, e);
+                    return;
+                }
+            }
+        }
+    })
+}
+
+/// Problem detected by the watcher thread, which keeps running after it.
+#[derive(Debug, Clone)]
+pub enum WatcherError {
+    /// The value of the pin couldn't be read after a change
+    ReadFailed { pin: u8, error: Arc<GpioError> },
+    /// The change stream of the pin returned an error, e.g. changes lost on an overflow
+    WatchFailed { pin: u8, error: Arc<GpioError> },
+    /// The change stream of the pin ended, so its changes are no longer detected
+    WatchEnded(u8),
+    /// The notifier of the pin couldn't deliver a value, e.g. its receiver was dropped
+    NotifyFailed { pin: u8, error: Arc<GpioError> },
+}
+
+/// Health of a [GpioWatcher], for supervising code to tell when to restart it.
+#[derive(Debug, Clone)]
+pub struct WatcherStatus {
+    /// Whether the watcher thread is running
+    pub running: bool,
+    /// Number of errors since the watcher started
+    pub error_count: u64,
+    /// Last error, if any
+    pub last_error: Option<WatcherError>,
+}
 
 /// Watcher for GPIO pins for detecting changes in GPIO pin's
-/// value (up or down) and sending notifications through watch channels.
-/// A single [GpioWatcher] can be used for multiple pins.
+/// value (up or down) and sending notifications through watch channels or callbacks.
+/// A single [GpioWatcher] can be used for multiple pins,
+/// and pins can be added or removed while the watcher is running.
 ///
-/// Dropping this will abort the watcher.
+/// Dropping this will abort the watcher, use [GpioWatcher::shutdown] to stop it gracefully.
 pub struct GpioWatcher {
+    state: Arc<Mutex<WatcherState>>,
+    commands: mpsc::UnboundedSender<Command>,
+    errors: broadcast::Sender<WatcherError>,
+    shutdown: CancellationToken,
     watcher_thread: JoinHandle<()>,
 }
 
+/// State shared between the [GpioWatcher] handle and its watcher thread.
+struct WatcherState {
+    pins: HashMap<u8, WatchedPin>,
+    health: WatcherHealth,
+}
+
+/// Errors reported by the watcher thread.
+struct WatcherHealth {
+    errors: broadcast::Sender<WatcherError>,
+    error_count: u64,
+    last_error: Option<WatcherError>,
+}
+
+impl WatcherHealth {
+    /// Log an error and report it to the subscribers.
+    fn report(&mut self, error: WatcherError) {
+        log::error!("GPIO watcher error: {:?}", error);
+        self.error_count += 1;
+        self.last_error = Some(error.clone());
+        let _ = self.errors.send(error);
+    }
+}
+
+/// Stream of the changes of a watched pin, ending with `None` when the change stream ends.
+type WatchStream = std::pin::Pin<Box<dyn Stream<Item = Option<Result<()>>> + Send>>;
+
+/// Changes to the set of change streams the watcher thread waits on.
+enum Command {
+    Add(u8, ChangeStream),
+    Remove(u8),
+}
+
+/// A pin being watched along with its notifier, debounce and throttle state.
+struct WatchedPin {
+    pin: Arc<GpioPin>,
+    notifier: Notifier,
+    debounce: Option<Duration>,
+    deadline: Option<Instant>,
+    changed_at: Instant,
+    last_value: u8,
+    throttle: Option<Duration>,
+    sent_at: Option<Instant>,
+    sent_value: u8,
+    held: Option<GpioEvent>,
+}
+
+impl WatchedPin {
+    /// Get the time the held event can be delivered, if there is one.
+    fn throttle_deadline(&self) -> Option<Instant> {
+        self.held?;
+        Some(self.sent_at? + self.throttle.unwrap_or_default())
+    }
+}
+
 impl Drop for GpioWatcher {
     fn drop(&mut self) {
         self.watcher_thread.abort();
@@ -28,75 +325,485 @@ impl Drop for GpioWatcher {
 
 impl GpioWatcher {
     /// Create a new [GpioWatcher] with a map of GPIO pins and watch [Sender]s
-    /// to notify the caller when a change is detected.
+    /// (or any other [Notifier]) to notify the caller when a change is detected.
+    /// Pins can be given as `Arc<GpioPin>` to keep reading them while they are watched.
     /// Dropping this will cancel the watcher.
-    pub async fn new(pin_map: HashMap<GpioPin, watch::Sender<u8>>) -> Result<Self> {
+    pub async fn new<P, N>(pin_map: HashMap<P, N>) -> Result<Self>
+    where
+        P: Into<Arc<GpioPin>>,
+        N: Into<Notifier>,
+    {
+        let pin_map: Vec<(Arc<GpioPin>, N)> = pin_map
+            .into_iter()
+            .map(|(pin, notifier)| (pin.into(), notifier))
+            .collect();
+
         // Check if all pins support watch
         for (pin, _) in &pin_map {
             if !pin.support_watch() {
-                bail!("Pin {} does not support watch", pin.get_pin_number());
+                return Err(GpioError::WatchUnsupported(pin.get_pin_number()));
             }
         }
 
-        // Initialize the notifier map
-        let mut notifier_map: HashMap<i32, (String, watch::Sender<u8>)> = HashMap::new();
+        // Spawn the watcher thread, pins' change streams are handed to it through commands
+        let (errors, _) = broadcast::channel(ERROR_CAPACITY);
+        let state = Arc::new(Mutex::new(WatcherState {
+            pins: HashMap::new(),
+            health: WatcherHealth {
+                errors: errors.clone(),
+                error_count: 0,
+                last_error: None,
+            },
+        }));
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let shutdown = CancellationToken::new();
+        let watcher_thread = tokio::spawn(run_watcher(receiver, state.clone(), shutdown.clone()));
 
-        // Create an inotify instance and add a watch for each pin
-        let inotify = Inotify::init()?;
+        // Add a watch for each pin
+        let watcher = Self {
+            state,
+            commands,
+            errors,
+            shutdown,
+            watcher_thread,
+        };
         for (pin, notifier) in pin_map {
-            // Send the initial value of the pin
-            notifier
-                .send(
-                    pin.read()
-                        .await
-                        .context("Failed to read the initial value for the pin")?,
-                )
-                .context("Failed to notify the initial value")?;
-
-            // Add a watch for the pin's value file
-            let wd = inotify.watches().add(
-                pin.get_value_path(),
-                WatchMask::MODIFY | WatchMask::CREATE | WatchMask::DELETE,
-            )?;
-            notifier_map.insert(
-                wd.get_watch_descriptor_id(),
-                (pin.get_value_path(), notifier),
-            );
-        }
-
-        // Convert inotify into a stream of events
-        let mut event_stream = inotify.into_event_stream([0u8; 4048])?;
-
-        // Spawn the watcher thread
-        let watcher_thread = tokio::spawn(async move {
-            // Wait for incoming events
-            while let Some(Ok(event)) = event_stream.next().await {
-                if event.mask.contains(EventMask::MODIFY) {
-                    // Get the path and notifier for the event
-                    let (value_path, notifier) =
-                        match notifier_map.get(&event.wd.get_watch_descriptor_id()) {
-                            Some((path, notifier)) => (path, notifier),
-                            None => continue,
-                        };
-
-                    // Get the value from the file
-                    let value = match fs::read_to_string(value_path).await {
-                        Ok(value) => value,
-                        Err(e) => {
-                            log::error!("Error reading GPIO value: {}", e);
-                            continue;
-                        }
-                    };
-
-                    // Notify the caller with the value
-                    let message = if value.trim().contains("1") { 1 } else { 0 };
-                    if let Err(e) = notifier.send(message) {
-                        log::warn!("Error sending message: {}", e);
+            watcher.add_pin(pin, notifier).await?;
+        }
+
+        Ok(watcher)
+    }
+
+    /// Create a new [GpioWatcher] calling a function for each pin when a change is detected.
+    /// This is a simpler alternative to [GpioWatcher::new] when no channel is needed.
+    /// The callbacks run on the watcher thread, so they should return quickly.
+    pub async fn with_callbacks<P, F>(callback_map: HashMap<P, F>) -> Result<Self>
+    where
+        P: Into<Arc<GpioPin>>,
+        F: Fn(u8) + Send + 'static,
+    {
+        let pin_map: HashMap<Arc<GpioPin>, Notifier> = callback_map
+            .into_iter()
+            .map(|(pin, callback)| (pin.into(), Notifier::callback(callback)))
+            .collect();
+        Self::new(pin_map).await
+    }
+
+    /// Create a new [GpioWatcher] publishing each pin's values into its own broadcast channel,
+    /// so several independent tasks can react to the same pin.
+    /// The returned map holds the broadcast [Sender]s by pin number, call `subscribe` on them
+    /// to get receivers. Subscribers only get the values sent after they subscribed.
+    pub async fn with_broadcast(
+        pins: Vec<impl Into<Arc<GpioPin>>>,
+        capacity: usize,
+    ) -> Result<(Self, HashMap<u8, broadcast::Sender<u8>>)> {
+        let mut senders = HashMap::new();
+        let mut pin_map = HashMap::new();
+        for pin in pins {
+            let pin: Arc<GpioPin> = pin.into();
+            let (sender, _) = broadcast::channel(capacity);
+            senders.insert(pin.get_pin_number(), sender.clone());
+            pin_map.insert(pin, sender);
+        }
+
+        Ok((Self::new(pin_map).await?, senders))
+    }
+
+    /// Create a new [GpioWatcher] sending the values of all the pins into a single channel,
+    /// as `(pin_number, value)`, for consumers waiting on all of them in one loop.
+    /// Values are dropped and reported through [GpioWatcher::errors] while the channel is full.
+    pub async fn with_channel(
+        pins: Vec<impl Into<Arc<GpioPin>>>,
+        capacity: usize,
+    ) -> Result<(Self, mpsc::Receiver<(u8, u8)>)> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let pin_map: HashMap<Arc<GpioPin>, _> = pins
+            .into_iter()
+            .map(|pin| (pin.into(), sender.clone()))
+            .collect();
+
+        Ok((Self::new(pin_map).await?, receiver))
+    }
+
+    /// Start watching another pin while the watcher is running.
+    /// The initial value of the pin is sent to the notifier right away.
+    pub async fn add_pin(
+        &self,
+        pin: impl Into<Arc<GpioPin>>,
+        notifier: impl Into<Notifier>,
+    ) -> Result<()> {
+        let pin = pin.into();
+        let notifier = notifier.into();
+        let pin_number = pin.get_pin_number();
+        if !pin.support_watch() {
+            return Err(GpioError::WatchUnsupported(pin_number));
+        }
+
+        let mut state = self.state.lock().await;
+        if state.pins.contains_key(&pin_number) {
+            return Err(GpioError::AlreadyWatched(pin_number));
+        }
+
+        // Start listening for changes before reading the initial value so none is missed
+        let changes = pin.changes()?;
+
+        // Send the initial value of the pin
+        let timestamp = Instant::now();
+        let value = pin.read().await?;
+        let result = notifier.notify(GpioEvent::new(pin_number, value.into(), timestamp));
+        pin.gpio().metrics().record_event(pin_number, &result);
+        result?;
+
+        if self
+            .commands
+            .send(Command::Add(pin_number, changes))
+            .is_err()
+        {
+            return Err(GpioError::TaskFailed(
+                "Watcher thread is not running".to_string(),
+            ));
+        }
+        state.pins.insert(
+            pin_number,
+            WatchedPin {
+                pin,
+                notifier,
+                debounce: None,
+                deadline: None,
+                changed_at: timestamp,
+                last_value: value,
+                throttle: None,
+                sent_at: None,
+                sent_value: value,
+                held: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Stop watching a pin while the watcher is running.
+    /// The pin is given back so it can be used elsewhere, use [Arc::try_unwrap]
+    /// to get it out if it's not shared.
+    pub async fn remove_pin(&self, pin_number: u8) -> Result<Arc<GpioPin>> {
+        let mut state = self.state.lock().await;
+        let Some(watched) = state.pins.remove(&pin_number) else {
+            return Err(GpioError::NotWatched(pin_number));
+        };
+
+        // Stop listening for changes, the thread is only gone if the watcher is dropped
+        let _ = self.commands.send(Command::Remove(pin_number));
+
+        Ok(watched.pin)
+    }
+
+    /// Set the debounce time of a watched pin, or disable debouncing with `None`.
+    /// While debouncing, the burst of changes a mechanical switch produces is suppressed
+    /// and the value is only forwarded once it didn't change for the given time.
+    /// A value that settles back to the last forwarded value is not forwarded at all.
+    pub async fn set_debounce(&self, pin_number: u8, debounce: Option<Duration>) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let Some(watched) = state.pins.get_mut(&pin_number) else {
+            return Err(GpioError::NotWatched(pin_number));
+        };
+        watched.debounce = debounce;
+        Ok(())
+    }
+
+    /// Set the throttle time of a watched pin, or disable throttling with `None`.
+    /// While throttling, at most one event is delivered per throttle time: the changes
+    /// coming sooner are coalesced and only the latest is delivered at the end of it,
+    /// unless it's back to the last delivered value.
+    pub async fn set_throttle(&self, pin_number: u8, throttle: Option<Duration>) -> Result<()> {
+        let mut state = self.state.lock().await;
+        let Some(watched) = state.pins.get_mut(&pin_number) else {
+            return Err(GpioError::NotWatched(pin_number));
+        };
+        watched.throttle = throttle;
+        Ok(())
+    }
+
+    /// Get the numbers of the pins currently being watched.
+    pub async fn pin_numbers(&self) -> Vec<u8> {
+        self.state.lock().await.pins.keys().copied().collect()
+    }
+
+    /// Get the health of the watcher: whether it's running and the errors it met.
+    pub async fn status(&self) -> WatcherStatus {
+        let state = self.state.lock().await;
+        WatcherStatus {
+            running: !self.watcher_thread.is_finished(),
+            error_count: state.health.error_count,
+            last_error: state.health.last_error.clone(),
+        }
+    }
+
+    /// Subscribe to the errors met by the watcher from now on,
+    /// e.g. to restart it when the changes of a pin are no longer detected.
+    pub fn errors(&self) -> broadcast::Receiver<WatcherError> {
+        self.errors.subscribe()
+    }
+
+    /// Stop the watcher gracefully: the changes already pending are notified,
+    /// the watches are removed and the watcher thread is awaited.
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.shutdown.cancel();
+        (&mut self.watcher_thread)
+            .await
+            .map_err(|e| GpioError::TaskFailed(e.to_string()))
+    }
+
+    /// Turn the watcher into a single stream of the events of all its pins,
+    /// which then stop notifying their own notifiers.
+    /// The stream starts with the last value of each pin, and dropping it stops the watcher.
+    pub async fn into_stream(self) -> impl Stream<Item = GpioEvent> + Send + Unpin {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        {
+            let mut state = self.state.lock().await;
+            for (&pin_number, watched) in state.pins.iter_mut() {
+                let level = watched.last_value.into();
+                let _ = sender.send(GpioEvent::new(pin_number, level, watched.changed_at));
+                watched.notifier = Notifier::EventChannel(sender.clone());
+            }
+        }
+
+        WatcherStream {
+            _watcher: self,
+            events: UnboundedReceiverStream::new(receiver),
+        }
+    }
+}
+
+/// Stream of the events of a [GpioWatcher], keeping the watcher running.
+struct WatcherStream {
+    _watcher: GpioWatcher,
+    events: UnboundedReceiverStream<GpioEvent>,
+}
+
+impl Stream for WatcherStream {
+    type Item = GpioEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<GpioEvent>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+/// Wait for pin changes and pending debounce deadlines, and notify the callers.
+/// Once shut down, the changes already pending are notified before exiting.
+async fn run_watcher(
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    state: Arc<Mutex<WatcherState>>,
+    shutdown: CancellationToken,
+) {
+    let mut changes = StreamMap::new();
+    loop {
+        // Find the next pin whose value should have settled or be delivered
+        let next_deadline = state
+            .lock()
+            .await
+            .pins
+            .values()
+            .flat_map(|w| [w.deadline, w.throttle_deadline()])
+            .flatten()
+            .min();
+        let settle_timer = time::sleep_until(next_deadline.unwrap_or_else(Instant::now));
+
+        tokio::select! {
+            // Stop waiting, the pending changes are drained below
+            _ = shutdown.cancelled() => break,
+
+            // Add or remove the change streams of pins
+            Some(command) = commands.recv() => apply_command(&mut changes, command),
+
+            // Wait for incoming changes
+            Some((pin_number, change)) = changes.next() => {
+                handle_change(&state, pin_number, change).await;
+            }
+
+            // Notify the values that settled and the throttled events that are due
+            _ = settle_timer, if next_deadline.is_some() => {
+                let now = Instant::now();
+                let mut state = state.lock().await;
+                let WatcherState { pins, health } = &mut *state;
+                for watched in pins.values_mut() {
+                    if watched.deadline.is_some_and(|deadline| deadline <= now) {
+                        watched.deadline = None;
+                        notify_value(watched, true, health).await;
+                    }
+                    if watched.throttle_deadline().is_some_and(|deadline| deadline <= now) {
+                        deliver_held(watched, health);
                     }
                 }
             }
+
+            else => break,
+        }
+    }
+
+    // Drain the commands and changes already pending
+    while let Ok(command) = commands.try_recv() {
+        apply_command(&mut changes, command);
+    }
+    while let Some(Some((pin_number, change))) = changes.next().now_or_never() {
+        handle_change(&state, pin_number, change).await;
+    }
+
+    // Notify the values still settling and the throttled events without waiting for them
+    let mut state = state.lock().await;
+    let WatcherState { pins, health } = &mut *state;
+    for watched in pins.values_mut() {
+        if watched.deadline.take().is_some() {
+            notify_value(watched, true, health).await;
+        }
+        deliver_held(watched, health);
+    }
+
+    // Dropping the change streams removes the watches
+    drop(changes);
+}
+
+/// Add or remove the change stream of a pin.
+fn apply_command(changes: &mut StreamMap<u8, WatchStream>, command: Command) {
+    match command {
+        Command::Add(pin_number, stream) => {
+            let stream = stream.map(Some).chain(tokio_stream::once(None));
+            changes.insert(pin_number, Box::pin(stream));
+        }
+        Command::Remove(pin_number) => {
+            changes.remove(&pin_number);
+        }
+    }
+}
+
+/// Handle a change of a pin, either waiting for its value to settle or notifying it.
+/// `None` means the change stream of the pin ended.
+async fn handle_change(state: &Mutex<WatcherState>, pin_number: u8, change: Option<Result<()>>) {
+    let timestamp = Instant::now();
+
+    // Get the pin for the change
+    let mut state = state.lock().await;
+    let WatcherState { pins, health } = &mut *state;
+    let Some(watched) = pins.get_mut(&pin_number) else {
+        return;
+    };
+
+    watched
+        .pin
+        .gpio()
+        .metrics()
+        .record(pin_number, |metrics| match &change {
+            Some(Ok(())) => {}
+            Some(Err(GpioError::WatchOverflow(_))) => {
+                metrics.errors += 1;
+                metrics.overflows += 1;
+            }
+            Some(Err(_)) | None => metrics.errors += 1,
         });
+    match change {
+        Some(Ok(())) => {}
+        // Changes were lost, but the value can still be read
+        Some(Err(e @ GpioError::WatchOverflow(_))) => health.report(WatcherError::WatchFailed {
+            pin: pin_number,
+            error: Arc::new(e),
+        }),
+        Some(Err(e)) => {
+            health.report(WatcherError::WatchFailed {
+                pin: pin_number,
+                error: Arc::new(e),
+            });
+            return;
+        }
+        None => {
+            health.report(WatcherError::WatchEnded(pin_number));
+            return;
+        }
+    }
+
+    // Either wait for the value to settle or notify right away
+    watched.changed_at = timestamp;
+    match watched.debounce {
+        Some(debounce) => watched.deadline = Some(timestamp + debounce),
+        None => notify_value(watched, false, health).await,
+    }
+}
+
+/// Read the current value of a watched pin and notify the caller with it.
+/// Settled values equal to the last notified value are skipped.
+async fn notify_value(watched: &mut WatchedPin, settled: bool, health: &mut WatcherHealth) {
+    // Get the value of the pin
+    let message = match watched.pin.read().await {
+        Ok(value) => value,
+        Err(e) => {
+            health.report(WatcherError::ReadFailed {
+                pin: watched.pin.get_pin_number(),
+                error: Arc::new(e),
+            });
+            return;
+        }
+    };
+
+    // Notify the caller with the value
+    if message == watched.last_value {
+        if settled {
+            return;
+        }
+        // The pin changed and came back before it was read, notify the missed edge first
+        notify_event(watched, message ^ 1, health);
+    }
+    notify_event(watched, message, health);
+}
+
+/// Notify the caller with a new value of a watched pin,
+/// or hold it until the end of the throttle time.
+fn notify_event(watched: &mut WatchedPin, value: u8, health: &mut WatcherHealth) {
+    watched.last_value = value;
+    let event = GpioEvent::new(
+        watched.pin.get_pin_number(),
+        value.into(),
+        watched.changed_at,
+    );
+    let throttled = watched
+        .throttle
+        .zip(watched.sent_at)
+        .is_some_and(|(throttle, sent_at)| Instant::now() < sent_at + throttle);
+    if throttled {
+        watched.held = Some(event);
+    } else {
+        deliver(watched, event, health);
+    }
+}
 
-        Ok(Self { watcher_thread })
+/// Deliver the event held by the throttle, unless it's back to the last delivered value.
+fn deliver_held(watched: &mut WatchedPin, health: &mut WatcherHealth) {
+    if let Some(event) = watched.held.take()
+        && event.value() != watched.sent_value
+    {
+        deliver(watched, event, health);
+    }
+}
+
+/// Send an event to the notifier of a watched pin.
+fn deliver(watched: &mut WatchedPin, event: GpioEvent, health: &mut WatcherHealth) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        pin = event.pin,
+        value = event.value(),
+        latency_us = event.timestamp.elapsed().as_micros() as u64,
+        "GPIO event delivered"
+    );
+    watched.held = None;
+    watched.sent_at = Some(Instant::now());
+    watched.sent_value = event.value();
+    let result = watched.notifier.notify(event);
+    watched
+        .pin
+        .gpio()
+        .metrics()
+        .record_event(event.pin, &result);
+    if let Err(e) = result {
+        health.report(WatcherError::NotifyFailed {
+            pin: event.pin,
+            error: Arc::new(e),
+        });
     }
 }
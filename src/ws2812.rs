@@ -0,0 +1,140 @@
+//
+// This file provides a driver for WS2812 addressable LEDs (NeoPixels), chained on a single
+// data line. Each LED takes the first 24 bits it receives, green, red then blue, and passes
+// the rest down the strip. A low line for more than 50µs latches the colors.
+//
+// Bits are 1.25µs pulses, long for a 1 and short for a 0, with a tolerance of about 150ns.
+// They can be bit-banged on a pin with busy waits, which only holds the timing with a backend
+// writing in well under a microsecond. Otherwise, each bit can be encoded as 3 SPI bits
+// sent at 2.4MHz, the strip data line on MOSI.
+//
+
+use super::bus::BitOrder;
+use super::error::Result;
+use super::pin::OutputPin;
+use super::spi::Spi;
+use std::time::{Duration, Instant};
+
+/// Length of a bit on the data line.
+const BIT_PERIOD: Duration = Duration::from_nanos(1250);
+/// High time of a 1 bit.
+const ONE_HIGH: Duration = Duration::from_nanos(800);
+/// High time of a 0 bit.
+const ZERO_HIGH: Duration = Duration::from_nanos(400);
+/// Low time latching the colors, with some margin over the 50µs of the datasheet.
+const RESET: Duration = Duration::from_micros(80);
+/// SPI clock frequency at which 3 SPI bits make up one bit of the strip.
+pub const SPI_FREQUENCY: f64 = 2_400_000.0;
+/// Zero bytes sent after the pixels to latch the colors, 80µs at the SPI frequency.
+const SPI_RESET_BYTES: usize = 24;
+
+/// Color of a LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Create a color from its red, green and blue components.
+    pub fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Get the bytes of the color in the order they are sent.
+    fn to_grb(self) -> [u8; 3] {
+        [self.g, self.r, self.b]
+    }
+}
+
+/// Output driving the data line of a [Ws2812] strip.
+#[derive(Debug)]
+enum Ws2812Output {
+    Pin(OutputPin),
+    Spi(Box<Spi>),
+}
+
+/// Strip of WS2812 LEDs.
+#[derive(Debug)]
+pub struct Ws2812 {
+    output: Ws2812Output,
+}
+
+impl Ws2812 {
+    /// Create a strip bit-banged on the given pin. The line is set low.
+    pub async fn new_pin(pin: OutputPin) -> Result<Self> {
+        pin.write(0).await?;
+        Ok(Self {
+            output: Ws2812Output::Pin(pin),
+        })
+    }
+
+    /// Create a strip driven by the MOSI line of an SPI bus in mode 0,
+    /// whose clock should run at [SPI_FREQUENCY].
+    pub fn new_spi(mut spi: Spi) -> Self {
+        spi.set_bit_order(BitOrder::MsbFirst);
+        Self {
+            output: Ws2812Output::Spi(Box::new(spi)),
+        }
+    }
+
+    /// Send the colors of the LEDs, from the start of the strip, and latch them.
+    /// LEDs past the end of the slice keep their colors.
+    pub async fn set_pixels(&self, pixels: &[Rgb]) -> Result<()> {
+        match &self.output {
+            Ws2812Output::Pin(pin) => {
+                let mut deadline = Instant::now();
+                for byte in pixels.iter().flat_map(|pixel| pixel.to_grb()) {
+                    for bit in (0..8).rev() {
+                        let high = if byte >> bit & 1 == 1 {
+                            ONE_HIGH
+                        } else {
+                            ZERO_HIGH
+                        };
+                        pin.write(1).await?;
+                        spin_until(deadline + high);
+                        pin.write(0).await?;
+                        deadline += BIT_PERIOD;
+                        spin_until(deadline);
+                    }
+                }
+                spin_until(Instant::now() + RESET);
+                Ok(())
+            }
+            Ws2812Output::Spi(spi) => spi.write(&encode_spi(pixels)).await,
+        }
+    }
+
+    /// Turn all the LEDs of a strip of the given length off.
+    pub async fn clear(&self, length: usize) -> Result<()> {
+        self.set_pixels(&vec![Rgb::default(); length]).await
+    }
+}
+
+/// Encode the colors into SPI bytes to send at [SPI_FREQUENCY], MSB first,
+/// each bit becoming `110` for a 1 and `100` for a 0, followed by the zero bytes of the reset.
+pub fn encode_spi(pixels: &[Rgb]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(pixels.len() * 9 + SPI_RESET_BYTES);
+    let mut buffer: u32 = 0;
+    let mut buffered = 0;
+    for byte in pixels.iter().flat_map(|pixel| pixel.to_grb()) {
+        for bit in (0..8).rev() {
+            buffer = buffer << 3 | 0b100 | (byte as u32 >> bit & 1) << 1;
+            buffered += 3;
+            if buffered >= 8 {
+                buffered -= 8;
+                bytes.push((buffer >> buffered) as u8);
+            }
+        }
+    }
+    bytes.extend(std::iter::repeat_n(0, SPI_RESET_BYTES));
+    bytes
+}
+
+/// Busy wait until the deadline, the bits being far shorter than the tokio timer resolution.
+fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}